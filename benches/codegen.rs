@@ -0,0 +1,75 @@
+//! Benchmarks for compiling large programs to C
+//!
+//! Run with `cargo bench`. Each benchmark prints the fastest of several runs.
+extern crate haumea;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::codegen::CodeGen;
+use haumea::codegen::c::CodeGenerator;
+use haumea::codegen::emitter::Emitter;
+
+/// How many times each benchmark is run
+const RUNS: u32 = 10;
+
+/// Returns a program with `count` functions, each with a few statements
+fn source(count: usize) -> String {
+    let mut source = String::new();
+    for i in 0..count {
+        source.push_str(&format!("to f_{} with (n) do
+    variable total
+    set total to n * {} + 1
+    if total > 100 then change total by 0 - 100 else display(total)
+    return total
+end
+", name(i), i));
+    }
+    source.push_str("to main return f_a(1)\n");
+    source
+}
+
+/// Returns a name for the number `i`, since names can't have digits
+fn name(mut i: usize) -> String {
+    let mut name = String::new();
+    loop {
+        name.push((b'a' + (i % 26) as u8) as char);
+        i /= 26;
+        if i == 0 {
+            return name;
+        }
+    }
+}
+
+/// Prints how long the fastest run of `f` took
+fn bench<T, F: FnMut() -> T>(name: &str, mut f: F) {
+    let mut fastest = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        black_box(f());
+        fastest = fastest.min(start.elapsed());
+    }
+    println!("{:<40} {:>10.2?}", name, fastest);
+}
+
+/// Writes `lines` lines of code to `out`
+fn emit(mut out: Emitter, lines: usize) -> String {
+    for i in 0..lines {
+        out.indent();
+        out.line(format_args!("x = x + {};", i));
+        out.dedent();
+    }
+    out.finish()
+}
+
+fn main() {
+    let source = source(20_000);
+    let program = parse(Scanner::new(&source)).unwrap();
+    bench("parse 20000 functions", || parse(Scanner::new(&source)).unwrap());
+    bench("compile 20000 functions", || CodeGenerator::new().compile(&program).unwrap());
+    // What sizing the output up front saves, without the rest of the compiler
+    let size = emit(Emitter::new("    "), 1_000_000).len();
+    bench("emit 1000000 lines, growing", || emit(Emitter::new("    "), 1_000_000));
+    bench("emit 1000000 lines, sized up front", || emit(Emitter::with_capacity("    ", size), 1_000_000));
+}
@@ -0,0 +1,65 @@
+//! Benchmarks for parsing large programs
+//!
+//! Run with `cargo bench`. Each benchmark prints the fastest of several runs,
+//! and how long each thousand lines took, which stays the same as programs
+//! grow since the parser reads each token once.
+extern crate haumea;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+
+/// How many times each benchmark is run
+const RUNS: u32 = 10;
+
+/// The statements that are repeated to make a long function, six lines in all
+const STATEMENTS: &str = "    set total to total * 42 + n modulo 7
+    if total >= 100 and not (n = 0) then change total by 0 - 100
+    else display(total, n)
+    for each item in [1, 2, total][1] through n do
+        change total by item
+    end
+";
+
+/// Returns a program with a `main` of about `lines` lines
+fn one_function(lines: usize) -> String {
+    let mut source = String::from("to main with (n) do\n    variable total\n");
+    for _ in 0..lines / 6 {
+        source.push_str(STATEMENTS);
+    }
+    source.push_str("    return total\nend\n");
+    source
+}
+
+/// Returns a program of about `lines` lines, in functions of 8 lines each
+fn many_functions(lines: usize) -> String {
+    let mut source = String::new();
+    for _ in 0..lines / 8 {
+        source.push_str("to compute with (n) do\n    variable total\n");
+        source.push_str(STATEMENTS);
+        source.push_str("end\n");
+    }
+    source
+}
+
+/// Prints how long the fastest parse of `source` took
+fn bench(name: &str, source: &str) {
+    let mut fastest = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        black_box(parse(Scanner::new(black_box(source))).unwrap());
+        fastest = fastest.min(start.elapsed());
+    }
+    let thousands = source.lines().count() as f64 / 1000.0;
+    println!("{:<40} {:>10.2?} {:>10.2?} per 1000 lines", name, fastest, fastest.div_f64(thousands));
+}
+
+fn main() {
+    for &lines in &[10_000, 20_000, 40_000] {
+        bench(&format!("parse a {} line function", lines), &one_function(lines));
+    }
+    for &lines in &[10_000, 20_000, 40_000] {
+        bench(&format!("parse {} lines of functions", lines), &many_functions(lines));
+    }
+}
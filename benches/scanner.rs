@@ -0,0 +1,44 @@
+//! Benchmarks for scanning large programs
+//!
+//! Run with `cargo bench`. Each benchmark prints the fastest of several runs,
+//! and how many megabytes of source that scans in a second.
+extern crate haumea;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use haumea::scanner::Scanner;
+
+/// How many times each benchmark is run
+const RUNS: u32 = 10;
+
+/// The code that is repeated to make a large program
+const FUNCTION: &str = "to compute_total with (first, second) do
+    variable running_total /* the total so far */
+    set running_total to first * 42 + second modulo 7
+    if running_total >= 100 and not (second = 0) then change running_total by 0 - 100
+    else display(running_total)
+    for each item in first through second do
+        change running_total by item
+    end
+    return running_total
+end
+";
+
+/// Prints how long the fastest scan of `source` took
+fn bench(name: &str, source: &str) {
+    let mut fastest = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        black_box(Scanner::new(black_box(source)).count());
+        fastest = fastest.min(start.elapsed());
+    }
+    let megabytes = source.len() as f64 / 1_000_000.0;
+    println!("{:<40} {:>10.2?} {:>8.1} MB/s", name, fastest, megabytes / fastest.as_secs_f64());
+}
+
+fn main() {
+    let source = FUNCTION.repeat(20_000);
+    bench("scan 20000 functions", &source);
+    let names: Vec<&str> = source.split_whitespace().filter(|word| word.chars().all(char::is_alphabetic)).collect();
+    bench("scan only names and keywords", &names.join(" "));
+}
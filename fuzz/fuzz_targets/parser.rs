@@ -0,0 +1,33 @@
+//! Checks that the parser never panics, reporting mistakes in the program as
+//! errors instead, and that every program it accepts can be compiled to C
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate haumea;
+
+use std::path::PathBuf;
+use haumea::loader::Loader;
+use haumea::parser::parse_str;
+use haumea::validate::validate;
+use haumea::codegen::CodeGen;
+use haumea::codegen::c::CodeGenerator;
+
+fuzz_target!(|data: &[u8]| {
+    let source = match ::std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    let _ = parse_str(source);
+    // Files used by the source are looked for in a directory that doesn't exist
+    let mut loader = Loader::new(PathBuf::from("/nonexistent/haumea-fuzz"));
+    let loaded = loader.load_source(source, None);
+    let directives = loader.directives();
+    let program = match loaded.and_then(|_| loader.finish()) {
+        Ok(program) => program,
+        Err(_) => return,
+    };
+    if let Err(problems) = validate(&program) {
+        panic!("The parser accepted an invalid tree: {}", problems[0]);
+    }
+    CodeGenerator::with_directives(Default::default(), directives).compile(&program).unwrap();
+});
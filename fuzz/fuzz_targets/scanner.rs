@@ -0,0 +1,15 @@
+//! Checks that the scanner reads any text without panicking or looping forever
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = ::std::str::from_utf8(data) {
+        // Every token is at least one char, except for errors at the end of the input
+        let count = Scanner::new(source).take(source.len() + 2).count();
+        assert!(count <= source.len() + 1, "The scanner read more tokens than the source has chars");
+    }
+});
@@ -0,0 +1,827 @@
+//! src/ast.rs
+//! The Haumea abstract syntax tree, and tools for walking and rewriting it.
+//!
+//! The tree itself is built by the parser, and its types are re-exported here.
+//! To look at every part of a Program, implement `Visitor` with the methods
+//! for the nodes you care about, and pass it to `walk_program`:
+//!
+//! ```
+//! # use haumea::scanner::Scanner;
+//! # use haumea::parser::parse;
+//! use haumea::ast::{self, Expression, Visitor};
+//!
+//! struct CallCounter(usize);
+//!
+//! impl Visitor for CallCounter {
+//!     fn visit_expression(&mut self, expression: &Expression) {
+//!         if let Expression::Call { .. } = *expression {
+//!             self.0 += 1;
+//!         }
+//!     }
+//! }
+//!
+//! let program = parse(Scanner::new("to main return f(g(1)) + 2")).unwrap();
+//! let mut counter = CallCounter(0);
+//! ast::walk_program(&mut counter, &program);
+//! assert_eq!(counter.0, 2);
+//! ```
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+use symbol::Symbol;
+use parser::FileId;
+pub use parser::{Block, Expression, Function, Ident, Operator, Program, Signature, Span, Statement, Type, Types,
+                 Visibility};
+
+/// Looks at the nodes of a tree, as they are walked by `walk_program`
+///
+/// Every method does nothing by default. Each node is visited before the
+/// nodes inside it, in the order they appear in the source.
+pub trait Visitor {
+    /// Visits a function, before its code
+    fn visit_function(&mut self, _func: &Function) {}
+    /// Visits a statement, before the statements and expressions inside it
+    fn visit_statement(&mut self, _statement: &Statement) {}
+    /// Visits an expression, before the expressions inside it
+    fn visit_expression(&mut self, _expression: &Expression) {}
+}
+
+/// Visits every function in `program`, and everything inside them
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &[Function]) {
+    for func in program {
+        walk_function(visitor, func);
+    }
+}
+
+/// Visits `func` and everything inside it
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, func: &Function) {
+    visitor.visit_function(func);
+    walk_statement(visitor, &func.code);
+}
+
+/// Visits `statement` and everything inside it
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    walk(visitor, Node::Statement(statement));
+}
+
+/// Visits `expression` and everything inside it
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    walk(visitor, Node::Expression(expression));
+}
+
+/// A statement or expression waiting to be visited
+enum Node<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+}
+
+/// Visits `node` and everything inside it
+///
+/// The nodes waiting to be visited are kept on a stack of their own rather
+/// than the call stack, so a tree nested as deeply as generated code can be
+/// is walked without overflowing it.
+fn walk<V: Visitor + ?Sized>(visitor: &mut V, node: Node) {
+    let mut waiting = vec![node];
+    let mut inside = vec![];
+    while let Some(node) = waiting.pop() {
+        match node {
+            Node::Statement(statement) => {
+                visitor.visit_statement(statement);
+                match *statement {
+                    Statement::Return(ref exp, _) |
+                    Statement::Set(_, ref exp, _) |
+                    Statement::Change(_, ref exp, _) => inside.push(Node::Expression(exp)),
+                    Statement::Var(..) | Statement::Break(..) | Statement::Continue(_) => (),
+                    Statement::If { ref cond, ref if_clause, ref else_clause, .. } => {
+                        inside.push(Node::Expression(cond));
+                        inside.push(Node::Statement(if_clause));
+                        if let Some(ref else_clause) = **else_clause {
+                            inside.push(Node::Statement(else_clause));
+                        }
+                    },
+                    Statement::Do(ref block, _) => inside.extend(block.iter().map(|sub| Node::Statement(sub))),
+                    Statement::Call { ref arguments, .. } => {
+                        inside.extend(arguments.iter().map(Node::Expression));
+                    },
+                    Statement::Forever(ref body, ..) => inside.push(Node::Statement(body)),
+                    Statement::While { ref cond, ref body, .. } => {
+                        inside.push(Node::Expression(cond));
+                        inside.push(Node::Statement(body));
+                    },
+                    Statement::ForEach { ref start, ref end, ref by, ref body, .. } => {
+                        inside.push(Node::Expression(start));
+                        inside.push(Node::Expression(end));
+                        inside.push(Node::Expression(by));
+                        inside.push(Node::Statement(body));
+                    },
+                }
+            },
+            Node::Expression(expression) => {
+                visitor.visit_expression(expression);
+                match *expression {
+                    Expression::BinaryOp { ref left, ref right, .. } => {
+                        inside.push(Node::Expression(left));
+                        inside.push(Node::Expression(right));
+                    },
+                    Expression::UnaryOp { ref expression, .. } | Expression::Length(ref expression, _) => {
+                        inside.push(Node::Expression(expression));
+                    },
+                    Expression::Index { ref list, ref index, .. } => {
+                        inside.push(Node::Expression(list));
+                        inside.push(Node::Expression(index));
+                    },
+                    Expression::List(ref items, _) => inside.extend(items.iter().map(|item| Node::Expression(item))),
+                    Expression::Integer(..) | Expression::BigInteger(..) | Expression::Float(..) |
+                    Expression::Ident(..) | Expression::Str(..) | Expression::Boolean(..) => (),
+                    Expression::Call { ref arguments, .. } => {
+                        inside.extend(arguments.iter().map(|arg| Node::Expression(arg)));
+                    },
+                }
+            },
+        }
+        // The nodes inside are visited in the order they are written, so the last goes on the stack first
+        waiting.extend(inside.drain(..).rev());
+    }
+}
+
+/// Identifies a node of a Program
+///
+/// The parser numbers each statement, expression and function it builds as it
+/// builds it, and stores the number in the node's span, so the id stays with the
+/// node when the tree is cloned or folded. Numbers are unique in a file, and
+/// the `ParseSession` doesn't number a node again unless the code it is in
+/// changes. Nodes made by passes after parsing, like `main` or the code of a
+/// template where it is used, have no id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId {
+    /// The file the node is in
+    pub file: FileId,
+    /// The node's number in its file
+    pub node: u32,
+}
+
+/// Results of an analysis, like the function a call resolves to, by node
+///
+/// Keeping results in a side table leaves the tree unchanged, so several
+/// analyses can run over the same tree and use each other's results.
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// use haumea::ast::{self, Expression, SideTable, Visitor};
+///
+/// /// Records the value of every number
+/// struct Numbers(SideTable<i64>);
+///
+/// impl Visitor for Numbers {
+///     fn visit_expression(&mut self, expression: &Expression) {
+///         if let (Expression::Integer(n, _), Some(id)) = (expression, expression.span().id()) {
+///             self.0.insert(id, *n);
+///         }
+///     }
+/// }
+///
+/// let program = parse(Scanner::new("to main return 1 + 2")).unwrap();
+/// let mut numbers = Numbers(SideTable::new());
+/// ast::walk_program(&mut numbers, &program);
+/// // The ids are kept when the tree is cloned
+/// if let ast::Statement::Return(Expression::BinaryOp { ref left, .. }, _) = program.clone()[0].code {
+///     assert_eq!(numbers.0[&left.span().id().unwrap()], 1);
+/// }
+/// ```
+pub type SideTable<T> = HashMap<NodeId, T>;
+
+/// Returns the number of nodes in `program`
+pub fn node_count(program: &[Function]) -> usize {
+    struct Counter(usize);
+    impl Visitor for Counter {
+        fn visit_function(&mut self, _func: &Function) {
+            self.0 += 1;
+        }
+        fn visit_statement(&mut self, _statement: &Statement) {
+            self.0 += 1;
+        }
+        fn visit_expression(&mut self, _expression: &Expression) {
+            self.0 += 1;
+        }
+    }
+    let mut counter = Counter(0);
+    walk_program(&mut counter, program);
+    counter.0
+}
+
+/// Rebuilds a tree, possibly changing its nodes, as it is walked by `fold_program`
+///
+/// Every method rebuilds its node by folding the nodes inside it by default,
+/// so a Folder only needs to implement the methods for the nodes it changes.
+/// An implementation can call the matching `rebuild_` function to fold the
+/// inside of a node as well.
+pub trait Folder {
+    /// Folds a function
+    fn fold_function(&mut self, func: Function) -> Function {
+        rebuild_function(self, func)
+    }
+    /// Folds a statement
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        rebuild_statement(self, statement)
+    }
+    /// Folds an expression
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        rebuild_expression(self, expression)
+    }
+}
+
+/// Folds every function in `program`
+pub fn fold_program<F: Folder + ?Sized>(folder: &mut F, program: Program) -> Program {
+    program.into_iter().map(|func| folder.fold_function(func)).collect()
+}
+
+/// Returns the value inside `rc`, cloning it if it is shared
+fn take<T: Clone>(rc: Arc<T>) -> T {
+    Arc::try_unwrap(rc).unwrap_or_else(|rc| (*rc).clone())
+}
+
+/// Rebuilds `func` with its code folded by `folder`
+pub fn rebuild_function<F: Folder + ?Sized>(folder: &mut F, func: Function) -> Function {
+    Function {
+        code: folder.fold_statement(func.code),
+        ..func
+    }
+}
+
+/// Rebuilds `statement` with the statements and expressions inside it folded by `folder`
+pub fn rebuild_statement<F: Folder + ?Sized>(folder: &mut F, mut statement: Statement) -> Statement {
+    match statement {
+        Statement::Return(ref mut exp, _) | Statement::Set(_, ref mut exp, _) | Statement::Change(_, ref mut exp, _) => {
+            fold_expression_in(folder, exp);
+        },
+        Statement::Var(..) | Statement::Break(..) | Statement::Continue(..) => (),
+        Statement::If { ref mut cond, ref mut if_clause, ref mut else_clause, .. } => {
+            fold_expression_in(folder, cond);
+            fold_statement_in(folder, if_clause);
+            if let Some(ref mut else_clause) = *Arc::make_mut(else_clause) {
+                *else_clause = folder.fold_statement(mem::take(else_clause));
+            }
+        },
+        Statement::Do(ref mut block, _) => {
+            for sub in block {
+                fold_statement_in(folder, sub);
+            }
+        },
+        Statement::Call { ref mut arguments, .. } => {
+            for arg in arguments {
+                fold_expression_in(folder, arg);
+            }
+        },
+        Statement::Forever(ref mut body, ..) => fold_statement_in(folder, body),
+        Statement::While { ref mut cond, ref mut body, .. } => {
+            fold_expression_in(folder, cond);
+            fold_statement_in(folder, body);
+        },
+        Statement::ForEach { ref mut start, ref mut end, ref mut by, ref mut body, .. } => {
+            fold_expression_in(folder, start);
+            fold_expression_in(folder, end);
+            fold_expression_in(folder, by);
+            fold_statement_in(folder, body);
+        },
+    }
+    statement
+}
+
+/// Folds `exp` with `folder` where it is
+fn fold_expression_in<F: Folder + ?Sized>(folder: &mut F, exp: &mut Expression) {
+    *exp = folder.fold_expression(mem::take(exp));
+}
+
+/// Folds the statement in `rc` with `folder` where it is, copying it first if it is shared
+fn fold_statement_in<F: Folder + ?Sized>(folder: &mut F, rc: &mut Arc<Statement>) {
+    let statement = Arc::make_mut(rc);
+    *statement = folder.fold_statement(mem::take(statement));
+}
+
+/// Rebuilds `expression` with the expressions inside it folded by `folder`
+pub fn rebuild_expression<F: Folder + ?Sized>(folder: &mut F, expression: Expression) -> Expression {
+    match expression {
+        Expression::BinaryOp { operator, left, right, span } => Expression::BinaryOp {
+            operator,
+            left: Arc::new(folder.fold_expression(take(left))),
+            right: Arc::new(folder.fold_expression(take(right))),
+            span,
+        },
+        Expression::UnaryOp { operator, expression, span } => Expression::UnaryOp {
+            operator,
+            expression: Arc::new(folder.fold_expression(take(expression))),
+            span,
+        },
+        Expression::List(items, span) => {
+            Expression::List(items.into_iter().map(|item| Arc::new(folder.fold_expression(take(item)))).collect(), span)
+        },
+        Expression::Index { list, index, span } => Expression::Index {
+            list: Arc::new(folder.fold_expression(take(list))),
+            index: Arc::new(folder.fold_expression(take(index))),
+            span,
+        },
+        Expression::Length(list, span) => Expression::Length(Arc::new(folder.fold_expression(take(list))), span),
+        Expression::Call { function, arguments, span } => Expression::Call {
+            function,
+            arguments: arguments.into_iter().map(|arg| Arc::new(folder.fold_expression(take(arg)))).collect(),
+            span,
+        },
+        exp @ Expression::Integer(..) | exp @ Expression::BigInteger(..) | exp @ Expression::Float(..) |
+        exp @ Expression::Ident(..) | exp @ Expression::Str(..) | exp @ Expression::Boolean(..) => exp,
+    }
+}
+
+/// Returns Haumea source code for `program`
+///
+/// Parsing the source gives back the same Program, except that an `if` with
+/// an `else` whose `then` part is an `if` without one is wrapped in a `do`
+/// block, so that the `else` stays with the outer `if`.
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::ast::to_source;
+/// let program = parse(Scanner::new("to main do display((1+2)*3) end")).unwrap();
+/// assert_eq!(to_source(&program), "to main do\n    display((1 + 2) * 3)\nend\n");
+/// ```
+pub fn to_source(program: &[Function]) -> String {
+    let functions: Vec<String> = program.iter().map(function_to_source).collect();
+    functions.join("\n")
+}
+
+/// The indentation of one level of nested code
+pub const INDENT: &str = "    ";
+
+/// Returns Haumea source code for `func`
+fn function_to_source(func: &Function) -> String {
+    let mut out = String::new();
+    if func.visibility == Visibility::Public {
+        out.push_str("public ");
+    }
+    if func.external.is_some() {
+        out.push_str("external ");
+    }
+    out.push_str("to ");
+    out.push_str(&func.name);
+    if let Some(ref sig) = func.signature {
+        let params: Vec<String> = sig.iter().enumerate().map(|(index, param)| match func.types.parameter(index) {
+            Some(declared) => format!("{}: {}", param, declared),
+            None => param.to_string(),
+        }).collect();
+        out.push_str(&format!(" with ({})", params.join(", ")));
+    }
+    if let Some(returns) = func.types.returns {
+        out.push_str(&format!(" returns {}", returns));
+    }
+    match func.external {
+        Some(ref external) => {
+            if let Some(ref header) = external.header {
+                out.push_str(&format!(" from \"{}\"", header));
+            }
+        },
+        None => {
+            out.push(' ');
+            out.push_str(&statement_to_source(&func.code, 0));
+        },
+    }
+    out.push('\n');
+    out
+}
+
+/// Returns Haumea source code for `statement`, whose first line is already indented to `indent`
+fn statement_to_source(statement: &Statement, indent: usize) -> String {
+    match *statement {
+        Statement::Return(ref exp, _) => format!("return {}", expression_to_source(exp)),
+        Statement::Set(ref name, ref exp, _) => format!("set {} to {}", name, expression_to_source(exp)),
+        Statement::Change(ref name, ref exp, _) => format!("change {} by {}", name, expression_to_source(exp)),
+        Statement::Var(ref name, _) => format!("variable {}", name),
+        Statement::If { ref cond, ref if_clause, ref else_clause, .. } => {
+            let mut out = format!("if {} then ", expression_to_source(cond));
+            match **else_clause {
+                Some(ref else_clause) => {
+                    let if_clause = match **if_clause {
+                        Statement::If { else_clause: ref inner_else, .. } if inner_else.is_none() => {
+                            statement_to_source(&Statement::Do(vec![if_clause.clone()], Span::default()), indent)
+                        },
+                        ref if_clause => statement_to_source(if_clause, indent),
+                    };
+                    out.push_str(&if_clause);
+                    out.push_str(" else ");
+                    out.push_str(&statement_to_source(else_clause, indent));
+                },
+                None => out.push_str(&statement_to_source(if_clause, indent)),
+            }
+            out
+        },
+        Statement::Do(ref block, _) => {
+            let mut out = "do\n".to_string();
+            for sub in block {
+                out.push_str(&INDENT.repeat(indent + 1));
+                out.push_str(&statement_to_source(sub, indent + 1));
+                out.push('\n');
+            }
+            out.push_str(&INDENT.repeat(indent));
+            out.push_str("end");
+            out
+        },
+        Statement::Call { ref function, ref arguments, .. } => {
+            let arguments: Vec<String> = arguments.iter().map(expression_to_source).collect();
+            format!("{}({})", function, arguments.join(", "))
+        },
+        Statement::Forever(ref body, label, _) => {
+            format!("forever {}{}", label_to_source(label), statement_to_source(body, indent))
+        },
+        Statement::While { ref cond, ref body, label, .. } => {
+            format!("while {} {}{}", expression_to_source(cond), label_to_source(label), statement_to_source(body, indent))
+        },
+        Statement::ForEach { ref ident, ref start, ref end, ref by, ref range_type, ref body, label, .. } => {
+            let mut out = format!("for each {} in {} {} {}", ident, expression_to_source(start),
+                                  range_type, expression_to_source(end));
+            if *by != Expression::Integer(1, Span::default()) {
+                out.push_str(&format!(" by {}", expression_to_source(by)));
+            }
+            out.push(' ');
+            out.push_str(&label_to_source(label));
+            out.push_str(&statement_to_source(body, indent));
+            out
+        },
+        Statement::Break(Some(label), _) => format!("break out of {}", label),
+        Statement::Break(None, _) => "break".to_string(),
+        Statement::Continue(_) => "continue".to_string(),
+    }
+}
+
+/// Returns `as label ` for a loop with a label, to go before its body
+fn label_to_source(label: Option<Ident>) -> String {
+    label.map(|label| format!("as {} ", label)).unwrap_or_default()
+}
+
+/// Returns a Haumea string literal for `text`, escaping what needs to be
+fn string_to_source(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Returns Haumea source code for `expression`
+pub fn expression_to_source(expression: &Expression) -> String {
+    match *expression {
+        Expression::Integer(n, _) => n.to_string(),
+        Expression::BigInteger(ref digits, _) => digits.clone(),
+        Expression::Float(n, _) => n.to_string(),
+        Expression::Ident(ref name, _) => name.to_string(),
+        Expression::Str(ref text, _) => string_to_source(text),
+        Expression::Boolean(value, _) => value.to_string(),
+        Expression::Call { ref function, ref arguments, .. } => {
+            let arguments: Vec<String> = arguments.iter().map(|arg| expression_to_source(arg)).collect();
+            format!("{}({})", function, arguments.join(", "))
+        },
+        Expression::List(ref items, _) => {
+            let items: Vec<String> = items.iter().map(|item| expression_to_source(item)).collect();
+            format!("[{}]", items.join(", "))
+        },
+        Expression::Index { ref list, ref index, .. } => match **list {
+            Expression::BinaryOp { .. } | Expression::UnaryOp { .. } | Expression::Length(..) => {
+                format!("({})[{}]", expression_to_source(list), expression_to_source(index))
+            },
+            _ => format!("{}[{}]", expression_to_source(list), expression_to_source(index)),
+        },
+        Expression::Length(ref list, _) => match **list {
+            Expression::BinaryOp { .. } | Expression::UnaryOp { .. } => format!("length of ({})", expression_to_source(list)),
+            _ => format!("length of {}", expression_to_source(list)),
+        },
+        Expression::UnaryOp { ref operator, ref expression, .. } => {
+            let operand = match **expression {
+                Expression::Integer(n, _) if n >= 0 => n.to_string(),
+                Expression::BigInteger(ref digits, _) => digits.clone(),
+                Expression::Float(n, _) if n.0 >= 0.0 => n.to_string(),
+                Expression::Ident(..) | Expression::Str(..) | Expression::Boolean(..) | Expression::Call { .. } |
+                Expression::List(..) | Expression::Index { .. } | Expression::Length(..) => expression_to_source(expression),
+                _ => format!("({})", expression_to_source(expression)),
+            };
+            match *operator {
+                Operator::LogicalNot => format!("not {}", operand),
+                ref operator => format!("{}{}", operator, operand),
+            }
+        },
+        Expression::BinaryOp { ref operator, ref left, ref right, .. } => {
+            // Binary operators group to the left, so only an operand on the right
+            // needs parentheses to bind as tightly as the operator. A unary operand
+            // on the right gets them too, as `*` and `-` together are read as one operator
+            let level = operator.precedence();
+            let left = match **left {
+                Expression::BinaryOp { operator: ref inner, .. } if inner.precedence() >= level => expression_to_source(left),
+                Expression::BinaryOp { .. } => format!("({})", expression_to_source(left)),
+                _ => expression_to_source(left),
+            };
+            let right = match **right {
+                Expression::BinaryOp { operator: ref inner, .. } if inner.precedence() > level => expression_to_source(right),
+                Expression::BinaryOp { .. } | Expression::UnaryOp { .. } => format!("({})", expression_to_source(right)),
+                _ => expression_to_source(right),
+            };
+            format!("{} {} {}", left, operator, right)
+        },
+    }
+}
+
+impl Function {
+    /// Constructs a new private Function called `name`, without parameters, that returns 0
+    ///
+    /// # Examples
+    /// ```
+    /// #[macro_use] extern crate haumea;
+    /// use haumea::ast::{expr, stmt, Function};
+    /// # fn main() {
+    /// let square = Function::new("square")
+    ///     .public()
+    ///     .params(&["n"])
+    ///     .body(block![stmt::ret(expr::mul(expr::ident("n"), expr::ident("n")))]);
+    /// assert_eq!(haumea::ast::to_source(&[square]),
+    ///            "public to square with (n) do\n    return n * n\nend\n");
+    /// # }
+    /// ```
+    pub fn new(name: &str) -> Function {
+        Function {
+            name: Symbol::from(name),
+            visibility: Visibility::Private,
+            external: None,
+            signature: None,
+            types: Types::default(),
+            code: Statement::Do(vec![], Span::default()),
+            span: Span::default(),
+        }
+    }
+
+    /// Makes the function public
+    pub fn public(self) -> Function {
+        Function { visibility: Visibility::Public, ..self }
+    }
+
+    /// Sets the names of the function's parameters
+    pub fn params(self, params: &[&str]) -> Function {
+        Function {
+            signature: Some(params.iter().map(|param| Symbol::from(*param)).collect()),
+            ..self
+        }
+    }
+
+    /// Sets the names and types of the function's parameters
+    pub fn typed_params(self, params: &[(&str, Type)]) -> Function {
+        let types = Types { parameters: params.iter().map(|&(_, declared)| Some(declared)).collect(), ..self.types };
+        Function {
+            signature: Some(params.iter().map(|&(param, _)| Symbol::from(param)).collect()),
+            types,
+            ..self
+        }
+    }
+
+    /// Sets the type the function returns
+    pub fn returns(self, returns: Type) -> Function {
+        let types = Types { returns: Some(returns), ..self.types };
+        Function { types, ..self }
+    }
+
+    /// Sets the function's code
+    pub fn body(self, code: Statement) -> Function {
+        Function { code, ..self }
+    }
+}
+
+/// Makes a `Statement::Do` block from a list of statements
+///
+/// block![stmt::call("display", vec![expr::int(1)]), stmt::ret(expr::int(0))]
+#[macro_export]
+macro_rules! block {
+    ( $( $statement:expr ),* $(,)* ) => (
+        $crate::parser::Statement::Do(vec![ $( ::std::sync::Arc::new($statement) ),* ], $crate::parser::Span::default())
+    );
+}
+
+/// Constructors for statements
+pub mod stmt {
+    use std::sync::Arc;
+    use parser::{Expression, Span, Statement};
+    use symbol::Symbol;
+
+    /// return value
+    pub fn ret(value: Expression) -> Statement {
+        Statement::Return(value, Span::default())
+    }
+
+    /// variable name
+    pub fn var(name: &str) -> Statement {
+        Statement::Var(Symbol::from(name), Span::default())
+    }
+
+    /// set name to value
+    pub fn set(name: &str, value: Expression) -> Statement {
+        Statement::Set(Symbol::from(name), value, Span::default())
+    }
+
+    /// change name by amount
+    pub fn change(name: &str, amount: Expression) -> Statement {
+        Statement::Change(Symbol::from(name), amount, Span::default())
+    }
+
+    /// if cond then if_clause
+    pub fn if_then(cond: Expression, if_clause: Statement) -> Statement {
+        Statement::If {
+            cond,
+            if_clause: Arc::new(if_clause),
+            else_clause: Arc::new(None),
+            span: Span::default(),
+        }
+    }
+
+    /// if cond then if_clause else else_clause
+    pub fn if_else(cond: Expression, if_clause: Statement, else_clause: Statement) -> Statement {
+        Statement::If {
+            cond,
+            if_clause: Arc::new(if_clause),
+            else_clause: Arc::new(Some(else_clause)),
+            span: Span::default(),
+        }
+    }
+
+    /// function(arguments...), as a statement
+    pub fn call<I: IntoIterator<Item = Expression>>(function: &str, arguments: I) -> Statement {
+        Statement::Call {
+            function: Symbol::from(function),
+            arguments: arguments.into_iter().collect(),
+            span: Span::default(),
+        }
+    }
+
+    /// forever body
+    pub fn forever(body: Statement) -> Statement {
+        Statement::Forever(Arc::new(body), None, Span::default())
+    }
+
+    /// while cond body
+    pub fn while_loop(cond: Expression, body: Statement) -> Statement {
+        Statement::While {
+            cond,
+            body: Arc::new(body),
+            label: None,
+            span: Span::default(),
+        }
+    }
+
+    /// `statement`, which is a loop, given the label `label`
+    ///
+    /// # Panics
+    /// Panics if `statement` isn't a loop.
+    pub fn labeled(label: &str, mut statement: Statement) -> Statement {
+        match statement {
+            Statement::Forever(_, ref mut old, _) | Statement::While { label: ref mut old, .. } |
+            Statement::ForEach { label: ref mut old, .. } => *old = Some(Symbol::from(label)),
+            _ => panic!("Only a loop can be labeled"),
+        }
+        statement
+    }
+
+    /// break
+    pub fn break_loop() -> Statement {
+        Statement::Break(None, Span::default())
+    }
+
+    /// break out of label
+    pub fn break_out_of(label: &str) -> Statement {
+        Statement::Break(Some(Symbol::from(label)), Span::default())
+    }
+
+    /// continue
+    pub fn continue_loop() -> Statement {
+        Statement::Continue(Span::default())
+    }
+
+    /// for each ident in start to end by step body
+    ///
+    /// Use `through` instead of `to` by passing `true` for `inclusive`.
+    pub fn for_each(ident: &str, start: Expression, end: Expression, step: Expression,
+                    inclusive: bool, body: Statement) -> Statement {
+        Statement::ForEach {
+            ident: Symbol::from(ident),
+            start,
+            end,
+            by: step,
+            range_type: if inclusive { "through" } else { "to" }.to_string(),
+            body: Arc::new(body),
+            label: None,
+            span: Span::default(),
+        }
+    }
+}
+
+/// Constructors for expressions
+pub mod expr {
+    use std::sync::Arc;
+    use parser::{Expression, Operator, Span};
+    use symbol::Symbol;
+
+    /// An integer literal
+    pub fn int(value: i64) -> Expression {
+        Expression::Integer(value, Span::default())
+    }
+
+    /// A string literal
+    pub fn text(text: &str) -> Expression {
+        Expression::Str(text.to_string(), Span::default())
+    }
+
+    /// `true` or `false`
+    pub fn boolean(value: bool) -> Expression {
+        Expression::Boolean(value, Span::default())
+    }
+
+    /// [items...]
+    pub fn list<I: IntoIterator<Item = Expression>>(items: I) -> Expression {
+        Expression::List(items.into_iter().map(Arc::new).collect(), Span::default())
+    }
+
+    /// list[index]
+    pub fn index(list: Expression, index: Expression) -> Expression {
+        Expression::Index {
+            list: Arc::new(list),
+            index: Arc::new(index),
+            span: Span::default(),
+        }
+    }
+
+    /// length of list
+    pub fn length(list: Expression) -> Expression {
+        Expression::Length(Arc::new(list), Span::default())
+    }
+
+    /// A variable or parameter
+    pub fn ident(name: &str) -> Expression {
+        Expression::Ident(Symbol::from(name), Span::default())
+    }
+
+    /// function(arguments...)
+    pub fn call<I: IntoIterator<Item = Expression>>(function: &str, arguments: I) -> Expression {
+        Expression::Call {
+            function: Symbol::from(function),
+            arguments: arguments.into_iter().map(Arc::new).collect(),
+            span: Span::default(),
+        }
+    }
+
+    /// A binary operation
+    pub fn binary(operator: Operator, left: Expression, right: Expression) -> Expression {
+        Expression::BinaryOp {
+            operator,
+            left: Arc::new(left),
+            right: Arc::new(right),
+            span: Span::default(),
+        }
+    }
+
+    /// A unary operation
+    pub fn unary(operator: Operator, expression: Expression) -> Expression {
+        Expression::UnaryOp {
+            operator,
+            expression: Arc::new(expression),
+            span: Span::default(),
+        }
+    }
+
+    /// left + right
+    pub fn add(left: Expression, right: Expression) -> Expression {
+        binary(Operator::Add, left, right)
+    }
+
+    /// left - right
+    pub fn sub(left: Expression, right: Expression) -> Expression {
+        binary(Operator::Sub, left, right)
+    }
+
+    /// left * right
+    pub fn mul(left: Expression, right: Expression) -> Expression {
+        binary(Operator::Mul, left, right)
+    }
+
+    /// left / right
+    pub fn div(left: Expression, right: Expression) -> Expression {
+        binary(Operator::Div, left, right)
+    }
+
+    /// -expression, the way the parser writes it
+    pub fn neg(expression: Expression) -> Expression {
+        unary(Operator::Negate, expression)
+    }
+}
@@ -0,0 +1,293 @@
+//! src/build.rs
+//! Builds compiled C code into libraries, using the system's `cc` and `ar`.
+//!
+//! The C backend's output already contains the runtime the program needs, so a
+//! library is just that output compiled by itself. Only `public` functions are
+//! exported from it, named like `hm_fn_square`; private functions are compiled
+//! as `static` C functions.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use parser::{Function, Program, Type, Visibility};
+use typecheck::Types;
+use flow;
+use codegen::c;
+use driver;
+
+/// Words that can't be used as names in Rust, but can be in Haumea
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "alignof", "become", "box", "break", "const", "continue", "crate",
+    "dyn", "else", "enum", "extern", "false", "final", "fn", "impl", "loop", "macro",
+    "match", "mod", "move", "mut", "offsetof", "override", "priv", "proc", "pub",
+    "pure", "ref", "self", "sizeof", "static", "struct", "super", "trait", "true",
+    "type", "typeof", "unsafe", "unsized", "virtual", "where", "yield",
+];
+
+/// The kind of library to build
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LibraryKind {
+    /// A static library (`libname.a`)
+    Static,
+    /// A shared library (`libname.so`)
+    Shared,
+}
+
+impl LibraryKind {
+    /// Returns the LibraryKind called `name`, using the names of rustc's crate types
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::build::LibraryKind;
+    /// assert_eq!(LibraryKind::from_name("staticlib"), Some(LibraryKind::Static));
+    /// assert_eq!(LibraryKind::from_name("cdylib"), Some(LibraryKind::Shared));
+    /// assert_eq!(LibraryKind::from_name("bin"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<LibraryKind> {
+        match name {
+            "staticlib" => Some(LibraryKind::Static),
+            "cdylib" => Some(LibraryKind::Shared),
+            _ => None,
+        }
+    }
+
+    /// Returns the file name of the library called `name`
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::build::LibraryKind;
+    /// assert_eq!(LibraryKind::Static.file_name("foo"), "libfoo.a");
+    /// assert_eq!(LibraryKind::Shared.file_name("foo"), "libfoo.so");
+    /// ```
+    pub fn file_name(&self, name: &str) -> String {
+        match *self {
+            LibraryKind::Static => format!("lib{}.a", name),
+            LibraryKind::Shared => format!("lib{}.so", name),
+        }
+    }
+}
+
+/// An error that stopped a library from being built
+#[derive(Debug)]
+pub enum BuildError {
+    /// A file could not be written, or a tool could not be run
+    Io(io::Error),
+    /// A tool ran, but failed
+    Failed {
+        command: String,
+        status: Option<i32>,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::Io(ref error) => write!(f, "{}", error),
+            BuildError::Failed { ref command, status: Some(status) } => {
+                write!(f, "`{}` failed with exit status {}", command, status)
+            },
+            BuildError::Failed { ref command, status: None } => {
+                write!(f, "`{}` was stopped by a signal", command)
+            },
+        }
+    }
+}
+
+impl From<io::Error> for BuildError {
+    fn from(error: io::Error) -> BuildError {
+        BuildError::Io(error)
+    }
+}
+
+/// Builds the compiled C code `source` into the library called `name` in `out_dir`
+///
+/// `flags` are passed to the C compiler when linking a shared library, for
+/// example the flags returned by `codegen::c::linker_flags`.
+///
+/// Returns the path of the built library.
+pub fn build_library(source: &str, name: &str, kind: LibraryKind,
+                     out_dir: &Path, flags: &[String]) -> Result<PathBuf, BuildError> {
+    fs::create_dir_all(out_dir)?;
+    let library = out_dir.join(kind.file_name(name));
+    in_work_dir(source, name, |work_dir, c_file| {
+        match kind {
+            LibraryKind::Static => {
+                let object = work_dir.join(format!("{}.o", name));
+                run(Command::new("cc").arg("-c").arg("-o").arg(&object).arg(c_file))?;
+                let _ = fs::remove_file(&library);
+                run(Command::new("ar").arg("rcs").arg(&library).arg(&object))
+            },
+            LibraryKind::Shared => {
+                run(Command::new("cc").args(["-shared", "-fPIC", "-o"]).arg(&library).arg(c_file).args(flags))
+            },
+        }
+    })?;
+    Ok(library)
+}
+
+/// Builds the compiled C code `source` into the program called `name` in `out_dir`
+///
+/// `flags` are passed to the C compiler, like they are for `build_library`.
+///
+/// Returns the path of the built program.
+pub fn build_executable(source: &str, name: &str, out_dir: &Path, flags: &[String]) -> Result<PathBuf, BuildError> {
+    fs::create_dir_all(out_dir)?;
+    let program = out_dir.join(name);
+    in_work_dir(source, name, |_, c_file| run(Command::new("cc").arg("-o").arg(&program).arg(c_file).args(flags)))?;
+    Ok(program)
+}
+
+/// Writes `source` to `name.c` in a new private directory, and calls `build`
+/// with the directory and the C file, removing the directory afterwards
+///
+/// Each build has a directory of its own, so two builds of libraries with
+/// the same name don't write over each other's files.
+fn in_work_dir<F>(source: &str, name: &str, build: F) -> Result<(), BuildError>
+    where F: FnOnce(&Path, &Path) -> Result<(), BuildError> {
+    let work_dir = driver::private_dir(&format!("haumea_build_{}", name))?;
+    let c_file = work_dir.join(format!("{}.c", name));
+    let built = fs::write(&c_file, source).map_err(BuildError::from).and_then(|()| build(&work_dir, &c_file));
+    let _ = fs::remove_dir_all(&work_dir);
+    built
+}
+
+/// Runs `command`, returning an error if it fails
+fn run(command: &mut Command) -> Result<(), BuildError> {
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(BuildError::Failed {
+            command: format!("{:?}", command),
+            status: status.code(),
+        })
+    }
+}
+
+/// Returns a Rust module that declares the public functions of `program`,
+/// linked from the library called `name`, with safe wrappers around them
+///
+/// Integers are `c_longlong`s and Reals are `f64`s. Text that a function
+/// returns is copied into a `String`. Rust has no way to make Haumea text or
+/// lists, so a function that takes them, or returns a list, has no binding.
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::build::{rust_bindings, LibraryKind};
+/// let program = parse(Scanner::new("public to square with (n) return n * n")).unwrap();
+/// let bindings = rust_bindings(&program, "square", LibraryKind::Static);
+/// assert!(bindings.contains("pub fn square(n: c_longlong) -> c_longlong"));
+/// ```
+pub fn rust_bindings(program: &Program, name: &str, kind: LibraryKind) -> String {
+    let types = Types::new(program);
+    let mut functions = vec![];
+    let mut skipped = vec![];
+    for func in program.iter().filter(|func| func.visibility == Visibility::Public && func.external.is_none()) {
+        if func.name == "main" {
+            continue;
+        }
+        match Binding::new(func, &types) {
+            Some(binding) => functions.push(binding),
+            None => skipped.push(func.name),
+        }
+    }
+    let mut out = format!("//! Rust bindings for the Haumea library `{}`\n", name);
+    out.push_str("//! Generated by the Haumea compiler; do not edit.\n");
+    for name in &skipped {
+        out.push_str(&format!("//!\n//! `{}` takes Text or a List, or returns a List, which Rust can't make, so it has no binding.\n", name));
+    }
+    out.push('\n');
+    // A library of functions on Reals doesn't use c_longlong
+    let text = functions.iter().any(|binding| binding.text);
+    if text {
+        out.push_str("use std::ffi::CStr;\n#[allow(unused_imports)]\nuse std::os::raw::{c_char, c_longlong};\n\n");
+    } else {
+        out.push_str("#[allow(unused_imports)]\nuse std::os::raw::c_longlong;\n\n");
+    }
+    out.push_str("mod ffi {\n    #[allow(unused_imports)]\n    use std::os::raw::c_longlong;\n\n");
+    out.push_str(&match kind {
+        LibraryKind::Static => format!("    #[link(name = \"{}\", kind = \"static\")]\n", name),
+        LibraryKind::Shared => format!("    #[link(name = \"{}\")]\n", name),
+    });
+    out.push_str("    extern \"C\" {\n");
+    for binding in &functions {
+        out.push_str(&format!("        #[link_name = \"{}\"]\n", c::function_name(&binding.func.name)));
+        let returns = if binding.text { " -> c_longlong" } else { binding.returns };
+        out.push_str(&format!("        pub fn {}({}){};\n", binding.rust_name, binding.params.join(", "), returns));
+    }
+    out.push_str("    }\n}\n");
+    if text {
+        out.push_str("
+/// Copies the Haumea text at `address` into a String, which is empty for
+/// a variable that was never set
+unsafe fn text(address: c_longlong) -> String {
+    if address == 0 {
+        return String::new();
+    }
+    CStr::from_ptr(address as isize as *const c_char).to_string_lossy().into_owned()
+}
+");
+    }
+    for binding in &functions {
+        let arguments: Vec<String> = binding.func.signature.iter().flat_map(|sig| sig.iter().map(|param| rust_ident(param))).collect();
+        let call = format!("ffi::{}({})", binding.rust_name, arguments.join(", "));
+        let call = if binding.text { format!("text({})", call) } else { call };
+        out.push_str(&format!("\n/// Calls the Haumea function `{}`\n", binding.func.name));
+        out.push_str(&format!("pub fn {}({}){} {{\n", binding.rust_name, binding.params.join(", "), binding.returns));
+        out.push_str(&format!("    unsafe {{ {} }}\n}}\n", call));
+    }
+    out
+}
+
+/// A public function that Rust can call
+struct Binding<'a> {
+    func: &'a Function,
+    rust_name: String,
+    /// The Rust parameters of the function
+    params: Vec<String>,
+    /// What the safe wrapper returns, which is nothing for a procedure
+    returns: &'static str,
+    /// Whether the function returns Text, which the wrapper copies into a String
+    text: bool,
+}
+
+impl<'a> Binding<'a> {
+    /// Returns the binding for `func`, or None if Rust can't call it
+    fn new(func: &'a Function, types: &Types) -> Option<Binding<'a>> {
+        let params = func.signature.iter().flatten().enumerate()
+            .map(|(index, param)| Some(format!("{}: {}", rust_ident(param), rust_type(types.parameter(func.name, index))?)))
+            .collect::<Option<Vec<_>>>()?;
+        let (returns, text) = match types.returns(func.name) {
+            _ if flow::is_procedure(func) => ("", false),
+            Some(Type::Real) => (" -> f64", false),
+            Some(Type::Text) => (" -> String", true),
+            Some(Type::List) => return None,
+            _ => (" -> c_longlong", false),
+        };
+        Some(Binding { func, rust_name: rust_ident(&func.name), params, returns, text })
+    }
+}
+
+/// Returns the Rust type that a Haumea value of the type `found` is passed
+/// as, or None if Rust can't make it
+///
+/// A value whose type isn't known is an Integer in C.
+fn rust_type(found: Option<Type>) -> Option<&'static str> {
+    match found {
+        Some(Type::Real) => Some("f64"),
+        Some(Type::Integer) | None => Some("c_longlong"),
+        Some(Type::Text) | Some(Type::List) | Some(Type::Nothing) => None,
+    }
+}
+
+/// Returns `name`, changed if necessary so that it is a valid Rust identifier
+fn rust_ident(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,275 @@
+//! src/builtins.rs
+//! The registry of functions built in to Haumea.
+//!
+//! Builtins are provided by each backend's runtime rather than being written in
+//! Haumea. A program may define a function with the same name as a builtin, in
+//! which case the program's definition shadows the builtin everywhere and the
+//! backend leaves the builtin out of its runtime.
+use parser::{Program, Type};
+
+/// A function built in to Haumea
+#[derive(Debug, Clone, PartialEq)]
+pub struct Builtin {
+    /// The name the function is called by
+    pub name: &'static str,
+    /// The number of arguments the function takes, or the least it takes if it is variadic
+    pub arity: usize,
+    /// The function takes any number of arguments from `arity` up, like `display(a, b, c)`
+    pub variadic: bool,
+    /// The type of each argument, or None where any type can be passed
+    pub parameters: &'static [Option<Type>],
+    /// Text can be passed where an Integer is expected, like `display("n is", n)`
+    pub takes_text: bool,
+    /// The function is passed the name of the function calling it, the call's
+    /// code and its line after its arguments, so it can say where it went wrong
+    pub located: bool,
+    /// The type the function returns, if it is always the same
+    pub returns: Option<Type>,
+    /// A short description of what the function does
+    pub description: &'static str,
+}
+
+/// Every builtin function, in the order backends emit them
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "display",
+        arity: 1,
+        variadic: true,
+        parameters: &[Some(Type::Integer)],
+        takes_text: true,
+        located: false,
+        returns: None,
+        description: "Displays numbers and text separated by spaces, followed by a newline",
+    },
+    Builtin {
+        name: "display_no_newline",
+        arity: 1,
+        variadic: true,
+        parameters: &[Some(Type::Integer)],
+        takes_text: true,
+        located: false,
+        returns: None,
+        description: "Displays numbers and text separated by spaces, without a newline after them",
+    },
+    Builtin {
+        name: "display_text",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::Text)],
+        takes_text: false,
+        located: false,
+        returns: None,
+        description: "Displays text followed by a newline",
+    },
+    Builtin {
+        name: "display_real",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::Real)],
+        takes_text: false,
+        located: false,
+        returns: None,
+        description: "Displays a decimal number followed by a newline",
+    },
+    Builtin {
+        name: "read",
+        arity: 0,
+        variadic: false,
+        parameters: &[],
+        takes_text: false,
+        located: false,
+        returns: Some(Type::Integer),
+        description: "Asks the user for a number and returns it",
+    },
+    Builtin {
+        name: "push",
+        arity: 2,
+        variadic: false,
+        parameters: &[Some(Type::List), None],
+        takes_text: false,
+        located: false,
+        returns: None,
+        description: "Adds a value to the end of a list",
+    },
+    Builtin {
+        name: "append",
+        arity: 2,
+        variadic: false,
+        parameters: &[Some(Type::List), None],
+        takes_text: false,
+        located: false,
+        returns: None,
+        description: "Adds a value to the end of a list, like push",
+    },
+    Builtin {
+        name: "length_of",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::List)],
+        takes_text: false,
+        located: false,
+        returns: Some(Type::Integer),
+        description: "Returns the number of items in a list",
+    },
+    Builtin {
+        name: "item_at",
+        arity: 2,
+        variadic: false,
+        parameters: &[Some(Type::List), Some(Type::Integer)],
+        takes_text: false,
+        located: true,
+        returns: None,
+        description: "Returns the item of a list at an index, counting from 0",
+    },
+    Builtin {
+        name: "set_item",
+        arity: 3,
+        variadic: false,
+        parameters: &[Some(Type::List), Some(Type::Integer), None],
+        takes_text: false,
+        located: true,
+        returns: None,
+        description: "Replaces the item of a list at an index, counting from 0",
+    },
+    Builtin {
+        name: "remove_last",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::List)],
+        takes_text: false,
+        located: true,
+        returns: None,
+        description: "Removes the last item of a list and returns it",
+    },
+    Builtin {
+        name: "join",
+        arity: 2,
+        variadic: true,
+        parameters: &[Some(Type::Text)],
+        takes_text: false,
+        located: false,
+        returns: Some(Type::Text),
+        description: "Returns the text it is given, one after another",
+    },
+    Builtin {
+        name: "character_code",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::Text)],
+        takes_text: false,
+        located: true,
+        returns: Some(Type::Integer),
+        description: "Returns the Unicode code of text that is one character",
+    },
+    Builtin {
+        name: "character_from_code",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::Integer)],
+        takes_text: false,
+        located: true,
+        returns: Some(Type::Text),
+        description: "Returns the character with a Unicode code",
+    },
+    Builtin {
+        name: "number_from_text",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::Text)],
+        takes_text: false,
+        located: true,
+        returns: Some(Type::Integer),
+        description: "Returns the whole number written in text, like \"-12\"",
+    },
+    Builtin {
+        name: "text_from_number",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::Integer)],
+        takes_text: false,
+        located: false,
+        returns: Some(Type::Text),
+        description: "Returns a number written as text",
+    },
+    Builtin {
+        name: "run_command",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::Text)],
+        takes_text: false,
+        located: true,
+        returns: Some(Type::Integer),
+        description: "Runs a command with the system's shell and returns its exit status",
+    },
+    Builtin {
+        name: "random_between",
+        arity: 2,
+        variadic: false,
+        parameters: &[Some(Type::Integer), Some(Type::Integer)],
+        takes_text: false,
+        located: false,
+        returns: Some(Type::Integer),
+        description: "Returns a random number from the first number through the second",
+    },
+    Builtin {
+        name: "seed_random",
+        arity: 1,
+        variadic: false,
+        parameters: &[Some(Type::Integer)],
+        takes_text: false,
+        located: false,
+        returns: None,
+        description: "Starts the random numbers again from a seed, so they are the same every time",
+    },
+];
+
+impl Builtin {
+    /// Returns true if the function can be called with `count` arguments
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::builtins;
+    /// let display = builtins::lookup("display").unwrap();
+    /// assert!(display.accepts(3));
+    /// assert!(!display.accepts(0));
+    /// ```
+    pub fn accepts(&self, count: usize) -> bool {
+        count == self.arity || (self.variadic && count > self.arity)
+    }
+
+    /// Returns the type argument `index` must be, if it must be one
+    ///
+    /// The arguments after the parameters of a variadic function have the type of its last one.
+    pub fn parameter(&self, index: usize) -> Option<Type> {
+        match self.parameters.get(index) {
+            Some(&parameter) => parameter,
+            None if self.variadic => self.parameters.last().cloned().flatten(),
+            None => None,
+        }
+    }
+}
+
+/// Returns the builtin called `name`, if there is one
+///
+/// # Examples
+/// ```
+/// # use haumea::builtins;
+/// assert_eq!(builtins::lookup("display").unwrap().arity, 1);
+/// assert!(builtins::lookup("factorial").is_none());
+/// ```
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Returns true if `program` defines a function that shadows `builtin`
+pub fn is_shadowed(builtin: &Builtin, program: &Program) -> bool {
+    program.iter().any(|func| func.name == builtin.name)
+}
+
+/// Returns the builtins that `program` can call, which are the ones it does not shadow
+///
+/// Backends define all of these, other than the C backend, which only
+/// defines the ones the program calls, so that they can't clash with headers.
+pub fn available_to(program: &Program) -> Vec<&'static Builtin> {
+    BUILTINS.iter().filter(|builtin| !is_shadowed(builtin, program)).collect()
+}
@@ -0,0 +1,228 @@
+//! src/check.rs
+//! Checks a loaded program for mistakes that the parser can't catch.
+//!
+//! Unlike `validate`, which finds bugs in the compiler, these are mistakes in
+//! the Haumea program. Errors stop the program from being compiled, while
+//! warnings point out code that compiles but probably doesn't do what was meant.
+use std::collections::HashMap;
+use std::fmt;
+use parser::{Expression, Function, Ident, Operator, Span, Statement};
+use ast;
+use ast::{walk_program, Visitor};
+use constants;
+use flow;
+use diagnostics::Diagnostic;
+pub use diagnostics::Severity;
+
+/// A mistake found in a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    /// The kind of problem, like "W0301"
+    pub code: &'static str,
+    /// The function the problem is in
+    pub function: String,
+    /// The code the problem is in
+    pub span: Span,
+    /// What is wrong
+    pub message: String,
+}
+
+impl Problem {
+    /// Returns the diagnostic that reports the problem
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            severity: self.severity,
+            code: self.code,
+            message: self.message.clone(),
+            span: self.span,
+            notes: vec![format!("in the function `{}`", self.function)],
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        if self.span.is_empty() {
+            write!(f, "{}: in `{}`, {}", severity, self.function, self.message)
+        } else {
+            write!(f, "{}: at line {}, in `{}`, {}", severity, self.span, self.function, self.message)
+        }
+    }
+}
+
+/// Checks every function in `program`, returning every problem found
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::check::{check, Severity};
+/// let program = parse(Scanner::new("to main for each i in 1 to 10 by 0 display(i)")).unwrap();
+/// let problems = check(&program);
+/// assert_eq!(problems[0].severity, Severity::Warning);
+/// ```
+pub fn check(program: &[Function]) -> Vec<Problem> {
+    let mut checker = Checker { function: String::new(), problems: vec![] };
+    walk_program(&mut checker, program);
+    checker.problems
+}
+
+/// Returns true if any of `problems` is an error
+pub fn has_errors(problems: &[Problem]) -> bool {
+    problems.iter().any(|problem| problem.severity == Severity::Error)
+}
+
+struct Checker {
+    /// The name of the function being checked
+    function: String,
+    problems: Vec<Problem>,
+}
+
+impl Checker {
+    fn problem(&mut self, severity: Severity, code: &'static str, span: Span, message: String) {
+        self.problems.push(Problem { severity, code, function: self.function.clone(), span, message });
+    }
+
+    /// Warns if the condition of a `keyword` statement is a number rather than a comparison
+    fn check_condition(&mut self, keyword: &str, cond: &Expression) {
+        if is_number(cond) {
+            let source = ast::expression_to_source(cond);
+            self.problem(Severity::Warning, "W0302", cond.span(),
+                         format!("the `{}` condition `{}` is a number, \
+                                  write `{} != 0` if that is what you meant", keyword, source, source));
+        }
+    }
+
+    /// Reports each `break` and `continue` in `statement` that isn't inside a
+    /// loop, and each `break out of` a label that no loop around it has
+    ///
+    /// `loops` has the label of each loop around `statement`, innermost last.
+    fn check_loops(&mut self, statement: &Statement, loops: &mut Vec<Option<Ident>>) {
+        match *statement {
+            Statement::Break(None, span) if loops.is_empty() => {
+                self.problem(Severity::Error, "E0303", span, "`break` is used outside of a loop".to_string());
+            },
+            Statement::Break(Some(label), span) if !loops.contains(&Some(label)) => {
+                self.problem(Severity::Error, "E0309", span,
+                             format!("there is no loop labeled `{}` around this `break`, label one with `as {}`",
+                                     label, label));
+            },
+            Statement::Continue(span) if loops.is_empty() => {
+                self.problem(Severity::Error, "E0304", span, "`continue` is used outside of a loop".to_string());
+            },
+            Statement::If { ref if_clause, ref else_clause, .. } => {
+                self.check_loops(if_clause, loops);
+                if let Some(ref else_clause) = **else_clause {
+                    self.check_loops(else_clause, loops);
+                }
+            },
+            Statement::Do(ref block, _) => {
+                for sub in block {
+                    self.check_loops(sub, loops);
+                }
+            },
+            Statement::Forever(ref body, label, _) | Statement::While { ref body, label, .. } |
+            Statement::ForEach { ref body, label, .. } => {
+                loops.push(label);
+                self.check_loops(body, loops);
+                loops.pop();
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Returns true if `expression` is certainly a number, and not used as true or false
+///
+/// Variables and calls to functions other than `read` may hold 1 or 0 as
+/// true or false, so they are never reported.
+fn is_number(expression: &Expression) -> bool {
+    match *expression {
+        Expression::Integer(..) | Expression::BigInteger(..) | Expression::Float(..) | Expression::Length(..) => true,
+        Expression::BinaryOp { ref operator, .. } | Expression::UnaryOp { ref operator, .. } => {
+            matches!(*operator, Operator::Add | Operator::Sub | Operator::Mul | Operator::Div |
+                                Operator::Modulo | Operator::Negate)
+        },
+        Expression::Call { ref function, .. } => *function == "read",
+        Expression::Ident(..) | Expression::Str(..) | Expression::Boolean(..) |
+        Expression::List(..) | Expression::Index { .. } => false,
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_function(&mut self, func: &Function) {
+        self.function = func.name.to_string();
+        self.check_loops(&func.code, &mut vec![]);
+        // Reaching the end of `main` is how a program usually succeeds
+        if func.name != "main" && func.external.is_none() && flow::has_return(&func.code) && flow::falls_through(&func.code) {
+            self.problem(Severity::Warning, "W0308", func.span,
+                         format!("`{}` returns a value on some paths, but can also reach its end, \
+                                  where it returns 0", func.name));
+        }
+        let params = match func.signature {
+            Some(ref params) => params,
+            None => return,
+        };
+        for (index, param) in params.iter().enumerate() {
+            if *param == func.name {
+                self.problem(Severity::Error, "E0305", func.span,
+                             format!("parameter {} is named `{}`, the same as its function", index + 1, param));
+            }
+            // Only report a name from its first use
+            if params[..index].contains(param) {
+                continue;
+            }
+            let uses: Vec<String> = params.iter().enumerate()
+                .filter(|&(_, other)| other == param)
+                .map(|(other, _)| (other + 1).to_string())
+                .collect();
+            if uses.len() > 1 {
+                self.problem(Severity::Error, "E0306", func.span,
+                             format!("parameters {} have the same name, `{}`", join_and(&uses), param));
+            }
+        }
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        match *statement {
+            // Constants have already been replaced by their values
+            Statement::ForEach { ref ident, ref by, .. } if constants::evaluate(by, &HashMap::new()) == Ok(0) => {
+                self.problem(Severity::Warning, "W0301", statement.span(),
+                             format!("the `for each` loop over `{}` counts by 0, so it never ends", ident));
+            },
+            Statement::If { ref cond, .. } => self.check_condition("if", cond),
+            Statement::While { ref cond, .. } => self.check_condition("while", cond),
+            Statement::Do(ref block, _) => {
+                if let Some((before, after)) = flow::unreachable(block) {
+                    self.problem(Severity::Warning, "W0307", after.span(),
+                                 format!("this statement is never run, because the one before it {}", stops(before)));
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Says why running `statement` never goes on to the statement after it
+fn stops(statement: &Statement) -> &'static str {
+    match *statement {
+        Statement::Return(..) => "always returns",
+        Statement::Break(..) => "always leaves the loop",
+        Statement::Continue(_) => "always goes on to the next time around the loop",
+        Statement::Forever(..) | Statement::While { .. } => "loops forever",
+        _ => "never reaches its end",
+    }
+}
+
+/// Joins `words` into a list like "1, 2 and 3"
+fn join_and(words: &[String]) -> String {
+    match words.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => words.join(""),
+    }
+}
@@ -0,0 +1,285 @@
+//! src/cli.rs
+//! Reads the compiler's command line.
+//!
+//! `haumea [command] [options] [file]`, where the command is `build` (the
+//! default), `check`, `tokens`, `ast`, `run` or `fmt`. Without a file, the
+//! program is read from stdin.
+use std::path::{Path, PathBuf};
+
+/// How to use the compiler, printed by `haumea --help`
+pub const USAGE: &str = "Usage: haumea [command] [options] [file]
+
+Commands:
+    build     compile the program, printing it or writing it to the -o file (the default)
+    check     load and check the program without compiling it
+    tokens    print the tokens the scanner reads from the file
+    ast       print the syntax tree of the loaded program, for other tools to read
+    run       run the program with the interpreter, or build and run it if it needs C
+    fmt       rewrite the file in the standard style, or print stdin in it
+    help      print this message
+
+Options:
+    -o, --output <file>    write the compiled program to <file>
+    --backend <name>       the language to compile to, `c` (the default), `js` or `python`
+    --opt <name>           turn on an optimization, like `tailcall`, which makes
+                           functions that return a call to themselves loop in C,
+                           or `dead-code`, which leaves out functions never called
+    --keywords=<locale>    also read keywords spelt in another language, like `es`
+    --bignum               make every number an arbitrary-precision integer
+    --runtime-checks       check for division by zero and loops that count by zero
+    --checked-arithmetic   stop with an error when arithmetic overflows in C, instead of wrapping
+    --deterministic        name temporary variables after their function
+    --no-prelude           leave the prelude out
+    --desugar              lower for each, forever and change to the core statements
+    --emit=<what>          print the loaded program as `expanded` source, or as `ast-json`,
+                           or build a `web` page that runs it in the -o folder
+    --crate-type=<kind>    build a `staticlib` or `cdylib` instead of printing C
+    --rust-bindings        also write Rust bindings for the library
+    --check                with fmt, fail if the file isn't formatted instead of rewriting it
+    --format <name>        with ast, print the tree as `json` (the default) or as `source`
+    --via <name>           with run, always build the program with `c` and run it,
+                           using $CC as the C compiler if it is set";
+
+/// The options that are switched on by giving their name
+const FLAGS: &[&str] = &[
+    "--bignum",
+    "--runtime-checks",
+    "--checked-arithmetic",
+    "--deterministic",
+    "--no-prelude",
+    "--desugar",
+    "--rust-bindings",
+    "--check",
+];
+
+/// The options that are given a value after a `=`
+const VALUED: &[&str] = &["--keywords", "--emit", "--crate-type"];
+
+/// The formats `ast` can print the syntax tree in
+const FORMATS: &[&str] = &["json", "source"];
+
+/// The ways `run` can be made to run the program with `--via`
+const VIAS: &[&str] = &["c"];
+
+/// The optimizations that can be turned on with `--opt`
+const OPTIMIZATIONS: &[&str] = &["tailcall", "dead-code"];
+
+/// What the compiler was asked to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Compile the program
+    Build,
+    /// Load and check the program without compiling it
+    Check,
+    /// Print the tokens in the file
+    Tokens,
+    /// Print the syntax tree of the program
+    Ast,
+    /// Run the program
+    Run,
+    /// Rewrite the file in the standard style
+    Fmt,
+    /// Print how to use the compiler
+    Help,
+}
+
+impl Command {
+    /// Returns the command called `name` on the command line
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "build" => Some(Command::Build),
+            "check" => Some(Command::Check),
+            "tokens" => Some(Command::Tokens),
+            "ast" => Some(Command::Ast),
+            "run" => Some(Command::Run),
+            "fmt" => Some(Command::Fmt),
+            "help" => Some(Command::Help),
+            _ => None,
+        }
+    }
+}
+
+/// The language the compiler generates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    C,
+    /// A JavaScript module, for running programs in the browser
+    Js,
+    /// A Python script, for showing what a program means in Python
+    Python,
+}
+
+impl Backend {
+    /// Returns the backend called `name` on the command line
+    fn from_name(name: &str) -> Result<Backend, String> {
+        match name {
+            "c" => Ok(Backend::C),
+            "js" => Ok(Backend::Js),
+            "python" => Ok(Backend::Python),
+            _ => Err(format!("unknown backend `{}`, expected `c`, `js` or `python`", name)),
+        }
+    }
+}
+
+/// A command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    /// The main file, or None if the program is read from stdin
+    pub input: Option<PathBuf>,
+    /// The file to write the compiled program to, or None to print it
+    pub output: Option<PathBuf>,
+    pub backend: Backend,
+    /// The arguments after the file, which are passed on to a program that is run
+    pub program_args: Vec<String>,
+    /// The options given, like `--bignum` and `--keywords=es`
+    options: Vec<String>,
+}
+
+impl Cli {
+    /// Returns true if the option `name`, like `--bignum`, was given
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|option| option == name)
+    }
+
+    /// Returns true if the optimization `name`, like `tailcall`, was turned on with `--opt`
+    pub fn optimizes(&self, name: &str) -> bool {
+        self.options.iter().any(|option| option.strip_prefix("--opt=") == Some(name))
+    }
+
+    /// Returns the value given to the option `name`, like `es` for `--keywords`
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options.iter()
+            .filter_map(|option| option.split_once('='))
+            .find(|&(option, _)| option == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Reads the command line `args`, without the name of the compiler
+///
+/// Without a command, a file that `is_script` is run rather than built, so a
+/// script started with `#!/usr/bin/env haumea` runs when it is run directly.
+/// Options can't come after the file when it is run, since the arguments
+/// after it are the program's.
+///
+/// # Examples
+/// ```
+/// # use std::path::Path;
+/// # use haumea::cli::{self, Backend, Command};
+/// let args = ["build", "foo.hau", "-o", "foo.c", "--backend", "c", "--keywords=es"];
+/// let cli = cli::parse(args.iter().map(|arg| arg.to_string()), |_| false).unwrap();
+/// assert_eq!(cli.command, Command::Build);
+/// assert_eq!(cli.output.as_deref(), Some(Path::new("foo.c")));
+/// assert_eq!(cli.backend, Backend::C);
+/// assert_eq!(cli.value("--keywords"), Some("es"));
+/// ```
+pub fn parse<I, F>(args: I, is_script: F) -> Result<Cli, String>
+    where I: IntoIterator<Item=String>, F: Fn(&Path) -> bool {
+    let mut args = args.into_iter().peekable();
+    let command = args.peek().and_then(|arg| Command::from_name(arg));
+    if command.is_some() {
+        args.next();
+    }
+    let mut cli = Cli {
+        command: command.unwrap_or(Command::Build),
+        input: None,
+        output: None,
+        backend: Backend::C,
+        program_args: vec![],
+        options: vec![],
+    };
+    while let Some(arg) = args.next() {
+        if arg == "-o" || arg == "--output" {
+            let path = args.next().ok_or_else(|| format!("{} needs the name of a file", arg))?;
+            cli.output = Some(PathBuf::from(path));
+        } else if let Some(path) = arg.strip_prefix("--output=") {
+            cli.output = Some(PathBuf::from(path));
+        } else if arg == "--backend" {
+            let name = args.next().ok_or_else(|| "--backend needs the name of a backend".to_string())?;
+            cli.backend = Backend::from_name(&name)?;
+        } else if let Some(name) = arg.strip_prefix("--backend=") {
+            cli.backend = Backend::from_name(name)?;
+        } else if arg == "--opt" {
+            let name = args.next().ok_or_else(|| "--opt needs the name of an optimization".to_string())?;
+            cli.options.push(optimization(&name)?);
+        } else if let Some(name) = arg.strip_prefix("--opt=") {
+            cli.options.push(optimization(name)?);
+        } else if arg == "--format" {
+            let name = args.next().ok_or_else(|| "--format needs the name of a format".to_string())?;
+            cli.options.push(ast_format(&name)?);
+        } else if let Some(name) = arg.strip_prefix("--format=") {
+            cli.options.push(ast_format(name)?);
+        } else if arg == "--via" {
+            let name = args.next().ok_or_else(|| "--via needs the name of a backend".to_string())?;
+            cli.options.push(via(&name)?);
+        } else if let Some(name) = arg.strip_prefix("--via=") {
+            cli.options.push(via(name)?);
+        } else if arg == "-h" || arg == "--help" {
+            cli.command = Command::Help;
+        } else if arg.starts_with('-') {
+            let name = arg.split('=').next().unwrap_or(&arg);
+            let known = if arg.contains('=') { VALUED.contains(&name) } else { FLAGS.contains(&name) };
+            if !known {
+                return Err(format!("unknown option `{}`", arg));
+            }
+            cli.options.push(arg);
+        } else if cli.input.is_none() {
+            let input = PathBuf::from(arg);
+            if command.is_none() && is_script(&input) {
+                cli.command = Command::Run;
+            }
+            cli.input = Some(input);
+            if cli.command == Command::Run {
+                cli.program_args = args.by_ref().collect();
+            }
+        } else {
+            return Err(format!("unexpected argument `{}`, only one file can be given", arg));
+        }
+    }
+    if cli.flag("--check") && cli.command != Command::Fmt {
+        return Err("--check can only be used with fmt".to_string());
+    }
+    if cli.value("--format").is_some() && cli.command != Command::Ast {
+        return Err("--format can only be used with ast".to_string());
+    }
+    if cli.value("--via").is_some() && cli.command != Command::Run {
+        return Err("--via can only be used with run".to_string());
+    }
+    if cli.output.is_some() {
+        if cli.command != Command::Build {
+            return Err("-o can only be used when building a program".to_string());
+        }
+        if cli.value("--crate-type").is_some() {
+            return Err("-o can't be used with --crate-type, libraries are built in the current directory".to_string());
+        }
+    }
+    Ok(cli)
+}
+
+/// Returns the option that prints the syntax tree in the format `name`
+fn ast_format(name: &str) -> Result<String, String> {
+    if FORMATS.contains(&name) {
+        Ok(format!("--format={}", name))
+    } else {
+        Err(format!("unknown format `{}`, expected `json` or `source`", name))
+    }
+}
+
+/// Returns the option that runs the program with the backend `name`
+fn via(name: &str) -> Result<String, String> {
+    if VIAS.contains(&name) {
+        Ok(format!("--via={}", name))
+    } else {
+        Err(format!("unknown way to run a program `{}`, expected `c`", name))
+    }
+}
+
+/// Returns the option that turns on the optimization `name`
+fn optimization(name: &str) -> Result<String, String> {
+    if OPTIMIZATIONS.contains(&name) {
+        Ok(format!("--opt={}", name))
+    } else {
+        Err(format!("unknown optimization `{}`, expected one of {}", name, OPTIMIZATIONS.join(", ")))
+    }
+}
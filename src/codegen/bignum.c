@@ -0,0 +1,426 @@
+
+/* Haumea bignum runtime */
+/* Arbitrary-precision integers used when compiling with --bignum. */
+/* Numbers are immutable, so results never alias their arguments. */
+#include <stdlib.h>
+#include <string.h>
+
+#define HM_BASE 1000000000u
+
+typedef struct hm_big {
+    int sign;              /* -1, 0 or 1 */
+    size_t len;            /* number of digits in use */
+    unsigned int *digits;  /* base 10^9, least significant first */
+} *haumea_int;
+
+static haumea_int hm_alloc(size_t len) {
+    haumea_int n = malloc(sizeof(struct hm_big));
+    n->sign = 0;
+    n->len = len;
+    n->digits = calloc(len ? len : 1, sizeof(unsigned int));
+    return n;
+}
+
+static void hm_free(haumea_int n) {
+    free(n->digits);
+    free(n);
+}
+
+static haumea_int hm_trim(haumea_int n) {
+    while (n->len > 0 && n->digits[n->len - 1] == 0) {
+        n->len--;
+    }
+    if (n->len == 0) {
+        n->sign = 0;
+    }
+    return n;
+}
+
+static haumea_int hm_from_long(long long value) {
+    haumea_int n = hm_alloc(3);
+    unsigned long long magnitude = value < 0 ? 0ull - (unsigned long long) value : (unsigned long long) value;
+    size_t i = 0;
+    n->sign = value < 0 ? -1 : 1;
+    while (magnitude > 0) {
+        n->digits[i++] = magnitude % HM_BASE;
+        magnitude /= HM_BASE;
+    }
+    n->len = i;
+    return hm_trim(n);
+}
+
+/* Wraps around like a C long long if the number is too large */
+static long long hm_to_long(haumea_int n) {
+    unsigned long long value = 0;
+    size_t i = n->len;
+    while (i-- > 0) {
+        value = value * HM_BASE + n->digits[i];
+    }
+    return (long long) (n->sign < 0 ? 0ull - value : value);
+}
+
+static int hm_truthy(haumea_int n) {
+    return n->sign != 0;
+}
+
+static int hm_cmp_abs(haumea_int a, haumea_int b) {
+    size_t i;
+    if (a->len != b->len) {
+        return a->len < b->len ? -1 : 1;
+    }
+    for (i = a->len; i-- > 0;) {
+        if (a->digits[i] != b->digits[i]) {
+            return a->digits[i] < b->digits[i] ? -1 : 1;
+        }
+    }
+    return 0;
+}
+
+static int hm_cmp(haumea_int a, haumea_int b) {
+    if (a->sign != b->sign) {
+        return a->sign < b->sign ? -1 : 1;
+    }
+    return a->sign * hm_cmp_abs(a, b);
+}
+
+static haumea_int hm_add_abs(haumea_int a, haumea_int b) {
+    size_t len = (a->len > b->len ? a->len : b->len) + 1, i;
+    haumea_int r = hm_alloc(len);
+    unsigned long carry = 0;
+    for (i = 0; i < len; i++) {
+        unsigned long sum = carry;
+        if (i < a->len) sum += a->digits[i];
+        if (i < b->len) sum += b->digits[i];
+        r->digits[i] = sum % HM_BASE;
+        carry = sum / HM_BASE;
+    }
+    r->sign = 1;
+    return hm_trim(r);
+}
+
+/* The magnitude of a must be at least the magnitude of b */
+static haumea_int hm_sub_abs(haumea_int a, haumea_int b) {
+    haumea_int r = hm_alloc(a->len);
+    long borrow = 0;
+    size_t i;
+    for (i = 0; i < a->len; i++) {
+        long diff = (long) a->digits[i] - borrow - (i < b->len ? (long) b->digits[i] : 0);
+        borrow = diff < 0;
+        if (diff < 0) diff += HM_BASE;
+        r->digits[i] = (unsigned int) diff;
+    }
+    r->sign = 1;
+    return hm_trim(r);
+}
+
+static haumea_int hm_neg(haumea_int a) {
+    haumea_int r = malloc(sizeof(struct hm_big));
+    *r = *a;
+    r->sign = -a->sign;
+    return r;
+}
+
+static haumea_int hm_add(haumea_int a, haumea_int b) {
+    haumea_int r;
+    if (a->sign == 0) return b;
+    if (b->sign == 0) return a;
+    if (a->sign == b->sign) {
+        r = hm_add_abs(a, b);
+        r->sign = a->sign;
+    } else if (hm_cmp_abs(a, b) >= 0) {
+        r = hm_sub_abs(a, b);
+        r->sign *= a->sign;
+    } else {
+        r = hm_sub_abs(b, a);
+        r->sign *= b->sign;
+    }
+    return r;
+}
+
+static haumea_int hm_sub(haumea_int a, haumea_int b) {
+    return hm_add(a, hm_neg(b));
+}
+
+static haumea_int hm_mul(haumea_int a, haumea_int b) {
+    haumea_int r;
+    size_t i, j;
+    if (a->sign == 0 || b->sign == 0) return hm_from_long(0l);
+    r = hm_alloc(a->len + b->len);
+    for (i = 0; i < a->len; i++) {
+        unsigned long long carry = 0;
+        for (j = 0; j < b->len || carry; j++) {
+            unsigned long long cur = r->digits[i + j] + carry;
+            if (j < b->len) cur += (unsigned long long) a->digits[i] * b->digits[j];
+            r->digits[i + j] = cur % HM_BASE;
+            carry = cur / HM_BASE;
+        }
+    }
+    r->sign = a->sign * b->sign;
+    return hm_trim(r);
+}
+
+/* Returns the magnitude of a times a single digit m */
+static haumea_int hm_mul_digit(haumea_int a, unsigned int m) {
+    haumea_int r = hm_alloc(a->len + 1);
+    unsigned long long carry = 0;
+    size_t i;
+    for (i = 0; i <= a->len; i++) {
+        unsigned long long cur = carry;
+        if (i < a->len) cur += (unsigned long long) a->digits[i] * m;
+        r->digits[i] = cur % HM_BASE;
+        carry = cur / HM_BASE;
+    }
+    r->sign = 1;
+    return hm_trim(r);
+}
+
+/* Divides like C does, truncating the quotient towards zero */
+static void hm_divmod(haumea_int a, haumea_int b, haumea_int *quotient, haumea_int *remainder) {
+    haumea_int q, r = hm_from_long(0l);
+    size_t i;
+    if (b->sign == 0) {
+        fprintf(stderr, "Error: division by zero\n");
+        exit(1);
+    }
+    q = hm_alloc(a->len);
+    for (i = a->len; i-- > 0;) {
+        unsigned int low = 0, high = HM_BASE - 1;
+        haumea_int shifted = hm_alloc(r->len + 1), product;
+        memcpy(shifted->digits + 1, r->digits, r->len * sizeof(unsigned int));
+        shifted->digits[0] = a->digits[i];
+        shifted->sign = 1;
+        hm_free(r);
+        r = hm_trim(shifted);
+        while (low < high) {
+            unsigned int mid = low + (high - low + 1) / 2;
+            product = hm_mul_digit(b, mid);
+            if (hm_cmp_abs(product, r) <= 0) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+            hm_free(product);
+        }
+        q->digits[i] = low;
+        if (low > 0) {
+            product = hm_mul_digit(b, low);
+            shifted = hm_sub_abs(r, product);
+            hm_free(product);
+            hm_free(r);
+            r = shifted;
+        }
+    }
+    q->sign = a->sign * b->sign;
+    r->sign *= a->sign;
+    *quotient = hm_trim(q);
+    *remainder = hm_trim(r);
+}
+
+static haumea_int hm_div(haumea_int a, haumea_int b) {
+    haumea_int q, r;
+    hm_divmod(a, b, &q, &r);
+    return q;
+}
+
+static haumea_int hm_mod(haumea_int a, haumea_int b) {
+    haumea_int q, r;
+    hm_divmod(a, b, &q, &r);
+    return r;
+}
+
+/* Bitwise operations and shifts act on the two's complement of the numbers, */
+/* as if they had infinitely many bits, so no bits are lost however large they are */
+
+/* Returns the two's complement of `n` in `len` words of 32 bits, least significant first */
+static unsigned int *hm_to_words(haumea_int n, size_t len) {
+    unsigned int *words = calloc(len ? len : 1, sizeof(unsigned int));
+    unsigned int *digits = calloc(n->len ? n->len : 1, sizeof(unsigned int));
+    size_t count = n->len, i, w = 0;
+    memcpy(digits, n->digits, n->len * sizeof(unsigned int));
+    /* Each division of the magnitude by 2^32 leaves the next word as its remainder */
+    while (count > 0) {
+        unsigned long long remainder = 0;
+        for (i = count; i-- > 0;) {
+            unsigned long long current = remainder * HM_BASE + digits[i];
+            digits[i] = (unsigned int) (current >> 32);
+            remainder = current & 0xffffffffull;
+        }
+        words[w++] = (unsigned int) remainder;
+        while (count > 0 && digits[count - 1] == 0) {
+            count--;
+        }
+    }
+    free(digits);
+    if (n->sign < 0) {
+        unsigned long long carry = 1;
+        for (i = 0; i < len; i++) {
+            unsigned long long current = (unsigned long long) (unsigned int) ~words[i] + carry;
+            words[i] = (unsigned int) current;
+            carry = current >> 32;
+        }
+    }
+    return words;
+}
+
+/* Returns the number whose two's complement is in `words`, which it overwrites */
+static haumea_int hm_from_words(unsigned int *words, size_t len) {
+    int negative = len > 0 && (words[len - 1] & 0x80000000u);
+    haumea_int n = hm_alloc(2 * len + 1);
+    size_t i, j;
+    if (negative) {
+        unsigned long long carry = 1;
+        for (i = 0; i < len; i++) {
+            unsigned long long current = (unsigned long long) (unsigned int) ~words[i] + carry;
+            words[i] = (unsigned int) current;
+            carry = current >> 32;
+        }
+    }
+    n->len = 0;
+    for (i = len; i-- > 0;) {
+        unsigned long long carry = words[i];
+        for (j = 0; j < n->len; j++) {
+            unsigned long long current = ((unsigned long long) n->digits[j] << 32) + carry;
+            n->digits[j] = (unsigned int) (current % HM_BASE);
+            carry = current / HM_BASE;
+        }
+        while (carry > 0) {
+            n->digits[n->len++] = (unsigned int) (carry % HM_BASE);
+            carry /= HM_BASE;
+        }
+    }
+    n->sign = negative ? -1 : 1;
+    return hm_trim(n);
+}
+
+/* Combines the words of `a` and `b` with `op`, which is '&', '|' or '^' */
+static haumea_int hm_bitwise(haumea_int a, haumea_int b, char op) {
+    size_t len = (a->len > b->len ? a->len : b->len) + 1, i;
+    unsigned int *x = hm_to_words(a, len), *y = hm_to_words(b, len);
+    haumea_int r;
+    for (i = 0; i < len; i++) {
+        x[i] = op == '&' ? x[i] & y[i] : op == '|' ? x[i] | y[i] : x[i] ^ y[i];
+    }
+    r = hm_from_words(x, len);
+    free(x);
+    free(y);
+    return r;
+}
+
+static haumea_int hm_bitand(haumea_int a, haumea_int b) {
+    return hm_bitwise(a, b, '&');
+}
+
+static haumea_int hm_bitor(haumea_int a, haumea_int b) {
+    return hm_bitwise(a, b, '|');
+}
+
+static haumea_int hm_bitxor(haumea_int a, haumea_int b) {
+    return hm_bitwise(a, b, '^');
+}
+
+/* Shifts `a` left by `count` bits, or right by -`count` bits if it is negative. */
+/* Shifting right rounds down, like shifting a negative machine integer does. */
+static haumea_int hm_shift(haumea_int a, haumea_int count) {
+    size_t len = a->len + 1, whole, i, result_len;
+    unsigned long long total;
+    unsigned int bits, fill = a->sign < 0 ? 0xffffffffu : 0u;
+    unsigned int *words, *result;
+    int left = count->sign > 0;
+    haumea_int r;
+    if (a->sign == 0 || count->sign == 0) {
+        return a;
+    }
+    /* A count of 10^18 or more moves every bit out, or needs more memory than there is */
+    if (count->len > 2) {
+        if (left) {
+            fprintf(stderr, "Error: the number is too large to shift that far\n");
+            exit(1);
+        }
+        return hm_from_long(a->sign < 0 ? -1l : 0l);
+    }
+    total = (unsigned long long) hm_to_long(left ? count : hm_neg(count));
+    whole = total / 32;
+    bits = (unsigned int) (total % 32);
+    if (!left && whole >= len) {
+        return hm_from_long(a->sign < 0 ? -1l : 0l);
+    }
+    result_len = left ? len + whole + 1 : len - whole;
+    result = calloc(result_len, sizeof(unsigned int));
+    if (result == NULL) {
+        fprintf(stderr, "Error: the number is too large to shift that far\n");
+        exit(1);
+    }
+    words = hm_to_words(a, len);
+    for (i = 0; i < result_len; i++) {
+        if (left) {
+            unsigned long long word, below;
+            if (i < whole) continue;
+            word = i - whole < len ? words[i - whole] : fill;
+            below = i == whole ? 0 : i - whole - 1 < len ? words[i - whole - 1] : fill;
+            result[i] = (unsigned int) (word << bits | (bits ? below >> (32 - bits) : 0));
+        } else {
+            unsigned long long word = words[i + whole];
+            unsigned long long above = i + whole + 1 < len ? words[i + whole + 1] : fill;
+            result[i] = (unsigned int) (word >> bits | (bits ? above << (32 - bits) : 0));
+        }
+    }
+    r = hm_from_words(result, result_len);
+    free(words);
+    free(result);
+    return r;
+}
+
+static haumea_int hm_shl(haumea_int a, haumea_int b) {
+    return hm_shift(a, b);
+}
+
+static haumea_int hm_shr(haumea_int a, haumea_int b) {
+    return hm_shift(a, hm_neg(b));
+}
+
+/* ~a is -a - 1 in two's complement */
+static haumea_int hm_bitnot(haumea_int a) {
+    return hm_sub(hm_neg(a), hm_from_long(1l));
+}
+
+static haumea_int hm_parse(const char *text) {
+    haumea_int n = hm_from_long(0l), ten = hm_from_long(10l);
+    int negative = *text == '-';
+    if (negative) text++;
+    for (; *text >= '0' && *text <= '9'; text++) {
+        n = hm_add(hm_mul(n, ten), hm_from_long(*text - '0'));
+    }
+    return negative ? hm_neg(n) : n;
+}
+
+/* Writes `n` in decimal to a new string, which the caller frees */
+static char *hm_to_string(haumea_int n) {
+    char *text = malloc(n->len * 9 + 3);
+    char *end = text;
+    size_t i;
+    if (n->sign == 0) {
+        strcpy(text, "0");
+        return text;
+    }
+    if (n->sign < 0) *end++ = '-';
+    end += sprintf(end, "%u", n->digits[n->len - 1]);
+    for (i = n->len - 1; i-- > 0;) {
+        end += sprintf(end, "%09u", n->digits[i]);
+    }
+    return text;
+}
+
+static void hm_print(haumea_int n) {
+    size_t i;
+    if (n->sign == 0) {
+        printf("0");
+        return;
+    }
+    if (n->sign < 0) printf("-");
+    printf("%u", n->digits[n->len - 1]);
+    for (i = n->len - 1; i-- > 0;) {
+        printf("%09u", n->digits[i]);
+    }
+}
+
+/* End bignum runtime */
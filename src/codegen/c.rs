@@ -1,60 +1,230 @@
 //! c.rs
 //! The C code generator for the haumea language.
-use std::rc::Rc;
-use parser;
-use codegen;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::sync::Arc;
+use parser::{self, Type};
+use codegen::{self, CodegenError};
+use builtins;
+use validate;
+use typecheck::Types;
+use ast;
+use flow;
+use names::UniqueNames;
+use codegen::emitter::Emitter;
+use codegen::scopes::Scopes;
 
-/// Unwraps a Rc or panics if it is not possible to do so.
-/// This is a macro because it needs to not take a reference to the passed in Rc,
-/// which is what would happen if it was a function.
-macro_rules! unwrap_rc {
-   ( $rc:expr ) => ( (*Rc::make_mut(&mut ($rc).clone())).clone() );
-   //                 ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
-   //           This is probably the ugliest line of Rust I've ever written. :P
-}
+/// The C runtime for arbitrary-precision integers, used with `Options::bignum`
+const BIGNUM_RUNTIME: &str = include_str!("bignum.c");
+
+/// The C runtime that reports failed checks, used with `Options::runtime_checks`
+const CHECKS_RUNTIME: &str = include_str!("checks.c");
+
+/// The C runtime that checks arithmetic for overflow, used with `Options::checked_arithmetic`
+const OVERFLOW_RUNTIME: &str = include_str!("overflow.c");
+
+/// The C runtime for lists, used by programs that have lists
+const LISTS_RUNTIME: &str = include_str!("lists.c");
+
+/// The C runtime for reals, used by programs that have decimal numbers
+const REALS_RUNTIME: &str = include_str!("reals.c");
+
+/// The C runtime for text, used by programs that have text
+const TEXT_RUNTIME: &str = include_str!("text.c");
+
+/// The C runtime for random numbers, used by programs that call `random_between` or `seed_random`
+const RANDOM_RUNTIME: &str = include_str!("random.c");
+
+/// About how many bytes of C are written for each node of the syntax tree
+const BYTES_PER_NODE: usize = 16;
+
+/// The C keywords, which variables can't be named
+const KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum",
+    "extern", "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return",
+    "short", "signed", "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void",
+    "volatile", "while", "_Bool", "_Complex", "_Imaginary",
+];
+
+/// What goes before the name of every function the program defines in C, so
+/// that they can't clash with C keywords, the C library or the runtime
+const FUNCTION_PREFIX: &str = "hm_fn_";
+
+/// The deepest that the generated C is indented, which only programs
+/// generated by other programs are nested past
+const DEEPEST_INDENT: usize = 32;
 
 pub struct CodeGenerator<'a> {
-    indent: &'a str,
     prolog: &'a str,
+    prolog_end: &'a str,
     epilog: &'a str,
-    ast: parser::Program,
-    _name_number: u32,
-    out: String,
+    options: codegen::Options,
+    directives: Vec<parser::Directive>,
+    /// The name of the function being compiled
+    function: parser::Ident,
+    in_main: bool,
+    /// The functions the program defines, other than `main`, whose C names have FUNCTION_PREFIX
+    defined: HashSet<parser::Ident>,
+    /// The external functions the program declares, which are called with C's own types
+    externals: HashMap<parser::Ident, External>,
+    names: UniqueNames,
+    /// The C names of the variables in scope
+    scopes: Scopes,
+    /// The variadic builtins the program calls, which are passed the number of arguments first
+    variadic: Vec<&'static str>,
+    /// The located builtins the program calls, which are passed where they were called from last
+    located: Vec<&'static str>,
+    /// The types of values, to compile `=`, `length of` and `display` on Text
+    types: Types,
+    /// The number of each piece of text in the program, which is its static haumea_text
+    texts: HashMap<String, usize>,
+    /// The C names of the parameters of the function being compiled, if it
+    /// returns calls to itself and they are compiled to loops
+    tail_call: Option<Vec<String>>,
+    /// The loops around the statement being compiled, innermost last
+    loops: Vec<Loop>,
+    /// The C labels after loops that `break out of` jumps to in the function being compiled
+    exits: Vec<String>,
+    out: Emitter,
+}
+
+/// An external function the program declares
+struct External {
+    /// Whether a header declares it, so that it can take and return any C number or pointer
+    from_header: bool,
+    /// Whether it returns nothing
+    procedure: bool,
+}
+
+/// A loop that is being compiled
+struct Loop {
+    /// The loop's label, if it has one
+    label: Option<parser::Ident>,
+    /// The C label after the loop, once a `break out of` it needs to jump there
+    exit: Option<String>,
 }
 
 impl<'a> codegen::CodeGen for CodeGenerator<'a> {
     /// Compile an Program created by `parser::parse` into a C program
-    fn compile(&mut self) -> String {
-        self.out.push_str(self.prolog);
-        for func in self.ast.clone().into_iter() {
+    fn compile(&mut self, program: &parser::Program) -> Result<String, CodegenError> {
+        // Without the bignum runtime, every integer is stored in a C long long
+        if self.options.bignum {
+            validate::validate(program)
+        } else {
+            validate::validate_machine_integers(program)
+        }.map_err(CodegenError::Invalid)?;
+        self.scopes = Scopes::new(program, &self.directives, KEYWORDS);
+        self.defined = program.iter()
+            .filter(|func| func.external.is_none() && func.name != "main")
+            .map(|func| func.name)
+            .collect();
+        // Once a header is included, it is expected to declare the externals that aren't `from` one
+        let headers = self.directives.iter().any(|directive| matches!(*directive, parser::Directive::Include(_)));
+        self.externals = program.iter()
+            .filter_map(|func| {
+                let from_header = func.external.as_ref()?.header.is_some() || headers;
+                Some((func.name, External { from_header, procedure: flow::is_procedure(func) }))
+            })
+            .collect();
+        // The list builtins need the list runtime, which is left out of programs without lists
+        let lists = uses_lists(program);
+        // Reals need the real runtime, which is left out of programs without them
+        let reals = uses_reals(program);
+        self.texts = texts(program);
+        self.types = Types::new(program);
+        let available = builtins::available_to(program);
+        // The text builtins need the text runtime, which is left out of programs without text
+        let text = uses_text(program);
+        // So do the random number builtins, which share the generator's state
+        let random = calls_builtin(program, &["random_between", "seed_random"]);
+        self.variadic = available.iter().filter(|builtin| builtin.variadic).map(|builtin| builtin.name).collect();
+        self.located = available.iter().filter(|builtin| builtin.located).map(|builtin| builtin.name).collect();
+        // Only the builtins that are called are defined, so that they can't clash with included headers
+        let called = called_functions(program);
+        let builtins = available.iter()
+            .filter(|builtin| called.contains(&parser::Ident::from(builtin.name)))
+            .map(|builtin| get_builtin_definition(builtin.name, self.options.bignum)
+                 .ok_or(CodegenError::UnknownBuiltin { backend: "c", name: builtin.name }))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Making room for all of the code up front saves copying it as it grows
+        let runtime = if self.options.bignum { BIGNUM_RUNTIME } else { "" };
+        let checks = if self.options.runtime_checks { get_checks_definition(self.options.bignum) } else { "" };
+        let overflow_runtime = if self.checks_overflow() { OVERFLOW_RUNTIME } else { "" };
+        let lists_runtime = if lists { LISTS_RUNTIME } else { "" };
+        let reals_runtime = if reals { REALS_RUNTIME } else { "" };
+        let text_runtime = if text { TEXT_RUNTIME } else { "" };
+        let random_runtime = if random { RANDOM_RUNTIME } else { "" };
+        let display_values = if text { get_display_values_definition(self.options.bignum) } else { "" };
+        self.out.reserve(self.prolog.len() + runtime.len() + checks.len() + overflow_runtime.len()
+                         + lists_runtime.len() + reals_runtime.len() + text_runtime.len() + display_values.len() + random_runtime.len()
+                         + self.prolog_end.len()
+                         + self.epilog.len() + builtins.iter().map(|definition| definition.len()).sum::<usize>()
+                         + ast::node_count(program) * BYTES_PER_NODE);
+        self.out.text(self.prolog);
+        self.out.text(runtime);
+        if self.options.runtime_checks || self.checks_overflow() {
+            self.out.text(CHECKS_RUNTIME);
+        }
+        self.out.text(checks);
+        self.out.text(overflow_runtime);
+        if lists {
+            let value_type = if self.options.bignum { "haumea_int" } else { "long long" };
+            self.out.line(format_args!("typedef {:} haumea_value;", value_type));
+            self.out.text(lists_runtime);
+        }
+        self.out.text(reals_runtime);
+        self.out.text(text_runtime);
+        self.out.text(display_values);
+        self.out.text(random_runtime);
+        self.compile_texts();
+        self.compile_externals(program);
+        for definition in builtins {
+            self.out.text(definition);
+        }
+        self.out.text(self.prolog_end);
+        self.compile_prototypes(program);
+        for func in program {
             self.compile_function(func);
         }
-        self.out.push_str(self.epilog);
-        self.out.clone()
+        self.out.text(self.epilog);
+        Ok(self.out.finish())
+    }
+}
+
+impl<'a> Default for CodeGenerator<'a> {
+    fn default() -> CodeGenerator<'a> {
+        CodeGenerator::new()
     }
 }
 
 impl<'a> CodeGenerator<'a> {
     /// Constructs a new CodeGenerator
-    pub fn new(ast: parser::Program) -> CodeGenerator<'a> {
+    pub fn new() -> CodeGenerator<'a> {
+        CodeGenerator::with_options(codegen::Options::default())
+    }
+
+    /// Constructs a new CodeGenerator that uses the given Options
+    pub fn with_options(options: codegen::Options) -> CodeGenerator<'a> {
+        CodeGenerator::with_directives(options, vec![])
+    }
+
+    /// Constructs a new CodeGenerator that uses the given Options and build directives
+    pub fn with_directives(options: codegen::Options, directives: Vec<parser::Directive>) -> CodeGenerator<'a> {
         CodeGenerator {
-            indent: "    ",
             prolog: "
 /* Haumea prolog */
+/* Every value is a long long, which is 64 bits wherever the program is */
+/* compiled. Text and lists are the addresses of their data, converted */
+/* through intptr_t, so they aren't cut short where a long is 32 bits. */
+#include <stdint.h>
 #include <stdio.h>
 
-long display(long n) {
-    printf(\"%ld\\n\", n);
-    return 0;
-}
-
-long read() {
-    printf(\"Enter an integer: \");
-    long n;
-    scanf(\"%ld\", &n);
-    return n;
+/* Wraps the value main returns into a valid exit status, like exit does */
+static int haumea_exit_code(long long value) {
+    return (int) (value & 0xff);
 }
-
+",
+            prolog_end: "
 /* End prolog */
 
 /* Start compiled program */
@@ -62,125 +232,323 @@ long read() {
             epilog: "
 /* End compiled program */
 ",
-            ast: ast,
-            _name_number: 0,
-            out: String::new(),
+            options,
+            directives,
+            function: parser::Ident::from(""),
+            in_main: false,
+            defined: HashSet::new(),
+            externals: HashMap::new(),
+            names: UniqueNames::new("__HAUMEA_TEMP_"),
+            scopes: Scopes::default(),
+            variadic: vec![],
+            located: vec![],
+            types: Types::default(),
+            texts: HashMap::new(),
+            tail_call: None,
+            loops: vec![],
+            exits: vec![],
+            out: Emitter::new("    ").flatten_after(DEEPEST_INDENT),
         }
     }
     
-    /// Compiles a Function
-    fn compile_function(&mut self, func: parser::Function) {
-        self.out.push_str("\n");
-        self.out.push_str(if func.name == "main" { "int " } else { "long " });
-        self.out.push_str(&func.name);
-        self.out.push_str("(");
-        if let Some(sig) = func.signature {
-            if let Some((last_param, first_params)) = sig.split_last() {
-                for param in first_params {
-                    self.out.push_str(&format!("long {:}, ", param));
+    /// Defines a static haumea_text for each piece of text in the program
+    fn compile_texts(&mut self) {
+        let mut texts: Vec<(&String, &usize)> = self.texts.iter().collect();
+        texts.sort_by_key(|&(_, &number)| number);
+        if !texts.is_empty() {
+            self.out.line("");
+        }
+        for (text, number) in texts {
+            self.out.line(format_args!("static const struct {{ long long length; char chars[{:}]; }} haumea_text_{:} = {{ {:}, \"{:}\" }};",
+                                       text.len() + 1, number, text.len(), c_escape(text)));
+        }
+    }
+
+    /// Includes the headers named by directives, defines the embedded files, and
+    /// declares the external functions, by including their header or with a prototype
+    fn compile_externals(&mut self, program: &parser::Program) {
+        let flags = linker_flags(&self.directives);
+        if !flags.is_empty() {
+            self.out.line(format_args!("/* Link with: {:} */", flags.join(" ")));
+        }
+        let directives = mem::take(&mut self.directives);
+        let mut headers = vec![];
+        for directive in &directives {
+            if let parser::Directive::Include(ref header) = *directive {
+                if !headers.contains(&header) {
+                    self.out.line(format_args!("#include \"{:}\"", header));
+                    headers.push(header);
                 }
-                self.out.push_str(&format!("long {:}", last_param));
             }
         }
-        self.out.push_str(") ");
-        self.out.push_str("{\n");
-        self.compile_statement(func.code, 1);
-        self.out.push_str(&format!("{:}return 0l;", self.indent));
-        self.out.push_str("\n}\n");
+        for directive in &directives {
+            if let parser::Directive::Embed { ref name, ref bytes, .. } = *directive {
+                self.compile_embed(name, bytes);
+            }
+        }
+        for func in program {
+            match func.external {
+                Some(parser::External { header: Some(ref header) }) if !headers.contains(&header) => {
+                    self.out.line(format_args!("#include \"{:}\"", header));
+                    headers.push(header);
+                },
+                Some(parser::External { header: Some(_) }) => (),
+                Some(parser::External { header: None }) if !self.externals[&func.name].from_header => {
+                    let params = match func.signature {
+                        Some(ref sig) if !sig.is_empty() => sig.iter().enumerate()
+                            .map(|(index, param)| match external_type(func.types.parameter(index)) {
+                                pointer if pointer.ends_with('*') => format!("{:}{:}", pointer, param),
+                                number => format!("{:} {:}", number, param),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        _ => "void".to_string(),
+                    };
+                    let return_type = if flow::is_procedure(func) { "void" } else { external_type(func.types.returns) };
+                    self.out.line(format_args!("{:} {:}({:});", return_type, func.name, params));
+                },
+                Some(parser::External { header: None }) => (),
+                None => (),
+            }
+        }
+        self.directives = directives;
+    }
+
+    /// Defines the functions that read an embedded file's bytes
+    fn compile_embed(&mut self, name: &str, bytes: &[u8]) {
+        let mut array = String::new();
+        for (i, byte) in bytes.iter().enumerate() {
+            array.push_str(if i % 16 == 0 { "\n    " } else { " " });
+            array.push_str(&format!("{:},", byte));
+        }
+        // C arrays can't be empty, so there is always an unused 0 at the end
+        self.out.text(&format!("
+static const unsigned char haumea_embed_{name:}[] = {{{array:}\n    0\n}};
+static const long long haumea_embed_{name:}_size = {size:}ll;
+", name = name, array = array, size = bytes.len()));
+        if self.options.bignum {
+            self.out.text(&format!("
+static haumea_int {name:}(haumea_int index) {{
+    long long i = hm_to_long(index);
+    return hm_from_long(i >= 0 && i < haumea_embed_{name:}_size ? haumea_embed_{name:}[i] : -1ll);
+}}
+
+static haumea_int {name:}_size(void) {{
+    return hm_from_long(haumea_embed_{name:}_size);
+}}
+", name = name));
+        } else {
+            self.out.text(&format!("
+static long long {name:}(long long i) {{
+    return i >= 0 && i < haumea_embed_{name:}_size ? haumea_embed_{name:}[i] : -1ll;
+}}
+
+static long long {name:}_size(void) {{
+    return haumea_embed_{name:}_size;
+}}
+", name = name));
+        }
+    }
+
+    /// Declares every function the program defines, other than `main`, so
+    /// they can be called before their definitions, whatever order they are in
+    fn compile_prototypes(&mut self, program: &parser::Program) {
+        let mut defined = program.iter().filter(|func| func.external.is_none() && func.name != "main").peekable();
+        if defined.peek().is_some() {
+            self.out.line("");
+        }
+        for func in defined {
+            let params = match func.signature {
+                Some(ref sig) if !sig.is_empty() => (0..sig.len())
+                    .map(|index| self.c_type(self.types.parameter(func.name, index)))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                _ => "void".to_string(),
+            };
+            let header = function_header(func, self.c_type(self.types.returns(func.name)), &params);
+            self.out.line(format_args!("{:};", header));
+        }
+    }
+
+    /// Compiles a Function
+    fn compile_function(&mut self, func: &parser::Function) {
+        if func.external.is_some() {
+            return;
+        }
+        self.function = func.name;
+        self.in_main = func.name == "main";
+        if self.options.deterministic {
+            self.names.restart(&func.name);
+        }
+        self.scopes.start_function();
+        self.exits.clear();
+        let names: Vec<String> = match func.signature {
+            Some(ref sig) => sig.iter().map(|param| self.scopes.declare(*param)).collect(),
+            None => vec![],
+        };
+        let params = names.iter().enumerate()
+            .map(|(index, name)| format!("{:} {:}", self.c_type(self.types.parameter(func.name, index)), name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        // main's returns become exit statuses, so it is always called
+        self.tail_call = if self.options.tail_calls && !self.in_main && returns_call_to_itself(func) {
+            Some(names)
+        } else {
+            None
+        };
+        let procedure = flow::is_procedure(func);
+        self.out.line("");
+        if self.in_main {
+            self.out.line(format_args!("int main({:}) {{", params));
+        } else {
+            let return_type = self.c_type(self.types.returns(func.name));
+            self.out.line(format_args!("{:} {{", function_header(func, return_type, &params)));
+        }
+        self.out.indent();
+        if self.tail_call.is_some() {
+            self.out.line("haumea_start: ;");
+        }
+        self.compile_statement(&func.code);
+        if flow::falls_through(&func.code) && !procedure {
+            let zero = if self.types.returns(func.name) == Some(Type::Real) && !self.in_main {
+                "0.0"
+            } else if !self.options.bignum {
+                "0ll"
+            } else if self.in_main {
+                "0"
+            } else {
+                "hm_from_long(0ll)"
+            };
+            self.out.line(format_args!("return {:};", zero));
+        }
+        self.out.dedent();
+        self.out.line("}");
     }
     
     /// Compiles a statement
-    fn compile_statement(&mut self, statement: parser::Statement, indent: i32) {
+    ///
+    /// What is left to do is kept on a stack rather than done by recursion,
+    /// since generated code can be nested too deeply to recurse through.
+    fn compile_statement(&mut self, statement: &parser::Statement) {
+        let mut waiting = vec![Task::Statement(statement)];
+        let mut then = vec![];
+        while let Some(task) = waiting.pop() {
+            match task {
+                Task::Statement(statement) => self.compile_one(statement, &mut then),
+                Task::Line(line) => self.out.line(line),
+                Task::Indent => self.out.indent(),
+                Task::Dedent => self.out.dedent(),
+                Task::Enter => self.scopes.enter(),
+                Task::Leave => self.scopes.leave(),
+                Task::EndLoop => {
+                    let exit = self.loops.pop().expect("The loop was pushed when it was started").exit;
+                    if let Some(exit) = exit {
+                        self.out.line(format_args!("{:}: ;", exit));
+                    }
+                },
+            }
+            // What comes first is done first, so it goes on the stack last
+            waiting.extend(then.drain(..).rev());
+        }
+    }
+
+    /// Compiles `statement`, leaving the statements inside it, and what comes after them, in `then`
+    fn compile_one<'s>(&mut self, statement: &'s parser::Statement, then: &mut Vec<Task<'s>>) {
         use parser::Statement;
     
-        match statement {
-            Statement::Return(exp) => {
-                let exp = self.compile_expression(exp);
-                self.out.push_str(&format!("{:}return {:};",
-                                      replicate(self.indent, indent),
-                                      exp));
+        match *statement {
+            Statement::Return(parser::Expression::Call { function, ref arguments, .. }, _)
+                if self.tail_call.is_some() && function == self.function => self.compile_tail_call(arguments),
+            Statement::Return(ref exp, _) if !self.in_main => {
+                let exp = self.compile_as(exp, self.types.returns(self.function));
+                self.out.line(format_args!("return {:};", exp));
             },
-            Statement::Do(block) => {
-                self.out.push_str(&format!("{:}{{\n", replicate(self.indent, indent)));
-                for sub_statement in block {
-                    let sub = unwrap_rc!(sub_statement);
-                    self.compile_statement(sub, indent+1);
-                };
-                self.out.push_str(&format!("{:}}}\n", replicate(self.indent, indent)));
-            },
-            Statement::Call {
-                function: func,
-                arguments: args,
-            } => {
-                self.out.push_str(&format!("{:}{:}(", replicate(self.indent, indent), func));
-                let len = args.len();
-                for (index, arg) in args.into_iter().enumerate() {
-                    let arg = self.compile_expression(arg);
-                    if index == len-1 {
-                        self.out.push_str(&arg);
-                    } else {
-                        self.out.push_str(&format!("{:}, ", arg));
-                    }
+            // main's returns become exit statuses
+            Statement::Return(ref exp, _) => {
+                let mut exp = self.compile_expression(exp);
+                if self.options.bignum {
+                    exp = format!("hm_to_long({:})", exp);
                 }
-                self.out.push_str(");\n");
+                self.out.line(format_args!("return haumea_exit_code({:});", exp));
+            },
+            Statement::Do(ref block, _) => {
+                self.out.line("{");
+                self.out.indent();
+                self.scopes.enter();
+                then.extend(block.iter().map(|sub_statement| Task::Statement(sub_statement)));
+                then.extend([Task::Leave, Task::Dedent, Task::Line("}")]);
             },
-            Statement::Var(ident) => {
-                self.out.push_str(&format!("{:}long {:};\n", replicate(self.indent, indent), ident));
+            Statement::Call { function, ref arguments, span } => {
+                let call = self.compile_call(function, arguments.iter().collect(), span);
+                self.out.line(format_args!("{:};", call));
             },
-            Statement::Set(ident, expr) => {
-                let expr = self.compile_expression(expr);
-                self.out.push_str(&format!("{:}{:} = {:};\n",
-                                      replicate(self.indent, indent),
-                                      ident,
-                                      expr
-                                  ));
+            // Variables start at 0, like they do in the interpreter
+            Statement::Var(ident, _) => {
+                let found = self.types.variable(self.function, ident);
+                let zero = if found == Some(Type::Real) {
+                    "0.0"
+                } else if self.options.bignum {
+                    "hm_from_long(0ll)"
+                } else {
+                    "0ll"
+                };
+                let name = self.scopes.declare(ident);
+                self.out.line(format_args!("{:} {:} = {:};", self.c_type(found), name, zero));
             },
-            Statement::Change(ident, expr) => {
-                let expr = self.compile_expression(expr);
-                self.out.push_str(&format!("{:}{:} += {:};\n",
-                                      replicate(self.indent, indent),
-                                      ident,
-                                      expr
-                                  ));
+            Statement::Set(ident, ref expr, _) => {
+                let expr = self.compile_as(expr, self.types.variable(self.function, ident));
+                self.out.line(format_args!("{:} = {:};", self.scopes.name(ident), expr));
+            },
+            Statement::Change(ident, ref expr, span) => {
+                let name = self.scopes.name(ident);
+                let exp = self.compile_expression(expr);
+                if self.options.bignum {
+                    self.out.line(format_args!("{:} = hm_add({:}, {:});", name, name, exp));
+                } else if self.checks_overflow() {
+                    let code = format!("change {} by {}", ident, ast::expression_to_source(expr));
+                    let checked = self.checked("haumea_add", &[&name, &exp], &code, span);
+                    self.out.line(format_args!("{:} = {:};", name, checked));
+                } else {
+                    self.out.line(format_args!("{:} += {:};", name, exp));
+                }
             },
             Statement::If {
-                cond,
-                if_clause,
-                else_clause,
+                ref cond,
+                ref if_clause,
+                ref else_clause,
+                ..
             } => {
-                let cond = self.compile_expression(cond);
-                self.out.push_str(&format!("{:}if {:}\n", replicate(self.indent, indent), cond));
-                let if_clause = unwrap_rc!(if_clause);
-                self.compile_statement(if_clause, indent+1);
-                let else_clause = unwrap_rc!(else_clause);
-                if let Some(else_) = else_clause {
-                    self.out.push_str(&format!("\n{:}else\n", replicate(self.indent, indent)));
-                    self.compile_statement(else_, indent+1);
-                    self.out.push_str("\n");
+                let cond = self.compile_condition(cond);
+                self.out.line(format_args!("if {:}", cond));
+                compile_body(if_clause, then);
+                if let Some(ref else_) = **else_clause {
+                    then.push(Task::Line("else"));
+                    compile_body(else_, then);
                 }
             },
-            Statement::Forever(block) => {
-                self.out.push_str(&format!("{:}while (1)\n", replicate(self.indent, indent)));
-                let block = unwrap_rc!(block);
-                self.compile_statement(block, indent+1);
+            Statement::Forever(ref block, label, _) => {
+                self.out.line("while (1)");
+                self.compile_loop_body(block, label, then);
             },
             Statement::While {
-                cond,
-                body,
+                ref cond,
+                ref body,
+                label,
+                ..
             } => {
-                let cond = self.compile_expression(cond);
-                self.out.push_str(&format!("{:}while {:}\n", replicate(self.indent, indent),
-                                           cond));
-                let body = unwrap_rc!(body);
-                self.compile_statement(body, indent+1);
+                let cond = self.compile_condition(cond);
+                self.out.line(format_args!("while {:}", cond));
+                self.compile_loop_body(body, label, then);
             },
             Statement::ForEach {
-                ident,
-                start,
-                end,
-                by,
-                range_type,
-                body,
+                ref ident,
+                ref start,
+                ref end,
+                ref by,
+                ref range_type,
+                ref body,
+                label,
+                span,
             } => {
                 let comparitor;
                 let neg_comparitor;
@@ -198,101 +566,1080 @@ long read() {
                 let end_name = self.get_unique_name();
                 let by_name = self.get_unique_name();
                 
+                let header = format!("for each {} in {} {} {} by {}", ident, ast::expression_to_source(start),
+                                     range_type, ast::expression_to_source(end), ast::expression_to_source(by));
+                let int_type = self.int_type();
                 let start = self.compile_expression(start);
-                self.out.push_str(&format!("{:}long {:} = {:};\n",
-                                      replicate(self.indent, indent),
-                                      start_name,
-                                      start,
-                                  ));
+                self.out.line(format_args!("{:} {:} = {:};", int_type, start_name, start));
                 let end = self.compile_expression(end);
-                self.out.push_str(&format!("{:}long {:} = {:};\n",
-                                      replicate(self.indent, indent),
-                                      end_name,
-                                      end)
-                                  );
-                let by = self.compile_expression(by);
-                self.out.push_str(&format!("{:}long {:} = {:};\n",
-                                      replicate(self.indent, indent),
-                                      by_name,
-                                      by)
-                                  );
-                let comp = format!("({:} < {:} ? {:} {:} {:} : {:} {:} {:})", 
-                                   start_name, end_name, ident, comparitor, end_name, ident, neg_comparitor, end_name);
-                self.out.push_str(&format!("{:}for (long {:} = {:}; {:}; {:} += {:})\n", replicate(self.indent, indent),
-                                      ident, start_name, comp, ident, by_name
-                                      ));
-                let body = unwrap_rc!(body);
-                self.compile_statement(body, indent+1);
+                self.out.line(format_args!("{:} {:} = {:};", int_type, end_name, end));
+                let mut by_code = self.compile_expression(by);
+                if self.options.runtime_checks {
+                    by_code = format!("haumea_step({:}, \"{:}\", \"{:}\", {:})",
+                                      by_code, self.function, c_escape(&header), span.start.line);
+                }
+                self.out.line(format_args!("{:} {:} = {:};", int_type, by_name, by_code));
+                // The loop counts down when it starts after its end, whatever the sign of `by`
+                if self.options.bignum {
+                    self.out.line(format_args!("if ((hm_cmp({:}, {:}) <= 0) != ({:}->sign > 0)) {:} = hm_neg({:});",
+                                               start_name, end_name, by_name, by_name, by_name));
+                } else {
+                    self.out.line(format_args!("if (({:} <= {:}) != ({:} > 0)) {:} = -{:};",
+                                               start_name, end_name, by_name, by_name, by_name));
+                }
+                // The loop's variable is only in scope in the loop
+                self.scopes.enter();
+                let ident = self.scopes.declare(*ident);
+                if self.options.bignum {
+                    let comp = format!("(hm_cmp({:}, {:}) <= 0 ? hm_cmp({:}, {:}) {:} 0 : hm_cmp({:}, {:}) {:} 0)",
+                                       start_name, end_name, ident, end_name, comparitor, ident, end_name, neg_comparitor);
+                    self.out.line(format_args!("for ({:} {:} = {:}; {:}; {:} = hm_add({:}, {:}))",
+                                               int_type, ident, start_name, comp, ident, ident, by_name));
+                } else {
+                    let comp = format!("({:} <= {:} ? {:} {:} {:} : {:} {:} {:})",
+                                       start_name, end_name, ident, comparitor, end_name, ident, neg_comparitor, end_name);
+                    self.out.line(format_args!("for (long long {:} = {:}; {:}; {:} += {:})",
+                                               ident, start_name, comp, ident, by_name));
+                }
+                self.compile_loop_body(body, label, then);
+                then.push(Task::Leave);
             },
+            Statement::Break(Some(label), _) => self.compile_break_out_of(label),
+            Statement::Break(None, _) => self.out.line("break;"),
+            Statement::Continue(_) => self.out.line("continue;"),
         }
     }
-    
-    /// Compiles an expression
-    fn compile_expression(&self, expr: parser::Expression) -> String {
+
+    /// Compiles `return` with a call to the function being compiled as going
+    /// back to its start with `arguments` as its parameters
+    ///
+    /// Every argument is worked out before any parameter is changed, since
+    /// they can use the parameters.
+    fn compile_tail_call(&mut self, arguments: &[Arc<parser::Expression>]) {
+        self.out.line("{");
+        self.out.indent();
+        let mut temps = vec![];
+        for (index, arg) in arguments.iter().enumerate() {
+            let temp = self.get_unique_name();
+            let found = self.types.parameter(self.function, index);
+            let arg = self.compile_as(arg, found);
+            self.out.line(format_args!("{:} {:} = {:};", self.c_type(found), temp, arg));
+            temps.push(temp);
+        }
+        let params = self.tail_call.clone().expect("Tail calls are only compiled in functions that have them");
+        for (param, temp) in params.iter().zip(&temps) {
+            self.out.line(format_args!("{:} = {:};", param, temp));
+        }
+        self.out.line("goto haumea_start;");
+        self.out.dedent();
+        self.out.line("}");
+    }
+
+    /// Compiles the body of a loop with the label `label`, and the C label after
+    /// the loop if a `break out of` it jumps there, after what is already in `then`
+    fn compile_loop_body<'s>(&mut self, body: &'s parser::Statement, label: Option<parser::Ident>,
+                             then: &mut Vec<Task<'s>>) {
+        self.loops.push(Loop { label, exit: None });
+        compile_body(body, then);
+        then.push(Task::EndLoop);
+    }
+
+    /// Compiles `break out of label`, which is a `goto` past the loop unless it is the innermost one
+    fn compile_break_out_of(&mut self, label: parser::Ident) {
+        let index = self.loops.iter().rposition(|outer| outer.label == Some(label))
+            .expect("`check` reports breaking out of loops that aren't there");
+        if index + 1 == self.loops.len() {
+            self.out.line("break;");
+            return;
+        }
+        let exit = match self.loops[index].exit {
+            Some(ref exit) => exit.clone(),
+            None => {
+                // Loops in different places can have the same label
+                let base = format!("haumea_break_{}", label);
+                let mut exit = base.clone();
+                let mut count = 1;
+                while self.exits.contains(&exit) {
+                    count += 1;
+                    exit = format!("{}_{}", base, count);
+                }
+                self.exits.push(exit.clone());
+                self.loops[index].exit = Some(exit.clone());
+                exit
+            },
+        };
+        self.out.line(format_args!("goto {:};", exit));
+    }
+
+    /// Compiles an expression to a value, which holds a Real as the bits of its double
+    fn compile_expression(&self, expr: &parser::Expression) -> String {
+        let code = self.compile_typed(expr);
+        if self.type_of(expr) != Some(Type::Real) {
+            code
+        } else if self.options.bignum {
+            format!("hm_from_long(haumea_real({:}))", code)
+        } else {
+            format!("haumea_real({:})", code)
+        }
+    }
+
+    /// Compiles an expression to a C double
+    fn compile_real(&self, expr: &parser::Expression) -> String {
+        if self.type_of(expr) == Some(Type::Real) {
+            self.compile_typed(expr)
+        } else if self.options.bignum {
+            format!("haumea_real_value(hm_to_long({:}))", self.compile_expression(expr))
+        } else {
+            format!("haumea_real_value({:})", self.compile_expression(expr))
+        }
+    }
+
+    /// Compiles an expression to a value of the type `found`, which is a C double for a Real
+    fn compile_as(&self, expr: &parser::Expression, found: Option<Type>) -> String {
+        if found == Some(Type::Real) {
+            self.compile_real(expr)
+        } else {
+            self.compile_expression(expr)
+        }
+    }
+
+    /// Compiles an expression to its own C type, which is a double if it is a Real
+    fn compile_typed(&self, expr: &parser::Expression) -> String {
         use parser::Expression;
     
-        match expr {
-            Expression::Integer(i) => format!("{:?}l", i),
-            Expression::Ident(name) => name,
+        match *expr {
+            // The smallest long long can't be written as a literal, since its digits are too large on their own
+            Expression::Integer(i64::MIN, _) if self.options.bignum => "hm_from_long(-9223372036854775807ll - 1)".to_string(),
+            Expression::Integer(i64::MIN, _) => "(-9223372036854775807ll - 1)".to_string(),
+            Expression::Integer(i, _) if self.options.bignum => format!("hm_from_long({:?}ll)", i),
+            Expression::Integer(i, _) => format!("{:?}ll", i),
+            Expression::BigInteger(ref digits, _) => format!("hm_parse(\"{}\")", digits),
+            // Rust writes every double so that it reads back the same
+            Expression::Float(n, _) => format!("{:?}", n.0),
+            Expression::Ident(name, _) => self.scopes.name(name),
+            // Booleans are the 1 and 0 that C comparisons give
+            Expression::Boolean(value, _) if self.options.bignum => format!("hm_from_long({:}ll)", value as i64),
+            Expression::Boolean(value, _) => format!("{:}ll", value as i64),
+            // Text is passed around as the address of its characters
+            Expression::Str(ref text, _) if self.options.bignum => format!("hm_from_long((long long) (intptr_t) haumea_text_{:}.chars)", self.texts[text]),
+            Expression::Str(ref text, _) => format!("((long long) (intptr_t) haumea_text_{:}.chars)", self.texts[text]),
+            // Lists are passed around as the address of their haumea_list
+            Expression::List(ref items, _) => {
+                let mut list = format!("haumea_list_new({:}", items.len());
+                for item in items {
+                    list.push_str(", ");
+                    list.push_str(&self.compile_expression(item));
+                }
+                list.push(')');
+                if self.options.bignum {
+                    format!("hm_from_long({:})", list)
+                } else {
+                    list
+                }
+            },
+            Expression::Index { ref list, ref index, span } => {
+                let mut list = self.compile_expression(list);
+                let mut index = self.compile_expression(index);
+                if self.options.bignum {
+                    list = format!("hm_to_long({:})", list);
+                    index = format!("hm_to_long({:})", index);
+                }
+                format!("haumea_list_get({:}, {:}, \"{:}\", \"{:}\", {:})", list, index, self.function,
+                        c_escape(&ast::expression_to_source(expr)), span.start.line)
+            },
+            Expression::Length(ref list, _) => {
+                let length = if self.type_of(list) == Some(Type::Text) { "haumea_text_length" } else { "haumea_list_length" };
+                if self.options.bignum {
+                    format!("hm_from_long({:}(hm_to_long({:})))", length, self.compile_expression(list))
+                } else {
+                    format!("{:}({:})", length, self.compile_expression(list))
+                }
+            },
             Expression::BinaryOp {
                 operator: op,
-                left,
-                right,
-            } => {
-                let lh = unwrap_rc!(left);
-                let rh = unwrap_rc!(right);
-                format!("({:} {:} {:})",
-                         self.compile_expression(lh),
-                         get_c_name(op),
-                         self.compile_expression(rh)
-                       )
+                ref left,
+                ref right,
+                ..
+            } if op.works_on_reals() && (self.type_of(left) == Some(Type::Real) || self.type_of(right) == Some(Type::Real)) => {
+                let real = format!("({:} {:} {:})", self.compile_real(left), get_c_name(op), self.compile_real(right));
+                if op.is_comparison() && self.options.bignum {
+                    format!("hm_from_long({:})", real)
+                } else {
+                    real
+                }
             },
-            Expression::Call {
-                function: func,
-                arguments: args,
+            Expression::BinaryOp {
+                operator: op,
+                ref left,
+                ref right,
+                span,
             } => {
-                let mut out = String::new();
-                out.push_str(&format!("{:}(", func));
-                let len = args.len();
-                for (index, arg) in args.into_iter().enumerate() {
-                    let arg = unwrap_rc!(arg);
-                    if index == len-1 {
-                        out.push_str(&self.compile_expression(arg));
+                let lh = self.compile_expression(left);
+                let mut rh = self.compile_expression(right);
+                let divides = op == parser::Operator::Div || op == parser::Operator::Modulo;
+                if self.options.runtime_checks && divides && !self.options.bignum && !self.checks_overflow() {
+                    let check = if op == parser::Operator::Div { "haumea_quotient" } else { "haumea_remainder" };
+                    return self.checked(check, &[&lh, &rh], &ast::expression_to_source(expr), span);
+                }
+                if self.options.runtime_checks && divides {
+                    rh = format!("haumea_divisor({:}, \"{:}\", \"{:}\", {:})",
+                                 rh, self.function, c_escape(&ast::expression_to_source(expr)), span.start.line);
+                }
+                let text = (op == parser::Operator::Equals || op == parser::Operator::NotEquals)
+                    && self.type_of(left) == Some(Type::Text);
+                if text {
+                    let not = if op == parser::Operator::NotEquals { "!" } else { "" };
+                    if self.options.bignum {
+                        format!("hm_from_long({:}haumea_text_equals(hm_to_long({:}), hm_to_long({:})))", not, lh, rh)
                     } else {
-                        out.push_str(&format!("{:}, ", self.compile_expression(arg)));
+                        format!("({:}haumea_text_equals({:}, {:}))", not, lh, rh)
                     }
+                } else if self.options.bignum {
+                    compile_bignum_binary_op(op, &lh, &rh)
+                } else if let Some(check) = overflow_check(op).filter(|_| self.checks_overflow()) {
+                    self.checked(check, &[&lh, &rh], &ast::expression_to_source(expr), span)
+                } else if op == parser::Operator::ShiftLeft {
+                    // Shifting by 64 bits or more, or by a negative number, is undefined in C,
+                    // and so is shifting a negative number left
+                    format!("((long long) ((unsigned long long) {:} << ({:} & 63)))", lh, rh)
+                } else if op == parser::Operator::ShiftRight {
+                    format!("({:} >> ({:} & 63))", lh, rh)
+                } else {
+                    format!("({:} {:} {:})", lh, get_c_name(op), rh)
                 }
-                out.push_str(")");
-                out
+            },
+            Expression::Call { function, ref arguments, span } => {
+                self.compile_call(function, arguments.iter().map(|arg| &**arg).collect(), span)
+            },
+            Expression::UnaryOp { operator: op, expression: ref exp, .. } if self.type_of(expr) == Some(Type::Real) => {
+                format!("({:}{:})", get_c_name(op), self.compile_real(exp))
             },
             Expression::UnaryOp {
                 operator: op,
-                expression: exp,
+                expression: ref exp,
+                span,
             } => {
-                let exp = unwrap_rc!(exp);
-                format!("({:}{:})",
-                         get_c_name(op),
-                         self.compile_expression(exp)
-                       )
+                let exp = self.compile_expression(exp);
+                if self.options.bignum {
+                    compile_bignum_unary_op(op, &exp)
+                } else if (op == parser::Operator::Negate || op == parser::Operator::Sub) && self.checks_overflow() {
+                    self.checked("haumea_neg", &[&exp], &ast::expression_to_source(expr), span)
+                } else {
+                    format!("({:}{:})", get_c_name(op), exp)
+                }
+            }
+        }
+    }
+    
+    /// Returns true if arithmetic is checked for overflow, which only
+    /// machine integers can do
+    fn checks_overflow(&self) -> bool {
+        self.options.checked_arithmetic && !self.options.bignum
+    }
+
+    /// Compiles a call to the runtime function `check` on `operands`, which
+    /// stops the program with an error naming `code` if it overflows
+    fn checked(&self, check: &str, operands: &[&str], code: &str, span: parser::Span) -> String {
+        format!("{:}({:}, \"{:}\", \"{:}\", {:})",
+                check, operands.join(", "), self.function, c_escape(code), span.start.line)
+    }
+
+    /// Compiles a call to `function` with `arguments`
+    ///
+    /// C can't tell how many arguments a variadic function was given, so variadic
+    /// builtins are passed the number of arguments before them. Located builtins
+    /// are passed the function, code and line of the call after them.
+    ///
+    /// A builtin `display` of Text is compiled to `haumea_display_values`, which
+    /// is passed whether each argument is text or a number.
+    fn compile_call(&self, function: parser::Ident, arguments: Vec<&parser::Expression>, span: parser::Span) -> String {
+        if let Some(external) = self.externals.get(&function) {
+            return self.compile_external_call(function, external, &arguments);
+        }
+        let mut args: Vec<String> = arguments.iter().enumerate()
+            .map(|(index, arg)| self.compile_as(arg, self.types.parameter(function, index)))
+            .collect();
+        let displays = function == "display" || function == "display_no_newline";
+        if displays && self.variadic.contains(&function.as_str())
+            && arguments.iter().any(|arg| self.type_of(arg) == Some(Type::Text)) {
+            let kinds: String = arguments.iter()
+                .map(|arg| if self.type_of(arg) == Some(Type::Text) { 't' } else { 'n' })
+                .collect();
+            let newline = if function == "display" { 1 } else { 0 };
+            return format!("haumea_display_values({:}, \"{:}\", {:})", newline, kinds, args.join(", "));
+        }
+        if self.variadic.contains(&function.as_str()) {
+            args.insert(0, args.len().to_string());
+        }
+        if self.located.contains(&function.as_str()) {
+            args.push(format!("\"{:}\", \"{:}(...)\", {:}", self.function, function, span.start.line));
+        }
+        if self.defined.contains(&function) {
+            format!("{:}({:})", function_name(&function), args.join(", "))
+        } else {
+            format!("{:}({:})", function, args.join(", "))
+        }
+    }
+
+    /// Compiles a call to the external function `function` with `arguments`,
+    /// converting them to the C types it takes and what it returns back
+    ///
+    /// Reals are passed as doubles and Text as a `const char *`. A parameter
+    /// without a type takes a `long long`, unless a header declares the
+    /// function, in which case each argument is passed as its own C type and
+    /// C converts it to whatever the header says.
+    fn compile_external_call(&self, function: parser::Ident, external: &External,
+                             arguments: &[&parser::Expression]) -> String {
+        let to_long = |code: String| if self.options.bignum { format!("hm_to_long({:})", code) } else { code };
+        let args = arguments.iter().enumerate().map(|(index, arg)| {
+            let found = match self.types.parameter(function, index) {
+                None if external.from_header => self.type_of(arg),
+                found => found,
+            };
+            match found {
+                Some(Type::Real) => self.compile_real(arg),
+                Some(Type::Text) => format!("(const char *) (intptr_t) {:}", to_long(self.compile_expression(arg))),
+                _ => to_long(self.compile_expression(arg)),
             }
+        }).collect::<Vec<_>>();
+        let call = format!("{:}({:})", function, args.join(", "));
+        let value = match self.types.returns(function) {
+            _ if external.procedure => return call,
+            Some(Type::Real) => return call,
+            Some(Type::Text) => format!("((long long) (intptr_t) {:})", call),
+            _ if external.from_header => format!("((long long) {:})", call),
+            _ => call,
+        };
+        if self.options.bignum {
+            format!("hm_from_long({:})", value)
+        } else {
+            value
+        }
+    }
+
+    /// Compiles an expression used as the condition of an if or while
+    fn compile_condition(&self, expr: &parser::Expression) -> String {
+        use parser::Expression;
+        // Operators are already compiled with parentheses around them
+        let parenthesized = matches!(*expr, Expression::BinaryOp { .. } | Expression::UnaryOp { .. });
+        let cond = self.compile_expression(expr);
+        if self.options.bignum {
+            format!("(hm_truthy({:}))", cond)
+        } else if parenthesized {
+            cond
+        } else {
+            format!("({:})", cond)
         }
     }
     
+    /// Returns the type of `expr` in the function being compiled, if it is known
+    fn type_of(&self, expr: &parser::Expression) -> Option<Type> {
+        self.types.expression(self.function, expr)
+    }
+
+    /// Returns the C type used for Haumea integers
+    fn int_type(&self) -> &'static str {
+        if self.options.bignum { "haumea_int" } else { "long long" }
+    }
+
+    /// Returns the C type of a value of the type `found`, which is a double for a Real
+    fn c_type(&self, found: Option<Type>) -> &'static str {
+        if found == Some(Type::Real) { "double" } else { self.int_type() }
+    }
+    
     /// Returns a unique name
     fn get_unique_name(&mut self) -> String {
-        self._name_number += 1;
-        format!("__HAUMEA_TEMP_{:}", self._name_number)
+        self.names.fresh()
     }
 }
 
+/// Something left to do by `compile_statement`
+enum Task<'s> {
+    /// Compile a statement
+    Statement(&'s parser::Statement),
+    /// Write a line
+    Line(&'static str),
+    Indent,
+    Dedent,
+    /// Start a block of variables
+    Enter,
+    /// Finish the block of variables started last
+    Leave,
+    /// Finish the loop started last, writing the label after it if it needs one
+    EndLoop,
+}
+
+/// Compiles the body of an if, else or loop, one level further in, after what is already in `then`
+fn compile_body<'s>(body: &'s parser::Statement, then: &mut Vec<Task<'s>>) {
+    then.extend([Task::Indent, Task::Enter]);
+    // A declaration isn't a statement in C, so it needs a block of its own
+    if let parser::Statement::Var(..) = *body {
+        then.extend([Task::Line("{"), Task::Indent, Task::Enter, Task::Statement(body), Task::Leave, Task::Dedent,
+                     Task::Line("}")]);
+    } else {
+        then.push(Task::Statement(body));
+    }
+    then.extend([Task::Leave, Task::Dedent]);
+}
+
 // Utility functions
 
-/// Replicates a &str t times
-fn replicate(s: &str, t: i32) -> String {
-    if t == 0 {
-        "".to_string()
+/// Returns true if `func` returns a call to itself anywhere
+///
+/// A call that is returned is the last thing the function does, so the
+/// function can go back to its start instead of calling itself.
+fn returns_call_to_itself(func: &parser::Function) -> bool {
+    struct Finder(parser::Ident, bool);
+    impl ast::Visitor for Finder {
+        fn visit_statement(&mut self, statement: &parser::Statement) {
+            if let parser::Statement::Return(parser::Expression::Call { function, .. }, _) = *statement {
+                self.1 |= function == self.0;
+            }
+        }
+    }
+    let mut finder = Finder(func.name, false);
+    ast::walk_function(&mut finder, func);
+    finder.1
+}
+
+/// Returns the C type an external function without a header takes or returns for the type `found`
+fn external_type(found: Option<Type>) -> &'static str {
+    match found {
+        Some(Type::Real) => "double",
+        Some(Type::Text) => "const char *",
+        _ => "long long",
+    }
+}
+
+/// Returns the start of the C definition of `func`, other than `main`, up to
+/// its body, with `params` between its parentheses
+fn function_header(func: &parser::Function, return_type: &str, params: &str) -> String {
+    let return_type = if flow::is_procedure(func) { "void" } else { return_type };
+    let storage = if func.visibility == parser::Visibility::Private { "static " } else { "" };
+    format!("{:}{:} {:}({:})", storage, return_type, function_name(&func.name), params)
+}
+
+/// Returns the flags the C compiler needs to link the libraries named by `directives`
+///
+/// # Examples
+/// ```
+/// # use haumea::parser::Directive;
+/// # use haumea::codegen::c::linker_flags;
+/// let directives = vec![Directive::Link("m".to_string()), Directive::Include("math.h".to_string())];
+/// assert_eq!(linker_flags(&directives), vec!["-lm".to_string()]);
+/// ```
+pub fn linker_flags(directives: &[parser::Directive]) -> Vec<String> {
+    directives.iter().filter_map(|directive| match *directive {
+        parser::Directive::Link(ref library) => Some(format!("-l{}", library)),
+        parser::Directive::Include(_) | parser::Directive::Embed { .. } => None,
+    }).collect()
+}
+
+/// Returns the C name of the function the program defines called `name`,
+/// which is also what a library exports a public function as
+///
+/// # Examples
+/// ```
+/// # use haumea::codegen::c::function_name;
+/// assert_eq!(function_name("double"), "hm_fn_double");
+/// ```
+pub fn function_name(name: &str) -> String {
+    format!("{}{}", FUNCTION_PREFIX, name)
+}
+
+/// Returns the C definitions of the functions that check values while the program runs
+fn get_checks_definition(bignum: bool) -> &'static str {
+    if bignum {
+        "
+static haumea_int haumea_divisor(haumea_int n, const char *function, const char *code, int line) {
+    if (n->sign == 0) {
+        haumea_fail(\"division by zero\", function, code, line);
+    }
+    return n;
+}
+
+static haumea_int haumea_step(haumea_int n, const char *function, const char *code, int line) {
+    if (n->sign == 0) {
+        haumea_fail(\"a loop that counts by zero\", function, code, line);
+    }
+    return n;
+}
+"
+    } else {
+        "
+static long long haumea_divisor(long long n, const char *function, const char *code, int line) {
+    if (n == 0) {
+        haumea_fail(\"division by zero\", function, code, line);
+    }
+    return n;
+}
+
+/* The smallest long long divided by -1 is one more than the largest, which crashes the program */
+static long long haumea_quotient(long long a, long long b, const char *function, const char *code, int line) {
+    haumea_divisor(b, function, code, line);
+    if (a == LLONG_MIN && b == -1) {
+        haumea_fail(\"a number too large for a machine integer\", function, code, line);
+    }
+    return a / b;
+}
+
+static long long haumea_remainder(long long a, long long b, const char *function, const char *code, int line) {
+    haumea_divisor(b, function, code, line);
+    if (a == LLONG_MIN && b == -1) {
+        haumea_fail(\"a number too large for a machine integer\", function, code, line);
+    }
+    return a % b;
+}
+
+static long long haumea_step(long long n, const char *function, const char *code, int line) {
+    if (n == 0) {
+        haumea_fail(\"a loop that counts by zero\", function, code, line);
+    }
+    return n;
+}
+"
+    }
+}
+
+/// The builtins that work on lists, which need the list runtime
+const LIST_BUILTINS: &[&str] = &["push", "append", "length_of", "item_at", "set_item", "remove_last"];
+
+/// Returns true if `program` makes lists or calls a list builtin, so it needs the list runtime
+fn uses_lists(program: &parser::Program) -> bool {
+    struct Finder(bool);
+    impl ast::Visitor for Finder {
+        fn visit_expression(&mut self, expression: &parser::Expression) {
+            match *expression {
+                parser::Expression::List(..) | parser::Expression::Index { .. } | parser::Expression::Length(..) => {
+                    self.0 = true;
+                },
+                parser::Expression::Call { ref function, .. } if LIST_BUILTINS.contains(&function.as_str()) => {
+                    self.0 = true;
+                },
+                _ => (),
+            }
+        }
+
+        fn visit_statement(&mut self, statement: &parser::Statement) {
+            if let parser::Statement::Call { ref function, .. } = *statement {
+                self.0 |= LIST_BUILTINS.contains(&function.as_str());
+            }
+        }
+    }
+    let mut finder = Finder(false);
+    ast::walk_program(&mut finder, program);
+    finder.0
+}
+
+/// Returns true if `program` has decimal numbers, declares Reals or calls `display_real`, so it needs the real runtime
+fn uses_reals(program: &parser::Program) -> bool {
+    let declared = program.iter().any(|func| {
+        func.types.returns == Some(Type::Real) || func.types.parameters.contains(&Some(Type::Real))
+    });
+    struct Finder(bool);
+    impl ast::Visitor for Finder {
+        fn visit_expression(&mut self, expression: &parser::Expression) {
+            match *expression {
+                parser::Expression::Float(..) => self.0 = true,
+                parser::Expression::Call { ref function, .. } if *function == "display_real" => self.0 = true,
+                _ => (),
+            }
+        }
+
+        fn visit_statement(&mut self, statement: &parser::Statement) {
+            if let parser::Statement::Call { ref function, .. } = *statement {
+                self.0 |= *function == "display_real";
+            }
+        }
+    }
+    let mut finder = Finder(declared);
+    ast::walk_program(&mut finder, program);
+    finder.0
+}
+
+/// The builtins that make or read text, which need the text runtime
+const TEXT_BUILTINS: &[&str] = &["join", "character_code", "character_from_code", "number_from_text", "text_from_number"];
+
+/// Returns true if `program` has text or calls a text builtin, so it needs the text runtime
+fn uses_text(program: &parser::Program) -> bool {
+    struct Finder(bool);
+    impl ast::Visitor for Finder {
+        fn visit_expression(&mut self, expression: &parser::Expression) {
+            match *expression {
+                parser::Expression::Str(..) => self.0 = true,
+                parser::Expression::Call { ref function, .. } if TEXT_BUILTINS.contains(&function.as_str()) => {
+                    self.0 = true;
+                },
+                _ => (),
+            }
+        }
+
+        fn visit_statement(&mut self, statement: &parser::Statement) {
+            if let parser::Statement::Call { ref function, .. } = *statement {
+                self.0 |= TEXT_BUILTINS.contains(&function.as_str());
+            }
+        }
+    }
+    let mut finder = Finder(false);
+    ast::walk_program(&mut finder, program);
+    finder.0
+}
+
+/// Returns the names of the functions `program` calls
+fn called_functions(program: &parser::Program) -> HashSet<parser::Ident> {
+    struct Finder(HashSet<parser::Ident>);
+    impl ast::Visitor for Finder {
+        fn visit_expression(&mut self, expression: &parser::Expression) {
+            if let parser::Expression::Call { function, .. } = *expression {
+                self.0.insert(function);
+            }
+        }
+
+        fn visit_statement(&mut self, statement: &parser::Statement) {
+            if let parser::Statement::Call { function, .. } = *statement {
+                self.0.insert(function);
+            }
+        }
+    }
+    let mut finder = Finder(HashSet::new());
+    ast::walk_program(&mut finder, program);
+    finder.0
+}
+
+/// Returns true if `program` calls any of the builtins called `names`
+fn calls_builtin(program: &parser::Program, names: &[&str]) -> bool {
+    struct Finder<'a>(&'a [&'a str], bool);
+    impl<'a> ast::Visitor for Finder<'a> {
+        fn visit_expression(&mut self, expression: &parser::Expression) {
+            if let parser::Expression::Call { ref function, .. } = *expression {
+                self.1 |= self.0.contains(&function.as_str());
+            }
+        }
+
+        fn visit_statement(&mut self, statement: &parser::Statement) {
+            if let parser::Statement::Call { ref function, .. } = *statement {
+                self.1 |= self.0.contains(&function.as_str());
+            }
+        }
+    }
+    let mut finder = Finder(names, false);
+    ast::walk_program(&mut finder, program);
+    finder.1
+}
+
+/// Numbers each piece of text in `program`, in the order they are first written
+fn texts(program: &parser::Program) -> HashMap<String, usize> {
+    struct Finder(HashMap<String, usize>);
+    impl ast::Visitor for Finder {
+        fn visit_expression(&mut self, expression: &parser::Expression) {
+            if let parser::Expression::Str(ref text, _) = *expression {
+                let number = self.0.len();
+                self.0.entry(text.clone()).or_insert(number);
+            }
+        }
+    }
+    let mut finder = Finder(HashMap::new());
+    ast::walk_program(&mut finder, program);
+    finder.0
+}
+
+/// Escapes `text` to be written in a C string literal
+fn c_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+/// Returns the C definition of `haumea_display_values`, which displays numbers
+/// and text, with a `t` in `kinds` for each argument that is text and an `n`
+/// for each number
+fn get_display_values_definition(bignum: bool) -> &'static str {
+    if bignum {
+        "
+#include <stdarg.h>
+static haumea_int haumea_display_values(int newline, const char *kinds, ...) {
+    va_list values;
+    va_start(values, kinds);
+    for (int i = 0; kinds[i] != '\\0'; i++) {
+        haumea_int value = va_arg(values, haumea_int);
+        printf(i == 0 ? \"\" : \" \");
+        if (kinds[i] == 't') {
+            haumea_text_print(hm_to_long(value));
+        } else {
+            hm_print(value);
+        }
+    }
+    va_end(values);
+    printf(newline ? \"\\n\" : \"\");
+    return hm_from_long(0ll);
+}
+"
     } else {
-        replicate(s, t-1) + s
+        "
+#include <stdarg.h>
+static long long haumea_display_values(int newline, const char *kinds, ...) {
+    va_list values;
+    va_start(values, kinds);
+    for (int i = 0; kinds[i] != '\\0'; i++) {
+        long long value = va_arg(values, long long);
+        printf(i == 0 ? \"\" : \" \");
+        if (kinds[i] == 't') {
+            haumea_text_print(value);
+        } else {
+            printf(\"%lld\", value);
+        }
+    }
+    va_end(values);
+    printf(newline ? \"\\n\" : \"\");
+    return 0;
+}
+"
+    }
+}
+
+/// Returns the C definition of a builtin function, or None if the backend has no definition of it
+fn get_builtin_definition(name: &str, bignum: bool) -> Option<&'static str> {
+    Some(match name {
+        "display" if bignum => "
+#include <stdarg.h>
+static haumea_int display(int count, ...) {
+    va_list numbers;
+    va_start(numbers, count);
+    for (int i = 0; i < count; i++) {
+        printf(i == 0 ? \"\" : \" \");
+        hm_print(va_arg(numbers, haumea_int));
+    }
+    va_end(numbers);
+    printf(\"\\n\");
+    return hm_from_long(0ll);
+}
+",
+        "display_no_newline" if bignum => "
+#include <stdarg.h>
+static haumea_int display_no_newline(int count, ...) {
+    va_list numbers;
+    va_start(numbers, count);
+    for (int i = 0; i < count; i++) {
+        printf(i == 0 ? \"\" : \" \");
+        hm_print(va_arg(numbers, haumea_int));
+    }
+    va_end(numbers);
+    return hm_from_long(0ll);
+}
+",
+        "read" if bignum => "
+static haumea_int read() {
+    char digits[1024];
+    printf(\"Enter an integer: \");
+    if (scanf(\"%1023s\", digits) != 1) {
+        return hm_from_long(0ll);
+    }
+    return hm_parse(digits);
+}
+",
+        "display_text" if bignum => "
+static haumea_int display_text(haumea_int text) {
+    printf(\"%s\\n\", (const char *) (intptr_t) hm_to_long(text));
+    return hm_from_long(0ll);
+}
+",
+        "random_between" if bignum => "
+static haumea_int random_between(haumea_int low, haumea_int high) {
+    return hm_from_long(haumea_random_between(hm_to_long(low), hm_to_long(high)));
+}
+",
+        "random_between" => "
+static long long random_between(long long low, long long high) {
+    return haumea_random_between(low, high);
+}
+",
+        "seed_random" if bignum => "
+static haumea_int seed_random(haumea_int seed) {
+    haumea_seed_random(hm_to_long(seed));
+    return hm_from_long(0ll);
+}
+",
+        "seed_random" => "
+static long long seed_random(long long seed) {
+    haumea_seed_random(seed);
+    return 0;
+}
+",
+        "push" if bignum => "
+static haumea_int push(haumea_int list, haumea_int value) {
+    haumea_list_push(hm_to_long(list), value);
+    return hm_from_long(0ll);
+}
+",
+        "append" if bignum => "
+static haumea_int append(haumea_int list, haumea_int value) {
+    haumea_list_push(hm_to_long(list), value);
+    return hm_from_long(0ll);
+}
+",
+        "length_of" if bignum => "
+static haumea_int length_of(haumea_int list) {
+    return hm_from_long(haumea_list_length(hm_to_long(list)));
+}
+",
+        "item_at" if bignum => "
+static haumea_int item_at(haumea_int list, haumea_int index, const char *function, const char *code, int line) {
+    return haumea_list_get(hm_to_long(list), hm_to_long(index), function, code, line);
+}
+",
+        "set_item" if bignum => "
+static haumea_int set_item(haumea_int list, haumea_int index, haumea_int value,
+                           const char *function, const char *code, int line) {
+    haumea_list_set(hm_to_long(list), hm_to_long(index), value, function, code, line);
+    return hm_from_long(0ll);
+}
+",
+        "remove_last" if bignum => "
+static haumea_int remove_last(haumea_int list, const char *function, const char *code, int line) {
+    return haumea_list_pop(hm_to_long(list), function, code, line);
+}
+",
+        "run_command" if bignum => "
+#include <stdlib.h>
+#include <sys/wait.h>
+static haumea_int run_command(haumea_int command, const char *function, const char *code, int line) {
+    int status;
+    fflush(stdout);
+    status = system((const char *) (intptr_t) hm_to_long(command));
+    if (status == -1) {
+        return hm_from_long(-1ll);
+    }
+    return hm_from_long(WIFEXITED(status) ? WEXITSTATUS(status) : 128 + WTERMSIG(status));
+}
+",
+        "run_command" => "
+#include <stdlib.h>
+#include <sys/wait.h>
+/* Like a shell, a command stopped by a signal has 128 plus the signal as its exit status */
+static long long run_command(long long command, const char *function, const char *code, int line) {
+    int status;
+    fflush(stdout);
+    status = system((const char *) (intptr_t) command);
+    if (status == -1) {
+        return -1;
+    }
+    return WIFEXITED(status) ? WEXITSTATUS(status) : 128 + WTERMSIG(status);
+}
+",
+        "character_code" if bignum => "
+static haumea_int character_code(haumea_int text, const char *function, const char *code, int line) {
+    return hm_from_long(haumea_character_code(hm_to_long(text), function, code, line));
+}
+",
+        "character_from_code" if bignum => "
+static haumea_int character_from_code(haumea_int character, const char *function, const char *code, int line) {
+    /* Numbers too large for a long long aren't characters either */
+    long long n = hm_cmp_abs(character, hm_from_long(0x10ffffll)) > 0 ? -1ll : hm_to_long(character);
+    return hm_from_long(haumea_character_from_code(n, function, code, line));
+}
+",
+        "number_from_text" if bignum => "
+static haumea_int number_from_text(haumea_int text, const char *function, const char *code, int line) {
+    haumea_check_number_text(hm_to_long(text), function, code, line);
+    return hm_parse((const char *) (intptr_t) hm_to_long(text));
+}
+",
+        "text_from_number" if bignum => "
+static haumea_int text_from_number(haumea_int n) {
+    char *digits = hm_to_string(n);
+    long long text = haumea_text_new(digits, strlen(digits));
+    free(digits);
+    return hm_from_long(text);
+}
+",
+        "character_code" => "
+static long long character_code(long long text, const char *function, const char *code, int line) {
+    return haumea_character_code(text, function, code, line);
+}
+",
+        "character_from_code" => "
+static long long character_from_code(long long character, const char *function, const char *code, int line) {
+    return haumea_character_from_code(character, function, code, line);
+}
+",
+        "number_from_text" => "
+static long long number_from_text(long long text, const char *function, const char *code, int line) {
+    return haumea_number_from_text(text, function, code, line);
+}
+",
+        "text_from_number" => "
+static long long text_from_number(long long n) {
+    return haumea_text_from_number(n);
+}
+",
+        "join" if bignum => "
+static haumea_int join(int count, ...) {
+    long long *texts = malloc(count * sizeof(long long));
+    va_list values;
+    long long joined;
+    va_start(values, count);
+    for (int i = 0; i < count; i++) {
+        texts[i] = hm_to_long(va_arg(values, haumea_int));
+    }
+    va_end(values);
+    joined = haumea_text_join(count, texts);
+    free(texts);
+    return hm_from_long(joined);
+}
+",
+        "join" => "
+static long long join(int count, ...) {
+    long long *texts = malloc(count * sizeof(long long));
+    va_list values;
+    long long joined;
+    va_start(values, count);
+    for (int i = 0; i < count; i++) {
+        texts[i] = va_arg(values, long long);
+    }
+    va_end(values);
+    joined = haumea_text_join(count, texts);
+    free(texts);
+    return joined;
+}
+",
+        "push" => "
+static long long push(long long list, long long value) {
+    haumea_list_push(list, value);
+    return 0;
+}
+",
+        "append" => "
+static long long append(long long list, long long value) {
+    haumea_list_push(list, value);
+    return 0;
+}
+",
+        "length_of" => "
+static long long length_of(long long list) {
+    return haumea_list_length(list);
+}
+",
+        "item_at" => "
+static long long item_at(long long list, long long index, const char *function, const char *code, int line) {
+    return haumea_list_get(list, index, function, code, line);
+}
+",
+        "set_item" => "
+static long long set_item(long long list, long long index, long long value, const char *function, const char *code, int line) {
+    haumea_list_set(list, index, value, function, code, line);
+    return 0;
+}
+",
+        "remove_last" => "
+static long long remove_last(long long list, const char *function, const char *code, int line) {
+    return haumea_list_pop(list, function, code, line);
+}
+",
+        "display" => "
+#include <stdarg.h>
+static long long display(int count, ...) {
+    va_list numbers;
+    va_start(numbers, count);
+    for (int i = 0; i < count; i++) {
+        printf(i == 0 ? \"%lld\" : \" %lld\", va_arg(numbers, long long));
+    }
+    va_end(numbers);
+    printf(\"\\n\");
+    return 0;
+}
+",
+        "display_no_newline" => "
+#include <stdarg.h>
+static long long display_no_newline(int count, ...) {
+    va_list numbers;
+    va_start(numbers, count);
+    for (int i = 0; i < count; i++) {
+        printf(i == 0 ? \"%lld\" : \" %lld\", va_arg(numbers, long long));
+    }
+    va_end(numbers);
+    return 0;
+}
+",
+        "display_real" if bignum => "
+static haumea_int display_real(double n) {
+    haumea_print_real(n);
+    printf(\"\\n\");
+    return hm_from_long(0ll);
+}
+",
+        "display_real" => "
+static long long display_real(double n) {
+    haumea_print_real(n);
+    printf(\"\\n\");
+    return 0;
+}
+",
+        "display_text" => "
+static long long display_text(long long text) {
+    printf(\"%s\\n\", (const char *) (intptr_t) text);
+    return 0;
+}
+",
+        "read" => "
+static long long read() {
+    printf(\"Enter an integer: \");
+    long long n;
+    scanf(\"%lld\", &n);
+    return n;
+}
+",
+        _ => return None,
+    })
+}
+
+/// Returns the runtime function that does `op` on machine integers, checking for overflow, if it can overflow
+fn overflow_check(op: parser::Operator) -> Option<&'static str> {
+    use parser::Operator::*;
+    match op {
+        Add => Some("haumea_add"),
+        Sub => Some("haumea_sub"),
+        Mul => Some("haumea_mul"),
+        Div => Some("haumea_div"),
+        Modulo => Some("haumea_mod"),
+        _ => None,
+    }
+}
+
+/// Compiles a binary operation on arbitrary-precision integers
+fn compile_bignum_binary_op(op: parser::Operator, lh: &str, rh: &str) -> String {
+    use parser::Operator::*;
+    let function = match op {
+        Add => "hm_add",
+        Sub => "hm_sub",
+        Mul => "hm_mul",
+        Div => "hm_div",
+        Modulo => "hm_mod",
+        BinaryAnd => "hm_bitand",
+        BinaryOr => "hm_bitor",
+        BinaryXor => "hm_bitxor",
+        ShiftLeft => "hm_shl",
+        ShiftRight => "hm_shr",
+        Equals | NotEquals | Gt | Lt | Gte | Lte => {
+            return format!("hm_from_long(hm_cmp({:}, {:}) {:} 0)", lh, rh, get_c_name(op))
+        },
+        LogicalAnd | LogicalOr => {
+            return format!("hm_from_long(hm_truthy({:}) {:} hm_truthy({:}))", lh, get_c_name(op), rh)
+        },
+        Negate | LogicalNot | BinaryNot => panic!("`{}` is not a binary operator!", op),
+    };
+    format!("{:}({:}, {:})", function, lh, rh)
+}
+
+/// Compiles a unary operation on arbitrary-precision integers
+fn compile_bignum_unary_op(op: parser::Operator, exp: &str) -> String {
+    use parser::Operator::*;
+    match op {
+        Sub | Negate => format!("hm_neg({:})", exp),
+        LogicalNot => format!("hm_from_long(!hm_truthy({:}))", exp),
+        BinaryNot => format!("hm_bitnot({:})", exp),
+        _ => panic!("`{}` is not a unary operator!", op),
     }
 }
 
@@ -316,6 +1663,9 @@ fn get_c_name(op: parser::Operator) -> &'static str {
         BinaryAnd => "&",
         BinaryOr => "|",
         BinaryNot => "~",
+        BinaryXor => "^",
+        ShiftLeft => "<<",
+        ShiftRight => ">>",
         Modulo => "%",
     }
 }
@@ -0,0 +1,14 @@
+/* Haumea runtime checks */
+/* Used when compiling with --runtime-checks, to stop the program with an */
+/* error that names the Haumea code at fault, instead of crashing. */
+#include <limits.h>
+#include <stdlib.h>
+
+static void haumea_fail(const char *error, const char *function, const char *code, int line) {
+    if (line > 0) {
+        fprintf(stderr, "Error: %s in %s, at `%s` on line %d\n", error, function, code, line);
+    } else {
+        fprintf(stderr, "Error: %s in %s, at `%s`\n", error, function, code);
+    }
+    exit(1);
+}
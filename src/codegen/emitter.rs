@@ -0,0 +1,108 @@
+//! emitter.rs
+//! Writes generated code one indented line at a time.
+use std::fmt::{self, Write};
+use std::mem;
+
+/// Collects the lines of generated code, indenting each line to the current level
+///
+/// An Emitter made with an empty indentation unit writes compact code, with
+/// every line starting at the left.
+///
+/// # Examples
+/// ```
+/// # use haumea::codegen::emitter::Emitter;
+/// let mut out = Emitter::new("    ");
+/// out.line("int main() {");
+/// out.indent();
+/// out.line(format_args!("return {};", 0));
+/// out.dedent();
+/// out.line("}");
+/// assert_eq!(out.finish(), "int main() {\n    return 0;\n}\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Emitter {
+    /// The text written before a line for each level of indentation
+    unit: &'static str,
+    /// The current level of indentation
+    level: usize,
+    /// The deepest level lines are indented to
+    deepest: usize,
+    out: String,
+}
+
+impl Emitter {
+    /// Constructs a new, empty Emitter that indents lines with `unit`
+    pub fn new(unit: &'static str) -> Emitter {
+        Emitter { unit, level: 0, deepest: usize::MAX, out: String::new() }
+    }
+
+    /// Constructs a new, empty Emitter with room for `capacity` bytes of code
+    pub fn with_capacity(unit: &'static str, capacity: usize) -> Emitter {
+        Emitter { unit, level: 0, deepest: usize::MAX, out: String::with_capacity(capacity) }
+    }
+
+    /// Indents lines no further than `deepest` levels, however deeply they are nested
+    ///
+    /// The code for a program nested thousands of levels deep, which is only
+    /// ever generated, would otherwise grow with the square of its depth. Only
+    /// code whose meaning doesn't depend on its indentation, like C, can be
+    /// flattened this way.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::codegen::emitter::Emitter;
+    /// let mut out = Emitter::new("  ").flatten_after(1);
+    /// out.indent();
+    /// out.indent();
+    /// out.line("{}");
+    /// assert_eq!(out.finish(), "  {}\n");
+    /// ```
+    pub fn flatten_after(mut self, deepest: usize) -> Emitter {
+        self.deepest = deepest;
+        self
+    }
+
+    /// Makes room for at least `additional` more bytes of code
+    pub fn reserve(&mut self, additional: usize) {
+        self.out.reserve(additional);
+    }
+
+    /// Indents the lines written after this one level further
+    pub fn indent(&mut self) {
+        self.level += 1;
+    }
+
+    /// Indents the lines written after this one level less
+    ///
+    /// Panics if the lines aren't indented.
+    pub fn dedent(&mut self) {
+        assert!(self.level > 0, "Dedented past the left margin!");
+        self.level -= 1;
+    }
+
+    /// Writes `text` as a line at the current indentation
+    ///
+    /// An empty line is written without indentation.
+    pub fn line<T: fmt::Display>(&mut self, text: T) {
+        let start = self.out.len();
+        for _ in 0..self.level.min(self.deepest) {
+            self.out.push_str(self.unit);
+        }
+        let indented = self.out.len();
+        write!(self.out, "{}", text).expect("Writing to a String failed!");
+        if self.out.len() == indented {
+            self.out.truncate(start);
+        }
+        self.out.push('\n');
+    }
+
+    /// Writes `text` as it is, without indenting it or ending the line
+    pub fn text(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    /// Returns the code written so far, leaving the Emitter empty
+    pub fn finish(&mut self) -> String {
+        mem::take(&mut self.out)
+    }
+}
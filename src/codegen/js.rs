@@ -0,0 +1,600 @@
+//! js.rs
+//! The JavaScript code generator for the haumea language.
+//!
+//! The compiled program is a self-contained JavaScript module that exports
+//! `run(input, command, output)`, which runs `main` and returns its exit
+//! status. `display` prints with `console.log`, or gives `output` the text if
+//! it is given, and `read` calls `input` for each number.
+//! Integers are BigInts, so `Options::bignum` changes nothing.
+use std::mem;
+use std::sync::Arc;
+use parser::{self, Type};
+use codegen::{self, CodegenError};
+use builtins;
+use validate;
+use typecheck::Types;
+use ast;
+use flow;
+use names::UniqueNames;
+use codegen::emitter::Emitter;
+use codegen::scopes::Scopes;
+
+/// The JavaScript runtime every compiled program starts with
+const RUNTIME: &str = include_str!("runtime.js");
+
+/// About how many bytes of JavaScript are written for each node of the syntax tree
+const BYTES_PER_NODE: usize = 16;
+
+/// Names a Haumea program may use that mean something else in JavaScript
+const RESERVED: &[&str] = &[
+    "arguments", "await", "case", "catch", "class", "const", "debugger", "default", "delete", "enum",
+    "eval", "export", "extends", "finally", "function", "implements", "import", "instanceof",
+    "interface", "let", "new", "null", "package", "protected", "run", "static", "super", "switch",
+    "this", "throw", "try", "typeof", "undefined", "var", "void", "yield", "BigInt",
+];
+
+pub struct CodeGenerator<'a> {
+    prolog: &'a str,
+    epilog: &'a str,
+    options: codegen::Options,
+    directives: Vec<parser::Directive>,
+    /// The name of the function being compiled
+    function: parser::Ident,
+    names: UniqueNames,
+    /// The JavaScript names of the variables in scope
+    scopes: Scopes,
+    /// The located builtins the program calls, which are passed where they were called from last
+    located: Vec<&'static str>,
+    /// The types of values, to compile arithmetic on Reals
+    types: Types,
+    out: Emitter,
+}
+
+impl<'a> codegen::CodeGen for CodeGenerator<'a> {
+    /// Compile an Program created by `parser::parse` into a JavaScript module
+    fn compile(&mut self, program: &parser::Program) -> Result<String, CodegenError> {
+        validate::validate(program).map_err(CodegenError::Invalid)?;
+        self.scopes = Scopes::new(program, &self.directives, RESERVED);
+        self.types = Types::new(program);
+        let available = builtins::available_to(program);
+        self.located = available.iter().filter(|builtin| builtin.located).map(|builtin| builtin.name).collect();
+        let builtins = available.iter()
+            .map(|builtin| get_builtin_definition(builtin.name)
+                 .ok_or(CodegenError::UnknownBuiltin { backend: "js", name: builtin.name }))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.out.reserve(RUNTIME.len() + self.prolog.len() + self.epilog.len()
+                         + builtins.iter().map(|definition| definition.len()).sum::<usize>()
+                         + ast::node_count(program) * BYTES_PER_NODE);
+        self.out.text(RUNTIME);
+        self.compile_externals(program);
+        for definition in builtins {
+            self.out.text(definition);
+        }
+        self.out.text(self.prolog);
+        for func in program {
+            self.compile_function(func);
+        }
+        self.out.text(self.epilog);
+        Ok(self.out.finish())
+    }
+}
+
+impl<'a> Default for CodeGenerator<'a> {
+    fn default() -> CodeGenerator<'a> {
+        CodeGenerator::new()
+    }
+}
+
+impl<'a> CodeGenerator<'a> {
+    /// Constructs a new CodeGenerator
+    pub fn new() -> CodeGenerator<'a> {
+        CodeGenerator::with_options(codegen::Options::default())
+    }
+
+    /// Constructs a new CodeGenerator that uses the given Options
+    pub fn with_options(options: codegen::Options) -> CodeGenerator<'a> {
+        CodeGenerator::with_directives(options, vec![])
+    }
+
+    /// Constructs a new CodeGenerator that uses the given Options and build directives
+    pub fn with_directives(options: codegen::Options, directives: Vec<parser::Directive>) -> CodeGenerator<'a> {
+        CodeGenerator {
+            prolog: "
+/* Start compiled program */
+",
+            epilog: "
+/* End compiled program */
+
+/* Runs the program, calling `input` for each number it reads, and returns its exit status */
+/* `run_command` calls `command` with the command, which returns its exit status, if it is given */
+/* `display` gives `output` the text it displays, with a newline if it ends a line, if it is given */
+export function run(input = () => 0, command = null, output = null) {
+    haumea_input = input;
+    haumea_command = command;
+    haumea_output = output;
+    try {
+        return haumea_exit_code(main());
+    } finally {
+        haumea_flush();
+    }
+}
+",
+            options,
+            directives,
+            function: parser::Ident::from(""),
+            names: UniqueNames::new("__HAUMEA_TEMP_"),
+            scopes: Scopes::default(),
+            located: vec![],
+            types: Types::default(),
+            out: Emitter::new("    "),
+        }
+    }
+
+    /// Defines the embedded files, and a stub for each external function,
+    /// which can't be called because there is no C to link with
+    fn compile_externals(&mut self, program: &parser::Program) {
+        let directives = mem::take(&mut self.directives);
+        for directive in &directives {
+            if let parser::Directive::Embed { ref name, ref bytes, .. } = *directive {
+                self.compile_embed(name, bytes);
+            }
+        }
+        self.directives = directives;
+        for func in program.iter().filter(|func| func.external.is_some()) {
+            let params = func.signature.iter().flatten().map(|param| js_name(param)).collect::<Vec<_>>();
+            self.out.text(&format!("
+function {name:}({params:}) {{
+    throw new Error(\"the external function `{function:}` can't be called from JavaScript\");
+}}
+", name = js_name(&func.name), params = params.join(", "), function = func.name));
+        }
+    }
+
+    /// Defines the functions that read an embedded file's bytes
+    fn compile_embed(&mut self, name: &str, bytes: &[u8]) {
+        let mut array = String::new();
+        for (i, byte) in bytes.iter().enumerate() {
+            array.push_str(if i % 16 == 0 { "\n    " } else { " " });
+            array.push_str(&format!("{:},", byte));
+        }
+        self.out.text(&format!("
+const haumea_embed_{name:} = new Uint8Array([{array:}\n]);
+
+function {function:}(i) {{
+    return i >= 0n && i < BigInt(haumea_embed_{name:}.length) ? BigInt(haumea_embed_{name:}[Number(i)]) : -1n;
+}}
+
+function {function:}_size() {{
+    return BigInt(haumea_embed_{name:}.length);
+}}
+", name = name, function = js_name(name), array = array));
+    }
+
+    /// Compiles a Function
+    fn compile_function(&mut self, func: &parser::Function) {
+        if func.external.is_some() {
+            return;
+        }
+        self.function = func.name;
+        if self.options.deterministic {
+            self.names.restart(&func.name);
+        }
+        self.scopes.start_function();
+        let params = func.signature.iter().flatten().map(|param| self.scopes.declare(*param)).collect::<Vec<_>>();
+        // `main` is run by `run`, rather than being exported itself
+        let export = if func.visibility == parser::Visibility::Private || func.name == "main" { "" } else { "export " };
+        self.out.line("");
+        self.out.line(format_args!("{:}function {:}({:}) {{", export, js_name(&func.name), params.join(", ")));
+        self.out.indent();
+        self.compile_statement(&func.code);
+        // Procedures return undefined, since nothing uses what they return
+        if flow::falls_through(&func.code) && !flow::is_procedure(func) {
+            self.out.line("return 0n;");
+        }
+        self.out.dedent();
+        self.out.line("}");
+    }
+
+    /// Compiles a statement
+    fn compile_statement(&mut self, statement: &parser::Statement) {
+        use parser::Statement;
+
+        match *statement {
+            Statement::Return(ref exp, _) => {
+                let exp = self.compile_expression(exp);
+                self.out.line(format_args!("return {:};", exp));
+            },
+            Statement::Do(ref block, _) => {
+                self.out.line("{");
+                self.out.indent();
+                self.scopes.enter();
+                for sub_statement in block {
+                    self.compile_statement(sub_statement);
+                }
+                self.scopes.leave();
+                self.out.dedent();
+                self.out.line("}");
+            },
+            Statement::Call { function, ref arguments, span } => {
+                let call = self.compile_call(function, arguments.iter().collect(), span);
+                self.out.line(format_args!("{:};", call));
+            },
+            // Variables start at 0, rather than JavaScript's undefined
+            Statement::Var(ident, _) => {
+                let name = self.scopes.declare(ident);
+                self.out.line(format_args!("let {:} = 0n;", name));
+            },
+            Statement::Set(ident, ref expr, _) => {
+                let expr = self.compile_expression(expr);
+                self.out.line(format_args!("{:} = {:};", self.scopes.name(ident), expr));
+            },
+            Statement::Change(ident, ref expr, span) => {
+                let name = self.scopes.name(ident);
+                let exp = self.compile_expression(expr);
+                if self.options.bignum {
+                    self.out.line(format_args!("{:} += {:};", name, exp));
+                } else {
+                    let code = format!("change {} by {}", ident, ast::expression_to_source(expr));
+                    let sum = self.machine_integer(format!("{:} + {:}", name, exp), &code, span);
+                    self.out.line(format_args!("{:} = {:};", name, sum));
+                }
+            },
+            Statement::If { ref cond, ref if_clause, ref else_clause, .. } => {
+                let cond = self.compile_expression(cond);
+                self.out.line(format_args!("if ({:})", cond));
+                self.compile_body(if_clause);
+                if let Some(ref else_) = **else_clause {
+                    self.out.line("else");
+                    self.compile_body(else_);
+                }
+            },
+            Statement::Forever(ref block, label, _) => {
+                self.out.line(format_args!("{:}while (true)", loop_label(label)));
+                self.compile_body(block);
+            },
+            Statement::While { ref cond, ref body, label, .. } => {
+                let cond = self.compile_expression(cond);
+                self.out.line(format_args!("{:}while ({:})", loop_label(label), cond));
+                self.compile_body(body);
+            },
+            Statement::ForEach { ref ident, ref start, ref end, ref by, ref range_type, ref body, label, span } => {
+                let (comparitor, neg_comparitor) = match &range_type[..] {
+                    "to" => ("<", ">"),
+                    "through" => ("<=", ">="),
+                    _ => panic!("Invalid range type {:?}!", range_type),
+                };
+                let start_name = self.names.fresh();
+                let end_name = self.names.fresh();
+                let by_name = self.names.fresh();
+
+                let start_code = self.compile_expression(start);
+                self.out.line(format_args!("const {:} = {:};", start_name, start_code));
+                let end_code = self.compile_expression(end);
+                self.out.line(format_args!("const {:} = {:};", end_name, end_code));
+                let mut by_code = self.compile_expression(by);
+                if self.options.runtime_checks {
+                    let header = format!("for each {} in {} {} {} by {}", ident, ast::expression_to_source(start),
+                                         range_type, ast::expression_to_source(end), ast::expression_to_source(by));
+                    by_code = format!("haumea_step({:}, \"{:}\", \"{:}\", {:})",
+                                      by_code, self.function, js_escape(&header), span.start.line);
+                }
+                self.out.line(format_args!("const {:} = haumea_towards({:}, {:}, {:});", by_name, start_name, end_name, by_code));
+                // The loop's variable is only in scope in the loop
+                self.scopes.enter();
+                let ident = self.scopes.declare(*ident);
+                self.out.line(format_args!("{:}for (let {:} = {:}; ({:} <= {:} ? {:} {:} {:} : {:} {:} {:}); {:} += {:})",
+                                           loop_label(label), ident, start_name, start_name, end_name, ident, comparitor, end_name,
+                                           ident, neg_comparitor, end_name, ident, by_name));
+                self.compile_body(body);
+                self.scopes.leave();
+            },
+            // JavaScript has labeled loops of its own
+            Statement::Break(Some(label), _) => self.out.line(format_args!("break {:};", js_name(&label))),
+            Statement::Break(None, _) => self.out.line("break;"),
+            Statement::Continue(_) => self.out.line("continue;"),
+        }
+    }
+
+    /// Compiles the body of an if, else or loop, one level further in
+    fn compile_body(&mut self, body: &parser::Statement) {
+        self.out.indent();
+        self.scopes.enter();
+        match *body {
+            // A declaration can't be the body of an if or loop in JavaScript, so it needs a block of its own
+            parser::Statement::Var(_, span) => {
+                self.compile_statement(&parser::Statement::Do(vec![Arc::new(body.clone())], span));
+            },
+            _ => self.compile_statement(body),
+        }
+        self.scopes.leave();
+        self.out.dedent();
+    }
+
+    /// Compiles an expression
+    fn compile_expression(&self, expr: &parser::Expression) -> String {
+        use parser::Expression;
+
+        match *expr {
+            Expression::Integer(i, _) => format!("{:}n", i),
+            Expression::BigInteger(ref digits, _) => format!("{:}n", digits),
+            // Reals are plain JavaScript numbers
+            Expression::Float(n, _) => n.to_string(),
+            Expression::Ident(name, _) => self.scopes.name(name),
+            Expression::Boolean(value, _) => format!("{:}n", value as i64),
+            Expression::Str(ref text, _) => format!("\"{:}\"", js_escape(text)),
+            Expression::List(ref items, _) => {
+                let items = items.iter()
+                    .map(|item| self.compile_expression(item))
+                    .collect::<Vec<_>>();
+                format!("[{:}]", items.join(", "))
+            },
+            Expression::Index { ref list, ref index, span } => {
+                format!("haumea_list_get({:}, {:}, \"{:}\", \"{:}\", {:})",
+                        self.compile_expression(list), self.compile_expression(index), self.function,
+                        js_escape(&ast::expression_to_source(expr)), span.start.line)
+            },
+            Expression::Length(ref list, _) => format!("haumea_length({:})", self.compile_expression(list)),
+            // Reals are numbers, which divide like C doubles and don't wrap around
+            Expression::BinaryOp { operator: op, ref left, ref right, .. } if self.is_real(expr) => {
+                format!("({:} {:} {:})", self.compile_expression(left), get_js_name(op), self.compile_expression(right))
+            },
+            Expression::BinaryOp { operator: op, ref left, ref right, span } => {
+                use parser::Operator::*;
+                let lh = self.compile_expression(left);
+                let mut rh = self.compile_expression(right);
+                if self.options.runtime_checks && (op == Div || op == Modulo) {
+                    rh = format!("haumea_divisor({:}, \"{:}\", \"{:}\", {:})",
+                                 rh, self.function, js_escape(&ast::expression_to_source(expr)), span.start.line);
+                }
+                match op {
+                    // Comparisons give true or false, which Haumea keeps as 1 or 0
+                    Equals | NotEquals | Gt | Lt | Gte | Lte | LogicalAnd | LogicalOr => {
+                        format!("haumea_bool({:} {:} {:})", lh, get_js_name(op), rh)
+                    },
+                    Add | Sub | Mul | Div | Modulo => {
+                        let arithmetic = format!("{:} {:} {:}", lh, get_js_name(op), rh);
+                        self.machine_integer(arithmetic, &ast::expression_to_source(expr), span)
+                    },
+                    // Bignums shift by the whole count, which BigInts shift the other way if it is negative
+                    ShiftLeft | ShiftRight if self.options.bignum => format!("({:} {:} {:})", lh, get_js_name(op), rh),
+                    // Shifts work on 64 bit integers, using only the low 6 bits of the count
+                    ShiftLeft => format!("BigInt.asIntN(64, {:} << ({:} & 63n))", lh, rh),
+                    ShiftRight => format!("({:} >> ({:} & 63n))", lh, rh),
+                    _ => format!("({:} {:} {:})", lh, get_js_name(op), rh),
+                }
+            },
+            Expression::Call { function, ref arguments, span } => {
+                self.compile_call(function, arguments.iter().map(|arg| &**arg).collect(), span)
+            },
+            Expression::UnaryOp { operator: op, expression: ref exp, span } => {
+                let negative_literal = matches!(**exp, Expression::Integer(..));
+                let exp = self.compile_expression(exp);
+                match op {
+                    parser::Operator::LogicalNot => format!("haumea_bool(!{:})", exp),
+                    // A negative number written in the code always fits
+                    parser::Operator::Negate | parser::Operator::Sub if !negative_literal && !self.is_real(expr) => {
+                        self.machine_integer(format!("-{:}", exp), &ast::expression_to_source(expr), span)
+                    },
+                    _ => format!("({:}{:})", get_js_name(op), exp),
+                }
+            },
+        }
+    }
+
+    /// Returns true if `expr` is a Real in the function being compiled
+    fn is_real(&self, expr: &parser::Expression) -> bool {
+        self.types.expression(self.function, expr) == Some(Type::Real)
+    }
+
+    /// Compiles the result of arithmetic on machine integers, which wraps around
+    /// like in C, or stops the program with --checked-arithmetic, if it is too large
+    ///
+    /// `code` is the Haumea code of the arithmetic, at `span`. Bignums are never too large.
+    fn machine_integer(&self, result: String, code: &str, span: parser::Span) -> String {
+        if self.options.bignum {
+            format!("({:})", result)
+        } else if self.options.checked_arithmetic {
+            format!("haumea_checked({:}, \"{:}\", \"{:}\", {:})", result, self.function, js_escape(code), span.start.line)
+        } else {
+            format!("BigInt.asIntN(64, {:})", result)
+        }
+    }
+
+    /// Compiles a call to `function` with `arguments`, passing located builtins
+    /// the function, code and line of the call after them
+    fn compile_call(&self, function: parser::Ident, arguments: Vec<&parser::Expression>, span: parser::Span) -> String {
+        let mut args: Vec<String> = arguments.iter().map(|arg| self.compile_expression(arg)).collect();
+        if self.located.contains(&function.as_str()) {
+            args.push(format!("\"{:}\", \"{:}(...)\", {:}", self.function, function, span.start.line));
+        }
+        format!("{:}({:})", js_name(&function), args.join(", "))
+    }
+}
+
+// Utility functions
+
+/// Returns the name `name` has in JavaScript, which is changed if it is reserved
+///
+/// # Examples
+/// ```
+/// # use haumea::codegen::js::js_name;
+/// assert_eq!(js_name("total"), "total");
+/// assert_eq!(js_name("new"), "new_");
+/// ```
+pub fn js_name(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Returns the JavaScript label that goes before a loop labeled `label`, if it has one
+fn loop_label(label: Option<parser::Ident>) -> String {
+    label.map(|label| format!("{}: ", js_name(&label))).unwrap_or_default()
+}
+
+/// Escapes `text` to be written in a JavaScript string literal
+fn js_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+/// Returns the JavaScript definition of a builtin function, or None if the backend has no definition of it
+fn get_builtin_definition(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "display" => "
+function display(...numbers) {
+    haumea_print(numbers.join(' '), true);
+    return 0n;
+}
+",
+        "display_no_newline" => "
+function display_no_newline(...numbers) {
+    haumea_print(numbers.join(' '), false);
+    return 0n;
+}
+",
+        "display_real" => "
+function display_real(n) {
+    haumea_print(haumea_real_text(n), true);
+    return 0n;
+}
+",
+        "display_text" => "
+function display_text(text) {
+    haumea_print(text, true);
+    return 0n;
+}
+",
+        "read" => "
+function read() {
+    const n = Number(haumea_input());
+    return Number.isFinite(n) ? BigInt(Math.trunc(n)) : 0n;
+}
+",
+        "push" => "
+function push(list, value) {
+    list.push(value);
+    return 0n;
+}
+",
+        "append" => "
+function append(list, value) {
+    list.push(value);
+    return 0n;
+}
+",
+        "length_of" => "
+function length_of(list) {
+    return BigInt(list.length);
+}
+",
+        "item_at" => "
+function item_at(list, index, fn, code, line) {
+    return haumea_list_get(list, index, fn, code, line);
+}
+",
+        "set_item" => "
+function set_item(list, index, value, fn, code, line) {
+    haumea_list_get(list, index, fn, code, line);
+    list[Number(index)] = value;
+    return 0n;
+}
+",
+        "remove_last" => "
+function remove_last(list, fn, code, line) {
+    if (list.length === 0) {
+        haumea_fail(\"the list is empty\", fn, code, line);
+    }
+    return list.pop();
+}
+",
+        "join" => "
+function join(...texts) {
+    return texts.join('');
+}
+",
+        "run_command" => "
+function run_command(command, fn, code, line) {
+    if (haumea_command === null) {
+        haumea_fail(\"commands aren't allowed\", fn, code, line);
+    }
+    haumea_flush();
+    return BigInt(haumea_command(command));
+}
+",
+        "character_code" => "
+function character_code(text, fn, code, line) {
+    const characters = [...text];
+    if (characters.length !== 1) {
+        haumea_fail(\"the text is not one character\", fn, code, line);
+    }
+    return BigInt(characters[0].codePointAt(0));
+}
+",
+        "character_from_code" => "
+function character_from_code(n, fn, code, line) {
+    if (n < 1n || n > 0x10ffffn || (n >= 0xd800n && n <= 0xdfffn)) {
+        haumea_fail(\"the number is not the code of a character\", fn, code, line);
+    }
+    return String.fromCodePoint(Number(n));
+}
+",
+        "number_from_text" => "
+function number_from_text(text, fn, code, line) {
+    if (!/^-?[0-9]+$/.test(text)) {
+        haumea_fail(\"the text is not a whole number\", fn, code, line);
+    }
+    return BigInt(text);
+}
+",
+        "text_from_number" => "
+function text_from_number(n) {
+    return n.toString();
+}
+",
+        "random_between" => "
+function random_between(low, high) {
+    return haumea_random_between(low, high);
+}
+",
+        "seed_random" => "
+function seed_random(seed) {
+    haumea_random_state = BigInt.asUintN(64, seed);
+    return 0n;
+}
+",
+        _ => return None,
+    })
+}
+
+/// Returns the JavaScript name of an operator
+fn get_js_name(op: parser::Operator) -> &'static str {
+    use parser::Operator::*;
+    match op {
+        Add => "+",
+        Sub | Negate => "-",
+        Mul => "*",
+        Div => "/",
+        Equals => "===",
+        NotEquals => "!==",
+        Gt => ">",
+        Lt => "<",
+        Gte => ">=",
+        Lte => "<=",
+        LogicalAnd => "&&",
+        LogicalOr => "||",
+        LogicalNot => "!",
+        BinaryAnd => "&",
+        BinaryOr => "|",
+        BinaryNot => "~",
+        BinaryXor => "^",
+        ShiftLeft => "<<",
+        ShiftRight => ">>",
+        Modulo => "%",
+    }
+}
@@ -0,0 +1,76 @@
+
+/* Haumea list runtime */
+/* Lists live on the heap and are passed around as the address of their */
+/* haumea_list, like text is passed around as the address of a string, */
+/* so a list changed by `push` is changed everywhere it was passed. */
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct haumea_list {
+    long long length;
+    long long capacity;
+    haumea_value *items;
+} haumea_list;
+
+/* Makes a list of the `length` values after it */
+static long long haumea_list_new(long long length, ...) {
+    haumea_list *list = malloc(sizeof(haumea_list));
+    va_list values;
+    long long i;
+    list->length = length;
+    list->capacity = length > 4 ? length : 4;
+    list->items = malloc(list->capacity * sizeof(haumea_value));
+    va_start(values, length);
+    for (i = 0; i < length; i++) {
+        list->items[i] = va_arg(values, haumea_value);
+    }
+    va_end(values);
+    return (long long) (intptr_t) list;
+}
+
+static long long haumea_list_length(long long list) {
+    return ((haumea_list *) (intptr_t) list)->length;
+}
+
+/* Stops the program if there is no item at `index` in `items` */
+static void haumea_list_check(haumea_list *items, long long index, const char *function, const char *code, int line) {
+    if (index < 0 || index >= items->length) {
+        fprintf(stderr, "Error: the index %lld is outside a list of length %lld in %s, at `%s` on line %d\n",
+                index, items->length, function, code, line);
+        exit(1);
+    }
+}
+
+/* Returns the item at `index`, or stops the program if there isn't one */
+static haumea_value haumea_list_get(long long list, long long index, const char *function, const char *code, int line) {
+    haumea_list *items = (haumea_list *) (intptr_t) list;
+    haumea_list_check(items, index, function, code, line);
+    return items->items[index];
+}
+
+/* Replaces the item at `index`, or stops the program if there isn't one */
+static void haumea_list_set(long long list, long long index, haumea_value value, const char *function, const char *code, int line) {
+    haumea_list *items = (haumea_list *) (intptr_t) list;
+    haumea_list_check(items, index, function, code, line);
+    items->items[index] = value;
+}
+
+/* Removes the last item and returns it, or stops the program if the list is empty */
+static haumea_value haumea_list_pop(long long list, const char *function, const char *code, int line) {
+    haumea_list *items = (haumea_list *) (intptr_t) list;
+    if (items->length == 0) {
+        fprintf(stderr, "Error: the list is empty in %s, at `%s` on line %d\n", function, code, line);
+        exit(1);
+    }
+    return items->items[--items->length];
+}
+
+static void haumea_list_push(long long list, haumea_value value) {
+    haumea_list *items = (haumea_list *) (intptr_t) list;
+    if (items->length == items->capacity) {
+        items->capacity *= 2;
+        items->items = realloc(items->items, items->capacity * sizeof(haumea_value));
+    }
+    items->items[items->length++] = value;
+}
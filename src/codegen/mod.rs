@@ -1,7 +1,69 @@
 //! This module contains the different Haumea code generators.
 
+use std::fmt;
+use parser::{Program, Span};
+use validate::Invalid;
+use diagnostics::Diagnostic;
+
 pub mod c;
+pub mod js;
+pub mod python;
+pub mod web;
+pub mod scopes;
+pub mod emitter;
 
 pub trait CodeGen {
-    fn compile(&mut self) -> String;
-}
\ No newline at end of file
+    /// Compiles `program`, which is checked with `validate::validate` first
+    fn compile(&mut self, program: &Program) -> Result<String, CodegenError>;
+}
+
+/// Why a program couldn't be compiled, which is a bug in the compiler rather than in the program
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodegenError {
+    /// The syntax tree breaks the rules checked by `validate::validate`
+    Invalid(Vec<Invalid>),
+    /// The program calls a builtin that `backend`, like "c", has no definition for
+    UnknownBuiltin { backend: &'static str, name: &'static str },
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodegenError::Invalid(ref problems) => {
+                let problems: Vec<String> = problems.iter().map(Invalid::to_string).collect();
+                write!(f, "{}", problems.join(", "))
+            },
+            CodegenError::UnknownBuiltin { backend, name } => {
+                write!(f, "the {} backend has no definition for the builtin `{}`", backend, name)
+            },
+        }
+    }
+}
+
+impl CodegenError {
+    /// Returns the diagnostics that report the error
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match *self {
+            CodegenError::Invalid(ref problems) => problems.iter().map(Invalid::diagnostic).collect(),
+            CodegenError::UnknownBuiltin { .. } => vec![Diagnostic::error("E0505", self.to_string(), Span::default())],
+        }
+    }
+}
+
+/// Options that change the code a generator produces
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Use arbitrary-precision integers instead of machine integers
+    pub bignum: bool,
+    /// Check for mistakes like dividing by zero while the program runs
+    pub runtime_checks: bool,
+    /// Name temporaries after the function they are in and their place in it,
+    /// so each function compiles to the same C however the program around it changes
+    pub deterministic: bool,
+    /// Compile a function that returns a call to itself to a loop, which
+    /// doesn't use more of the stack each time around, instead of a call
+    pub tail_calls: bool,
+    /// Stop the program with an error when arithmetic on machine integers
+    /// overflows, instead of letting it wrap around
+    pub checked_arithmetic: bool,
+}
@@ -0,0 +1,51 @@
+
+/* Haumea overflow checks */
+/* Used when compiling with --checked-arithmetic, to stop the program with */
+/* an error that names the Haumea code at fault, instead of wrapping around. */
+#include <limits.h>
+
+static long long haumea_add(long long a, long long b, const char *function, const char *code, int line) {
+    long long result;
+    if (__builtin_add_overflow(a, b, &result)) {
+        haumea_fail("a number too large for a machine integer", function, code, line);
+    }
+    return result;
+}
+
+static long long haumea_sub(long long a, long long b, const char *function, const char *code, int line) {
+    long long result;
+    if (__builtin_sub_overflow(a, b, &result)) {
+        haumea_fail("a number too large for a machine integer", function, code, line);
+    }
+    return result;
+}
+
+static long long haumea_mul(long long a, long long b, const char *function, const char *code, int line) {
+    long long result;
+    if (__builtin_mul_overflow(a, b, &result)) {
+        haumea_fail("a number too large for a machine integer", function, code, line);
+    }
+    return result;
+}
+
+/* The smallest long long divided by -1 is one more than the largest */
+static long long haumea_div(long long a, long long b, const char *function, const char *code, int line) {
+    if (a == LLONG_MIN && b == -1) {
+        haumea_fail("a number too large for a machine integer", function, code, line);
+    }
+    return a / b;
+}
+
+static long long haumea_mod(long long a, long long b, const char *function, const char *code, int line) {
+    if (a == LLONG_MIN && b == -1) {
+        haumea_fail("a number too large for a machine integer", function, code, line);
+    }
+    return a % b;
+}
+
+static long long haumea_neg(long long n, const char *function, const char *code, int line) {
+    if (n == LLONG_MIN) {
+        haumea_fail("a number too large for a machine integer", function, code, line);
+    }
+    return -n;
+}
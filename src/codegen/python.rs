@@ -0,0 +1,811 @@
+//! python.rs
+//! The Python code generator for the haumea language.
+//!
+//! The compiled program is a Python 3 script that runs `main` and exits with
+//! its status, and is written to be read, so that a Haumea program can be
+//! shown next to the Python it means. Blocks are indented rather than
+//! bracketed, conditions are Python conditions, and `for each` loops are
+//! `for` loops. `display` prints with `print`, and `read` reads a line with
+//! `input`. Integers are Python ints, so `Options::bignum` changes nothing.
+//!
+//! Without `Options::runtime_checks`, a list is indexed with Python's own
+//! indexing, which counts negative indices from the end of the list.
+use std::mem;
+use parser::{self, Type};
+use codegen::{self, CodegenError};
+use builtins;
+use validate;
+use typecheck::Types;
+use ast;
+use flow;
+use names::UniqueNames;
+use codegen::emitter::Emitter;
+use codegen::scopes::Scopes;
+
+/// The Python runtime every compiled program starts with
+const RUNTIME: &str = include_str!("runtime.py");
+
+/// About how many bytes of Python are written for each node of the syntax tree
+const BYTES_PER_NODE: usize = 12;
+
+/// Names a Haumea program may use that mean something else in Python
+const RESERVED: &[&str] = &[
+    "and", "as", "assert", "async", "await", "bool", "break", "bytes", "class", "continue", "def", "del",
+    "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "input", "int",
+    "is", "lambda", "len", "list", "max", "min", "nonlocal", "not", "or", "pass", "print", "raise",
+    "range", "return", "str", "sys", "try", "while", "with", "yield", "False", "None", "True",
+];
+
+pub struct CodeGenerator<'a> {
+    prolog: &'a str,
+    epilog: &'a str,
+    options: codegen::Options,
+    directives: Vec<parser::Directive>,
+    /// The name of the function being compiled
+    function: parser::Ident,
+    names: UniqueNames,
+    /// The Python names of the variables in scope
+    scopes: Scopes,
+    /// The located builtins the program calls, which are passed where they were called from last
+    located: Vec<&'static str>,
+    /// The types of values, to compile arithmetic on Reals
+    types: Types,
+    /// For each loop the statement is in, innermost last, what `continue` has
+    /// to do before going round again, if anything
+    steps: Vec<Option<String>>,
+    /// The label of each loop the statement is in, innermost last
+    labels: Vec<Option<parser::Ident>>,
+    out: Emitter,
+}
+
+impl<'a> codegen::CodeGen for CodeGenerator<'a> {
+    /// Compile an Program created by `parser::parse` into a Python script
+    fn compile(&mut self, program: &parser::Program) -> Result<String, CodegenError> {
+        validate::validate(program).map_err(CodegenError::Invalid)?;
+        self.scopes = Scopes::new(program, &self.directives, RESERVED);
+        self.types = Types::new(program);
+        let available = builtins::available_to(program);
+        self.located = available.iter().filter(|builtin| builtin.located).map(|builtin| builtin.name).collect();
+        let builtins = available.iter()
+            .map(|builtin| get_builtin_definition(builtin.name)
+                 .ok_or(CodegenError::UnknownBuiltin { backend: "python", name: builtin.name }))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.out.reserve(RUNTIME.len() + self.prolog.len() + self.epilog.len()
+                         + builtins.iter().map(|definition| definition.len()).sum::<usize>()
+                         + ast::node_count(program) * BYTES_PER_NODE);
+        self.out.text(RUNTIME);
+        self.compile_externals(program);
+        for definition in builtins {
+            self.out.text(definition);
+        }
+        self.out.text(self.prolog);
+        for func in program {
+            self.compile_function(func);
+        }
+        self.out.text(self.epilog);
+        Ok(self.out.finish())
+    }
+}
+
+impl<'a> Default for CodeGenerator<'a> {
+    fn default() -> CodeGenerator<'a> {
+        CodeGenerator::new()
+    }
+}
+
+impl<'a> CodeGenerator<'a> {
+    /// Constructs a new CodeGenerator
+    pub fn new() -> CodeGenerator<'a> {
+        CodeGenerator::with_options(codegen::Options::default())
+    }
+
+    /// Constructs a new CodeGenerator that uses the given Options
+    pub fn with_options(options: codegen::Options) -> CodeGenerator<'a> {
+        CodeGenerator::with_directives(options, vec![])
+    }
+
+    /// Constructs a new CodeGenerator that uses the given Options and build directives
+    pub fn with_directives(options: codegen::Options, directives: Vec<parser::Directive>) -> CodeGenerator<'a> {
+        CodeGenerator {
+            prolog: "
+
+# Start compiled program
+",
+            epilog: "
+
+# End compiled program
+
+if __name__ == \"__main__\":
+    sys.exit(haumea_exit_code(main()))
+",
+            options,
+            directives,
+            function: parser::Ident::from(""),
+            names: UniqueNames::new("__HAUMEA_TEMP_"),
+            scopes: Scopes::default(),
+            located: vec![],
+            types: Types::default(),
+            steps: vec![],
+            labels: vec![],
+            out: Emitter::new("    "),
+        }
+    }
+
+    /// Defines the embedded files, and a stub for each external function,
+    /// which can't be called because there is no C to link with
+    fn compile_externals(&mut self, program: &parser::Program) {
+        let directives = mem::take(&mut self.directives);
+        for directive in &directives {
+            if let parser::Directive::Embed { ref name, ref bytes, .. } = *directive {
+                self.compile_embed(name, bytes);
+            }
+        }
+        self.directives = directives;
+        for func in program.iter().filter(|func| func.external.is_some()) {
+            let params = func.signature.iter().flatten().map(|param| py_name(param)).collect::<Vec<_>>();
+            self.out.text(&format!("
+
+def {name:}({params:}):
+    raise RuntimeError(\"the external function `{function:}` can't be called from Python\")
+", name = py_name(&func.name), params = params.join(", "), function = func.name));
+        }
+    }
+
+    /// Defines the functions that read an embedded file's bytes
+    fn compile_embed(&mut self, name: &str, bytes: &[u8]) {
+        let mut array = String::new();
+        for (i, byte) in bytes.iter().enumerate() {
+            array.push_str(if i % 16 == 0 { "\n    " } else { " " });
+            array.push_str(&format!("{:},", byte));
+        }
+        self.out.text(&format!("
+
+haumea_embed_{name:} = bytes([{array:}\n])
+
+
+def {function:}(i):
+    return haumea_embed_{name:}[i] if 0 <= i < len(haumea_embed_{name:}) else -1
+
+
+def {function:}_size():
+    return len(haumea_embed_{name:})
+", name = name, function = py_name(name), array = array));
+    }
+
+    /// Compiles a Function
+    fn compile_function(&mut self, func: &parser::Function) {
+        if func.external.is_some() {
+            return;
+        }
+        self.function = func.name;
+        if self.options.deterministic {
+            self.names.restart(&func.name);
+        }
+        self.scopes.start_function();
+        let params = func.signature.iter().flatten().map(|param| self.scopes.declare(*param)).collect::<Vec<_>>();
+        self.out.line("");
+        self.out.line("");
+        self.out.line(format_args!("def {:}({:}):", py_name(&func.name), params.join(", ")));
+        self.out.indent();
+        self.compile_statement(&func.code);
+        // Procedures return None, since nothing uses what they return
+        if flow::falls_through(&func.code) && !flow::is_procedure(func) {
+            self.out.line("return 0");
+        } else if is_empty(&func.code) {
+            self.out.line("pass");
+        }
+        self.out.dedent();
+    }
+
+    /// Compiles a statement
+    fn compile_statement(&mut self, statement: &parser::Statement) {
+        use parser::Statement;
+
+        match *statement {
+            Statement::Return(ref exp, _) => {
+                let exp = self.compile_expression(exp);
+                self.out.line(format_args!("return {:}", exp));
+            },
+            // Python has no blocks of its own, so the statements are written where the block is
+            Statement::Do(ref block, _) => {
+                self.scopes.enter();
+                for sub_statement in block {
+                    self.compile_statement(sub_statement);
+                }
+                self.scopes.leave();
+            },
+            Statement::Call { function, ref arguments, span } => {
+                let call = self.compile_call(function, arguments.iter().collect(), span);
+                self.out.line(format_args!("{:}", call));
+            },
+            // Variables start at 0, and are only declared by being set in Python
+            Statement::Var(ident, _) => {
+                let name = self.scopes.declare(ident);
+                self.out.line(format_args!("{:} = 0", name));
+            },
+            Statement::Set(ident, ref expr, _) => {
+                let expr = self.compile_expression(expr);
+                self.out.line(format_args!("{:} = {:}", self.scopes.name(ident), expr));
+            },
+            Statement::Change(ident, ref expr, span) => {
+                let name = self.scopes.name(ident);
+                let exp = self.compile_operand(expr);
+                if self.options.bignum {
+                    self.out.line(format_args!("{:} += {:}", name, exp));
+                } else {
+                    let code = format!("change {} by {}", ident, ast::expression_to_source(expr));
+                    let sum = self.machine_integer(format!("{:} + {:}", name, exp), &code, span);
+                    self.out.line(format_args!("{:} = {:}", name, sum));
+                }
+            },
+            Statement::If { ref cond, ref if_clause, ref else_clause, .. } => {
+                let cond = self.compile_condition(cond, false);
+                self.out.line(format_args!("if {:}:", cond));
+                self.compile_body(if_clause);
+                let mut else_clause = else_clause;
+                while let Some(ref else_) = **else_clause {
+                    match *else_ {
+                        // `else if` is written `elif`, so a chain of them isn't indented further each time
+                        Statement::If { ref cond, ref if_clause, else_clause: ref next, .. } => {
+                            let cond = self.compile_condition(cond, false);
+                            self.out.line(format_args!("elif {:}:", cond));
+                            self.compile_body(if_clause);
+                            else_clause = next;
+                        },
+                        _ => {
+                            self.out.line("else:");
+                            self.compile_body(else_);
+                            break;
+                        },
+                    }
+                }
+            },
+            Statement::Forever(ref block, label, _) => {
+                let caught = self.start_catching(block, label);
+                self.out.line("while True:");
+                self.compile_loop(block, None, label);
+                self.stop_catching(label, caught);
+            },
+            Statement::While { ref cond, ref body, label, .. } => {
+                let cond = self.compile_condition(cond, false);
+                let caught = self.start_catching(body, label);
+                self.out.line(format_args!("while {:}:", cond));
+                self.compile_loop(body, None, label);
+                self.stop_catching(label, caught);
+            },
+            Statement::ForEach { ref ident, ref start, ref end, ref by, ref range_type, ref body, label, span } => {
+                let through = match &range_type[..] {
+                    "to" => false,
+                    "through" => true,
+                    _ => panic!("Invalid range type {:?}!", range_type),
+                };
+                let start_code = self.compile_expression(start);
+                let end_code = self.compile_expression(end);
+                let mut by_code = self.compile_expression(by);
+                if self.options.runtime_checks {
+                    let header = format!("for each {} in {} {} {} by {}", ident, ast::expression_to_source(start),
+                                         range_type, ast::expression_to_source(end), ast::expression_to_source(by));
+                    by_code = format!("haumea_step({:}, \"{:}\", \"{:}\", {:})",
+                                      by_code, self.function, py_escape(&header), span.start.line);
+                }
+                // The loop's variable is only in scope in the loop
+                self.scopes.enter();
+                let name = self.scopes.declare(*ident);
+                if sets(body, *ident) {
+                    // Setting the variable changes where the loop goes next,
+                    // which it can't do to the numbers a `for` loop goes through
+                    let start_name = self.names.fresh();
+                    let end_name = self.names.fresh();
+                    let by_name = self.names.fresh();
+                    self.out.line(format_args!("{:} = {:}", start_name, start_code));
+                    self.out.line(format_args!("{:} = {:}", end_name, end_code));
+                    self.out.line(format_args!("{:} = haumea_towards({:}, {:}, {:})", by_name, start_name, end_name, by_code));
+                    self.out.line(format_args!("{:} = {:}", name, start_name));
+                    let caught = self.start_catching(body, label);
+                    self.out.line(format_args!("while haumea_in_range({:}, {:}, {:}, {:}):",
+                                               name, start_name, end_name, py_bool(through)));
+                    let step = format!("{:} += {:}", name, by_name);
+                    self.compile_loop(body, Some(step.clone()), label);
+                    self.out.indent();
+                    self.out.line(step);
+                    self.out.dedent();
+                    self.stop_catching(label, caught);
+                } else {
+                    let range = match (integer(start), integer(end), integer(by).and_then(i64::checked_abs)) {
+                        // Python's range counts the same way once the step goes the way the loop does
+                        (Some(first), Some(last), Some(size)) if size > 0 => {
+                            let step = if first <= last { size } else { -size };
+                            let stop = if !through { last } else if step > 0 { last + 1 } else { last - 1 };
+                            if step == 1 {
+                                format!("range({:}, {:})", first, stop)
+                            } else {
+                                format!("range({:}, {:}, {:})", first, stop, step)
+                            }
+                        },
+                        _ => format!("haumea_range({:}, {:}, {:}, {:})", start_code, end_code, by_code, py_bool(through)),
+                    };
+                    let caught = self.start_catching(body, label);
+                    self.out.line(format_args!("for {:} in {:}:", name, range));
+                    self.compile_loop(body, None, label);
+                    self.stop_catching(label, caught);
+                }
+                self.scopes.leave();
+            },
+            Statement::Break(Some(label), _) if self.labels.last() != Some(&Some(label)) => {
+                self.out.line(format_args!("raise HaumeaBreak(\"{:}\")", label));
+            },
+            Statement::Break(..) => self.out.line("break"),
+            Statement::Continue(_) => {
+                if let Some(Some(step)) = self.steps.last().cloned() {
+                    self.out.line(step);
+                }
+                self.out.line("continue");
+            },
+        }
+    }
+
+    /// Compiles the body of a loop labeled `label`, where `continue` does `step` before going round again
+    fn compile_loop(&mut self, body: &parser::Statement, step: Option<String>, label: Option<parser::Ident>) {
+        self.steps.push(step);
+        self.labels.push(label);
+        self.compile_body(body);
+        self.labels.pop();
+        self.steps.pop();
+    }
+
+    /// Starts a `try` around a loop labeled `label` if a loop in its `body` breaks out of it
+    ///
+    /// Python has no labeled loops, so `break out of` a loop around the
+    /// innermost one raises a `HaumeaBreak`, which is caught after the loop.
+    /// Returns true if the `try` was started.
+    fn start_catching(&mut self, body: &parser::Statement, label: Option<parser::Ident>) -> bool {
+        if !label.is_some_and(|label| breaks_out_of_inner_loop(body, label)) {
+            return false;
+        }
+        self.out.line("try:");
+        self.out.indent();
+        true
+    }
+
+    /// Ends the `try` started by `start_catching` for the loop labeled `label`, if it was `caught`
+    fn stop_catching(&mut self, label: Option<parser::Ident>, caught: bool) {
+        if let (Some(label), true) = (label, caught) {
+            self.out.dedent();
+            self.out.line("except HaumeaBreak as stop:");
+            self.out.indent();
+            self.out.line(format_args!("if stop.label != \"{:}\":", label));
+            self.out.indent();
+            self.out.line("raise");
+            self.out.dedent();
+            self.out.dedent();
+        }
+    }
+
+    /// Compiles the body of an if, else or loop, one level further in
+    fn compile_body(&mut self, body: &parser::Statement) {
+        self.out.indent();
+        self.scopes.enter();
+        if is_empty(body) {
+            self.out.line("pass");
+        } else {
+            self.compile_statement(body);
+        }
+        self.scopes.leave();
+        self.out.dedent();
+    }
+
+    /// Compiles an expression that is used as a condition, where only whether it is 0 matters
+    ///
+    /// Comparisons and `and`, `or` and `not` are written as Python's own,
+    /// which give True or False. When `exact` is true, the other expressions
+    /// are compared with 0, so that the condition is always True or False.
+    fn compile_condition(&self, expr: &parser::Expression, exact: bool) -> String {
+        use parser::Expression;
+        use parser::Operator::*;
+
+        match *expr {
+            Expression::Boolean(value, _) => py_bool(value).to_string(),
+            Expression::BinaryOp { operator: op @ LogicalAnd, ref left, ref right, .. } |
+            Expression::BinaryOp { operator: op @ LogicalOr, ref left, ref right, .. } => {
+                format!("{:} {:} {:}", self.compile_clause(left, op), get_py_name(op), self.compile_clause(right, op))
+            },
+            Expression::BinaryOp { operator: op, ref left, ref right, .. } if op.is_comparison() => {
+                format!("{:} {:} {:}", self.compile_operand(left), get_py_name(op), self.compile_operand(right))
+            },
+            Expression::UnaryOp { operator: LogicalNot, expression: ref exp, .. } if is_condition(exp) => {
+                format!("not {:}", self.compile_clause(exp, LogicalNot))
+            },
+            Expression::UnaryOp { operator: LogicalNot, expression: ref exp, .. } => {
+                format!("{:} == 0", self.compile_operand(exp))
+            },
+            _ if exact => format!("{:} != 0", self.compile_operand(expr)),
+            _ => self.compile_expression(expr),
+        }
+    }
+
+    /// Compiles a condition that is inside `and`, `or` or `not`, which is
+    /// bracketed if it is joined by another of them
+    fn compile_clause(&self, expr: &parser::Expression, outer: parser::Operator) -> String {
+        use parser::Operator::*;
+
+        match *expr {
+            parser::Expression::BinaryOp { operator: op, .. } if (op == LogicalAnd || op == LogicalOr) && op != outer => {
+                format!("({:})", self.compile_condition(expr, true))
+            },
+            _ => self.compile_condition(expr, true),
+        }
+    }
+
+    /// Compiles an expression that is an operand of an operator, bracketing it
+    /// if it is an operation on two operands itself
+    ///
+    /// Operators on one operand go before any others in Python, so they don't need brackets.
+    fn compile_operand(&self, expr: &parser::Expression) -> String {
+        match *expr {
+            // Division is a call to the runtime, which needs no brackets
+            parser::Expression::BinaryOp { operator: parser::Operator::Div, .. } |
+            parser::Expression::BinaryOp { operator: parser::Operator::Modulo, .. } => self.compile_expression(expr),
+            // So is arithmetic on machine integers, which wraps around
+            parser::Expression::BinaryOp { operator: parser::Operator::Add | parser::Operator::Sub | parser::Operator::Mul, .. }
+                if !self.options.bignum && !self.is_real(expr) => self.compile_expression(expr),
+            parser::Expression::BinaryOp { .. } if !is_condition(expr) => {
+                format!("({:})", self.compile_expression(expr))
+            },
+            _ => self.compile_expression(expr),
+        }
+    }
+
+    /// Compiles an expression
+    fn compile_expression(&self, expr: &parser::Expression) -> String {
+        use parser::Expression;
+
+        match *expr {
+            Expression::Integer(i, _) => i.to_string(),
+            Expression::BigInteger(ref digits, _) => digits.clone(),
+            // Rust writes floats like Python's repr, always with a point or an exponent
+            Expression::Float(n, _) => format!("{:?}", n.0),
+            Expression::Ident(name, _) => self.scopes.name(name),
+            Expression::Boolean(value, _) => (value as i64).to_string(),
+            Expression::Str(ref text, _) => format!("\"{:}\"", py_escape(text)),
+            Expression::List(ref items, _) => {
+                let items = items.iter()
+                    .map(|item| self.compile_expression(item))
+                    .collect::<Vec<_>>();
+                format!("[{:}]", items.join(", "))
+            },
+            Expression::Index { ref list, ref index, span } => {
+                if self.options.runtime_checks {
+                    format!("haumea_list_get({:}, {:}, \"{:}\", \"{:}\", {:})",
+                            self.compile_expression(list), self.compile_expression(index), self.function,
+                            py_escape(&ast::expression_to_source(expr)), span.start.line)
+                } else {
+                    format!("{:}[{:}]", self.compile_operand(list), self.compile_expression(index))
+                }
+            },
+            Expression::Length(ref list, _) => format!("len({:})", self.compile_expression(list)),
+            // Comparisons give True or False, which Haumea keeps as 1 or 0
+            _ if is_condition(expr) => format!("int({:})", self.compile_condition(expr, true)),
+            // Reals are Python floats, which don't wrap around
+            Expression::BinaryOp { operator: parser::Operator::Div, ref left, ref right, .. } if self.is_real(expr) => {
+                format!("haumea_real_div({:}, {:})", self.compile_expression(left), self.compile_expression(right))
+            },
+            Expression::BinaryOp { operator: op, ref left, ref right, .. } if self.is_real(expr) => {
+                format!("{:} {:} {:}", self.compile_operand(left), get_py_name(op), self.compile_operand(right))
+            },
+            Expression::BinaryOp { operator: op, ref left, ref right, span } => {
+                use parser::Operator::*;
+                let lh = self.compile_operand(left);
+                let mut rh = self.compile_operand(right);
+                if self.options.runtime_checks && (op == Div || op == Modulo) {
+                    rh = format!("haumea_divisor({:}, \"{:}\", \"{:}\", {:})",
+                                 rh, self.function, py_escape(&ast::expression_to_source(expr)), span.start.line);
+                }
+                let arithmetic = match op {
+                    // Python's `//` and `%` round down, rather than towards zero like Haumea
+                    Div => format!("haumea_div({:}, {:})", lh, rh),
+                    Modulo => format!("haumea_mod({:}, {:})", lh, rh),
+                    Add | Sub | Mul => format!("{:} {:} {:}", lh, get_py_name(op), rh),
+                    // Bignums shift by the whole count, and the other way if it is negative
+                    ShiftLeft if self.options.bignum => return format!("haumea_shift({:}, {:})", lh, rh),
+                    ShiftRight if self.options.bignum => return format!("haumea_shift({:}, -{:})", lh, rh),
+                    // Shifts work on 64 bit integers, using only the low 6 bits of the count
+                    ShiftLeft => return format!("haumea_shl({:}, {:})", lh, rh),
+                    ShiftRight => return format!("{:} >> ({:} & 63)", lh, rh),
+                    _ => return format!("{:} {:} {:}", lh, get_py_name(op), rh),
+                };
+                self.machine_integer(arithmetic, &ast::expression_to_source(expr), span)
+            },
+            Expression::Call { function, ref arguments, span } => {
+                self.compile_call(function, arguments.iter().map(|arg| &**arg).collect(), span)
+            },
+            // A negative number written in the code always fits
+            Expression::UnaryOp { operator: parser::Operator::Negate | parser::Operator::Sub, expression: ref exp, span }
+                if integer(expr).is_none() && !self.is_real(expr) => {
+                let negated = format!("-{:}", self.compile_operand(exp));
+                self.machine_integer(negated, &ast::expression_to_source(expr), span)
+            },
+            Expression::UnaryOp { operator: op, expression: ref exp, .. } => {
+                format!("{:}{:}", get_py_name(op), self.compile_operand(exp))
+            },
+        }
+    }
+
+    /// Returns true if `expr` is a Real in the function being compiled
+    fn is_real(&self, expr: &parser::Expression) -> bool {
+        self.types.expression(self.function, expr) == Some(Type::Real)
+    }
+
+    /// Compiles the result of arithmetic on machine integers, which wraps around
+    /// like in C, or stops the program with --checked-arithmetic, if it is too large
+    ///
+    /// `code` is the Haumea code of the arithmetic, at `span`. Bignums are never too large.
+    fn machine_integer(&self, result: String, code: &str, span: parser::Span) -> String {
+        if self.options.bignum {
+            result
+        } else if self.options.checked_arithmetic {
+            format!("haumea_checked({:}, \"{:}\", \"{:}\", {:})", result, self.function, py_escape(code), span.start.line)
+        } else {
+            format!("haumea_wrap({:})", result)
+        }
+    }
+
+    /// Compiles a call to `function` with `arguments`, passing located builtins
+    /// the function, code and line of the call after them
+    fn compile_call(&self, function: parser::Ident, arguments: Vec<&parser::Expression>, span: parser::Span) -> String {
+        let mut args: Vec<String> = arguments.iter().map(|arg| self.compile_expression(arg)).collect();
+        if self.located.contains(&function.as_str()) {
+            args.push(format!("\"{:}\", \"{:}(...)\", {:}", self.function, function, span.start.line));
+        }
+        format!("{:}({:})", py_name(&function), args.join(", "))
+    }
+}
+
+// Utility functions
+
+/// Returns the name `name` has in Python, which is changed if it is reserved
+///
+/// # Examples
+/// ```
+/// # use haumea::codegen::python::py_name;
+/// assert_eq!(py_name("total"), "total");
+/// assert_eq!(py_name("lambda"), "lambda_");
+/// ```
+pub fn py_name(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Escapes `text` to be written in a Python string literal
+fn py_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+/// Returns the Python name of a boolean
+fn py_bool(value: bool) -> &'static str {
+    if value { "True" } else { "False" }
+}
+
+/// Returns true if `expr` is true or false, rather than a number, in Python
+fn is_condition(expr: &parser::Expression) -> bool {
+    use parser::Operator::*;
+
+    match *expr {
+        parser::Expression::BinaryOp { operator, .. } => {
+            operator.is_comparison() || operator == LogicalAnd || operator == LogicalOr
+        },
+        parser::Expression::UnaryOp { operator, .. } => operator == LogicalNot,
+        _ => false,
+    }
+}
+
+/// Returns the integer `expr` is, if it is a whole number written in the code
+fn integer(expr: &parser::Expression) -> Option<i64> {
+    match *expr {
+        parser::Expression::Integer(n, _) => Some(n),
+        parser::Expression::UnaryOp { operator: parser::Operator::Negate, expression: ref exp, .. } |
+        parser::Expression::UnaryOp { operator: parser::Operator::Sub, expression: ref exp, .. } => {
+            integer(exp).and_then(i64::checked_neg)
+        },
+        _ => None,
+    }
+}
+
+/// Returns true if `statement` doesn't do anything, so it compiles to no Python
+fn is_empty(statement: &parser::Statement) -> bool {
+    match *statement {
+        parser::Statement::Do(ref block, _) => block.iter().all(|sub| is_empty(sub)),
+        _ => false,
+    }
+}
+
+/// Returns true if a loop in `statement` has a `break out of label` in it,
+/// which leaves that loop and the one labeled `label` around it
+fn breaks_out_of_inner_loop(statement: &parser::Statement, label: parser::Ident) -> bool {
+    match *statement {
+        parser::Statement::Do(ref block, _) => block.iter().any(|sub| breaks_out_of_inner_loop(sub, label)),
+        parser::Statement::If { ref if_clause, ref else_clause, .. } => {
+            breaks_out_of_inner_loop(if_clause, label) ||
+                (**else_clause).as_ref().is_some_and(|else_clause| breaks_out_of_inner_loop(else_clause, label))
+        },
+        parser::Statement::Forever(..) | parser::Statement::While { .. } | parser::Statement::ForEach { .. } => {
+            flow::breaks_out_of(statement, label)
+        },
+        _ => false,
+    }
+}
+
+/// Returns true if `statement` sets or changes the variable `ident`
+fn sets(statement: &parser::Statement, ident: parser::Ident) -> bool {
+    struct Finder(parser::Ident, bool);
+    impl ast::Visitor for Finder {
+        fn visit_statement(&mut self, statement: &parser::Statement) {
+            match *statement {
+                parser::Statement::Set(ident, ..) | parser::Statement::Change(ident, ..) => self.1 |= ident == self.0,
+                _ => (),
+            }
+        }
+    }
+    let mut finder = Finder(ident, false);
+    ast::walk_statement(&mut finder, statement);
+    finder.1
+}
+
+/// Returns the Python definition of a builtin function, or None if the backend has no definition of it
+fn get_builtin_definition(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "display" => "
+
+def display(*numbers):
+    print(*numbers)
+    return 0
+",
+        "display_no_newline" => "
+
+def display_no_newline(*numbers):
+    print(*numbers, end=\"\")
+    return 0
+",
+        "display_real" => "
+
+def display_real(n):
+    print(haumea_real_text(n))
+    return 0
+",
+        "display_text" => "
+
+def display_text(text):
+    print(text)
+    return 0
+",
+        "read" => "
+
+def read():
+    try:
+        return int(input(\"Enter an integer: \"))
+    except (ValueError, EOFError):
+        return 0
+",
+        "push" => "
+
+def push(items, value):
+    items.append(value)
+    return 0
+",
+        "append" => "
+
+def append(items, value):
+    items.append(value)
+    return 0
+",
+        "length_of" => "
+
+def length_of(items):
+    return len(items)
+",
+        "item_at" => "
+
+def item_at(items, index, function, code, line):
+    return haumea_list_get(items, index, function, code, line)
+",
+        "set_item" => "
+
+def set_item(items, index, value, function, code, line):
+    haumea_list_get(items, index, function, code, line)
+    items[index] = value
+    return 0
+",
+        "remove_last" => "
+
+def remove_last(items, function, code, line):
+    if not items:
+        haumea_fail(\"the list is empty\", function, code, line)
+    return items.pop()
+",
+        "join" => "
+
+def join(*texts):
+    return \"\".join(texts)
+",
+        "run_command" => "
+
+def run_command(command, function, code, line):
+    sys.stdout.flush()
+    status = subprocess.run(command, shell=True).returncode
+    # Like a shell, a command stopped by a signal has 128 plus the signal as its exit status
+    return 128 - status if status < 0 else status
+",
+        "character_code" => "
+
+def character_code(text, function, code, line):
+    if len(text) != 1:
+        haumea_fail(\"the text is not one character\", function, code, line)
+    return ord(text)
+",
+        "character_from_code" => "
+
+def character_from_code(n, function, code, line):
+    if n < 1 or n > 0x10FFFF or 0xD800 <= n <= 0xDFFF:
+        haumea_fail(\"the number is not the code of a character\", function, code, line)
+    return chr(n)
+",
+        "number_from_text" => "
+
+def number_from_text(text, function, code, line):
+    digits = text[1:] if text.startswith(\"-\") else text
+    if not (digits.isascii() and digits.isdigit()):
+        haumea_fail(\"the text is not a whole number\", function, code, line)
+    return int(text)
+",
+        "text_from_number" => "
+
+def text_from_number(n):
+    return str(n)
+",
+        "random_between" => "
+
+def random_between(low, high):
+    return haumea_random.between(low, high)
+",
+        "seed_random" => "
+
+def seed_random(seed):
+    haumea_random.state = seed & HaumeaRandom.MASK
+    return 0
+",
+        _ => return None,
+    })
+}
+
+/// Returns the Python name of an operator
+fn get_py_name(op: parser::Operator) -> &'static str {
+    use parser::Operator::*;
+    match op {
+        Add => "+",
+        Sub | Negate => "-",
+        Mul => "*",
+        Div => "//",
+        Equals => "==",
+        NotEquals => "!=",
+        Gt => ">",
+        Lt => "<",
+        Gte => ">=",
+        Lte => "<=",
+        LogicalAnd => "and",
+        LogicalOr => "or",
+        LogicalNot => "not",
+        BinaryAnd => "&",
+        BinaryOr => "|",
+        BinaryNot => "~",
+        BinaryXor => "^",
+        ShiftLeft => "<<",
+        ShiftRight => ">>",
+        Modulo => "%",
+    }
+}
@@ -0,0 +1,42 @@
+/* Haumea random number runtime */
+/* Random numbers come from splitmix64, like in the interpreter and the other */
+/* backends, so a program that calls `seed_random` displays the same numbers */
+/* however it is run. Until then, the seed is the time the program started. */
+#include <time.h>
+
+static unsigned long long haumea_random_state;
+static int haumea_random_seeded;
+
+static void haumea_seed_random(long long seed) {
+    haumea_random_state = (unsigned long long) seed;
+    haumea_random_seeded = 1;
+}
+
+/* Returns the next random number, from 0 up to the largest unsigned long long */
+static unsigned long long haumea_random_next(void) {
+    unsigned long long z;
+    if (!haumea_random_seeded) {
+        haumea_seed_random((long long) time(NULL));
+    }
+    haumea_random_state += 0x9e3779b97f4a7c15ull;
+    z = haumea_random_state;
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9ull;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111ebull;
+    return z ^ (z >> 31);
+}
+
+/* Returns a random number from `low` through `high`, which can be given either way round */
+static long long haumea_random_between(long long low, long long high) {
+    unsigned long long span;
+    if (low > high) {
+        long long swap = low;
+        low = high;
+        high = swap;
+    }
+    /* The span of every long long is one more than an unsigned long long holds, so it wraps to 0 */
+    span = (unsigned long long) high - (unsigned long long) low + 1;
+    if (span == 0) {
+        return (long long) haumea_random_next();
+    }
+    return (long long) ((unsigned long long) low + haumea_random_next() % span);
+}
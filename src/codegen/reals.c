@@ -0,0 +1,75 @@
+
+/* Haumea real runtime */
+/* Reals are worked on as doubles, and passed around in the same long long as */
+/* every other value where their type isn't known, holding the bits of their */
+/* double, like text is passed around as the address of a string. */
+#include <math.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Returns the long long holding the bits of `value` */
+static long long haumea_real(double value) {
+    long long bits;
+    memcpy(&bits, &value, sizeof bits);
+    return bits;
+}
+
+/* Returns the double whose bits `bits` holds */
+static double haumea_real_value(long long bits) {
+    double value;
+    memcpy(&value, &bits, sizeof value);
+    return value;
+}
+
+/* Prints `value` with the fewest digits that read back as the same double, */
+/* written out in full rather than with an exponent, like the interpreter */
+static void haumea_print_real(double value) {
+    char text[32], digits[20];
+    char *c;
+    int precision, count = 0, point, i;
+    if (isnan(value)) {
+        printf("NaN");
+        return;
+    }
+    if (isinf(value)) {
+        printf(value < 0 ? "-inf" : "inf");
+        return;
+    }
+    for (precision = 0; precision < 17; precision++) {
+        snprintf(text, sizeof text, "%.*e", precision, value);
+        if (strtod(text, NULL) == value) {
+            break;
+        }
+    }
+    /* text is like -1.25e+22, so the digits before the e are moved by the exponent */
+    c = text;
+    if (*c == '-') {
+        putchar('-');
+        c++;
+    }
+    for (; *c != 'e'; c++) {
+        if (*c != '.') {
+            digits[count++] = *c;
+        }
+    }
+    while (count > 1 && digits[count - 1] == '0') {
+        count--;
+    }
+    point = atoi(c + 1) + 1;
+    if (point <= 0) {
+        printf("0.");
+        for (i = point; i < 0; i++) {
+            putchar('0');
+        }
+        fwrite(digits, 1, count, stdout);
+    } else if (point >= count) {
+        fwrite(digits, 1, count, stdout);
+        for (i = count; i < point; i++) {
+            putchar('0');
+        }
+    } else {
+        fwrite(digits, 1, point, stdout);
+        putchar('.');
+        fwrite(digits + point, 1, count - point, stdout);
+    }
+}
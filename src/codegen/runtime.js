@@ -0,0 +1,137 @@
+/* Haumea runtime */
+/* Integers are BigInts, wrapped around to 64 bits like C longs unless the program uses bignums, */
+/* reals are numbers, text is a string and lists are arrays. */
+
+/* Returns the next number `read` gives, and is replaced by the input `run` is given */
+let haumea_input = () => 0;
+
+/* Runs a command and returns its exit status, and is replaced by the `command` option `run` is given */
+/* Without one, programs can't run commands, since they may be running somewhere they shouldn't */
+let haumea_command = null;
+
+/* Is given what the program displays, and is replaced by the `output` `run` is given */
+/* Without one, the program displays with console.log */
+let haumea_output = null;
+
+/* The text displayed since the last newline, since console.log always ends a line */
+let haumea_line = "";
+
+/* Displays `text` after the rest of the line, and ends the line if `newline` is true */
+function haumea_print(text, newline) {
+    if (haumea_output !== null) {
+        haumea_output(newline ? text + "\n" : text);
+        return;
+    }
+    haumea_line += text;
+    if (newline) {
+        console.log(haumea_line);
+        haumea_line = "";
+    }
+}
+
+/* Displays the rest of the line, without ending it if the platform can */
+function haumea_flush() {
+    if (haumea_line !== "") {
+        if (globalThis.process?.stdout) {
+            process.stdout.write(haumea_line);
+        } else {
+            console.log(haumea_line);
+        }
+        haumea_line = "";
+    }
+}
+
+/* Wraps the value main returns into a valid exit status, like exit does */
+function haumea_exit_code(value) {
+    return Number(BigInt.asUintN(8, value));
+}
+
+/* Turns the true or false of a JavaScript comparison into the 1 or 0 Haumea uses */
+function haumea_bool(value) {
+    return value ? 1n : 0n;
+}
+
+/* The length of a list, or the number of characters in text, which can be longer than its .length */
+function haumea_length(value) {
+    return BigInt(typeof value === "string" ? [...value].length : value.length);
+}
+
+/* Writes a real with the fewest digits that read back as the same number, without an exponent */
+function haumea_real_text(n) {
+    if (Number.isNaN(n)) {
+        return "NaN";
+    }
+    if (!Number.isFinite(n)) {
+        return n < 0 ? "-inf" : "inf";
+    }
+    const [mantissa, exponent] = n.toExponential().split("e");
+    const sign = n < 0 || Object.is(n, -0) ? "-" : "";
+    const digits = mantissa.replace("-", "").replace(".", "");
+    const point = Number(exponent) + 1;
+    if (point <= 0) {
+        return `${sign}0.${"0".repeat(-point)}${digits}`;
+    }
+    if (point >= digits.length) {
+        return sign + digits + "0".repeat(point - digits.length);
+    }
+    return `${sign}${digits.slice(0, point)}.${digits.slice(point)}`;
+}
+
+function haumea_fail(error, fn, code, line) {
+    throw new Error(`${error} in ${fn}, at \`${code}\` on line ${line}`);
+}
+
+/* Stops the program if `n` doesn't fit in a 64 bit integer, for --checked-arithmetic */
+function haumea_checked(n, fn, code, line) {
+    if (n !== BigInt.asIntN(64, n)) {
+        haumea_fail("a number too large for a machine integer", fn, code, line);
+    }
+    return n;
+}
+
+function haumea_list_get(list, index, fn, code, line) {
+    if (index < 0n || index >= BigInt(list.length)) {
+        haumea_fail(`the index ${index} is outside a list of length ${list.length}`, fn, code, line);
+    }
+    return list[Number(index)];
+}
+
+function haumea_divisor(n, fn, code, line) {
+    if (n === 0n) {
+        haumea_fail("division by zero", fn, code, line);
+    }
+    return n;
+}
+
+function haumea_step(n, fn, code, line) {
+    if (n === 0n) {
+        haumea_fail("a loop that counts by zero", fn, code, line);
+    }
+    return n;
+}
+
+/* Returns `by`, made to go the way a `for each` loop from `start` to `end` counts */
+function haumea_towards(start, end, by) {
+    return (start <= end) === (by > 0n) ? by : -by;
+}
+
+/* splitmix64, the random number generator every backend uses, so a seed gives the same numbers. */
+/* Until `seed_random` is called, the seed is the time, as in the compiled program. */
+let haumea_random_state = BigInt(Math.floor(Date.now() / 1000));
+
+function haumea_random_next() {
+    const mask = (1n << 64n) - 1n;
+    haumea_random_state = (haumea_random_state + 0x9e3779b97f4a7c15n) & mask;
+    let z = haumea_random_state;
+    z = ((z ^ (z >> 30n)) * 0xbf58476d1ce4e5b9n) & mask;
+    z = ((z ^ (z >> 27n)) * 0x94d049bb133111ebn) & mask;
+    return z ^ (z >> 31n);
+}
+
+/* Returns a random number from `low` through `high`, which can be given either way round */
+function haumea_random_between(low, high) {
+    if (low > high) {
+        [low, high] = [high, low];
+    }
+    return low + haumea_random_next() % (high - low + 1n);
+}
@@ -0,0 +1,137 @@
+# Haumea runtime
+# Integers are Python ints, wrapped around to 64 bits like C longs unless the program uses bignums,
+# reals are floats, text is a str and lists are lists.
+import decimal
+import math
+import subprocess
+import sys
+import time
+
+
+def haumea_exit_code(value):
+    """Wraps the value main returns into a valid exit status, like exit does"""
+    return value & 0xff
+
+
+def haumea_div(a, b):
+    """Divides like Haumea, rounding towards zero rather than down like `//`"""
+    quotient = abs(a) // abs(b)
+    return quotient if (a < 0) == (b < 0) else -quotient
+
+
+def haumea_mod(a, b):
+    """The remainder of `haumea_div`, which has the sign of `a` rather than of `b` like `%`"""
+    return a - b * haumea_div(a, b)
+
+
+def haumea_wrap(n):
+    """Wraps `n` around to a 64 bit integer, like arithmetic on a C long"""
+    return (n + 2 ** 63) % 2 ** 64 - 2 ** 63
+
+
+def haumea_checked(n, function, code, line):
+    """Stops the program if `n` doesn't fit in a 64 bit integer, for --checked-arithmetic"""
+    if n < -2 ** 63 or n >= 2 ** 63:
+        haumea_fail("a number too large for a machine integer", function, code, line)
+    return n
+
+
+def haumea_shl(a, b):
+    """Shifts `a` left like a 64 bit integer, by the low 6 bits of `b`"""
+    return haumea_wrap(a << (b & 63))
+
+
+def haumea_shift(a, b):
+    """Shifts the bignum `a` left by `b` bits, or right if `b` is negative"""
+    return a << b if b >= 0 else a >> -b
+
+
+def haumea_real_div(a, b):
+    """Divides the reals `a` and `b` like C doubles, giving an infinity or NaN when `b` is 0"""
+    if b == 0:
+        if a == 0 or math.isnan(a):
+            return math.nan
+        return math.copysign(math.inf, a) * math.copysign(1.0, b)
+    return a / b
+
+
+def haumea_real_text(n):
+    """Writes `n` with the fewest digits that read back as the same float, without an exponent"""
+    if math.isinf(n):
+        return "-inf" if n < 0 else "inf"
+    return format(decimal.Decimal(repr(n)).normalize(), "f")
+
+
+def haumea_in_range(i, start, end, through):
+    """Returns True if a `for each` loop from `start` to `end` goes on to `i`"""
+    if start <= end:
+        return i <= end if through else i < end
+    return i >= end if through else i > end
+
+
+def haumea_towards(start, end, by):
+    """Returns `by`, made to go the way a `for each` loop from `start` to `end` counts"""
+    return by if (start <= end) == (by > 0) else -by
+
+
+def haumea_range(start, end, by, through):
+    """The numbers a `for each` loop counts through, down if it starts after its end, whatever the sign of `by`"""
+    by = haumea_towards(start, end, by)
+    i = start
+    while haumea_in_range(i, start, end, through):
+        yield i
+        i += by
+
+
+class HaumeaBreak(Exception):
+    """Leaves every loop up to the one labeled `label`, for `break out of` a loop around another"""
+
+    def __init__(self, label):
+        super().__init__(label)
+        self.label = label
+
+
+def haumea_fail(error, function, code, line):
+    sys.exit(f"Error: {error} in {function}, at `{code}` on line {line}")
+
+
+def haumea_list_get(items, index, function, code, line):
+    if index < 0 or index >= len(items):
+        haumea_fail(f"the index {index} is outside a list of length {len(items)}", function, code, line)
+    return items[index]
+
+
+def haumea_divisor(n, function, code, line):
+    if n == 0:
+        haumea_fail("division by zero", function, code, line)
+    return n
+
+
+def haumea_step(n, function, code, line):
+    if n == 0:
+        haumea_fail("a loop that counts by zero", function, code, line)
+    return n
+
+
+class HaumeaRandom:
+    """splitmix64, the random number generator every backend uses, so a seed gives the same numbers"""
+    MASK = (1 << 64) - 1
+
+    def __init__(self):
+        # Until `seed_random` is called, the seed is the time, as in the compiled program
+        self.state = int(time.time()) & self.MASK
+
+    def next(self):
+        self.state = (self.state + 0x9E3779B97F4A7C15) & self.MASK
+        z = self.state
+        z = ((z ^ (z >> 30)) * 0xBF58476D1CE4E5B9) & self.MASK
+        z = ((z ^ (z >> 27)) * 0x94D049BB133111EB) & self.MASK
+        return z ^ (z >> 31)
+
+    def between(self, low, high):
+        """A random number from `low` through `high`, which can be given either way round"""
+        low, high = min(low, high), max(low, high)
+        return low + self.next() % (high - low + 1)
+
+
+haumea_random = HaumeaRandom()
@@ -0,0 +1,103 @@
+//! scopes.rs
+//! Names the variables of a function in the generated code.
+//!
+//! Haumea variables live in blocks, like C and JavaScript ones, but a name can
+//! be declared again in another block of the same function, and can be the
+//! name of a function or a word the generated language reserves. The first
+//! declaration of a name in a function keeps it, unless it is reserved, and
+//! every other declaration gets a name of its own, like `x_2`, which can't
+//! be a Haumea name since those have no digits.
+use std::collections::{HashMap, HashSet};
+use parser::{Directive, Function, Ident};
+use builtins::BUILTINS;
+
+/// The prefix of every name the runtimes define
+const RUNTIME_PREFIX: &str = "haumea_";
+
+#[derive(Debug, Default)]
+pub struct Scopes {
+    /// The names variables can't keep: the functions and the reserved words
+    reserved: HashSet<String>,
+    /// The generated name of each variable declared in each block, innermost last
+    scopes: Vec<HashMap<Ident, String>>,
+    /// How many times each name has been declared in the function
+    declared: HashMap<Ident, usize>,
+}
+
+impl Scopes {
+    /// Constructs Scopes for `program`, whose variables can't keep the name of
+    /// a function, a file embedded by `directives`, or any of `keywords`
+    pub fn new(program: &[Function], directives: &[Directive], keywords: &[&str]) -> Scopes {
+        let mut reserved: HashSet<String> = keywords.iter().map(|keyword| keyword.to_string()).collect();
+        reserved.extend(program.iter().map(|func| func.name.to_string()));
+        reserved.extend(BUILTINS.iter().map(|builtin| builtin.name.to_string()));
+        for directive in directives {
+            if let Directive::Embed { ref name, .. } = *directive {
+                reserved.insert(name.to_string());
+                reserved.insert(format!("{}_size", name));
+            }
+        }
+        Scopes { reserved, scopes: vec![], declared: HashMap::new() }
+    }
+
+    /// Starts a function, whose parameters are declared in a block of their own
+    pub fn start_function(&mut self) {
+        self.scopes = vec![HashMap::new()];
+        self.declared.clear();
+    }
+
+    /// Starts a block inside the current one
+    pub fn enter(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Finishes the block started last, forgetting the variables declared in it
+    pub fn leave(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Declares `name` in the current block, returning its generated name
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::codegen::scopes::Scopes;
+    /// # use haumea::symbol::Symbol;
+    /// let mut scopes = Scopes::new(&[], &[], &["int"]);
+    /// scopes.start_function();
+    /// assert_eq!(scopes.declare(Symbol::from("x")), "x");
+    /// scopes.enter();
+    /// assert_eq!(scopes.declare(Symbol::from("x")), "x_2");
+    /// scopes.leave();
+    /// assert_eq!(scopes.name(Symbol::from("x")), "x");
+    /// assert_eq!(scopes.declare(Symbol::from("int")), "int_1");
+    /// ```
+    pub fn declare(&mut self, name: Ident) -> String {
+        let count = {
+            let count = self.declared.entry(name).or_insert(0);
+            *count += 1;
+            *count
+        };
+        let generated = if count == 1 && !self.is_reserved(name.as_str()) {
+            name.to_string()
+        } else {
+            format!("{}_{}", name, count)
+        };
+        self.scopes.last_mut().expect("There is always a scope").insert(name, generated.clone());
+        generated
+    }
+
+    /// Returns the generated name of the variable `name`
+    pub fn name(&self, name: Ident) -> String {
+        self.scopes.iter().rev()
+            .filter_map(|scope| scope.get(&name))
+            .next()
+            .cloned()
+            // Every variable has been declared once the program is checked
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Returns true if a variable can't keep the name `name`
+    fn is_reserved(&self, name: &str) -> bool {
+        self.reserved.contains(name) || name.starts_with(RUNTIME_PREFIX)
+    }
+}
@@ -0,0 +1,194 @@
+/* Haumea text runtime */
+/* Text is passed around as the address of its characters, which end with */
+/* a NUL so C can print them, like a string. The number of bytes in the */
+/* text is kept in front of them. Text written in the program is a static */
+/* haumea_text, and text made by `join` is cut from an arena, since it is */
+/* never freed. */
+#include <limits.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct haumea_text {
+    long long length;
+    char chars[];
+} haumea_text;
+
+/* How many bytes the arena asks for at a time */
+#define HAUMEA_ARENA_BLOCK 65536
+
+static char *haumea_arena;
+static size_t haumea_arena_left;
+
+/* Returns `size` bytes from the arena, lined up for a long long */
+static void *haumea_arena_alloc(size_t size) {
+    char *memory;
+    size = (size + sizeof(long long) - 1) / sizeof(long long) * sizeof(long long);
+    if (size > haumea_arena_left) {
+        size_t block = size > HAUMEA_ARENA_BLOCK ? size : HAUMEA_ARENA_BLOCK;
+        haumea_arena = malloc(block);
+        if (haumea_arena == NULL) {
+            fprintf(stderr, "Error: out of memory for text\n");
+            exit(1);
+        }
+        haumea_arena_left = block;
+    }
+    memory = haumea_arena;
+    haumea_arena += size;
+    haumea_arena_left -= size;
+    return memory;
+}
+
+/* Returns the number of bytes in `text`, which is empty if it is a variable that was never set */
+static long long haumea_text_bytes(long long text) {
+    if (text == 0) {
+        return 0;
+    }
+    return ((haumea_text *) ((char *) (intptr_t) text - offsetof(haumea_text, chars)))->length;
+}
+
+/* Returns the number of characters in `text`, counting each character of UTF-8 once */
+static long long haumea_text_length(long long text) {
+    long long bytes = haumea_text_bytes(text);
+    long long length = 0;
+    long long i;
+    for (i = 0; i < bytes; i++) {
+        if ((((const unsigned char *) (intptr_t) text)[i] & 0xc0) != 0x80) {
+            length++;
+        }
+    }
+    return length;
+}
+
+/* Returns 1 if `a` and `b` have the same characters, or else 0 */
+static long long haumea_text_equals(long long a, long long b) {
+    long long bytes = haumea_text_bytes(a);
+    return bytes == haumea_text_bytes(b) && (bytes == 0 || memcmp((const char *) (intptr_t) a, (const char *) (intptr_t) b, bytes) == 0);
+}
+
+/* Makes text of the `length` bytes at `bytes` */
+static long long haumea_text_new(const char *bytes, long long length) {
+    haumea_text *text = haumea_arena_alloc(offsetof(haumea_text, chars) + length + 1);
+    text->length = length;
+    memcpy(text->chars, bytes, length);
+    text->chars[length] = '\0';
+    return (long long) (intptr_t) text->chars;
+}
+
+/* Makes text of the `count` texts in `texts`, one after another */
+static long long haumea_text_join(int count, const long long *texts) {
+    haumea_text *joined;
+    long long length = 0;
+    int i;
+    for (i = 0; i < count; i++) {
+        length += haumea_text_bytes(texts[i]);
+    }
+    joined = haumea_arena_alloc(offsetof(haumea_text, chars) + length + 1);
+    joined->length = 0;
+    for (i = 0; i < count; i++) {
+        long long bytes = haumea_text_bytes(texts[i]);
+        memcpy(joined->chars + joined->length, (const char *) (intptr_t) texts[i], bytes);
+        joined->length += bytes;
+    }
+    joined->chars[length] = '\0';
+    return (long long) (intptr_t) joined->chars;
+}
+
+/* Prints `text`, without a newline */
+static void haumea_text_print(long long text) {
+    fwrite((const char *) (intptr_t) text, 1, haumea_text_bytes(text), stdout);
+}
+
+/* Stops the program because a conversion failed with `error` */
+static void haumea_text_fail(const char *error, const char *function, const char *code, int line) {
+    fprintf(stderr, "Error: %s in %s, at `%s` on line %d\n", error, function, code, line);
+    exit(1);
+}
+
+/* Returns the code of the character that is all of `text`, or stops the program if it isn't one character */
+static long long haumea_character_code(long long text, const char *function, const char *code, int line) {
+    const unsigned char *bytes = (const unsigned char *) (intptr_t) text;
+    long long length = haumea_text_bytes(text);
+    long long character;
+    long long i;
+    if (haumea_text_length(text) != 1) {
+        haumea_text_fail("the text is not one character", function, code, line);
+    }
+    /* The first byte of UTF-8 says how many bytes follow it with its high bits */
+    character = bytes[0] & (length == 1 ? 0x7f : 0x7f >> length);
+    for (i = 1; i < length; i++) {
+        character = character << 6 | (bytes[i] & 0x3f);
+    }
+    return character;
+}
+
+/* Returns the text of the character with the code `character`, or stops the program if there isn't one */
+static long long haumea_character_from_code(long long character, const char *function, const char *code, int line) {
+    char bytes[4];
+    if (character < 1 || character > 0x10ffff || (character >= 0xd800 && character <= 0xdfff)) {
+        haumea_text_fail("the number is not the code of a character", function, code, line);
+    }
+    if (character < 0x80) {
+        bytes[0] = (char) character;
+        return haumea_text_new(bytes, 1);
+    }
+    if (character < 0x800) {
+        bytes[0] = (char) (0xc0 | character >> 6);
+        bytes[1] = (char) (0x80 | (character & 0x3f));
+        return haumea_text_new(bytes, 2);
+    }
+    if (character < 0x10000) {
+        bytes[0] = (char) (0xe0 | character >> 12);
+        bytes[1] = (char) (0x80 | (character >> 6 & 0x3f));
+        bytes[2] = (char) (0x80 | (character & 0x3f));
+        return haumea_text_new(bytes, 3);
+    }
+    bytes[0] = (char) (0xf0 | character >> 18);
+    bytes[1] = (char) (0x80 | (character >> 12 & 0x3f));
+    bytes[2] = (char) (0x80 | (character >> 6 & 0x3f));
+    bytes[3] = (char) (0x80 | (character & 0x3f));
+    return haumea_text_new(bytes, 4);
+}
+
+/* Stops the program if `text` isn't a whole number written in decimal, like `-12` */
+static void haumea_check_number_text(long long text, const char *function, const char *code, int line) {
+    const char *chars = (const char *) (intptr_t) text;
+    long long length = haumea_text_bytes(text);
+    long long i = length > 0 && chars[0] == '-' ? 1 : 0;
+    if (i == length) {
+        haumea_text_fail("the text is not a whole number", function, code, line);
+    }
+    for (; i < length; i++) {
+        if (chars[i] < '0' || chars[i] > '9') {
+            haumea_text_fail("the text is not a whole number", function, code, line);
+        }
+    }
+}
+
+/* Returns the whole number written in `text`, or stops the program if it isn't one or is too large */
+static long long haumea_number_from_text(long long text, const char *function, const char *code, int line) {
+    const char *chars = (const char *) (intptr_t) text;
+    long long length = haumea_text_bytes(text);
+    int negative;
+    unsigned long long limit;
+    unsigned long long magnitude = 0;
+    long long i;
+    haumea_check_number_text(text, function, code, line);
+    negative = chars[0] == '-';
+    limit = negative ? (unsigned long long) LLONG_MAX + 1 : (unsigned long long) LLONG_MAX;
+    for (i = negative; i < length; i++) {
+        unsigned long long digit = chars[i] - '0';
+        if (magnitude > (limit - digit) / 10) {
+            haumea_text_fail("a number too large for a machine integer", function, code, line);
+        }
+        magnitude = magnitude * 10 + digit;
+    }
+    return negative ? (long long) (0ull - magnitude) : (long long) magnitude;
+}
+
+/* Makes the text of `n` written in decimal */
+static long long haumea_text_from_number(long long n) {
+    char digits[24];
+    return haumea_text_new(digits, sprintf(digits, "%lld", n));
+}
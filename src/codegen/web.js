@@ -0,0 +1,25 @@
+/* Haumea web glue */
+/* Runs a program compiled to JavaScript in the web page made by --emit=web. */
+/* What the program displays goes in the page's <pre id="output">, and `read` */
+/* asks for each number with a prompt. */
+
+/* Runs the program with the compiled module's `run` */
+function haumea_start(run) {
+    const output = document.getElementById("output");
+    const write = (text) => {
+        output.textContent += text;
+    };
+    /* Messages after the program's output start on a line of their own */
+    const message = (text) => {
+        const start = output.textContent === "" || output.textContent.endsWith("\n") ? "" : "\n";
+        write(`${start}${text}\n`);
+    };
+    try {
+        const status = run(() => prompt("Enter an integer:"), null, write);
+        if (status !== 0) {
+            message(`The program exited with status ${status}`);
+        }
+    } catch (error) {
+        message(`Error: ${error.message}`);
+    }
+}
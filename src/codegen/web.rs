@@ -0,0 +1,58 @@
+//! web.rs
+//! Bundles a program compiled to JavaScript into a web page.
+//!
+//! `--emit=web` writes a folder with `index.html` and `haumea.js` in it, which
+//! can be shared and opened in a browser straight from the disk. The page has
+//! the compiled module in it, since browsers don't load modules from files,
+//! and `haumea.js` runs it: what the program displays goes in a `<pre>` on the
+//! page, and `read` asks for each number with a prompt.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The script that runs the compiled program in the page
+pub const GLUE: &str = include_str!("web.js");
+
+/// Returns the web page that runs `module`, the program called `title` compiled to JavaScript
+///
+/// # Examples
+/// ```
+/// # use haumea::codegen::web;
+/// let page = web::page("hello", "export function run() { return 0; }");
+/// assert!(page.contains("<title>hello</title>"));
+/// assert!(page.contains("<pre id=\"output\"></pre>"));
+/// ```
+pub fn page(title: &str, module: &str) -> String {
+    let title = html_escape(title);
+    // `</script` in a string would end the script early, and `<\/` in a string is the same text
+    let module = module.replace("</script", "<\\/script");
+    format!("<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<script src=\"haumea.js\"></script>
+</head>
+<body>
+<h1>{title}</h1>
+<pre id=\"output\"></pre>
+<script type=\"module\">
+{module}
+haumea_start(run);
+</script>
+</body>
+</html>
+", title = title, module = module)
+}
+
+/// Writes the web page that runs `module`, the program called `title`, and its glue to the folder `dir`
+pub fn write(dir: &Path, title: &str, module: &str) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("index.html"), page(title, module))?;
+    fs::write(dir.join("haumea.js"), GLUE)
+}
+
+/// Escapes `text` to be written in HTML
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,147 @@
+//! src/constants.rs
+//! Evaluates constant declarations at compile time.
+//!
+//! `define SIZE as 8 * 8` declares a constant. Its value can be any
+//! expression made of numbers, operators and constants declared before it, and
+//! is computed by the compiler using the same rules as the C backend. Every use
+//! of the constant in the file's functions is then replaced by its value.
+use std::collections::HashMap;
+use std::mem;
+use parser::{Constant, Expression, Function, Operator, Span, Statement};
+use ast::{rebuild_expression, rebuild_statement, Folder};
+use symbol::Symbol;
+
+/// The values of a file's constants, by name
+pub type Values = HashMap<Symbol, i64>;
+
+/// Evaluates `constants` in order, returning their values
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse_module;
+/// # use haumea::constants::evaluate_all;
+/// # use haumea::symbol::Symbol;
+/// let module = parse_module(Scanner::new("define SIZE as 8 * 8 define HALF as SIZE / 2")).unwrap();
+/// let values = evaluate_all(&module.constants).unwrap();
+/// assert_eq!(values[&Symbol::from("SIZE")], 64);
+/// assert_eq!(values[&Symbol::from("HALF")], 32);
+/// ```
+pub fn evaluate_all(constants: &[Constant]) -> Result<Values, String> {
+    let mut values = Values::new();
+    for constant in constants {
+        if values.contains_key(&constant.name) {
+            return Err(format!("the constant `{}` is defined more than once", constant.name));
+        }
+        let value = evaluate(&constant.value, &values)
+            .map_err(|error| format!("in the constant `{}`, {}", constant.name, error))?;
+        values.insert(constant.name, value);
+    }
+    Ok(values)
+}
+
+/// Returns the value of the constant expression `expression`
+pub fn evaluate(expression: &Expression, values: &Values) -> Result<i64, String> {
+    match *expression {
+        Expression::Integer(n, _) => Ok(n),
+        Expression::BigInteger(..) => Err("its value is too large".to_string()),
+        Expression::Boolean(value, _) => Ok(value as i64),
+        Expression::Ident(ref name, _) => values.get(name).cloned()
+            .ok_or_else(|| format!("`{}` is not a constant defined before it", name)),
+        Expression::Call { ref function, .. } => {
+            Err(format!("the function `{}` can't be called in a constant", function))
+        },
+        Expression::Str(..) => Err("a constant must be a number, not text".to_string()),
+        Expression::Float(..) => Err("a constant must be a whole number".to_string()),
+        Expression::List(..) | Expression::Index { .. } | Expression::Length(..) => {
+            Err("a constant must be a number, not a list".to_string())
+        },
+        Expression::UnaryOp { ref operator, ref expression, .. } => {
+            let value = evaluate(expression, values)?;
+            match *operator {
+                Operator::Negate | Operator::Sub => value.checked_neg().ok_or_else(overflow),
+                Operator::LogicalNot => Ok((value == 0) as i64),
+                Operator::BinaryNot => Ok(!value),
+                ref op => Err(format!("`{}` is not a unary operator", op)),
+            }
+        },
+        Expression::BinaryOp { ref operator, ref left, ref right, .. } => {
+            let left = evaluate(left, values)?;
+            let right = evaluate(right, values)?;
+            match *operator {
+                Operator::Add => left.checked_add(right).ok_or_else(overflow),
+                Operator::Sub => left.checked_sub(right).ok_or_else(overflow),
+                Operator::Mul => left.checked_mul(right).ok_or_else(overflow),
+                Operator::Div | Operator::Modulo if right == 0 => Err("it divides by zero".to_string()),
+                Operator::Div => left.checked_div(right).ok_or_else(overflow),
+                Operator::Modulo => left.checked_rem(right).ok_or_else(overflow),
+                Operator::Equals => Ok((left == right) as i64),
+                Operator::NotEquals => Ok((left != right) as i64),
+                Operator::Gt => Ok((left > right) as i64),
+                Operator::Lt => Ok((left < right) as i64),
+                Operator::Gte => Ok((left >= right) as i64),
+                Operator::Lte => Ok((left <= right) as i64),
+                Operator::LogicalAnd => Ok((left != 0 && right != 0) as i64),
+                Operator::LogicalOr => Ok((left != 0 || right != 0) as i64),
+                Operator::BinaryAnd => Ok(left & right),
+                Operator::BinaryOr => Ok(left | right),
+                Operator::BinaryXor => Ok(left ^ right),
+                // Only the low 6 bits of the count are used, like when the program runs
+                Operator::ShiftLeft => Ok(left.wrapping_shl((right & 63) as u32)),
+                Operator::ShiftRight => Ok(left >> (right & 63)),
+                ref op => Err(format!("`{}` is not a binary operator", op)),
+            }
+        },
+    }
+}
+
+fn overflow() -> String {
+    "its value is too large".to_string()
+}
+
+/// Replaces every use of a constant in `func` with its value
+///
+/// Returns an error if `func` uses the name of a constant for a parameter or variable.
+pub fn substitute(func: &mut Function, values: &Values) -> Result<(), String> {
+    if let Some(ref sig) = func.signature {
+        if let Some(param) = sig.iter().find(|param| values.contains_key(*param)) {
+            return Err(format!("the parameter `{}` of `{}` has the same name as a constant", param, func.name));
+        }
+    }
+    let mut substituter = Substituter { values, error: None };
+    let code = mem::replace(&mut func.code, Statement::Do(vec![], Span::default()));
+    func.code = substituter.fold_statement(code);
+    match substituter.error {
+        Some(error) => Err(format!("in `{}`, {}", func.name, error)),
+        None => Ok(()),
+    }
+}
+
+/// Replaces constants with their values
+struct Substituter<'a> {
+    values: &'a Values,
+    /// The first error found
+    error: Option<String>,
+}
+
+impl<'a> Folder for Substituter<'a> {
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        match statement {
+            Statement::Set(ref name, ..) |
+            Statement::Change(ref name, ..) |
+            Statement::Var(ref name, _) |
+            Statement::ForEach { ident: ref name, .. } if self.values.contains_key(name) && self.error.is_none() => {
+                self.error = Some(format!("the constant `{}` can't be changed", name));
+            },
+            _ => (),
+        }
+        rebuild_statement(self, statement)
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        match expression {
+            Expression::Ident(ref name, span) if self.values.contains_key(name) => Expression::Integer(self.values[name], span),
+            expression => rebuild_expression(self, expression),
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! src/cst.rs
+//! A concrete syntax tree, which keeps every character of the source.
+//!
+//! Unlike the syntax tree built by the parser, this tree keeps the whitespace
+//! and comments (called trivia) between tokens, and the exact text of every
+//! token, so printing it gives back the source it was built from. Tools like
+//! formatters and editors can change the tokens they care about and print the
+//! tree again, without disturbing the rest of the user's formatting, and
+//! `lower` turns the tree into the parser's syntax tree.
+//!
+//! The root node holds a `Declaration` node for each use, directive, constant,
+//! template and function, and the trivia between them. Inside a declaration,
+//! `do ... end` blocks and parentheses are nodes of their own.
+use std::ops::Range;
+use scanner::{Scanner, Token};
+use parser::{self, Module, ParseError};
+
+/// The kind of a token or node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    /// Spaces, tabs and newlines
+    Whitespace,
+    /// A comment, like `/* ... */`
+    Comment,
+    Number,
+    Ident,
+    Keyword,
+    Operator,
+    StringLit,
+    Lp,
+    Rp,
+    Lb,
+    Rb,
+    Comma,
+    Colon,
+    /// A character the scanner doesn't understand
+    Error,
+    /// The node holding a whole file
+    Root,
+    /// A node holding a use, directive, constant, template or function
+    Declaration,
+    /// A node holding a `do ... end` block
+    Block,
+    /// A node holding an expression in parentheses
+    Parens,
+}
+
+impl SyntaxKind {
+    /// Returns whether a token of this kind is trivia, which the parser skips
+    pub fn is_trivia(&self) -> bool {
+        *self == SyntaxKind::Whitespace || *self == SyntaxKind::Comment
+    }
+}
+
+/// A token, with its exact text from the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub kind: SyntaxKind,
+    pub text: String,
+}
+
+/// A node, holding tokens and other nodes in source order
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+/// A child of a node
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind) -> SyntaxNode {
+        SyntaxNode { kind, children: vec![] }
+    }
+
+    /// Returns the source code of the node, including its trivia
+    pub fn text(&self) -> String {
+        self.tokens().iter().map(|token| &token.text[..]).collect()
+    }
+
+    /// Returns the tokens in the node, in source order
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = vec![];
+        for child in &self.children {
+            match *child {
+                SyntaxElement::Node(ref node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(ref token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    /// Returns the tokens in the node, in source order, so they can be changed
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::cst::{self, SyntaxKind};
+    /// let mut tree = cst::parse("to main do /* keep me */\n    return  size end");
+    /// for token in tree.tokens_mut() {
+    ///     if token.kind == SyntaxKind::Ident && token.text == "size" {
+    ///         token.text = "length".to_string();
+    ///     }
+    /// }
+    /// assert_eq!(tree.text(), "to main do /* keep me */\n    return  length end");
+    /// ```
+    pub fn tokens_mut(&mut self) -> Vec<&mut SyntaxToken> {
+        let mut tokens = vec![];
+        for child in &mut self.children {
+            match *child {
+                SyntaxElement::Node(ref mut node) => tokens.extend(node.tokens_mut()),
+                SyntaxElement::Token(ref mut token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    /// Returns the nodes directly inside this node
+    pub fn child_nodes(&self) -> Vec<&SyntaxNode> {
+        self.children.iter().filter_map(|child| match *child {
+            SyntaxElement::Node(ref node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        }).collect()
+    }
+
+    /// Parses the node's source code into the parser's syntax tree
+    ///
+    /// Like the parser, this returns every mistake if the code is not valid.
+    pub fn lower(&self) -> Result<Module, Vec<ParseError>> {
+        parser::parse_module(Scanner::new(&self.text()))
+    }
+}
+
+/// Builds the concrete syntax tree of `source`
+///
+/// Panics with the parser's first error if `source` is not valid.
+///
+/// # Examples
+/// ```
+/// # use haumea::cst::{self, SyntaxKind};
+/// let source = "/* Squares */\nto square with (n) return n * n\n";
+/// let tree = cst::parse(source);
+/// assert_eq!(tree.text(), source);
+/// assert_eq!(tree.child_nodes()[0].text(), "to square with (n) return n * n");
+/// assert_eq!(tree.child_nodes()[0].child_nodes()[0].kind, SyntaxKind::Parens);
+/// ```
+pub fn parse(source: &str) -> SyntaxNode {
+    let mut scanner = Scanner::new(source);
+    let mut tokens = vec![];
+    loop {
+        match scanner.next_token_with_range() {
+            (Token::EOF(_), _) => break,
+            token => tokens.push(token),
+        }
+    }
+    let sizes = match parser::declaration_sizes(tokens.iter().map(|(token, _)| token.clone())) {
+        Ok(sizes) => sizes,
+        Err(error) => panic!("{}", error),
+    };
+
+    let mut root = SyntaxNode::new(SyntaxKind::Root);
+    // The declaration being built, and the blocks and parentheses open inside it
+    let mut open: Vec<SyntaxNode> = vec![];
+    let mut sizes = sizes.into_iter();
+    let mut left_in_declaration = 0;
+    let mut end_of_last = 0;
+    for (token, range) in tokens {
+        let trivia = trivia(&source[end_of_last..range.start]);
+        end_of_last = range.end;
+        match open.last_mut() {
+            Some(node) => node.children.extend(trivia),
+            None => root.children.extend(trivia),
+        }
+        if left_in_declaration == 0 {
+            left_in_declaration = sizes.next().expect("Every token is in a declaration");
+            open.push(SyntaxNode::new(SyntaxKind::Declaration));
+        }
+        let kind = token_kind(&token);
+        let is_do = kind == SyntaxKind::Keyword && &source[range.clone()] == "do";
+        let is_end = kind == SyntaxKind::Keyword && &source[range.clone()] == "end";
+        if is_do || kind == SyntaxKind::Lp {
+            open.push(SyntaxNode::new(if is_do { SyntaxKind::Block } else { SyntaxKind::Parens }));
+        }
+        open.last_mut().unwrap().children.push(SyntaxElement::Token(SyntaxToken {
+            kind,
+            text: source[range].to_string(),
+        }));
+        let closes = match open.last().unwrap().kind {
+            SyntaxKind::Block => is_end,
+            SyntaxKind::Parens => kind == SyntaxKind::Rp,
+            _ => false,
+        };
+        if closes {
+            close(&mut open);
+        }
+        left_in_declaration -= 1;
+        if left_in_declaration == 0 {
+            while open.len() > 1 {
+                close(&mut open);
+            }
+            root.children.push(SyntaxElement::Node(open.pop().unwrap()));
+        }
+    }
+    root.children.extend(trivia(&source[end_of_last..]));
+    root
+}
+
+/// A change to source code: the text in `range`, in bytes, is replaced by `text`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl TextEdit {
+    /// Returns `source` with the edit made to it
+    pub fn apply(&self, source: &str) -> String {
+        format!("{}{}{}", &source[..self.range.start], self.text, &source[self.range.end..])
+    }
+}
+
+/// The result of `parse_incremental`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reparsed {
+    /// The tree of the edited source
+    pub tree: SyntaxNode,
+    /// The positions in `tree.children` of the nodes and trivia that were parsed
+    /// again; everything outside of this range was kept from the old tree
+    pub changed: Range<usize>,
+}
+
+/// Returns the tree of `old_tree`'s source after `edit` is made to it
+///
+/// Only the declarations next to the edit are parsed again, and the rest are
+/// copied from the old tree, so a small edit to a large file is parsed
+/// quickly. The tree is the same as `parse` would build from the edited source.
+///
+/// # Examples
+/// ```
+/// # use haumea::cst::{self, TextEdit};
+/// let source = "to one return 1\nto two return 2\nto three return 3\nto four return 4\n";
+/// let tree = cst::parse(source);
+/// let edit = TextEdit { range: 65..66, text: "5".to_string() };
+/// let reparsed = cst::parse_incremental(&tree, &edit);
+/// assert_eq!(reparsed.tree, cst::parse(&edit.apply(source)));
+/// assert_eq!(reparsed.changed, 4..8);
+/// ```
+pub fn parse_incremental(old_tree: &SyntaxNode, edit: &TextEdit) -> Reparsed {
+    let old_text = old_tree.text();
+    let new_text = edit.apply(&old_text);
+    // A comment or string starting or ending in the edit can change how
+    // everything after it is read
+    let removed = &old_text[edit.range.clone()];
+    if ["/*", "*/", "\""].iter().any(|marker| edit.text.contains(marker) || removed.contains(marker)) {
+        let tree = parse(&new_text);
+        let changed = 0..tree.children.len();
+        return Reparsed { tree, changed };
+    }
+
+    // Where each child of the root starts and ends in the old source
+    let mut ranges = vec![];
+    let mut offset = 0;
+    for child in &old_tree.children {
+        let len = match *child {
+            SyntaxElement::Node(ref node) => node.text().len(),
+            SyntaxElement::Token(ref token) => token.text.len(),
+        };
+        ranges.push(offset..offset + len);
+        offset += len;
+    }
+    let is_declaration = |i: usize| match old_tree.children[i] {
+        SyntaxElement::Node(_) => true,
+        SyntaxElement::Token(_) => false,
+    };
+    // The children touching the edit. Edits at the edge of a child can join onto its tokens.
+    let touched: Vec<usize> = (0..ranges.len())
+        .filter(|&i| ranges[i].start <= edit.range.end && ranges[i].end >= edit.range.start)
+        .collect();
+    let (mut first, mut last) = match (touched.first(), touched.last()) {
+        (Some(&first), Some(&last)) => (first, last + 1),
+        _ => (0, ranges.len()),
+    };
+    // The declaration before can continue into the edit, and the edit can continue
+    // into the declaration after, so they are parsed again as well
+    first = (0..first).rev().find(|&i| is_declaration(i)).unwrap_or(0);
+    last = (last..ranges.len()).find(|&i| is_declaration(i)).map(|i| i + 1).unwrap_or(ranges.len());
+
+    let start = if first < ranges.len() { ranges[first].start } else { old_text.len() };
+    let end = if last > 0 { ranges[last - 1].end } else { 0 };
+    let start = start.min(edit.range.start);
+    let end = end.max(edit.range.end);
+    let new_end = end + edit.text.len() - (edit.range.end - edit.range.start);
+    let middle = parse(&new_text[start..new_end]);
+
+    let mut tree = SyntaxNode::new(SyntaxKind::Root);
+    tree.children.extend(old_tree.children[..first].iter().cloned());
+    let changed = first..first + middle.children.len();
+    tree.children.extend(middle.children);
+    tree.children.extend(old_tree.children[last..].iter().cloned());
+    Reparsed { tree, changed }
+}
+
+/// Moves the innermost open node into the node it is in
+fn close(open: &mut Vec<SyntaxNode>) {
+    let node = open.pop().unwrap();
+    open.last_mut().unwrap().children.push(SyntaxElement::Node(node));
+}
+
+fn token_kind(token: &Token) -> SyntaxKind {
+    match *token {
+        Token::Number(..) | Token::BigNumber(..) | Token::Float(..) => SyntaxKind::Number,
+        Token::TooLarge(..) => SyntaxKind::Error,
+        Token::Ident(..) => SyntaxKind::Ident,
+        Token::Keyword(..) => SyntaxKind::Keyword,
+        Token::Operator(..) => SyntaxKind::Operator,
+        Token::StringLit(..) => SyntaxKind::StringLit,
+        Token::Comment(..) => SyntaxKind::Comment,
+        Token::Lp(_) => SyntaxKind::Lp,
+        Token::Rp(_) => SyntaxKind::Rp,
+        Token::Lb(_) => SyntaxKind::Lb,
+        Token::Rb(_) => SyntaxKind::Rb,
+        Token::Comma(_) => SyntaxKind::Comma,
+        Token::Colon(_) => SyntaxKind::Colon,
+        Token::Error(..) | Token::BadEscape(..) | Token::UnterminatedComment(_) | Token::EOF(_) => SyntaxKind::Error,
+    }
+}
+
+/// Splits the text between two tokens into whitespace and comments
+fn trivia(text: &str) -> Vec<SyntaxElement> {
+    let mut elements = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (kind, len) = if rest.starts_with("/*") {
+            (SyntaxKind::Comment, comment_len(rest))
+        } else if rest.starts_with("#!") {
+            (SyntaxKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else {
+            // The scanner also skips the byte order mark a file can start with
+            match rest.find(|c: char| !c.is_whitespace() && c != '\u{feff}').unwrap_or(rest.len()) {
+                // The scanner skipped this, so it must be the end of a comment
+                0 => (SyntaxKind::Comment, rest.len()),
+                len => (SyntaxKind::Whitespace, len),
+            }
+        };
+        elements.push(SyntaxElement::Token(SyntaxToken { kind, text: rest[..len].to_string() }));
+        rest = &rest[len..];
+    }
+    elements
+}
+
+/// Returns the length of the comment at the start of `text`, which can contain other comments
+fn comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+    text.len()
+}
@@ -0,0 +1,102 @@
+//! src/dead_code.rs
+//! Removes the functions that a program never calls.
+//!
+//! The calls in each function make a call graph, which is followed from the
+//! functions that are run from outside the program: `main`, and the public
+//! functions of a library. Every function it doesn't reach is dead, so it can
+//! be left out of the compiled program. This is what `--opt dead-code` does,
+//! which keeps the unused functions of the prelude and of used files out of
+//! the generated code, and warns about the program's own ones.
+use std::collections::{HashMap, HashSet};
+use parser::{Expression, Function, Ident, Program, Statement, Visibility};
+use ast::{self, Visitor};
+use check::{Problem, Severity};
+
+/// Returns the functions that are run from outside the program: `main`, and
+/// every public function of a `library`, which is called by whatever links it
+pub fn roots(program: &[Function], library: bool) -> Vec<Ident> {
+    program.iter()
+        .filter(|func| func.name == "main" || (library && func.visibility == Visibility::Public))
+        .map(|func| func.name)
+        .collect()
+}
+
+/// Returns the names of the `roots` and of every function they call, directly
+/// or through other functions
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::symbol::Symbol;
+/// # use haumea::dead_code::reachable;
+/// let program = parse(Scanner::new("to a return b() to b return 1 to c return a() to main return a()")).unwrap();
+/// let reached = reachable(&program, &[Symbol::from("main")]);
+/// assert!(reached.contains(&Symbol::from("b")));
+/// assert!(!reached.contains(&Symbol::from("c")));
+/// ```
+pub fn reachable(program: &[Function], roots: &[Ident]) -> HashSet<Ident> {
+    let graph: HashMap<Ident, &Function> = program.iter().map(|func| (func.name, func)).collect();
+    let mut reached: HashSet<Ident> = HashSet::new();
+    let mut waiting: Vec<Ident> = roots.to_vec();
+    while let Some(name) = waiting.pop() {
+        // Builtins and undefined functions aren't in the program
+        let func = match graph.get(&name) {
+            Some(func) if reached.insert(name) => func,
+            _ => continue,
+        };
+        let mut calls = Calls(vec![]);
+        ast::walk_function(&mut calls, func);
+        waiting.extend(calls.0.into_iter().filter(|callee| !reached.contains(callee)));
+    }
+    reached
+}
+
+/// Returns a warning for each function in `program` that the `roots` never call
+pub fn warnings(program: &[Function], roots: &[Ident]) -> Vec<Problem> {
+    let reached = reachable(program, roots);
+    program.iter()
+        .filter(|func| !reached.contains(&func.name))
+        .map(|func| Problem {
+            severity: Severity::Warning,
+            code: "W0310",
+            function: func.name.to_string(),
+            span: func.span,
+            message: format!("`{}` is never called, so it is left out of the compiled program", func.name),
+        })
+        .collect()
+}
+
+/// Returns `program` without the functions that the `roots` never call
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::symbol::Symbol;
+/// # use haumea::dead_code::eliminate;
+/// let program = parse(Scanner::new("to unused return 1 to main return 0")).unwrap();
+/// let program = eliminate(program, &[Symbol::from("main")]);
+/// assert_eq!(program.len(), 1);
+/// ```
+pub fn eliminate(program: Program, roots: &[Ident]) -> Program {
+    let reached = reachable(&program, roots);
+    program.into_iter().filter(|func| reached.contains(&func.name)).collect()
+}
+
+/// Collects the names of the functions called
+struct Calls(Vec<Ident>);
+
+impl Visitor for Calls {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Call { function, .. } = *statement {
+            self.0.push(function);
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::Call { function, .. } = *expression {
+            self.0.push(function);
+        }
+    }
+}
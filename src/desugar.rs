@@ -0,0 +1,138 @@
+//! src/desugar.rs
+//! Lowers the statements that are shorthand for other statements.
+//!
+//! After desugaring, a program only uses the core statements: `return`, `do`,
+//! `variable`, `set`, `if`, `while`, `break`, `continue` and calls. So
+//!
+//! forever display(1)
+//!
+//! becomes `while 1 display(1)`, `change x by 2` becomes `set x to x + 2`, and
+//! `for each` becomes a `while` loop over a variable, with its start, end and
+//! step computed once before the loop, as the C backend does.
+use std::mem;
+use std::sync::Arc;
+use parser::{Expression, Operator, Program, Statement};
+use names::UniqueNames;
+use symbol::Symbol;
+use ast::{expr, fold_program, rebuild_statement, stmt, Folder};
+
+/// Returns `program` with every statement lowered to a core statement
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::desugar::desugar;
+/// let program = desugar(parse(Scanner::new("to main forever change x by 2")).unwrap());
+/// assert_eq!(program, parse(Scanner::new("to main while 1 set x to x + 2")).unwrap());
+/// ```
+pub fn desugar(program: Program) -> Program {
+    let mut desugarer = Desugarer {
+        names: UniqueNames::new("__HAUMEA_LOOP_"),
+    };
+    fold_program(&mut desugarer, program)
+}
+
+struct Desugarer {
+    /// Generates the names of the variables holding a loop's range
+    names: UniqueNames,
+}
+
+impl Desugarer {
+    /// Returns the core statements for a `for each` loop
+    fn for_each(&mut self, ident: Symbol, start: Expression, end: Expression, by: Expression,
+                range_type: &str, body: Statement) -> Statement {
+        let (comparitor, neg_comparitor) = match range_type {
+            "to" => (Operator::Lt, Operator::Gt),
+            "through" => (Operator::Lte, Operator::Gte),
+            _ => panic!("Invalid range type {:?}!", range_type),
+        };
+        let start_name = self.names.fresh();
+        let end_name = self.names.fresh();
+        let by_name = self.names.fresh();
+        // Counts up if the range goes up, and down if it goes down, whatever the sign of `by`
+        let up = || expr::binary(Operator::Lte, expr::ident(&start_name), expr::ident(&end_name));
+        let down = || expr::binary(Operator::Gt, expr::ident(&start_name), expr::ident(&end_name));
+        let turn = stmt::if_then(
+            expr::binary(Operator::LogicalOr,
+                expr::binary(Operator::LogicalAnd, up(), expr::binary(Operator::Lt, expr::ident(&by_name), expr::int(0))),
+                expr::binary(Operator::LogicalAnd, down(), expr::binary(Operator::Gt, expr::ident(&by_name), expr::int(0)))),
+            stmt::set(&by_name, expr::neg(expr::ident(&by_name))));
+        let cond = expr::binary(Operator::LogicalOr,
+            expr::binary(Operator::LogicalAnd, up(), expr::binary(comparitor, expr::ident(&ident), expr::ident(&end_name))),
+            expr::binary(Operator::LogicalAnd, down(), expr::binary(neg_comparitor, expr::ident(&ident), expr::ident(&end_name))));
+        let step = stmt::set(&ident, expr::add(expr::ident(&ident), expr::ident(&by_name)));
+        let mut body = continue_after(body, &step);
+        if let Statement::Do(ref mut block, _) = body {
+            block.push(Arc::new(step));
+        } else {
+            body = block![body, step];
+        }
+        block![
+            stmt::var(&start_name),
+            stmt::set(&start_name, start),
+            stmt::var(&end_name),
+            stmt::set(&end_name, end),
+            stmt::var(&by_name),
+            stmt::set(&by_name, by),
+            turn,
+            stmt::var(&ident),
+            stmt::set(&ident, expr::ident(&start_name)),
+            stmt::while_loop(cond, body),
+        ]
+    }
+}
+
+/// Returns `statement` with `step` run before each `continue` that goes on to the loop it is in
+///
+/// The `continue`s inside inner loops go on to those loops, so they are left alone.
+fn continue_after(mut statement: Statement, step: &Statement) -> Statement {
+    match statement {
+        Statement::Continue(span) => {
+            return Statement::Do(vec![Arc::new(step.clone()), Arc::new(stmt::continue_loop())], span);
+        },
+        Statement::If { ref mut if_clause, ref mut else_clause, .. } => {
+            continue_after_in(Arc::make_mut(if_clause), step);
+            if let Some(ref mut else_clause) = *Arc::make_mut(else_clause) {
+                continue_after_in(else_clause, step);
+            }
+        },
+        Statement::Do(ref mut block, _) => {
+            for sub in block {
+                continue_after_in(Arc::make_mut(sub), step);
+            }
+        },
+        _ => (),
+    }
+    statement
+}
+
+/// Changes `statement` where it is, like `continue_after`
+fn continue_after_in(statement: &mut Statement, step: &Statement) {
+    *statement = continue_after(mem::take(statement), step);
+}
+
+impl Folder for Desugarer {
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        let mut statement = rebuild_statement(self, statement);
+        match statement {
+            Statement::Forever(ref mut body, label, span) => Statement::While { cond: expr::int(1), body: mem::take(body), label, span },
+            Statement::Change(name, ref mut amount, span) => {
+                let value = expr::add(expr::ident(&name), mem::take(amount));
+                Statement::Set(name, value, span)
+            },
+            Statement::ForEach { ident, ref mut start, ref mut end, ref mut by, ref range_type, ref mut body, label, span } => {
+                let body = Arc::unwrap_or_clone(mem::take(body));
+                let mut lowered = self.for_each(ident, mem::take(start), mem::take(end), mem::take(by), range_type, body);
+                // The label moves to the `while` at the end of the lowered block
+                if let (Some(label), Statement::Do(ref mut block, _)) = (label, &mut lowered) {
+                    let lowered_loop = block.pop().expect("a lowered for each ends with its loop");
+                    block.push(Arc::new(stmt::labeled(&label, (*lowered_loop).clone())));
+                }
+                *lowered.span_mut() = span;
+                lowered
+            },
+            statement => statement,
+        }
+    }
+}
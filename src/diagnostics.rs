@@ -0,0 +1,150 @@
+//! src/diagnostics.rs
+//! Reports the mistakes found in a program, with the code they are in.
+//!
+//! Each pass finds mistakes of its own kinds, like `ParseError` and
+//! `SemanticError`, and turns them into a `Diagnostic` to be reported. A
+//! diagnostic has a code naming its kind, like `E0201`, and is rendered like
+//! rustc's, with the line it is on and a caret under the code it is about:
+//!
+//! ```text
+//! error[E0201]: the variable `x` is not declared, add `variable x` before it is used
+//!  --> main.hau:1:12
+//!   |
+//! 1 | to main do set x to 5 end
+//!   |            ^^^^^^^^^^
+//!   = note: in the function `main`
+//! ```
+use std::fmt;
+use parser::Span;
+use scanner::ScanState;
+
+/// How serious a mistake is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The program can still be compiled
+    Warning,
+    /// The program can't be compiled
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A mistake in a program, ready to be reported
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The kind of mistake, like "E0201", which stays the same when the message changes
+    pub code: &'static str,
+    /// What is wrong
+    pub message: String,
+    /// The code the mistake is in, which is empty if it isn't at one place
+    pub span: Span,
+    /// More about the mistake, like the function it is in
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Constructs an error with no notes
+    pub fn error(code: &'static str, message: String, span: Span) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, code, message, span, notes: vec![] }
+    }
+
+    /// Constructs a warning with no notes
+    pub fn warning(code: &'static str, message: String, span: Span) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, code, message, span, notes: vec![] }
+    }
+
+    /// Adds `note` after the notes the diagnostic already has
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders the diagnostic with the line of `source` it is on, if the source is known
+    ///
+    /// `path` is the file the source was read from, if it was.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::diagnostics::Diagnostic;
+    /// # use haumea::parser::Span;
+    /// # use haumea::scanner::ScanState;
+    /// let span = Span::new(ScanState::new(2, 3), ScanState::new(2, 3));
+    /// let diagnostic = Diagnostic::error("E0202", "`shout` is not defined".to_string(), span)
+    ///     .with_note("in the function `main`");
+    /// assert_eq!(diagnostic.render(Some("to main do\n  shout(1)\nend"), Some("main.hau")),
+    ///            "error[E0202]: `shout` is not defined
+    ///  --> main.hau:2:3
+    ///   |
+    /// 2 |   shout(1)
+    ///   |   ^^^^^
+    ///   = note: in the function `main`
+    /// ");
+    /// ```
+    pub fn render(&self, source: Option<&str>, path: Option<&str>) -> String {
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+        let line = match source {
+            Some(source) if !self.span.is_empty() => source.lines().nth(self.span.start.line as usize - 1),
+            _ => None,
+        };
+        let (start, end) = (self.span.start.column.max(1) as usize, self.span.end.column.max(1) as usize);
+        if !self.span.is_empty() {
+            out.push_str(&format!(" --> {}{}:{}\n", path.map_or(String::new(), |path| format!("{}:", path)),
+                                  self.span.start.line, start));
+        }
+        let number = self.span.start.line.to_string();
+        let margin = " ".repeat(number.len());
+        if let Some(line) = line {
+            let line = line.trim_end_matches('\r');
+            // The underline runs to the end of the span's last token, if it is on the same line
+            let last = if self.span.end.line == self.span.start.line && end >= start { end } else { start };
+            let length = last - start + token_length(line, last);
+            out.push_str(&format!("{} |\n", margin));
+            out.push_str(&format!("{} | {}\n", number, line));
+            out.push_str(&format!("{} | {}{}\n", margin, indentation(line, start), "^".repeat(length)));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{} = note: {}\n", margin, note));
+        }
+        out
+    }
+}
+
+/// Writes the diagnostic on one line, like "error[E0201]: at line 1:12, the variable `x` is not declared"
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: ", self.severity, self.code)?;
+        if !self.span.is_empty() {
+            write!(f, "at line {}, ", self.span)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Returns the span of the single character at `state`
+pub fn point(state: ScanState) -> Span {
+    Span::new(state, state)
+}
+
+/// Returns true if any of `diagnostics` is an error
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+/// Returns the whitespace that lines a caret up under `column` of `line`, keeping its tabs
+fn indentation(line: &str, column: usize) -> String {
+    line.chars().take(column - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect()
+}
+
+/// Returns how many characters long the token starting at `column` of `line` is, at least 1
+fn token_length(line: &str, column: usize) -> usize {
+    let rest = line.chars().skip(column - 1);
+    rest.take_while(|&c| c.is_alphanumeric() || c == '_').count().max(1)
+}
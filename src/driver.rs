@@ -0,0 +1,221 @@
+//! src/driver.rs
+//! Compiles generated C with the system's C compiler and runs the program.
+//!
+//! This is what `haumea run` does for programs the interpreter can't run, and
+//! for every program with `--via c`. The C is written to a directory of its
+//! own in the system's temporary directory and compiled with `$CC`, or `cc`
+//! if it isn't set. The program shares `haumea`'s stdin, stdout and stderr,
+//! so its output is streamed as it is written. The directory is removed once
+//! the program has finished.
+//!
+//! When the C compiler fails, the error has the compiler's messages and the
+//! lines of the generated C they are about, since a bug in the C backend is
+//! much easier to find with them.
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many lines of the generated C are shown before and after each line
+/// the C compiler reports a mistake on
+const CONTEXT_LINES: usize = 2;
+
+/// How many names `private_dir` tries before giving up
+const DIR_ATTEMPTS: usize = 100;
+
+/// An error that stopped a program from being compiled or run
+#[derive(Debug)]
+pub enum DriverError {
+    /// A file could not be written, or the C compiler could not be started
+    Io(io::Error),
+    /// The C compiler ran, but failed
+    Compile {
+        /// The C compiler, like `cc`
+        compiler: String,
+        /// The compiler's exit status, or None if it was stopped by a signal
+        status: Option<i32>,
+        /// What the compiler wrote to stderr
+        messages: String,
+        /// The lines of the generated C the messages are about, with their numbers
+        context: Vec<(usize, String)>,
+    },
+    /// The built program could not be started
+    Run {
+        program: PathBuf,
+        error: io::Error,
+    },
+    /// The built program was killed by the signal `signal`
+    Killed {
+        signal: i32,
+    },
+}
+
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DriverError::Io(ref error) => write!(f, "{}", error),
+            DriverError::Compile { ref compiler, status, ref messages, ref context } => {
+                match status {
+                    Some(status) => write!(f, "`{}` failed with exit status {}", compiler, status)?,
+                    None => write!(f, "`{}` was stopped by a signal", compiler)?,
+                }
+                if !messages.trim().is_empty() {
+                    write!(f, "\n{}", messages.trim_end())?;
+                }
+                if !context.is_empty() {
+                    write!(f, "\nnote: the generated C is")?;
+                    let width = context.last().map_or(0, |&(number, _)| number.to_string().len());
+                    let mut last = 0;
+                    for &(number, ref line) in context {
+                        if last != 0 && number > last + 1 {
+                            write!(f, "\n{:>width$} |", "...", width = width)?;
+                        }
+                        write!(f, "\n{:>width$} | {}", number, line, width = width)?;
+                        last = number;
+                    }
+                }
+                Ok(())
+            },
+            DriverError::Run { ref program, ref error } => {
+                write!(f, "could not run {}: {}", program.display(), error)
+            },
+            DriverError::Killed { signal } => write!(f, "the program was killed by signal {}", signal),
+        }
+    }
+}
+
+impl From<io::Error> for DriverError {
+    fn from(error: io::Error) -> DriverError {
+        DriverError::Io(error)
+    }
+}
+
+/// Returns the C compiler to use, which is `$CC` if it is set, or else `cc`
+pub fn c_compiler() -> String {
+    env::var("CC").ok().filter(|compiler| !compiler.trim().is_empty()).unwrap_or_else(|| "cc".to_string())
+}
+
+/// Makes a new directory in the system's temporary directory that only its
+/// owner can use, with a name starting with `prefix`
+///
+/// Unlike `fs::create_dir_all`, this fails rather than use a directory or
+/// link that is already there, which someone else could have made.
+///
+/// # Examples
+/// ```
+/// # use haumea::driver::private_dir;
+/// let first = private_dir("haumea_doc").unwrap();
+/// let second = private_dir("haumea_doc").unwrap();
+/// assert_ne!(first, second);
+/// # std::fs::remove_dir(first).unwrap();
+/// # std::fs::remove_dir(second).unwrap();
+/// ```
+pub fn private_dir(prefix: &str) -> io::Result<PathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    for _ in 0..DIR_ATTEMPTS {
+        // The time makes the name hard to guess, and the count tells apart the directories of one process
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.subsec_nanos());
+        let name = format!("{}_{}_{}_{}", prefix, process::id(), COUNT.fetch_add(1, Ordering::Relaxed), nanos);
+        let dir = env::temp_dir().join(name);
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "could not make a new temporary directory"))
+}
+
+/// Compiles the generated C `code` into the program called `name` in `work_dir`
+/// with the C compiler `compiler`
+///
+/// `flags` are passed to the C compiler after the source file, for example the
+/// flags returned by `codegen::c::linker_flags`. The compiler's warnings are
+/// passed on to stderr.
+///
+/// Returns the path of the built program.
+pub fn compile(code: &str, name: &str, compiler: &str, work_dir: &Path,
+               flags: &[String]) -> Result<PathBuf, DriverError> {
+    fs::create_dir_all(work_dir)?;
+    let c_file = work_dir.join(format!("{}.c", name));
+    fs::write(&c_file, code)?;
+    let program = work_dir.join(name);
+    // $CC can have flags of its own, like `gcc -m32`
+    let mut words = compiler.split_whitespace();
+    let executable = words.next().unwrap_or("cc");
+    let output = Command::new(executable).args(words).arg("-o").arg(&program).arg(&c_file).args(flags).output()?;
+    let messages = String::from_utf8_lossy(&output.stderr).into_owned();
+    if !output.status.success() {
+        let context = context(code, &messages, &c_file);
+        return Err(DriverError::Compile { compiler: compiler.to_string(), status: output.status.code(), messages, context });
+    }
+    io::stderr().write_all(messages.as_bytes())?;
+    Ok(program)
+}
+
+/// Compiles the generated C `code` into the program called `name` and runs it
+/// with the arguments `args`
+///
+/// Returns the program's exit status, or DriverError::Killed if it was
+/// stopped by a signal.
+pub fn run(code: &str, name: &str, flags: &[String], args: &[String]) -> Result<i32, DriverError> {
+    let work_dir = private_dir("haumea_run")?;
+    let status = compile(code, name, &c_compiler(), &work_dir, flags).and_then(|program| {
+        Command::new(&program).args(args).status().map_err(|error| DriverError::Run { program, error })
+    });
+    let _ = fs::remove_dir_all(&work_dir);
+    let status = status?;
+    match status.code() {
+        Some(code) => Ok(code),
+        None => Err(DriverError::Killed { signal: signal(status).unwrap_or(0) }),
+    }
+}
+
+/// Returns the signal that stopped a program with the exit status `status`
+#[cfg(unix)]
+pub(crate) fn signal(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+/// Returns the signal that stopped a program, which is never known off unix
+#[cfg(not(unix))]
+pub(crate) fn signal(_status: ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Returns the lines of `code` around each line that the C compiler's
+/// `messages` about `c_file` point at, other than its warnings
+fn context(code: &str, messages: &str, c_file: &Path) -> Vec<(usize, String)> {
+    let prefix = format!("{}:", c_file.display());
+    let mut numbers: Vec<usize> = messages.lines()
+        .filter(|message| !message.contains(": warning:"))
+        .filter_map(|message| message.strip_prefix(&prefix))
+        .filter_map(|rest| rest.split(':').next()?.parse().ok())
+        .filter(|&number| number > 0)
+        .collect();
+    numbers.sort_unstable();
+    numbers.dedup();
+    let lines: Vec<&str> = code.lines().collect();
+    let mut context: Vec<(usize, String)> = vec![];
+    for number in numbers {
+        let first = number.saturating_sub(CONTEXT_LINES).max(1);
+        let last = (number + CONTEXT_LINES).min(lines.len());
+        for shown in first..=last {
+            if context.last().is_none_or(|&(previous, _)| previous < shown) {
+                context.push((shown, lines[shown - 1].to_string()));
+            }
+        }
+    }
+    context
+}
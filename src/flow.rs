@@ -0,0 +1,173 @@
+//! src/flow.rs
+//! Works out where running a function can go.
+//!
+//! A statement falls through if running it can finish and go on to the
+//! statement after it, rather than always returning, leaving the loop it is
+//! in, going on to the next time around it, or looping forever.
+use std::collections::HashMap;
+use parser::{Block, Expression, Function, Ident, Statement, Type};
+use constants;
+
+/// Returns true if running `statement` can go on to the statement after it
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::flow::falls_through;
+/// let program = parse(Scanner::new("to f with (n) if n > 0 then return 1 else return 2
+///                                   to main forever if read() = 0 then break")).unwrap();
+/// assert!(!falls_through(&program[0].code));
+/// assert!(falls_through(&program[1].code));
+/// ```
+pub fn falls_through(statement: &Statement) -> bool {
+    // Generated code can be nested too deeply to recurse through, so the
+    // statements are worked through with a stack. A block or `if` is answered
+    // once the statements inside it are, from their answers on `answers`.
+    let mut waiting = vec![(statement, false)];
+    let mut answers: Vec<bool> = vec![];
+    while let Some((statement, inside_done)) = waiting.pop() {
+        let answer = match *statement {
+            Statement::Return(..) | Statement::Break(..) | Statement::Continue(_) => false,
+            Statement::Do(ref block, _) if !inside_done => {
+                waiting.push((statement, true));
+                waiting.extend(block.iter().map(|sub| (&**sub, false)));
+                continue;
+            },
+            Statement::Do(ref block, _) => {
+                let start = answers.len() - block.len();
+                answers.drain(start..).all(|answer| answer)
+            },
+            Statement::If { ref if_clause, ref else_clause, .. } => match **else_clause {
+                Some(ref else_clause) if !inside_done => {
+                    waiting.push((statement, true));
+                    waiting.push((if_clause, false));
+                    waiting.push((else_clause, false));
+                    continue;
+                },
+                Some(_) => {
+                    let start = answers.len() - 2;
+                    answers.drain(start..).any(|answer| answer)
+                },
+                None => true,
+            },
+            // Only a `break` leaves a loop that never stops by itself
+            Statement::Forever(ref body, label, _) => breaks(body, label),
+            Statement::While { ref cond, ref body, label, .. } if is_always_true(cond) => breaks(body, label),
+            Statement::Var(..) | Statement::Set(..) | Statement::Change(..) | Statement::Call { .. } |
+            Statement::While { .. } | Statement::ForEach { .. } => true,
+        };
+        answers.push(answer);
+    }
+    answers.pop().expect("The statement has an answer")
+}
+
+/// Returns the first statement in `block` that can never be run, and the one before it
+pub fn unreachable(block: &Block) -> Option<(&Statement, &Statement)> {
+    block.windows(2)
+        .find(|pair| !falls_through(&pair[0]))
+        .map(|pair| (&*pair[0], &*pair[1]))
+}
+
+/// Returns true if `func` is a procedure, which doesn't return a value
+///
+/// A procedure either declares that it `returns Nothing`, or declares nothing
+/// and has no `return` in it. `main` and external functions always return a value.
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::flow::is_procedure;
+/// let program = parse(Scanner::new("to greet do display(1) end
+///                                   to one return 1
+///                                   to main do greet() end")).unwrap();
+/// assert!(is_procedure(&program[0]));
+/// assert!(!is_procedure(&program[1]));
+/// assert!(!is_procedure(&program[2]));
+/// ```
+pub fn is_procedure(func: &Function) -> bool {
+    match func.types.returns {
+        Some(returns) => returns == Type::Nothing && func.name != "main",
+        None => func.name != "main" && func.external.is_none() && !has_return(&func.code),
+    }
+}
+
+/// Returns true if there is a `return` anywhere in `statement`
+pub fn has_return(statement: &Statement) -> bool {
+    any_inside(statement, |statement| match *statement {
+        Statement::Return(..) => Some(true),
+        _ => None,
+    })
+}
+
+/// Returns true if `found` is true of `statement` or a statement inside it
+///
+/// `found` returns `None` to look at the statements inside the one it is
+/// given, and otherwise whether it found what it is looking for there, without
+/// looking inside. The statements are searched with a stack rather than
+/// recursion, since generated code can be nested too deeply to recurse through.
+fn any_inside<F: FnMut(&Statement) -> Option<bool>>(statement: &Statement, mut found: F) -> bool {
+    let mut waiting = vec![statement];
+    while let Some(statement) = waiting.pop() {
+        match found(statement) {
+            Some(true) => return true,
+            Some(false) => continue,
+            None => (),
+        }
+        match *statement {
+            Statement::Do(ref block, _) => waiting.extend(block.iter().map(|sub| &**sub)),
+            Statement::If { ref if_clause, ref else_clause, .. } => {
+                waiting.push(if_clause);
+                if let Some(ref else_clause) = **else_clause {
+                    waiting.push(else_clause);
+                }
+            },
+            Statement::Forever(ref body, ..) | Statement::While { ref body, .. } |
+            Statement::ForEach { ref body, .. } => waiting.push(body),
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Returns true if `statement` can leave the loop it is in, which is labeled
+/// `label` if it has a label, with `break`
+fn breaks(statement: &Statement, label: Option<Ident>) -> bool {
+    any_inside(statement, |statement| match *statement {
+        // `break out of` a loop around this one leaves this loop, but doesn't go on after it
+        Statement::Break(target, _) => Some(target.is_none() || target == label),
+        // A plain `break` inside another loop leaves that loop instead
+        Statement::Forever(ref body, ..) | Statement::While { ref body, .. } | Statement::ForEach { ref body, .. } => {
+            Some(label.is_some_and(|label| breaks_out_of(statement, label) && breaks_out_of(body, label)))
+        },
+        _ => None,
+    })
+}
+
+/// Returns true if there is a `break out of label` in `statement` that leaves
+/// the loop labeled `label` around it
+///
+/// A loop inside `statement` with the same label hides the one around it.
+///
+/// # Examples
+/// ```
+/// # use haumea::ast::stmt;
+/// # use haumea::flow::breaks_out_of;
+/// assert!(breaks_out_of(&stmt::forever(stmt::break_out_of("outer")), "outer".into()));
+/// assert!(!breaks_out_of(&stmt::labeled("outer", stmt::forever(stmt::break_out_of("outer"))), "outer".into()));
+/// ```
+pub fn breaks_out_of(statement: &Statement, label: Ident) -> bool {
+    any_inside(statement, |statement| match *statement {
+        Statement::Break(target, _) => Some(target == Some(label)),
+        Statement::Forever(_, inner, _) | Statement::While { label: inner, .. } |
+        Statement::ForEach { label: inner, .. } if inner == Some(label) => Some(false),
+        _ => None,
+    })
+}
+
+/// Returns true if the loop condition `cond` is a constant other than 0
+fn is_always_true(cond: &Expression) -> bool {
+    // Constants have already been replaced by their values
+    matches!(constants::evaluate(cond, &HashMap::new()), Ok(value) if value != 0)
+}
@@ -0,0 +1,242 @@
+//! src/formatter.rs
+//! Rewrites Haumea source code in one consistent style, keeping its comments.
+//!
+//! The code stays on the lines it was written on, so a short `if` stays on
+//! one line, but every line is indented by how deeply it is nested, tokens are
+//! spaced the same way everywhere, and trailing whitespace and runs of blank
+//! lines are removed. The statements in a block are indented four spaces more
+//! than the block, and a statement that goes on over several lines is
+//! indented four spaces more on the lines after its first.
+//!
+//! The tokens and comments come from the concrete syntax tree in `cst`, and
+//! how deeply each line is nested comes from the spans in the parser's tree.
+use std::collections::HashMap;
+use scanner::{Scanner, ScanState, Token};
+use parser::{self, ParseError, Span, Statement};
+use cst::{self, SyntaxKind, SyntaxToken};
+use ast::INDENT;
+
+
+/// Returns `source` formatted, or the mistakes that stop it from being parsed
+///
+/// # Examples
+/// ```
+/// # use haumea::formatter::format;
+/// let source = "to main do   /* say hi */\nvariable x\n  set x to(1+ -2)*3\n\n\n\ndisplay( x ) end";
+/// assert_eq!(format(source).unwrap(),
+///            "to main do /* say hi */\n    variable x\n    set x to (1 + -2) * 3\n\n    display(x) end\n");
+/// ```
+pub fn format(source: &str) -> Result<String, Vec<ParseError>> {
+    // How large numbers can be doesn't change their layout, so programs for --bignum can be formatted too
+    let module = parser::parse_module(Scanner::new(source).with_big_numbers())?;
+    let tree = cst::parse(source);
+    let lines = split_lines(tree.tokens());
+
+    // Where every token is, in the order of the tokens in the tree
+    let mut scanner = Scanner::new(source);
+    let mut states = vec![];
+    loop {
+        match scanner.next_token_with_range().0 {
+            Token::EOF(_) => break,
+            token => states.push(token.state()),
+        }
+    }
+    let mut states = states.into_iter();
+    let mut layout = Layout { indents: HashMap::new(), firsts: HashMap::new() };
+    for line in &lines {
+        for (index, token) in line.tokens.iter().enumerate().filter(|&(_, token)| !token.kind.is_trivia()) {
+            let state = states.next().expect("The scanner reads every token in the tree");
+            if index == 0 {
+                layout.firsts.insert(line.number, (state, token.text.clone()));
+            }
+        }
+    }
+    for func in &module.functions {
+        match func.code {
+            // The statements outside of any function are put in a `main` that starts with them
+            Statement::Do(ref block, span) if span.start == func.span.start => {
+                for statement in block {
+                    layout.walk(statement, span, 0);
+                }
+            },
+            ref code => {
+                layout.lines(func.span, Span::default(), 0);
+                layout.walk(code, func.span, layout.nested(code.span(), 0));
+            },
+        }
+    }
+    for template in &module.templates {
+        layout.walk(&template.body, Span::default(), layout.nested(template.body.span(), 0));
+    }
+
+    let mut out = String::new();
+    let mut blank = false;
+    let mut last_code: Vec<&SyntaxToken> = vec![];
+    for line in &lines {
+        if line.tokens.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(&INDENT.repeat(layout.indents.get(&line.number).cloned().unwrap_or(0)));
+        for (index, token) in line.tokens.iter().enumerate() {
+            if index > 0 && spaced(line.tokens[index - 1], token, &last_code) {
+                out.push(' ');
+            }
+            out.push_str(&token.text);
+            if !token.kind.is_trivia() {
+                last_code.push(token);
+            }
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// The tokens on one line of the source, without whitespace
+struct Line<'a> {
+    /// The number of the line the tokens start on, from 1
+    number: u32,
+    tokens: Vec<&'a SyntaxToken>,
+}
+
+/// Splits `tokens` into the lines they are on
+///
+/// A comment that goes on over several lines stays on the line it starts on.
+fn split_lines(tokens: Vec<&SyntaxToken>) -> Vec<Line<'_>> {
+    let mut lines = vec![Line { number: 1, tokens: vec![] }];
+    let mut number = 1;
+    for token in tokens {
+        let breaks = token.text.matches('\n').count() as u32;
+        if token.kind == SyntaxKind::Whitespace {
+            for _ in 0..breaks {
+                number += 1;
+                lines.push(Line { number, tokens: vec![] });
+            }
+        } else {
+            lines.last_mut().expect("There is always a line").tokens.push(token);
+            number += breaks;
+        }
+    }
+    lines
+}
+
+/// How deeply each line is nested
+struct Layout {
+    /// The indentation of each line, in levels, where it isn't 0
+    indents: HashMap<u32, usize>,
+    /// Where the first token of each line is, and its text
+    firsts: HashMap<u32, (ScanState, String)>,
+}
+
+impl Layout {
+    /// Returns true if the line `number` starts with the token at `state`
+    fn starts_with(&self, number: u32, state: ScanState) -> bool {
+        self.firsts.get(&number).is_some_and(|&(first, _)| first == state)
+    }
+
+    /// Returns true if the line `number` starts with the keyword `word`
+    fn starts_with_word(&self, number: u32, word: &str) -> bool {
+        self.firsts.get(&number).is_some_and(|(_, text)| text == word)
+    }
+
+    /// Returns the indentation of the code at `span`, which is part of code indented by `indent`
+    ///
+    /// Code that starts a line goes on from the line before, so it is indented
+    /// one level more, but code after other code on its line is at its level.
+    fn nested(&self, span: Span, indent: usize) -> usize {
+        if self.starts_with(span.start.line, span.start) { indent + 1 } else { indent }
+    }
+
+    /// Indents the first line of `span` by `indent` if it starts there, and the
+    /// lines after it one level more
+    ///
+    /// Returns false if `span` isn't in `parent`, which is only the case for
+    /// statements that came from a template, and are laid out where the
+    /// template is declared.
+    fn lines(&mut self, span: Span, parent: Span, indent: usize) -> bool {
+        if span.is_empty() || !(parent.is_empty() || contains(parent, span.start)) {
+            return false;
+        }
+        if self.starts_with(span.start.line, span.start) {
+            self.indents.insert(span.start.line, indent);
+        }
+        for number in span.start.line + 1..=span.end.line {
+            self.indents.insert(number, indent + 1);
+        }
+        true
+    }
+
+    /// Works out the indentation of the lines `statement` is on, which is in
+    /// `parent` and indented by `indent`
+    fn walk(&mut self, statement: &Statement, parent: Span, indent: usize) {
+        let span = statement.span();
+        if !self.lines(span, parent, indent) {
+            return;
+        }
+        match *statement {
+            Statement::Do(ref block, _) => {
+                if self.starts_with(span.end.line, span.end) {
+                    self.indents.insert(span.end.line, indent);
+                }
+                for sub in block {
+                    self.walk(sub, span, indent + 1);
+                }
+            },
+            Statement::If { ref if_clause, ref else_clause, .. } => {
+                for number in span.start.line + 1..=span.end.line {
+                    if self.starts_with_word(number, "else") {
+                        self.indents.insert(number, indent);
+                    }
+                }
+                self.walk(if_clause, span, self.nested(if_clause.span(), indent));
+                if let Some(ref else_clause) = **else_clause {
+                    self.walk(else_clause, span, self.nested(else_clause.span(), indent));
+                }
+            },
+            Statement::Forever(ref body, ..) | Statement::While { ref body, .. } |
+            Statement::ForEach { ref body, .. } => self.walk(body, span, self.nested(body.span(), indent)),
+            Statement::Return(..) | Statement::Var(..) | Statement::Set(..) | Statement::Change(..) |
+            Statement::Call { .. } | Statement::Break(..) | Statement::Continue(_) => (),
+        }
+    }
+}
+
+/// Returns true if `state` is inside `span`
+fn contains(span: Span, state: ScanState) -> bool {
+    let position = (state.line, state.column);
+    (span.start.line, span.start.column) <= position && position <= (span.end.line, span.end.column)
+}
+
+/// Returns true if there is a space between `before` and `token` on a line
+///
+/// `code` is every token before `token` that isn't a comment.
+fn spaced(before: &SyntaxToken, token: &SyntaxToken, code: &[&SyntaxToken]) -> bool {
+    if before.kind == SyntaxKind::Comment || token.kind == SyntaxKind::Comment {
+        return true;
+    }
+    match token.kind {
+        SyntaxKind::Rp | SyntaxKind::Rb | SyntaxKind::Comma | SyntaxKind::Colon => return false,
+        // A call, or an index
+        SyntaxKind::Lp if before.kind == SyntaxKind::Ident => return false,
+        SyntaxKind::Lb if [SyntaxKind::Ident, SyntaxKind::Rp, SyntaxKind::Rb, SyntaxKind::StringLit]
+            .contains(&before.kind) => return false,
+        _ => (),
+    }
+    match before.kind {
+        SyntaxKind::Lp | SyntaxKind::Lb => false,
+        // `-` and `~` are written against their operand when they are prefix operators
+        SyntaxKind::Operator if before.text == "-" || before.text == "~" => {
+            let operand_before = code.len().checked_sub(2).map(|index| code[index]);
+            !operand_before.is_none_or(|operand| match operand.kind {
+                SyntaxKind::Keyword => operand.text != "true" && operand.text != "false",
+                SyntaxKind::Operator | SyntaxKind::Lp | SyntaxKind::Lb | SyntaxKind::Comma | SyntaxKind::Colon => true,
+                _ => false,
+            })
+        },
+        _ => true,
+    }
+}
@@ -0,0 +1,113 @@
+//! src/ice.rs
+//! Reports internal compiler errors, which are bugs in the compiler rather than
+//! mistakes in the program being compiled.
+//!
+//! Mistakes in programs are reported as errors, like "At line 1:5, ...", and
+//! the few functions that panic on them, like `cst::parse`, panic with the
+//! same messages. Any other panic means one of the compiler's own invariants
+//! broke. The driver catches those, and writes a `Report` with the
+//! smallest part of the program that still makes the compiler fail, so it can
+//! be attached to a bug report.
+use std::any::Any;
+use std::fmt;
+use std::panic;
+use cst::{self, SyntaxElement};
+
+/// The starts of the messages that mistakes in programs are reported with
+pub const DIAGNOSTICS: &[&str] = &[
+    "At line ",
+    "Invalid constant: ",
+    "Invalid use of a constant: ",
+    "Invalid use of a template: ",
+];
+
+/// Returns the message a panic was started with, or "" if it wasn't started with one
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload.downcast_ref::<String>().map(|s| &s[..])
+        .or_else(|| payload.downcast_ref::<&str>().cloned())
+        .unwrap_or("")
+}
+
+/// Returns true if the panic message `message` reports a mistake in the program,
+/// and not a bug in the compiler
+///
+/// # Examples
+/// ```
+/// # use haumea::ice::is_diagnostic;
+/// assert!(is_diagnostic("At line 1:9, expected an expression, but found ')'!"));
+/// assert!(!is_diagnostic("Dedented past the left margin!"));
+/// ```
+pub fn is_diagnostic(message: &str) -> bool {
+    DIAGNOSTICS.iter().any(|start| message.starts_with(start))
+}
+
+/// Returns the declarations of `source` that `fails` still fails without the others
+///
+/// Each declaration is left out in turn, and stays out if `fails` is still true
+/// for the source without it. If `source` can't be split into declarations,
+/// it is returned as it is.
+///
+/// # Examples
+/// ```
+/// # use haumea::ice::minimize;
+/// let source = "to one return 1\nto bad return 0 / 0\nto two return 2\n";
+/// assert_eq!(minimize(source, |source| source.contains("/ 0")), "to bad return 0 / 0\n");
+/// ```
+pub fn minimize<F: FnMut(&str) -> bool>(source: &str, mut fails: F) -> String {
+    let tree = match panic::catch_unwind(|| cst::parse(source)) {
+        Ok(tree) => tree,
+        Err(_) => return source.to_string(),
+    };
+    let mut declarations: Vec<String> = tree.children.iter().filter_map(|child| match *child {
+        SyntaxElement::Node(ref node) => Some(node.text()),
+        SyntaxElement::Token(_) => None,
+    }).collect();
+    let join = |declarations: &[String]| -> String {
+        declarations.iter().map(|declaration| format!("{}\n", declaration)).collect()
+    };
+    let mut index = 0;
+    while index < declarations.len() {
+        let mut fewer = declarations.clone();
+        fewer.remove(index);
+        if fails(&join(&fewer)) {
+            declarations = fewer;
+        } else {
+            index += 1;
+        }
+    }
+    join(&declarations)
+}
+
+/// A report of an internal compiler error
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// The version of the compiler
+    pub version: &'static str,
+    /// What the compiler was doing when it failed
+    pub phase: &'static str,
+    /// The message the compiler panicked with
+    pub message: String,
+    /// Where in the compiler's code it panicked, if that is known
+    pub location: Option<String>,
+    /// The smallest source that makes the compiler fail
+    pub source: String,
+    /// The syntax tree of the source, if it could be parsed
+    pub ast: Option<String>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Haumea internal compiler error report")?;
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "phase: {}", self.phase)?;
+        writeln!(f, "message: {}", self.message)?;
+        if let Some(ref location) = self.location {
+            writeln!(f, "location: {}", location)?;
+        }
+        writeln!(f, "\n--- source ---\n{}", self.source)?;
+        match self.ast {
+            Some(ref ast) => writeln!(f, "--- ast ---\n{}", ast),
+            None => writeln!(f, "--- ast ---\nThe source could not be parsed."),
+        }
+    }
+}
@@ -0,0 +1,823 @@
+//! src/interp.rs
+//! Runs a loaded program directly, without compiling it to C.
+//!
+//! The interpreter walks the syntax tree, keeping the variables of each
+//! function call on a stack of environments. It runs programs the way the C
+//! backend compiles them: numbers are machine integers, `for each` works out
+//! its range once before the loop, and `and` and `or` only work out their
+//! right side when they need to. Where the compiled program would crash or
+//! quietly go wrong, like dividing by zero or overflowing, the interpreter
+//! stops with a RuntimeError instead.
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use parser::{Directive, Expression, Float, Function, Ident, Operator, Program, Span, Statement, Type};
+use ast;
+use builtins;
+use driver;
+use symbol::Symbol;
+
+/// How many calls deep a program can go before it is stopped
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// How much stack programs should be run with
+///
+/// Each call takes tens of kilobytes of stack in a debug build, far more than
+/// the main thread has, so programs should be run on a thread of their own
+/// with this much stack.
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// How much of STACK_SIZE is kept free for the call that is running
+///
+/// How much stack a call takes depends on how deeply the expressions it is
+/// called from are nested, so rather than guess how many calls fit, the
+/// interpreter stops a program once its calls have used all but this much.
+const STACK_MARGIN: usize = 16 * 1024 * 1024;
+
+/// A value that a Haumea program works with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Integer(i64),
+    /// A decimal number, which is an IEEE 754 double like in C
+    Real(Float),
+    /// Text from a string literal
+    Text(Arc<str>),
+    /// A list, which is shared by every value it is copied to, as in the C backend
+    List(Rc<RefCell<Vec<Value>>>),
+}
+
+/// A mistake that stops a program while it runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// The code `code` in `function`, written at `span`, divided by zero
+    DivisionByZero { function: Ident, code: String, span: Span },
+    /// The `for each` loop `code` in `function` counts by zero, so it would never end
+    ZeroStep { function: Ident, code: String, span: Span },
+    /// The result of `code` in `function` doesn't fit in a machine integer
+    Overflow { function: Ident, code: String, span: Span },
+    /// Text is used as a number, or a number as text, by `code` in `function`
+    WrongType { function: Ident, code: String, span: Span },
+    /// `code` in `function` reads the item at `index` of a list with only `length` items
+    OutOfBounds { function: Ident, code: String, index: i64, length: usize, span: Span },
+    /// `code` in `function` removes the last item of an empty list
+    EmptyList { function: Ident, code: String, span: Span },
+    /// `code` in `function` runs a command, but the Interpreter doesn't allow it
+    CommandsNotAllowed { function: Ident, code: String, span: Span },
+    /// `code` in `function` converts a value that has no conversion, for the reason `error`
+    BadConversion { function: Ident, code: String, error: &'static str, span: Span },
+    /// A variable is used without being declared
+    UndeclaredVariable { function: Ident, name: Ident },
+    /// A function is called that the program doesn't define
+    UndeclaredFunction { function: Ident, name: Ident },
+    /// A function is called with the wrong number of arguments
+    WrongArguments { function: Ident, expected: usize, given: usize },
+    /// An external function is called, which only the C backend can do
+    External(Ident),
+    /// The calls are nested more than MAX_CALL_DEPTH deep, or too deeply for
+    /// the stack, usually because of endless recursion
+    TooDeep(Ident),
+    /// The program has no `main` function
+    NoMain,
+    /// Reading input or writing output failed
+    Io { kind: io::ErrorKind, message: String },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeError::DivisionByZero { function, ref code, span } => {
+                write!(f, "division by zero in {}, at `{}`{}", function, code, on_line(span))
+            },
+            RuntimeError::ZeroStep { function, ref code, span } => {
+                write!(f, "a loop that counts by zero in {}, at `{}`{}", function, code, on_line(span))
+            },
+            RuntimeError::Overflow { function, ref code, span } => {
+                write!(f, "a number too large for a machine integer in {}, at `{}`{}; \
+                           --bignum allows any size", function, code, on_line(span))
+            },
+            RuntimeError::WrongType { function, ref code, span } => {
+                write!(f, "text and numbers are mixed up in {}, at `{}`{}", function, code, on_line(span))
+            },
+            RuntimeError::OutOfBounds { function, ref code, index, length, span } => {
+                write!(f, "the index {} is outside a list of length {} in {}, at `{}`{}",
+                       index, length, function, code, on_line(span))
+            },
+            RuntimeError::EmptyList { function, ref code, span } => {
+                write!(f, "the list is empty in {}, at `{}`{}", function, code, on_line(span))
+            },
+            RuntimeError::CommandsNotAllowed { function, ref code, span } => {
+                write!(f, "commands aren't allowed in {}, at `{}`{}", function, code, on_line(span))
+            },
+            RuntimeError::BadConversion { function, ref code, error, span } => {
+                write!(f, "{} in {}, at `{}`{}", error, function, code, on_line(span))
+            },
+            RuntimeError::UndeclaredVariable { function, name } => {
+                write!(f, "in `{}`, the variable `{}` is not declared", function, name)
+            },
+            RuntimeError::UndeclaredFunction { function, name } => {
+                write!(f, "in `{}`, `{}` is called, but there is no function called `{}`", function, name, name)
+            },
+            RuntimeError::WrongArguments { function, expected, given } => {
+                write!(f, "`{}` takes {} arguments, but was given {}", function, expected, given)
+            },
+            RuntimeError::External(name) => {
+                write!(f, "`{}` is an external function, which can only be called by the compiled program", name)
+            },
+            RuntimeError::TooDeep(name) => {
+                write!(f, "the calls are nested too deeply at `{}`", name)
+            },
+            RuntimeError::NoMain => write!(f, "there is no `main` function to run"),
+            RuntimeError::Io { ref message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Returns where `span` is for an error message, like " on line 3", if it was parsed
+fn on_line(span: Span) -> String {
+    if span.is_empty() {
+        String::new()
+    } else {
+        format!(" on line {}", span.start.line)
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(error: io::Error) -> RuntimeError {
+        RuntimeError::Io { kind: error.kind(), message: error.to_string() }
+    }
+}
+
+/// Runs `command` with the system's shell, like C's `system`, and returns its exit status
+///
+/// Like a shell, a command stopped by a signal has 128 plus the signal as its
+/// exit status, and a command that can't be started has -1.
+fn run_command(command: &str) -> i64 {
+    let mut shell = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    shell.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(command);
+    match shell.status() {
+        Ok(status) => status.code().map_or_else(|| 128 + i64::from(driver::signal(status).unwrap_or(0)), i64::from),
+        Err(_) => -1,
+    }
+}
+
+/// Returns about where the top of the stack is, to measure how much of it has been used
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Returns true if `program` calls external functions, which only the C backend can run
+pub fn needs_c(program: &[Function]) -> bool {
+    program.iter().any(|func| func.external.is_some())
+}
+
+/// Runs programs, reading what they read from `input` and writing what they display to `output`
+pub struct Interpreter<R, W> {
+    input: R,
+    output: W,
+    /// The bytes of each embedded file, by the name of the function that reads them
+    embeds: HashMap<Ident, Vec<u8>>,
+    /// The words of input read but not used yet
+    words: VecDeque<String>,
+    /// The state of the random number generator, once it has been seeded
+    random: Option<u64>,
+    /// `run_command` can run commands, which it can't unless `allow_commands` is
+    /// called, so a program run by another one can't do anything outside of it
+    commands: bool,
+}
+
+impl<R: BufRead, W: Write> Interpreter<R, W> {
+    /// Constructs a new Interpreter
+    pub fn new(input: R, output: W) -> Interpreter<R, W> {
+        Interpreter::with_directives(input, output, &[])
+    }
+
+    /// Constructs a new Interpreter for a program built with the build directives `directives`
+    pub fn with_directives(input: R, output: W, directives: &[Directive]) -> Interpreter<R, W> {
+        let embeds = directives.iter().filter_map(|directive| match *directive {
+            Directive::Embed { name, ref bytes, .. } => Some((name, bytes.clone())),
+            _ => None,
+        }).collect();
+        Interpreter {
+            input,
+            output,
+            embeds,
+            words: VecDeque::new(),
+            random: None,
+            commands: false,
+        }
+    }
+
+    /// Lets the program run commands with `run_command`
+    pub fn allow_commands(mut self) -> Interpreter<R, W> {
+        self.commands = true;
+        self
+    }
+
+    /// Runs the `main` function of `program`, returning its exit status
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::scanner::Scanner;
+    /// # use haumea::parser::parse;
+    /// # use haumea::interp::Interpreter;
+    /// let program = parse(Scanner::new("to main do display(6 * 7) return 3 end")).unwrap();
+    /// let mut output = vec![];
+    /// assert_eq!(Interpreter::new(&b""[..], &mut output).run(&program), Ok(3));
+    /// assert_eq!(output, b"42\n");
+    /// ```
+    pub fn run(&mut self, program: &Program) -> Result<i32, RuntimeError> {
+        let functions: HashMap<Ident, &Function> = program.iter().map(|func| (func.name, func)).collect();
+        if !functions.contains_key(&Symbol::from("main")) {
+            return Err(RuntimeError::NoMain);
+        }
+        let result = {
+            let mut machine = Machine {
+                interpreter: self,
+                functions,
+                frames: vec![],
+                stack_start: stack_address(),
+            };
+            machine.call(Symbol::from("main"), vec![], Span::default())
+        };
+        self.output.flush()?;
+        match result? {
+            Value::Integer(status) => Ok((status & 0xff) as i32),
+            _ => Err(RuntimeError::WrongType {
+                function: Symbol::from("main"),
+                code: "return".to_string(),
+                span: Span::default(),
+            }),
+        }
+    }
+
+    /// Asks for an integer and reads it, or returns 0 if what is read isn't one
+    fn read(&mut self) -> Result<i64, RuntimeError> {
+        write!(self.output, "Enter an integer: ")?;
+        self.output.flush()?;
+        while self.words.is_empty() {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(0);
+            }
+            self.words.extend(line.split_whitespace().map(str::to_string));
+        }
+        Ok(self.words.pop_front().and_then(|word| word.parse().ok()).unwrap_or(0))
+    }
+
+    /// Returns the next random number from splitmix64, the generator every backend uses
+    ///
+    /// Until `seed_random` is called, the seed is the time, as in the compiled program.
+    fn random(&mut self) -> u64 {
+        let state = self.random.get_or_insert_with(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+        });
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number from `low` through `high`, which can be given either way round
+    fn random_between(&mut self, low: i64, high: i64) -> i64 {
+        let (low, high) = if low > high { (high, low) } else { (low, high) };
+        // The span of every i64 is one more than a u64 holds, so it wraps to 0
+        let span = (high as u64).wrapping_sub(low as u64).wrapping_add(1);
+        let random = self.random();
+        if span == 0 {
+            random as i64
+        } else {
+            (low as u64).wrapping_add(random % span) as i64
+        }
+    }
+}
+
+/// What running a statement leads to
+enum Flow {
+    /// Carry on with the next statement
+    Next,
+    /// The function returns the value
+    Return(Value),
+    /// The loop being run stops, or the loop around it with the label
+    Break(Option<Ident>),
+    /// The loop being run goes on to its next time around
+    Continue,
+}
+
+/// The variables of a function call
+struct Frame {
+    function: Ident,
+    /// The variables declared in each block, innermost last
+    scopes: Vec<HashMap<Ident, Value>>,
+}
+
+/// The state of a program while it runs
+struct Machine<'a, 'p, R: 'a, W: 'a> {
+    interpreter: &'a mut Interpreter<R, W>,
+    functions: HashMap<Ident, &'p Function>,
+    /// The environment of each call, innermost last
+    frames: Vec<Frame>,
+    /// Where the stack was when the program started
+    stack_start: usize,
+}
+
+impl<'a, 'p, R: BufRead, W: Write> Machine<'a, 'p, R, W> {
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("There is always a frame while code runs")
+    }
+
+    fn function(&self) -> Ident {
+        self.frames.last().map(|frame| frame.function).unwrap_or_else(|| Symbol::from("main"))
+    }
+
+    /// Returns the error for using text as a number, or a number as text, in `code` at `span`
+    fn wrong_type(&self, code: &str, span: Span) -> RuntimeError {
+        RuntimeError::WrongType { function: self.function(), code: code.to_string(), span }
+    }
+
+    /// Returns the error for the call to the conversion `name` at `span` failing for the reason `error`
+    fn bad_conversion(&self, name: Ident, error: &'static str, span: Span) -> RuntimeError {
+        RuntimeError::BadConversion { function: self.function(), code: format!("{}(...)", name), error, span }
+    }
+
+    /// Returns the number `value`, or an error for `code` if it is text
+    fn integer(&self, value: Value, code: &Expression) -> Result<i64, RuntimeError> {
+        match value {
+            Value::Integer(n) => Ok(n),
+            _ => Err(self.wrong_type(&ast::expression_to_source(code), code.span())),
+        }
+    }
+
+    /// Calls the function called `name` with `arguments`, from the call at `span`
+    fn call(&mut self, name: Ident, arguments: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        let func = match self.functions.get(&name) {
+            Some(func) => *func,
+            None => return self.call_builtin(name, arguments, span),
+        };
+        if func.external.is_some() {
+            return Err(RuntimeError::External(name));
+        }
+        let params = func.signature.as_deref().unwrap_or(&[]);
+        if params.len() != arguments.len() {
+            return Err(RuntimeError::WrongArguments { function: name, expected: params.len(), given: arguments.len() });
+        }
+        if self.frames.len() >= MAX_CALL_DEPTH || self.stack_start.abs_diff(stack_address()) > STACK_SIZE - STACK_MARGIN {
+            return Err(RuntimeError::TooDeep(name));
+        }
+        self.frames.push(Frame {
+            function: name,
+            scopes: vec![params.iter().cloned().zip(arguments).collect()],
+        });
+        let flow = self.statement(&func.code);
+        self.frames.pop();
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Next => Ok(Value::Integer(0)),
+            Flow::Break(_) | Flow::Continue => panic!("`break` or `continue` is used outside of a loop"),
+        }
+    }
+
+    /// Calls a builtin, or a function that reads an embedded file
+    fn call_builtin(&mut self, name: Ident, arguments: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        // `data_size()` is the length of the file embedded as `data`
+        let size_of = name.strip_suffix("_size").map(Symbol::from);
+        let builtin = builtins::lookup(&name);
+        let expected = match builtin {
+            Some(builtin) => builtin.arity,
+            None if self.interpreter.embeds.contains_key(&name) => 1,
+            None if size_of.is_some_and(|file| self.interpreter.embeds.contains_key(&file)) => 0,
+            None => return Err(RuntimeError::UndeclaredFunction { function: self.function(), name }),
+        };
+        if !builtin.map_or(arguments.len() == expected, |builtin| builtin.accepts(arguments.len())) {
+            return Err(RuntimeError::WrongArguments { function: name, expected, given: arguments.len() });
+        }
+        if name == "display" || name == "display_no_newline" {
+            let mut numbers = vec![];
+            for argument in arguments {
+                match argument {
+                    Value::Integer(n) => numbers.push(n.to_string()),
+                    Value::Text(text) => numbers.push(text.to_string()),
+                    _ => return Err(self.wrong_type(&format!("{}(...)", name), span)),
+                }
+            }
+            let end = if name == "display" { "\n" } else { "" };
+            write!(self.interpreter.output, "{}{}", numbers.join(" "), end)?;
+            return Ok(Value::Integer(0));
+        }
+        if name == "join" {
+            let mut joined = String::new();
+            for argument in arguments {
+                match argument {
+                    Value::Text(text) => joined.push_str(&text),
+                    _ => return Err(self.wrong_type("join(...)", span)),
+                }
+            }
+            return Ok(Value::Text(Arc::from(joined)));
+        }
+        if name == "random_between" {
+            return match (&arguments[0], &arguments[1]) {
+                (&Value::Integer(low), &Value::Integer(high)) => Ok(Value::Integer(self.interpreter.random_between(low, high))),
+                _ => Err(self.wrong_type("random_between(...)", span)),
+            };
+        }
+        if builtin.is_some_and(|builtin| builtin.parameters.first() == Some(&Some(Type::List))) {
+            return match arguments.first() {
+                Some(Value::List(items)) => self.call_list_builtin(name, &mut items.borrow_mut(), &arguments[1..], span),
+                _ => Err(self.wrong_type(&format!("{}(...)", name), span)),
+            };
+        }
+        match (name.as_str(), arguments.into_iter().next()) {
+            ("read", _) => Ok(Value::Integer(self.interpreter.read()?)),
+            ("display_text", Some(Value::Text(text))) => {
+                writeln!(self.interpreter.output, "{}", text)?;
+                Ok(Value::Integer(0))
+            },
+            ("display_real", Some(Value::Real(n))) => {
+                writeln!(self.interpreter.output, "{}", n.0)?;
+                Ok(Value::Integer(0))
+            },
+            ("seed_random", Some(Value::Integer(seed))) => {
+                self.interpreter.random = Some(seed as u64);
+                Ok(Value::Integer(0))
+            },
+            ("character_code", Some(Value::Text(text))) => {
+                let mut characters = text.chars();
+                match (characters.next(), characters.next()) {
+                    (Some(character), None) => Ok(Value::Integer(character as i64)),
+                    _ => Err(self.bad_conversion(name, "the text is not one character", span)),
+                }
+            },
+            // Code 0 would end the text early in C
+            ("character_from_code", Some(Value::Integer(n))) => {
+                match u32::try_from(n).ok().filter(|&n| n != 0).and_then(char::from_u32) {
+                    Some(character) => Ok(Value::Text(Arc::from(character.to_string()))),
+                    None => Err(self.bad_conversion(name, "the number is not the code of a character", span)),
+                }
+            },
+            ("number_from_text", Some(Value::Text(text))) => {
+                let digits = text.strip_prefix('-').unwrap_or(&text);
+                if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return Err(self.bad_conversion(name, "the text is not a whole number", span));
+                }
+                text.parse().map(Value::Integer).map_err(|_| RuntimeError::Overflow {
+                    function: self.function(), code: format!("{}(...)", name), span,
+                })
+            },
+            ("text_from_number", Some(Value::Integer(n))) => Ok(Value::Text(Arc::from(n.to_string()))),
+            ("run_command", Some(Value::Text(command))) => {
+                if !self.interpreter.commands {
+                    return Err(RuntimeError::CommandsNotAllowed { function: self.function(), code: "run_command(...)".to_string(), span });
+                }
+                // What the program displayed comes before what the command does
+                self.interpreter.output.flush()?;
+                Ok(Value::Integer(run_command(&command)))
+            },
+            ("display_text", _) | ("display_real", _) | ("seed_random", _) | ("character_code", _)
+                | ("character_from_code", _) | ("number_from_text", _) | ("text_from_number", _) | ("run_command", _) => {
+                Err(self.wrong_type(&format!("{}(...)", name), span))
+            },
+            (_, None) => {
+                let file = size_of.expect("Only a file's size is read without an index");
+                Ok(Value::Integer(self.interpreter.embeds[&file].len() as i64))
+            },
+            (_, Some(Value::Integer(i))) => {
+                let byte = usize::try_from(i).ok().and_then(|i| self.interpreter.embeds[&name].get(i));
+                Ok(Value::Integer(byte.map_or(-1, |&byte| byte as i64)))
+            },
+            (_, Some(_)) => Err(self.wrong_type(&format!("{}(...)", name), span)),
+        }
+    }
+
+    /// Calls a builtin that works on the list `items`, with the `arguments` after the list
+    fn call_list_builtin(&mut self, name: Ident, items: &mut Vec<Value>, arguments: &[Value], span: Span)
+                         -> Result<Value, RuntimeError> {
+        let code = format!("{}(...)", name);
+        let index = match (name.as_str(), arguments.first()) {
+            ("item_at", Some(&Value::Integer(index))) | ("set_item", Some(&Value::Integer(index))) => {
+                match usize::try_from(index).ok().filter(|&i| i < items.len()) {
+                    Some(i) => i,
+                    None => return Err(RuntimeError::OutOfBounds {
+                        function: self.function(), code, index, length: items.len(), span,
+                    }),
+                }
+            },
+            _ => 0,
+        };
+        match (name.as_str(), arguments) {
+            ("push", [value]) | ("append", [value]) => {
+                items.push(value.clone());
+                Ok(Value::Integer(0))
+            },
+            ("length_of", []) => Ok(Value::Integer(items.len() as i64)),
+            ("item_at", [Value::Integer(_)]) => Ok(items[index].clone()),
+            ("set_item", [Value::Integer(_), value]) => {
+                items[index] = value.clone();
+                Ok(Value::Integer(0))
+            },
+            ("remove_last", []) => items.pop().ok_or(RuntimeError::EmptyList { function: self.function(), code, span }),
+            _ => Err(self.wrong_type(&code, span)),
+        }
+    }
+
+    /// Runs `statement` in a block of its own
+    fn body(&mut self, statement: &Statement) -> Result<Flow, RuntimeError> {
+        self.frame().scopes.push(HashMap::new());
+        let flow = self.statement(statement);
+        self.frame().scopes.pop();
+        flow
+    }
+
+    /// Returns the variable called `name`, to be changed
+    fn variable(&mut self, name: Ident) -> Result<&mut Value, RuntimeError> {
+        let function = self.function();
+        self.frame().scopes.iter_mut().rev()
+            .find_map(|scope| scope.get_mut(&name))
+            .ok_or(RuntimeError::UndeclaredVariable { function, name })
+    }
+
+    /// Returns true if `cond` holds, like a condition in C
+    fn condition(&mut self, cond: &Expression) -> Result<bool, RuntimeError> {
+        let value = self.expression(cond)?;
+        Ok(self.integer(value, cond)? != 0)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<Flow, RuntimeError> {
+        match *statement {
+            Statement::Return(ref exp, _) => Ok(Flow::Return(self.expression(exp)?)),
+            Statement::Var(name, _) => {
+                self.frame().scopes.last_mut().expect("There is always a scope").insert(name, Value::Integer(0));
+                Ok(Flow::Next)
+            },
+            Statement::Set(name, ref exp, _) => {
+                let value = self.expression(exp)?;
+                *self.variable(name)? = value;
+                Ok(Flow::Next)
+            },
+            Statement::Change(name, ref exp, span) => {
+                let value = self.expression(exp)?;
+                let by = self.integer(value, exp)?;
+                let code = format!("change {} by {}", name, ast::expression_to_source(exp));
+                let function = self.function();
+                let variable = self.variable(name)?;
+                *variable = match *variable {
+                    Value::Integer(n) => {
+                        Value::Integer(n.checked_add(by).ok_or(RuntimeError::Overflow { function, code, span })?)
+                    },
+                    _ => return Err(RuntimeError::WrongType { function, code, span }),
+                };
+                Ok(Flow::Next)
+            },
+            Statement::If { ref cond, ref if_clause, ref else_clause, .. } => {
+                if self.condition(cond)? {
+                    self.body(if_clause)
+                } else if let Some(ref else_clause) = **else_clause {
+                    self.body(else_clause)
+                } else {
+                    Ok(Flow::Next)
+                }
+            },
+            Statement::Do(ref block, _) => {
+                self.frame().scopes.push(HashMap::new());
+                let mut flow = Ok(Flow::Next);
+                for sub in block {
+                    flow = self.statement(sub);
+                    if !matches!(flow, Ok(Flow::Next)) {
+                        break;
+                    }
+                }
+                self.frame().scopes.pop();
+                flow
+            },
+            Statement::Call { function, ref arguments, span } => {
+                let arguments = arguments.iter().map(|arg| self.expression(arg)).collect::<Result<_, _>>()?;
+                self.call(function, arguments, span)?;
+                Ok(Flow::Next)
+            },
+            Statement::Forever(ref body, label, _) => loop {
+                match self.body(body)? {
+                    Flow::Break(target) if target.is_none() || target == label => return Ok(Flow::Next),
+                    Flow::Next | Flow::Continue => (),
+                    flow => return Ok(flow),
+                }
+            },
+            Statement::While { ref cond, ref body, label, .. } => {
+                while self.condition(cond)? {
+                    match self.body(body)? {
+                        Flow::Break(target) if target.is_none() || target == label => break,
+                        Flow::Next | Flow::Continue => (),
+                        flow => return Ok(flow),
+                    }
+                }
+                Ok(Flow::Next)
+            },
+            Statement::Break(label, _) => Ok(Flow::Break(label)),
+            Statement::Continue(_) => Ok(Flow::Continue),
+            Statement::ForEach { ident, ref start, ref end, ref by, ref range_type, ref body, label, span } => {
+                let code = format!("for each {} in {} {} {} by {}", ident, ast::expression_to_source(start),
+                                   range_type, ast::expression_to_source(end), ast::expression_to_source(by));
+                let start_value = self.expression(start)?;
+                let start = self.integer(start_value, start)?;
+                let end_value = self.expression(end)?;
+                let end = self.integer(end_value, end)?;
+                let by_value = self.expression(by)?;
+                let by = self.integer(by_value, by)?;
+                let function = self.function();
+                if by == 0 {
+                    return Err(RuntimeError::ZeroStep { function, code, span });
+                }
+                let through = range_type == "through";
+                // The loop counts down when it starts after its end, whatever the sign of `by`
+                let up = start <= end;
+                let by = match (by.checked_abs(), up) {
+                    (Some(size), true) => size,
+                    (Some(size), false) => -size,
+                    // The smallest integer has no opposite, so counting up by it counts by the largest
+                    (None, true) => i64::MAX,
+                    (None, false) => i64::MIN,
+                };
+                let in_range = |i: i64| match (up, through) {
+                    (true, false) => i < end,
+                    (true, true) => i <= end,
+                    (false, false) => i > end,
+                    (false, true) => i >= end,
+                };
+                self.frame().scopes.push(Some((ident, Value::Integer(start))).into_iter().collect());
+                let flow = loop {
+                    let i = match *self.variable(ident)? {
+                        Value::Integer(i) => i,
+                        _ => break Err(self.wrong_type(&code, span)),
+                    };
+                    if !in_range(i) {
+                        break Ok(Flow::Next);
+                    }
+                    match self.body(body) {
+                        Ok(Flow::Next) | Ok(Flow::Continue) => (),
+                        Ok(Flow::Break(target)) if target.is_none() || target == label => break Ok(Flow::Next),
+                        flow => break flow,
+                    }
+                    let variable = self.variable(ident)?;
+                    *variable = match *variable {
+                        // Counting past the largest or smallest integer is past the end too
+                        Value::Integer(i) => match i.checked_add(by) {
+                            Some(next) => Value::Integer(next),
+                            None => break Ok(Flow::Next),
+                        },
+                        _ => break Err(RuntimeError::WrongType { function, code, span }),
+                    };
+                };
+                self.frame().scopes.pop();
+                flow
+            },
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<Value, RuntimeError> {
+        match *expression {
+            Expression::Integer(n, _) => Ok(Value::Integer(n)),
+            // The interpreter only has machine integers
+            Expression::BigInteger(..) => self.checked(None, expression),
+            Expression::Float(n, _) => Ok(Value::Real(n)),
+            Expression::Boolean(value, _) => Ok(Value::Integer(value as i64)),
+            Expression::Str(ref text, _) => Ok(Value::Text(Arc::from(text.as_str()))),
+            Expression::List(ref items, _) => {
+                let items = items.iter().map(|item| self.expression(item)).collect::<Result<_, _>>()?;
+                Ok(Value::List(Rc::new(RefCell::new(items))))
+            },
+            Expression::Index { ref list, ref index, span } => {
+                let items = match self.expression(list)? {
+                    Value::List(items) => items,
+                    _ => return Err(self.wrong_type(&ast::expression_to_source(expression), span)),
+                };
+                let value = self.expression(index)?;
+                let i = self.integer(value, index)?;
+                let items = items.borrow();
+                usize::try_from(i).ok().and_then(|i| items.get(i)).cloned().ok_or_else(|| RuntimeError::OutOfBounds {
+                    function: self.function(),
+                    code: ast::expression_to_source(expression),
+                    index: i,
+                    length: items.len(),
+                    span,
+                })
+            },
+            Expression::Length(ref list, span) => match self.expression(list)? {
+                Value::List(items) => Ok(Value::Integer(items.borrow().len() as i64)),
+                Value::Text(text) => Ok(Value::Integer(text.chars().count() as i64)),
+                _ => Err(self.wrong_type(&ast::expression_to_source(expression), span)),
+            },
+            Expression::Ident(name, _) => self.variable(name).map(|value| value.clone()),
+            Expression::Call { function, ref arguments, span } => {
+                let arguments = arguments.iter().map(|arg| self.expression(arg)).collect::<Result<_, _>>()?;
+                self.call(function, arguments, span)
+            },
+            Expression::UnaryOp { operator, expression: ref operand, span } => {
+                let value = self.expression(operand)?;
+                if let Value::Real(n) = value {
+                    return match operator {
+                        Operator::Sub | Operator::Negate => Ok(Value::Real(Float(-n.0))),
+                        _ => Err(self.wrong_type(&ast::expression_to_source(expression), span)),
+                    };
+                }
+                let n = self.integer(value, operand)?;
+                let result = match operator {
+                    Operator::Sub | Operator::Negate => n.checked_neg(),
+                    Operator::LogicalNot => Some((n == 0) as i64),
+                    Operator::BinaryNot => Some(!n),
+                    _ => panic!("`{}` is not a unary operator!", operator),
+                };
+                self.checked(result, expression)
+            },
+            Expression::BinaryOp { operator, ref left, ref right, span } => {
+                let value = self.expression(left)?;
+                // Text is equal to other text with the same characters
+                if let (Value::Text(lh), Operator::Equals | Operator::NotEquals) = (&value, operator) {
+                    return match self.expression(right)? {
+                        Value::Text(rh) => Ok(Value::Integer(((lh == &rh) == (operator == Operator::Equals)) as i64)),
+                        _ => Err(self.wrong_type(&ast::expression_to_source(expression), span)),
+                    };
+                }
+                if let Value::Real(lh) = value {
+                    return match self.expression(right)? {
+                        Value::Real(rh) => self.real_operation(operator, lh.0, rh.0, expression),
+                        _ => Err(self.wrong_type(&ast::expression_to_source(expression), span)),
+                    };
+                }
+                let lh = self.integer(value, left)?;
+                // `and` and `or` only work out their right side if they need to, as in C
+                match operator {
+                    Operator::LogicalAnd if lh == 0 => return Ok(Value::Integer(0)),
+                    Operator::LogicalOr if lh != 0 => return Ok(Value::Integer(1)),
+                    _ => (),
+                }
+                let value = self.expression(right)?;
+                let rh = self.integer(value, right)?;
+                if rh == 0 && (operator == Operator::Div || operator == Operator::Modulo) {
+                    let code = ast::expression_to_source(expression);
+                    return Err(RuntimeError::DivisionByZero { function: self.function(), code, span });
+                }
+                let result = match operator {
+                    Operator::Add => lh.checked_add(rh),
+                    Operator::Sub => lh.checked_sub(rh),
+                    Operator::Mul => lh.checked_mul(rh),
+                    Operator::Div => lh.checked_div(rh),
+                    Operator::Modulo => lh.checked_rem(rh),
+                    Operator::Equals => Some((lh == rh) as i64),
+                    Operator::NotEquals => Some((lh != rh) as i64),
+                    Operator::Gt => Some((lh > rh) as i64),
+                    Operator::Lt => Some((lh < rh) as i64),
+                    Operator::Gte => Some((lh >= rh) as i64),
+                    Operator::Lte => Some((lh <= rh) as i64),
+                    Operator::LogicalAnd | Operator::LogicalOr => Some((rh != 0) as i64),
+                    Operator::BinaryAnd => Some(lh & rh),
+                    Operator::BinaryOr => Some(lh | rh),
+                    Operator::BinaryXor => Some(lh ^ rh),
+                    // Only the low 6 bits of the count are used, like in every backend
+                    Operator::ShiftLeft => Some(lh.wrapping_shl((rh & 63) as u32)),
+                    Operator::ShiftRight => Some(lh >> (rh & 63)),
+                    Operator::Negate | Operator::LogicalNot | Operator::BinaryNot => {
+                        panic!("`{}` is not a binary operator!", operator)
+                    },
+                };
+                self.checked(result, expression)
+            },
+        }
+    }
+
+    /// Returns the result of `expression`, which is `operator` on the Reals `lh` and `rh`
+    ///
+    /// Reals follow IEEE 754, as C doubles do, so they never overflow and
+    /// dividing by zero gives an infinity or NaN.
+    fn real_operation(&self, operator: Operator, lh: f64, rh: f64, expression: &Expression) -> Result<Value, RuntimeError> {
+        Ok(match operator {
+            Operator::Add => Value::Real(Float(lh + rh)),
+            Operator::Sub => Value::Real(Float(lh - rh)),
+            Operator::Mul => Value::Real(Float(lh * rh)),
+            Operator::Div => Value::Real(Float(lh / rh)),
+            Operator::Equals => Value::Integer((lh == rh) as i64),
+            Operator::NotEquals => Value::Integer((lh != rh) as i64),
+            Operator::Gt => Value::Integer((lh > rh) as i64),
+            Operator::Lt => Value::Integer((lh < rh) as i64),
+            Operator::Gte => Value::Integer((lh >= rh) as i64),
+            Operator::Lte => Value::Integer((lh <= rh) as i64),
+            _ => return Err(self.wrong_type(&ast::expression_to_source(expression), expression.span())),
+        })
+    }
+
+    /// Returns the result of `expression`, or an error if it overflowed
+    fn checked(&self, result: Option<i64>, expression: &Expression) -> Result<Value, RuntimeError> {
+        result.map(Value::Integer).ok_or_else(|| {
+            RuntimeError::Overflow {
+                function: self.function(),
+                code: ast::expression_to_source(expression),
+                span: expression.span(),
+            }
+        })
+    }
+}
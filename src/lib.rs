@@ -1,3 +1,35 @@
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+pub extern crate serde_json;
+
 pub mod scanner;
+pub mod locale;
+pub mod symbol;
 pub mod parser;
+#[macro_use]
+pub mod ast;
+pub mod constants;
+pub mod templates;
 pub mod codegen;
+pub mod prelude;
+pub mod builtins;
+pub mod loader;
+pub mod build;
+pub mod driver;
+pub mod cli;
+pub mod names;
+pub mod desugar;
+pub mod dead_code;
+pub mod validate;
+pub use validate::diagnose as validate;
+pub mod diagnostics;
+pub mod flow;
+pub mod check;
+pub mod semantic;
+pub mod typecheck;
+pub mod interp;
+pub mod ice;
+pub mod cst;
+pub mod formatter;
+pub mod query;
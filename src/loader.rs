@@ -0,0 +1,602 @@
+//! src/loader.rs
+//! Loads a Haumea program that is split across several files.
+//!
+//! A file imports another with `use "name"` (or `use name`). The name is
+//! looked up relative to the importing file first, and then relative to the
+//! project root. `.hau` is added to names without an extension.
+//!
+//! Every file is parsed at most once, even if several files use it, and the
+//! functions of a file are placed after the functions of the files it uses.
+//! The files a file uses are parsed at the same time on several threads, but
+//! they are loaded in order afterwards, so the same error is reported for a
+//! project no matter which file finishes parsing first.
+//!
+//! Each file is a module with its own functions. Inside a module, a call to
+//! `helper` means the module's own `helper` if it has one, or else the `helper`
+//! of the one module it uses that defines it. If several used modules define
+//! `helper`, the call must be qualified with the module's namespace, as in
+//! `mathlib.helper(3)`. The namespace is the used file's name, or the alias
+//! given with `use "mathlib" as m`. Calls that don't name a function of any
+//! module (like builtins) are left alone.
+//!
+//! Only functions marked `public` can be called from other files.
+//!
+//! Functions keep their names in the loaded Program unless several modules
+//! define the same name, in which case all but the main file's are renamed
+//! to `<file name>__<function name>`. External functions are never renamed,
+//! and are only included once even if several modules declare them.
+//!
+//! Every span in the loaded Program records the file it was written in, as a
+//! `FileId`, whose path `Loader::files` gives.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use scanner::Scanner;
+use locale::Locale;
+use parser::{self, Directive, Expression, FileId, Module, ParseError, Program, Statement, Visibility};
+use ast::{rebuild_expression, rebuild_statement, Folder};
+use symbol::Symbol;
+
+/// The extension added to used names that don't have one
+pub const EXTENSION: &str = "hau";
+
+/// The name of the threads that parse used files
+const WORKER: &str = "haumea-loader";
+
+/// An error that stopped a program from being loaded
+#[derive(Debug)]
+pub enum LoadError {
+    /// A file could not be read
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A file has mistakes that stop it from being parsed
+    Parse {
+        /// The file, if the source was read from one
+        path: Option<PathBuf>,
+        errors: Vec<ParseError>,
+    },
+    /// A use declaration named a file that could not be found
+    NotFound {
+        name: String,
+        importer: Option<PathBuf>,
+    },
+    /// A file uses itself, directly or through other files
+    ///
+    /// The content is the chain of files, starting and ending with the same file
+    Cycle(Vec<PathBuf>),
+    /// An unqualified call names a function defined in several used modules
+    Ambiguous {
+        name: String,
+        namespaces: Vec<String>,
+        importer: Option<PathBuf>,
+    },
+    /// A qualified call uses a namespace that the file doesn't use
+    UnknownNamespace {
+        name: String,
+        importer: Option<PathBuf>,
+    },
+    /// A qualified call names a function that its module doesn't define
+    UnknownFunction {
+        name: String,
+        importer: Option<PathBuf>,
+    },
+    /// A call names a function of another file that isn't public
+    Private {
+        name: String,
+        importer: Option<PathBuf>,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io { ref path, ref error } => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            },
+            LoadError::Parse { ref path, ref errors } => {
+                let errors: Vec<String> = errors.iter().map(|error| match *path {
+                    Some(ref path) => format!("{}: {}", path.display(), error),
+                    None => error.to_string(),
+                }).collect();
+                write!(f, "{}", errors.join("\n"))
+            },
+            LoadError::NotFound { ref name, importer: Some(ref importer) } => {
+                write!(f, "could not find the file {:?} used by {}", name, importer.display())
+            },
+            LoadError::NotFound { ref name, importer: None } => {
+                write!(f, "could not find the file {:?}", name)
+            },
+            LoadError::Cycle(ref chain) => {
+                let names: Vec<String> = chain.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "files use each other in a cycle: {}", names.join(" uses "))
+            },
+            LoadError::Ambiguous { ref name, ref namespaces, ref importer } => {
+                let choices: Vec<String> = namespaces.iter().map(|ns| format!("{}.{}", ns, name)).collect();
+                write!(f, "`{}` is defined in more than one used file{}, so it must be called as one of {}",
+                       name, describe_importer(importer), choices.join(" or "))
+            },
+            LoadError::UnknownNamespace { ref name, ref importer } => {
+                write!(f, "`{}` does not name a used file{}", name, describe_importer(importer))
+            },
+            LoadError::UnknownFunction { ref name, ref importer } => {
+                write!(f, "`{}` is not a function of the file it names{}", name, describe_importer(importer))
+            },
+            LoadError::Private { ref name, ref importer } => {
+                write!(f, "`{}` is private to the file that defines it, so it can't be called{} \
+                           (mark it `public` to allow this)", name, describe_importer(importer))
+            },
+        }
+    }
+}
+
+/// Returns " in <path>", or nothing if the source wasn't read from a file
+fn describe_importer(importer: &Option<PathBuf>) -> String {
+    match *importer {
+        Some(ref path) => format!(" in {}", path.display()),
+        None => String::new(),
+    }
+}
+
+/// A loaded source file
+#[derive(Debug)]
+struct LoadedModule {
+    /// The file the module was read from, if any
+    path: Option<PathBuf>,
+    /// The functions the module defines
+    functions: Program,
+    /// The build directives of the module
+    directives: Vec<Directive>,
+    /// The namespaces of the modules this module uses, with their indexes in Loader::modules
+    uses: Vec<(String, usize)>,
+}
+
+/// Loads source files and the files they use into a single Program
+#[derive(Debug)]
+pub struct Loader {
+    /// The directory used names are looked up in after the importing file's directory
+    root: PathBuf,
+    /// The modules that have been fully loaded, each after the modules it uses
+    modules: Vec<LoadedModule>,
+    /// The files that are being loaded, innermost last
+    loading: Vec<PathBuf>,
+    /// The files that have been parsed, but not loaded yet
+    parsed: HashMap<PathBuf, Result<Module, LoadError>>,
+    /// The other spellings of keywords that files are written with
+    locale: Locale,
+    /// Whether files can have integers too large for a machine integer
+    big_numbers: bool,
+}
+
+impl Loader {
+    /// Constructs a new Loader for the project rooted at `root`
+    pub fn new<P: Into<PathBuf>>(root: P) -> Loader {
+        Loader {
+            root: root.into(),
+            modules: vec![],
+            loading: vec![],
+            parsed: HashMap::new(),
+            locale: Locale::default(),
+            big_numbers: false,
+        }
+    }
+
+    /// Reads the files loaded after this with the spellings of keywords in `locale`
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.locale = locale;
+    }
+
+    /// Lets the files loaded after this have integers of any size, as programs compiled with `--bignum` can
+    pub fn set_big_numbers(&mut self, big_numbers: bool) {
+        self.big_numbers = big_numbers;
+    }
+
+    /// Returns how many files have been loaded, counting a source that wasn't read from a file
+    pub fn modules(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// Returns the path of each file that has been loaded, in the order of
+    /// their FileIds, which is None for a source that wasn't read from a file
+    pub fn files(&self) -> Vec<Option<PathBuf>> {
+        self.modules.iter().map(|module| module.path.clone()).collect()
+    }
+
+    /// Loads the file at `path` and everything it uses
+    ///
+    /// Returns the index of the file's module.
+    pub fn load_file(&mut self, path: &Path) -> Result<usize, LoadError> {
+        let path = canonicalize(path)?;
+        if let Some(index) = self.modules.iter().position(|module| module.path.as_ref() == Some(&path)) {
+            return Ok(index);
+        }
+        if let Some(start) = self.loading.iter().position(|p| *p == path) {
+            let mut chain = self.loading[start..].to_vec();
+            chain.push(path);
+            return Err(LoadError::Cycle(chain));
+        }
+        let module = match self.parsed.remove(&path) {
+            Some(parsed) => parsed?,
+            None => parse_file(&path, &self.locale, self.big_numbers)?,
+        };
+        self.loading.push(path.clone());
+        let result = self.load_module(module, Some(&path));
+        self.loading.pop();
+        result
+    }
+
+    /// Loads `source` and everything it uses
+    ///
+    /// `path` is the file the source was read from, if any. It is used to
+    /// resolve the source's use declarations.
+    ///
+    /// Returns the index of the source's module.
+    pub fn load_source(&mut self, source: &str, path: Option<&Path>) -> Result<usize, LoadError> {
+        let module = parser::parse_module(scanner(source, &self.locale, self.big_numbers)).map_err(|errors| LoadError::Parse {
+            path: path.map(|path| path.to_path_buf()),
+            errors,
+        })?;
+        self.load_module(module, path)
+    }
+
+    /// Loads `module`, read from the file at `path`, and everything it uses
+    fn load_module(&mut self, mut module: Module, path: Option<&Path>) -> Result<usize, LoadError> {
+        self.parse_used(&module, path);
+        for directive in &mut module.directives {
+            if let Directive::Embed { path: ref name, ref mut bytes, .. } = *directive {
+                let embedded = self.find(Path::new(name), path).ok_or_else(|| LoadError::NotFound {
+                    name: name.to_string(),
+                    importer: path.map(|path| path.to_path_buf()),
+                })?;
+                *bytes = fs::read(&embedded).map_err(|error| LoadError::Io {
+                    path: embedded,
+                    error,
+                })?;
+            }
+        }
+        let mut uses = vec![];
+        for used in &module.uses {
+            let used_path = self.resolve(&used.path, path)?;
+            uses.push((used.namespace().to_string(), self.load_file(&used_path)?));
+        }
+        self.modules.push(LoadedModule {
+            path: path.map(|path| path.to_path_buf()),
+            functions: module.functions,
+            directives: module.directives,
+            uses,
+        });
+        Ok(self.modules.len() - 1)
+    }
+
+    /// Parses every file that `module` uses, directly or through other files, on several threads
+    ///
+    /// Files that can't be found are skipped, and their errors are reported
+    /// when the module is loaded.
+    fn parse_used(&mut self, module: &Module, path: Option<&Path>) {
+        let mut wave = self.unparsed_uses(module, path);
+        while !wave.is_empty() {
+            let results = parse_files(&wave, &self.locale, self.big_numbers);
+            let mut next = vec![];
+            for (file, result) in wave.into_iter().zip(results) {
+                if let Ok(ref used) = result {
+                    next.extend(self.unparsed_uses(used, Some(&file)));
+                }
+                self.parsed.insert(file, result);
+            }
+            next.retain(|file| !self.parsed.contains_key(file));
+            next.dedup();
+            wave = next;
+        }
+    }
+
+    /// Returns the canonical paths of the files `module` uses that haven't been parsed
+    fn unparsed_uses(&self, module: &Module, path: Option<&Path>) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = vec![];
+        for used in &module.uses {
+            let file = match self.resolve(&used.path, path).ok().and_then(|file| file.canonicalize().ok()) {
+                Some(file) => file,
+                None => continue,
+            };
+            let known = self.parsed.contains_key(&file)
+                || self.loading.contains(&file)
+                || self.modules.iter().any(|module| module.path.as_ref() == Some(&file));
+            if !known && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        files
+    }
+
+    /// Returns the path of the file called `name`, used by the file at `importer`
+    pub fn resolve(&self, name: &str, importer: Option<&Path>) -> Result<PathBuf, LoadError> {
+        let mut file_name = PathBuf::from(name);
+        if file_name.extension().is_none() {
+            file_name.set_extension(EXTENSION);
+        }
+        self.find(&file_name, importer).ok_or_else(|| LoadError::NotFound {
+            name: name.to_string(),
+            importer: importer.map(|path| path.to_path_buf()),
+        })
+    }
+
+    /// Returns the path of the file `file_name`, looked up next to `importer` and then in the root
+    fn find(&self, file_name: &Path, importer: Option<&Path>) -> Option<PathBuf> {
+        let importer_dir = importer.and_then(|path| path.parent());
+        importer_dir.into_iter()
+            .chain(Some(self.root.as_path()))
+            .map(|dir| dir.join(file_name))
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// Returns the build directives of every loaded module, without duplicates
+    pub fn directives(&self) -> Vec<Directive> {
+        let mut directives: Vec<Directive> = vec![];
+        for directive in self.modules.iter().flat_map(|module| &module.directives) {
+            if !directives.contains(directive) {
+                directives.push(directive.clone());
+            }
+        }
+        directives
+    }
+
+    /// Resolves the calls in every module and returns the loaded Program
+    pub fn finish(self) -> Result<Program, LoadError> {
+        let names = self.output_names();
+        let mut program = vec![];
+        for (index, module) in self.modules.iter().enumerate() {
+            let resolve = |name: Symbol| self.resolve_call(index, name, &names);
+            for func in &module.functions {
+                if func.external.is_some() && program.iter().any(|other: &parser::Function| other.name == func.name) {
+                    continue;
+                }
+                let mut func = func.clone();
+                func.name = names[index][&func.name];
+                let file = FileId(index as u32);
+                func.span.file = file;
+                let mut renamer = CallRenamer { resolve: &resolve, file, error: None };
+                func = renamer.fold_function(func);
+                if let Some(error) = renamer.error {
+                    return Err(error);
+                }
+                program.push(func);
+            }
+        }
+        Ok(program)
+    }
+
+    /// Returns the name each function of each module has in the loaded Program
+    fn output_names(&self) -> Vec<HashMap<Symbol, Symbol>> {
+        let mut counts = HashMap::new();
+        for module in &self.modules {
+            for func in module.functions.iter().filter(|func| func.external.is_none()) {
+                *counts.entry(func.name).or_insert(0) += 1;
+            }
+        }
+        let main_module = self.modules.len().saturating_sub(1);
+        let mut prefixes: Vec<String> = vec![];
+        self.modules.iter().enumerate().map(|(index, module)| {
+            let prefix = self.unique_prefix(module, &prefixes);
+            prefixes.push(prefix.clone());
+            module.functions.iter().map(|func| {
+                // External functions must keep the name they are linked by
+                let name = if func.external.is_some() || counts[&func.name] == 1 || index == main_module {
+                    func.name
+                } else {
+                    Symbol::from(format!("{}__{}", prefix, func.name))
+                };
+                (func.name, name)
+            }).collect()
+        }).collect()
+    }
+
+    /// Returns a prefix for renamed functions of `module` that isn't in `taken`
+    fn unique_prefix(&self, module: &LoadedModule, taken: &[String]) -> String {
+        let stem = module.path.as_ref()
+            .and_then(|path| path.file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "main".to_string());
+        let base: String = stem.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let mut prefix = base.clone();
+        let mut number = 1;
+        while taken.contains(&prefix) {
+            number += 1;
+            prefix = format!("{}_{}", base, number);
+        }
+        prefix
+    }
+
+    /// Returns true if the module at `index` defines a public function called `name`
+    fn is_public(&self, index: usize, name: &str) -> bool {
+        self.modules[index].functions.iter()
+            .any(|func| func.name == name && func.visibility == Visibility::Public)
+    }
+
+    /// Returns the name a call to `name` from the module at `index` should use
+    fn resolve_call(&self, index: usize, name: Symbol, names: &[HashMap<Symbol, Symbol>])
+                    -> Result<Symbol, LoadError> {
+        let module = &self.modules[index];
+        if let Some(dot) = name.find('.') {
+            let (namespace, function) = (&name[..dot], &name[dot + 1..]);
+            let used = module.uses.iter()
+                .find(|(ns, _)| ns == namespace)
+                .ok_or_else(|| LoadError::UnknownNamespace {
+                    name: namespace.to_string(),
+                    importer: module.path.clone(),
+                })?.1;
+            let output_name = names[used].get(&Symbol::from(function)).cloned().ok_or_else(|| LoadError::UnknownFunction {
+                name: name.to_string(),
+                importer: module.path.clone(),
+            })?;
+            if !self.is_public(used, function) {
+                return Err(LoadError::Private {
+                    name: name.to_string(),
+                    importer: module.path.clone(),
+                });
+            }
+            return Ok(output_name);
+        }
+        if let Some(&own) = names[index].get(&name) {
+            return Ok(own);
+        }
+        let mut candidates: Vec<&(String, usize)> = module.uses.iter()
+            .filter(|&&(_, used)| names[used].contains_key(&name))
+            .collect();
+        candidates.dedup_by_key(|&mut &(_, used)| used);
+        if !candidates.is_empty() && candidates.iter().all(|&&(_, used)| !self.is_public(used, &name)) {
+            return Err(LoadError::Private {
+                name: name.to_string(),
+                importer: module.path.clone(),
+            });
+        }
+        candidates.retain(|&&(_, used)| self.is_public(used, &name));
+        match candidates.len() {
+            0 => Ok(name),
+            1 => Ok(names[candidates[0].1][&name]),
+            _ => Err(LoadError::Ambiguous {
+                name: name.to_string(),
+                namespaces: candidates.iter().map(|(ns, _)| ns.clone()).collect(),
+                importer: module.path.clone(),
+            }),
+        }
+    }
+}
+
+/// Replaces the function name of every call with `resolve(name)`, and puts
+/// every statement and expression in `file`
+struct CallRenamer<'a, F: 'a> {
+    resolve: &'a F,
+    /// The file the function was loaded from
+    file: FileId,
+    /// The first error returned by `resolve`
+    error: Option<LoadError>,
+}
+
+impl<'a, F> CallRenamer<'a, F> where F: Fn(Symbol) -> Result<Symbol, LoadError> {
+    /// Returns the new name of the function called `function`
+    fn rename(&mut self, function: Symbol) -> Symbol {
+        match (self.resolve)(function) {
+            Ok(name) => name,
+            Err(error) => {
+                if self.error.is_none() {
+                    self.error = Some(error);
+                }
+                function
+            },
+        }
+    }
+}
+
+impl<'a, F> Folder for CallRenamer<'a, F> where F: Fn(Symbol) -> Result<Symbol, LoadError> {
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        let mut statement = rebuild_statement(self, statement);
+        statement.span_mut().file = self.file;
+        if let Statement::Call { ref mut function, .. } = statement {
+            *function = self.rename(*function);
+        }
+        statement
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        let mut expression = rebuild_expression(self, expression);
+        expression.span_mut().file = self.file;
+        match expression {
+            Expression::Call { function, arguments, span } => Expression::Call {
+                function: self.rename(function),
+                arguments,
+                span,
+            },
+            expression => expression,
+        }
+    }
+}
+
+/// Loads the file at `path` and everything it uses, using its directory as the project root
+pub fn load(path: &Path) -> Result<Program, LoadError> {
+    let root = path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+    let mut loader = Loader::new(root);
+    loader.load_file(path)?;
+    loader.finish()
+}
+
+/// Parses the files at `paths` on as many threads as there are cores
+///
+/// The results are in the same order as the paths.
+fn parse_files(paths: &[PathBuf], locale: &Locale, big_numbers: bool) -> Vec<Result<Module, LoadError>> {
+    let next = AtomicUsize::new(0);
+    let workers = thread::available_parallelism().map_or(1, |cores| cores.get()).min(paths.len());
+    let mut results: Vec<Option<Result<Module, LoadError>>> = paths.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers).map(|_| {
+            thread::Builder::new().name(WORKER.to_string()).spawn_scoped(scope, || {
+                let mut parsed = vec![];
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let path = match paths.get(index) {
+                        Some(path) => path,
+                        None => return parsed,
+                    };
+                    parsed.push((index, parse_file(path, locale, big_numbers)));
+                }
+            }).expect("Could not start a thread to parse files")
+        }).collect();
+        for handle in handles {
+            let parsed = match handle.join() {
+                Ok(parsed) => parsed,
+                Err(payload) => panic::resume_unwind(payload),
+            };
+            for (index, result) in parsed {
+                results[index] = Some(result);
+            }
+        }
+    });
+    results.into_iter().map(|result| result.expect("Every file is parsed")).collect()
+}
+
+/// Reads and parses the file at `path` with the spellings in `locale`
+fn parse_file(path: &Path, locale: &Locale, big_numbers: bool) -> Result<Module, LoadError> {
+    let source = read_file(path)?;
+    parser::parse_module(scanner(&source, locale, big_numbers)).map_err(|errors| LoadError::Parse {
+        path: Some(path.to_path_buf()),
+        errors,
+    })
+}
+
+/// Returns a scanner for `source` with the spellings in `locale`, which reads
+/// integers of any size if `big_numbers` is true
+fn scanner<'a>(source: &'a str, locale: &'a Locale, big_numbers: bool) -> Scanner<'a> {
+    let scanner = Scanner::with_locale(source, locale);
+    if big_numbers {
+        scanner.with_big_numbers()
+    } else {
+        scanner
+    }
+}
+
+/// Returns the canonical form of `path`, so the same file always has the same path
+fn canonicalize(path: &Path) -> Result<PathBuf, LoadError> {
+    path.canonicalize().map_err(|error| LoadError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Returns the contents of the file at `path`
+fn read_file(path: &Path) -> Result<String, LoadError> {
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|error| LoadError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    Ok(source)
+}
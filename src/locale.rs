@@ -0,0 +1,120 @@
+//! src/locale.rs
+//! Other spellings for Haumea's keywords and word operators, so programs can be
+//! written in languages other than English.
+//!
+//! A locale only changes how the scanner reads words: `si` is read as the
+//! keyword `if`, and `y` as the operator `and`, so the parser, the backends and
+//! every tool after the scanner work the same whatever the locale is. The
+//! English spellings can still be used.
+//!
+//! Locales are read from keyword maps, with one spelling on each line:
+//!
+//! ```text
+//! # Spanish
+//! si = if
+//! mientras = while
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use scanner::{is_operator_word, Keyword};
+
+/// The keyword maps built in to Haumea, by name
+const BUILTIN: &[(&str, &str)] = &[
+    ("es", include_str!("locales/es.map")),
+];
+
+/// What a word in a locale is read as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Word {
+    /// A keyword
+    Keyword(Keyword),
+    /// A word operator, by its English name
+    Operator(String),
+}
+
+/// A mistake in a keyword map
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapError {
+    /// The line of the mistake
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at line {} of the keyword map, {}", self.line, self.message)
+    }
+}
+
+/// The spellings of a locale's keywords and word operators
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Locale {
+    words: HashMap<String, Word>,
+}
+
+impl Locale {
+    /// Returns the locale built in to Haumea called `name`, if there is one
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::locale::{Locale, Word};
+    /// # use haumea::scanner::Keyword;
+    /// let spanish = Locale::builtin("es").unwrap();
+    /// assert_eq!(spanish.lookup("mientras"), Some(&Word::Keyword(Keyword::While)));
+    /// assert!(Locale::builtin("tlh").is_none());
+    /// ```
+    pub fn builtin(name: &str) -> Option<Locale> {
+        BUILTIN.iter()
+            .find(|&&(builtin, _)| builtin == name)
+            .map(|&(_, map)| Locale::parse(map).expect("The built in keyword maps are valid"))
+    }
+
+    /// Reads the keyword map `map`
+    ///
+    /// Each line is blank, a comment starting with `#`, or `spelling = word`,
+    /// where `word` is an English keyword or word operator. A word can have
+    /// many spellings, but a spelling can only be used once, and can't be an
+    /// English keyword.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::locale::{Locale, Word};
+    /// let locale = Locale::parse("# Spanish\nsi = if\ny = and").unwrap();
+    /// assert_eq!(locale.lookup("y"), Some(&Word::Operator("and".to_string())));
+    /// assert!(Locale::parse("si = whether").is_err());
+    /// ```
+    pub fn parse(map: &str) -> Result<Locale, MapError> {
+        let mut locale = Locale::default();
+        for (index, line) in map.lines().enumerate() {
+            let error = |message: String| MapError { line: index + 1, message };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (spelling, english) = match line.split_once('=') {
+                Some((spelling, english)) => (spelling.trim(), english.trim()),
+                None => return Err(error(format!("expected `spelling = word`, but found `{}`", line))),
+            };
+            if spelling.is_empty() || !spelling.chars().all(|c| c.is_ascii_alphabetic() || c == '_') {
+                return Err(error(format!("`{}` can't be a word, words are letters and underscores", spelling)));
+            }
+            if Keyword::from_word(spelling).is_some() || is_operator_word(spelling) {
+                return Err(error(format!("`{}` is already an English keyword", spelling)));
+            }
+            let word = match Keyword::from_word(english) {
+                Some(keyword) => Word::Keyword(keyword),
+                None if is_operator_word(english) => Word::Operator(english.to_string()),
+                None => return Err(error(format!("`{}` is not a keyword or a word operator", english))),
+            };
+            if locale.words.insert(spelling.to_string(), word).is_some() {
+                return Err(error(format!("`{}` is given more than once", spelling)));
+            }
+        }
+        Ok(locale)
+    }
+
+    /// Returns what `word` is read as in this locale, if it is one of its spellings
+    pub fn lookup(&self, word: &str) -> Option<&Word> {
+        self.words.get(word)
+    }
+}
@@ -1,17 +1,591 @@
 extern crate haumea;
+use std::env;
+use std::fs;
 use std::io;
 use std::io::prelude::*;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 // Load the CodeGen trait into scope
 use haumea::codegen::CodeGen;
+use haumea::cli::{self, Backend, Cli, Command};
+use haumea::ice;
+use haumea::diagnostics::Diagnostic;
+
+/// What the compiler is doing, for reports of internal compiler errors
+static PHASE: Mutex<&str> = Mutex::new("reading the command line");
+/// Where in the compiler's code the last internal compiler error happened
+static LOCATION: Mutex<Option<String>> = Mutex::new(None);
+/// The program read from stdin, if it was
+static STDIN_SOURCE: Mutex<Option<String>> = Mutex::new(None);
+/// The name the loaded program is given in the query database
+const PROGRAM: &str = "program";
+/// Whether panics are being caught without being reported
+static QUIET: AtomicBool = AtomicBool::new(false);
 
 fn main() {
-    let mut source = String::new();
-    let mut stdin = io::stdin();
-    stdin.read_to_string(&mut source).expect("Must provide input");
-    let scanner = haumea::scanner::Scanner::new(&source);
-    let ast = haumea::parser::parse(scanner);
-    let mut cg = haumea::codegen::c::CodeGenerator::new(ast);
-    let out = cg.compile();
-    println!("{}", out);
+    let cli = match cli::parse(env::args().skip(1), is_script) {
+        Ok(cli) => cli,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            eprintln!("note: run `haumea --help` to see how to use the compiler");
+            process::exit(1);
+        },
+    };
+    install_panic_hook();
+    if let Err(payload) = panic::catch_unwind(|| compile(&cli)) {
+        let message = ice::panic_message(&*payload).to_string();
+        // The panic hook has already reported mistakes in the program
+        if ice::is_diagnostic(&message) {
+            process::exit(1);
+        }
+        report_ice(&cli, message);
+        process::exit(101);
+    }
+}
+
+/// Sets what the compiler is doing
+fn phase(name: &'static str) {
+    *PHASE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = name;
+}
+
+/// Prints the mistakes the parser reports as errors, and keeps where internal compiler errors happen
+fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        if QUIET.load(Ordering::Relaxed) {
+            return;
+        }
+        let message = ice::panic_message(info.payload());
+        if ice::is_diagnostic(message) {
+            eprintln!("error: {}", message);
+        } else if let Some(location) = info.location() {
+            *LOCATION.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(location.to_string());
+        }
+    }));
+}
+
+/// Does what the command line `cli` asks for
+fn compile(cli: &Cli) {
+    if cli.command == Command::Help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    let use_prelude = !cli.flag("--no-prelude");
+    let options = options(cli);
+    // --crate-type=staticlib or --crate-type=cdylib builds a library instead of printing C
+    let library_kind = cli.value("--crate-type")
+        .map(|name| match haumea::build::LibraryKind::from_name(name) {
+            Some(kind) => kind,
+            None => {
+                eprintln!("error: unknown crate type {}, expected staticlib or cdylib", name);
+                process::exit(1);
+            },
+        });
+    // --keywords=es, or --keywords=file.map, also reads keywords spelt in another language
+    let locale = cli.value("--keywords").map(load_locale).unwrap_or_default();
+    if cli.command == Command::Tokens {
+        print_tokens(&read_input(cli), &locale);
+        return;
+    }
+    if cli.command == Command::Fmt {
+        format(cli);
+        return;
+    }
+    phase("loading the program");
+    // The program is read from the file given on the command line, or from stdin
+    let (loader, loaded) = match cli.input {
+        Some(ref path) => {
+            let root = path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+            let mut loader = haumea::loader::Loader::new(root);
+            loader.set_locale(locale);
+            loader.set_big_numbers(cli.flag("--bignum"));
+            let loaded = loader.load_file(path);
+            (loader, loaded)
+        },
+        None => {
+            let source = read_input(cli);
+            *STDIN_SOURCE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(source.clone());
+            let root = env::current_dir().expect("Must be run in a directory");
+            let mut loader = haumea::loader::Loader::new(root);
+            loader.set_locale(locale);
+            loader.set_big_numbers(cli.flag("--bignum"));
+            let loaded = loader.load_source(&source, None);
+            (loader, loaded)
+        },
+    };
+    let directives = loader.directives();
+    // Mistakes found after loading are shown in the file their span is in
+    let files = loader.files();
+    let mut ast = match loaded.and_then(|_| loader.finish()) {
+        Ok(ast) => ast,
+        Err(haumea::loader::LoadError::Parse { path, errors }) => {
+            let source = read_source(path.as_deref());
+            for error in &errors {
+                report(&error.diagnostic(), source.as_deref(), path.as_ref());
+            }
+            process::exit(1);
+        },
+        Err(error) => {
+            // A file can have several mistakes, each reported on its own line
+            for line in error.to_string().lines() {
+                eprintln!("error: {}", line);
+            }
+            process::exit(1);
+        },
+    };
+    // --desugar lowers for each, forever and change to the core statements
+    if cli.flag("--desugar") {
+        ast = haumea::desugar::desugar(ast);
+    }
+    // --emit=expanded prints the loaded program, with constants and templates expanded
+    if cli.value("--emit") == Some("expanded") {
+        print!("{}", haumea::ast::to_source(&ast));
+        return;
+    }
+    // --emit=ast-json prints the loaded program's syntax tree as JSON
+    if cli.value("--emit") == Some("ast-json") {
+        print_ast_json(&ast);
+        return;
+    }
+    // ast prints the syntax tree for other tools, without checking the program
+    if cli.command == Command::Ast {
+        match cli.value("--format") {
+            Some("source") => print!("{}", haumea::ast::to_source(&ast)),
+            _ => print_ast_json(&ast),
+        }
+        return;
+    }
+    phase("checking the program");
+    // --opt dead-code leaves out the functions that are never called, warning about
+    // the program's own ones, which are all there is before the prelude is included
+    let dead_code = cli.optimizes("dead-code");
+    let roots = haumea::dead_code::roots(&ast, library_kind.is_some());
+    let unused = if dead_code { haumea::dead_code::warnings(&ast, &roots) } else { vec![] };
+    if use_prelude {
+        ast = haumea::prelude::include(ast);
+    }
+    // The program is checked, and compiled to C, by the queries of a database
+    let mut db = haumea::query::Database::new();
+    db.set_options(options.clone());
+    db.set_program(PROGRAM, ast.clone(), directives.clone());
+    let diagnostics = db.diagnostics(PROGRAM);
+    for diagnostic in diagnostics.iter().cloned().chain(unused.iter().map(|problem| problem.diagnostic())) {
+        report_in_file(&diagnostic, &files);
+    }
+    if haumea::diagnostics::has_errors(&diagnostics) {
+        process::exit(1);
+    }
+    if let Err(problems) = haumea::validate::validate(&ast) {
+        let problems: Vec<String> = problems.iter().map(|problem| problem.to_string()).collect();
+        panic!("The loaded program is not valid: {}", problems.join(", "));
+    }
+    if cli.command == Command::Check {
+        return;
+    }
+    if dead_code {
+        ast = haumea::dead_code::eliminate(ast, &roots);
+        db.set_program(PROGRAM, ast.clone(), directives.clone());
+    }
+    // run interprets the program, unless it needs C for big numbers or external functions, or --via c is given
+    let via_c = cli.value("--via") == Some("c");
+    if cli.command == Command::Run && !via_c && !options.bignum && !haumea::interp::needs_c(&ast) {
+        phase("running the program");
+        interpret(&ast, &directives);
+    }
+    let rust_bindings = cli.flag("--rust-bindings");
+    if rust_bindings && library_kind.is_none() {
+        eprintln!("error: --rust-bindings can only be used when building a library with --crate-type");
+        process::exit(1);
+    }
+    if rust_bindings && options.bignum {
+        eprintln!("error: --rust-bindings can't be used with --bignum");
+        process::exit(1);
+    }
+    let name = library_name(cli.input.as_deref());
+    let bindings = match library_kind {
+        Some(kind) if rust_bindings => Some(haumea::build::rust_bindings(&ast, &name, kind)),
+        _ => None,
+    };
+    if via_c && cli.backend != Backend::C {
+        eprintln!("error: --via c runs the program with the C backend, so it can't be used with --backend");
+        process::exit(1);
+    }
+    // --emit=web builds a web page that runs the program, with the js backend
+    let web = cli.value("--emit") == Some("web");
+    if web && (cli.command == Command::Run || library_kind.is_some() || cli.backend == Backend::Python) {
+        eprintln!("error: --emit=web builds a web page with the js backend, so it can only be used to build a program");
+        process::exit(1);
+    }
+    let backend = if web { Backend::Js } else { cli.backend };
+    if cli.backend == Backend::Js && (cli.command == Command::Run || library_kind.is_some()) {
+        eprintln!("error: the js backend can only build a JavaScript module, which is run with a JavaScript engine");
+        process::exit(1);
+    }
+    if cli.backend == Backend::Python && (cli.command == Command::Run || library_kind.is_some()) {
+        eprintln!("error: the python backend can only build a Python script, which is run with python3");
+        process::exit(1);
+    }
+    let flags = haumea::codegen::c::linker_flags(&directives);
+    let compiled = match backend {
+        Backend::C => {
+            phase("generating C");
+            (*db.c_code(PROGRAM)).clone()
+        },
+        Backend::Js => {
+            phase("generating JavaScript");
+            haumea::codegen::js::CodeGenerator::with_directives(options, directives).compile(&ast)
+                .map_err(|error| error.diagnostics())
+        },
+        Backend::Python => {
+            phase("generating Python");
+            haumea::codegen::python::CodeGenerator::with_directives(options, directives).compile(&ast)
+                .map_err(|error| error.diagnostics())
+        },
+    };
+    // The program has been checked, so it failing to compile is a bug in the compiler
+    let out = compiled.unwrap_or_else(|diagnostics| panic!("{}", describe_all(&diagnostics)));
+    phase("building the program");
+    if cli.command == Command::Run {
+        run_program(&out, &name, &flags, &cli.program_args);
+    }
+    // The page goes in the -o folder, or one named after the program
+    if web {
+        let dir = cli.output.clone().unwrap_or_else(|| PathBuf::from(&name));
+        match haumea::codegen::web::write(&dir, &name, &out) {
+            Ok(()) => eprintln!("Wrote {}", dir.display()),
+            Err(error) => {
+                eprintln!("error: could not write {}: {}", dir.display(), error);
+                process::exit(1);
+            },
+        }
+        return;
+    }
+    let kind = match library_kind {
+        Some(kind) => kind,
+        None => {
+            write_output(cli, &out);
+            return;
+        },
+    };
+    let out_dir = env::current_dir().expect("Must be run in a directory");
+    match haumea::build::build_library(&out, &name, kind, &out_dir, &flags) {
+        Ok(library) => eprintln!("Built {}", library.display()),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        },
+    }
+    // --rust-bindings also writes a Rust module for calling the library
+    if let Some(bindings) = bindings {
+        let path = out_dir.join(format!("{}.rs", name));
+        if let Err(error) = fs::write(&path, bindings) {
+            eprintln!("error: could not write {}: {}", path.display(), error);
+            process::exit(1);
+        }
+        eprintln!("Wrote Rust bindings to {}", path.display());
+    }
+}
+
+/// Rewrites the input file in the standard style, or prints stdin in it
+///
+/// With --check, the file is left as it is, and the compiler fails if it isn't formatted.
+fn format(cli: &Cli) {
+    let source = read_input(cli);
+    let formatted = match haumea::formatter::format(&source) {
+        Ok(formatted) => formatted,
+        Err(errors) => {
+            for error in &errors {
+                report(&error.diagnostic(), Some(&source), cli.input.as_ref());
+            }
+            process::exit(1);
+        },
+    };
+    let name = cli.input.as_ref().map_or("stdin".to_string(), |path| path.display().to_string());
+    if cli.flag("--check") {
+        if formatted != source {
+            eprintln!("error: {} is not formatted, run `haumea fmt` on it", name);
+            process::exit(1);
+        }
+        return;
+    }
+    match cli.input {
+        Some(ref path) if formatted != source => {
+            if let Err(error) = fs::write(path, formatted) {
+                eprintln!("error: could not write {}: {}", name, error);
+                process::exit(1);
+            }
+        },
+        Some(_) => (),
+        None => print!("{}", formatted),
+    }
+}
+
+/// Returns the source read from `path`, or from stdin if there is no path
+fn read_source(path: Option<&Path>) -> Option<String> {
+    match path {
+        Some(path) => fs::read_to_string(path).ok(),
+        None => STDIN_SOURCE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone(),
+    }
+}
+
+/// Tells the user about a mistake in the program, showing the code it is in if `source` is known
+fn report(diagnostic: &Diagnostic, source: Option<&str>, path: Option<&PathBuf>) {
+    let path = path.map(|path| shown_path(path));
+    eprint!("{}", diagnostic.render(source, path.as_deref()));
+}
+
+/// Tells the user about a mistake in the loaded program, in the file of
+/// `files` that its span is in, which is None for the source read from stdin
+fn report_in_file(diagnostic: &Diagnostic, files: &[Option<PathBuf>]) {
+    let path = files.get(diagnostic.span.file.index()).cloned().flatten();
+    let source = read_source(path.as_deref());
+    report(diagnostic, source.as_deref(), path.as_ref());
+}
+
+/// Returns how `path` is shown in messages, which is relative to the
+/// current directory if it is inside it, however it was given
+fn shown_path(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    match env::current_dir().and_then(|dir| dir.canonicalize()) {
+        Ok(dir) => path.strip_prefix(&dir).unwrap_or(&path).display().to_string(),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// Tells the user that the compiler failed, and writes a report of the failure to attach to a bug report
+fn report_ice(cli: &Cli, message: String) {
+    let phase = *PHASE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let version = env!("CARGO_PKG_VERSION");
+    eprintln!("error: internal compiler error while {}: {}", phase, message);
+    eprintln!("note: this is a bug in Haumea {}, not in your program", version);
+    let source = match cli.input {
+        Some(ref path) => fs::read_to_string(path).ok(),
+        None => STDIN_SOURCE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone(),
+    };
+    let source = match source {
+        Some(source) => source,
+        None => return,
+    };
+    // The report is made while panics are expected, so they aren't reported again
+    QUIET.store(true, Ordering::Relaxed);
+    let root = cli.input.as_ref().and_then(|path| path.parent()).map(|dir| dir.to_path_buf())
+        .unwrap_or_else(|| env::current_dir().unwrap_or_default());
+    let source = ice::minimize(&source, |source| fails_with(cli, &root, source, &message));
+    let locale = cli.value("--keywords").and_then(haumea::locale::Locale::builtin).unwrap_or_default();
+    let ast = panic::catch_unwind(|| {
+        haumea::parser::parse_module(haumea::scanner::Scanner::with_locale(&source, &locale))
+            .ok()
+            .map(|module| format!("{:#?}", module))
+    }).ok().flatten();
+    QUIET.store(false, Ordering::Relaxed);
+    let report = ice::Report {
+        version,
+        phase,
+        message,
+        location: LOCATION.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone(),
+        source,
+        ast,
+    };
+    let path = env::temp_dir().join(format!("haumea-ice-{}.txt", process::id()));
+    match fs::write(&path, report.to_string()) {
+        Ok(()) => eprintln!("note: please report it, attaching {}", path.display()),
+        Err(error) => eprintln!("note: please report it; the report could not be written to {}: {}",
+                                path.display(), error),
+    }
+}
+
+/// Returns true if compiling `source` with the command line `cli` fails with the internal compiler error `message`
+fn fails_with(cli: &Cli, root: &Path, source: &str, message: &str) -> bool {
+    let result = panic::catch_unwind(|| {
+        let mut loader = haumea::loader::Loader::new(PathBuf::from(root));
+        if let Some(name) = cli.value("--keywords") {
+            loader.set_locale(haumea::locale::Locale::builtin(name).unwrap_or_default());
+        }
+        loader.set_big_numbers(cli.flag("--bignum"));
+        let loaded = loader.load_source(source, None);
+        let directives = loader.directives();
+        let mut ast = match loaded.and_then(|_| loader.finish()) {
+            Ok(ast) => ast,
+            Err(_) => return,
+        };
+        if cli.flag("--desugar") {
+            ast = haumea::desugar::desugar(ast);
+        }
+        if !cli.flag("--no-prelude") {
+            ast = haumea::prelude::include(ast);
+        }
+        let mut db = haumea::query::Database::new();
+        db.set_options(options(cli));
+        db.set_program(PROGRAM, ast.clone(), directives.clone());
+        if haumea::diagnostics::has_errors(&db.diagnostics(PROGRAM)) {
+            return;
+        }
+        if let Err(problems) = haumea::validate::validate(&ast) {
+            let problems: Vec<String> = problems.iter().map(|problem| problem.to_string()).collect();
+            panic!("The loaded program is not valid: {}", problems.join(", "));
+        }
+        if cli.optimizes("dead-code") {
+            let roots = haumea::dead_code::roots(&ast, cli.value("--crate-type").is_some());
+            ast = haumea::dead_code::eliminate(ast, &roots);
+            db.set_program(PROGRAM, ast, directives);
+        }
+        if let Err(ref diagnostics) = *db.c_code(PROGRAM) {
+            panic!("{}", describe_all(diagnostics));
+        }
+    });
+    match result {
+        Ok(()) => false,
+        Err(payload) => ice::panic_message(&*payload) == message,
+    }
+}
+
+/// Describes every one of `diagnostics` in one line
+fn describe_all(diagnostics: &[Diagnostic]) -> String {
+    let described: Vec<String> = diagnostics.iter().map(Diagnostic::to_string).collect();
+    described.join(", ")
+}
+
+/// Returns the code generator options the command line `cli` asks for
+fn options(cli: &Cli) -> haumea::codegen::Options {
+    haumea::codegen::Options {
+        bignum: cli.flag("--bignum"),
+        runtime_checks: cli.flag("--runtime-checks"),
+        deterministic: cli.flag("--deterministic"),
+        tail_calls: cli.optimizes("tailcall"),
+        checked_arithmetic: cli.flag("--checked-arithmetic"),
+    }
+}
+
+/// Returns the built in locale called `name`, or else the locale in the keyword map at the path `name`
+fn load_locale(name: &str) -> haumea::locale::Locale {
+    if let Some(locale) = haumea::locale::Locale::builtin(name) {
+        return locale;
+    }
+    let map = match fs::read_to_string(name) {
+        Ok(map) => map,
+        Err(error) => {
+            eprintln!("error: {} is not a built in locale, and could not be read: {}", name, error);
+            process::exit(1);
+        },
+    };
+    match haumea::locale::Locale::parse(&map) {
+        Ok(locale) => locale,
+        Err(error) => {
+            eprintln!("error: in {}, {}", name, error);
+            process::exit(1);
+        },
+    }
+}
+
+/// Returns true if the file at `path` starts with a `#!` line
+fn is_script(path: &Path) -> bool {
+    let mut start = [0; 5];
+    let read = fs::File::open(path).and_then(|mut file| file.read(&mut start)).unwrap_or(0);
+    let start = &start[..read];
+    // The line can come after a byte order mark
+    start.starts_with(b"#!") || start.starts_with(b"\xef\xbb\xbf#!")
+}
+
+/// Builds the compiled C code `code` into a program and runs it with the
+/// arguments `args`, then exits with the program's exit status
+fn run_program(code: &str, name: &str, flags: &[String], args: &[String]) -> ! {
+    match haumea::driver::run(code, name, flags, args) {
+        Ok(status) => process::exit(status),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            // Like a shell, exit with 128 plus the signal that killed the program
+            process::exit(match error {
+                haumea::driver::DriverError::Killed { signal } => 128 + signal,
+                _ => 1,
+            });
+        },
+    }
+}
+
+/// Runs the loaded program `ast` with the interpreter, then exits with its exit status
+fn interpret(ast: &haumea::parser::Program, directives: &[haumea::parser::Directive]) -> ! {
+    let result = thread::scope(|scope| {
+        thread::Builder::new().stack_size(haumea::interp::STACK_SIZE).spawn_scoped(scope, || {
+            let stdin = io::stdin();
+            haumea::interp::Interpreter::with_directives(stdin.lock(), io::stdout(), directives)
+                .allow_commands()
+                .run(ast)
+        }).expect("The interpreter's thread can be started").join()
+    });
+    match result.unwrap_or_else(|payload| panic::resume_unwind(payload)) {
+        Ok(status) => process::exit(status),
+        // A reader that stops early, like `head`, closes the pipe, which isn't a mistake
+        Err(haumea::interp::RuntimeError::Io { kind: io::ErrorKind::BrokenPipe, .. }) => process::exit(0),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        },
+    }
+}
+
+/// Returns the source of the main file, or of stdin if there isn't one
+fn read_input(cli: &Cli) -> String {
+    match cli.input {
+        Some(ref path) => fs::read_to_string(path).unwrap_or_else(|error| {
+            eprintln!("error: could not read {}: {}", path.display(), error);
+            process::exit(1);
+        }),
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).expect("Must provide input");
+            source
+        },
+    }
+}
+
+/// Writes the compiled program `out` to the output file, or prints it if there isn't one
+fn write_output(cli: &Cli, out: &str) {
+    match cli.output {
+        Some(ref path) => {
+            if let Err(error) = fs::write(path, out) {
+                eprintln!("error: could not write {}: {}", path.display(), error);
+                process::exit(1);
+            }
+        },
+        None => {
+            // A reader that stops early, like `head`, closes the pipe, which isn't a mistake
+            if let Err(error) = writeln!(io::stdout(), "{}", out) {
+                if error.kind() != io::ErrorKind::BrokenPipe {
+                    eprintln!("error: could not print the program: {}", error);
+                    process::exit(1);
+                }
+            }
+        },
+    }
+}
+
+/// Prints each token in `source`, with its line and column
+fn print_tokens(source: &str, locale: &haumea::locale::Locale) {
+    for token in haumea::scanner::Scanner::with_locale(source, locale) {
+        let state = token.state();
+        println!("{}:{}\t{}", state.line, state.column, token);
+    }
+}
+
+/// Returns the name of the library built from `main_file`
+fn library_name(main_file: Option<&Path>) -> String {
+    main_file
+        .and_then(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "haumea".to_string())
+}
+
+#[cfg(feature = "serde")]
+fn print_ast_json(ast: &haumea::parser::Program) {
+    println!("{}", haumea::serde_json::to_string_pretty(ast).expect("The AST can always be serialized"));
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_ast_json(_ast: &haumea::parser::Program) {
+    eprintln!("error: printing the syntax tree as JSON needs haumea to be built with the serde feature");
+    process::exit(1);
 }
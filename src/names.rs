@@ -0,0 +1,53 @@
+//! src/names.rs
+//! Generates names that can't clash with the names in a Haumea program.
+
+/// Generates a new name each time `fresh` is called
+#[derive(Debug, Clone)]
+pub struct UniqueNames {
+    /// The start of every generated name
+    prefix: &'static str,
+    /// The name of the scope names are generated in, followed by an underscore
+    scope: String,
+    /// The number of names generated so far in the scope
+    count: u32,
+}
+
+impl UniqueNames {
+    /// Constructs a new UniqueNames whose names start with `prefix`
+    ///
+    /// `prefix` should be something a program is unlikely to use, like `__HAUMEA_TEMP_`.
+    pub fn new(prefix: &'static str) -> UniqueNames {
+        UniqueNames { prefix, scope: String::new(), count: 0 }
+    }
+
+    /// Returns a name that hasn't been returned before
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::names::UniqueNames;
+    /// let mut names = UniqueNames::new("__TEMP_");
+    /// assert_eq!(names.fresh(), "__TEMP_1");
+    /// assert_eq!(names.fresh(), "__TEMP_2");
+    /// ```
+    pub fn fresh(&mut self) -> String {
+        self.count += 1;
+        format!("{}{}{}", self.prefix, self.scope, self.count)
+    }
+
+    /// Starts numbering names from 1 again, in the scope called `scope`
+    ///
+    /// Names from different scopes never clash, so the names generated in a
+    /// scope don't depend on how many were generated before it.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::names::UniqueNames;
+    /// let mut names = UniqueNames::new("__TEMP_");
+    /// names.restart("main");
+    /// assert_eq!(names.fresh(), "__TEMP_main_1");
+    /// ```
+    pub fn restart(&mut self, scope: &str) {
+        self.scope = format!("{}_", scope);
+        self.count = 0;
+    }
+}
@@ -1,45 +1,357 @@
 /// src/parser.rs
 /// The parser for the haumea language.
-use std::rc::Rc;
-use scanner::{Scanner, Token, ScanState};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::Peekable;
+use std::mem;
+use std::sync::Arc;
+use scanner::{self, Keyword, Scanner, ScanError, Token, ScanState};
+use symbol::Symbol;
+use diagnostics::{self, Diagnostic};
+use constants;
+use ast;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use templates;
+
+pub mod incremental;
 
 /// A Program is a Vec of Functions
 pub type Program = Vec<Function>;
 
-/// A Block is a Vec of Rc<Statement>s
-pub type Block = Vec<Rc<Statement>>;
+/// A Block is a Vec of Arc<Statement>s
+///
+/// Statements are shared behind `Arc`s rather than kept in an arena, since
+/// `Visitor`, `Folder`, the builders in `ast`, template expansion, the serde
+/// feature and the query database all work on owned trees. Copying a tree
+/// only copies the `Arc`s, and passes that change a statement use
+/// `Arc::make_mut`, which only copies the statements that are shared.
+pub type Block = Vec<Arc<Statement>>;
+
+/// The type of a value, which can be declared in a function's signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Type {
+    /// A whole number, which is also used as true or false
+    Integer,
+    /// A decimal number, like 1.5
+    Real,
+    /// Text, like "Hello"
+    Text,
+    /// A list, like [1, 2, 3]
+    List,
+    /// No value, which is what a procedure returns
+    Nothing,
+}
+
+impl Type {
+    /// Returns the type spelt `name`, if it is one
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::parser::Type;
+    /// assert_eq!(Type::from_name("Integer"), Some(Type::Integer));
+    /// assert_eq!(Type::from_name("integer"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "Integer" => Some(Type::Integer),
+            "Real" => Some(Type::Real),
+            "Text" => Some(Type::Text),
+            "List" => Some(Type::List),
+            "Nothing" => Some(Type::Nothing),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Integer => write!(f, "Integer"),
+            Type::Real => write!(f, "Real"),
+            Type::Text => write!(f, "Text"),
+            Type::List => write!(f, "List"),
+            Type::Nothing => write!(f, "Nothing"),
+        }
+    }
+}
+
+/// The types declared in a function's signature
+///
+/// to add with (a: Integer, b: Integer) returns Integer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Types {
+    /// The type of each parameter, or None for a parameter without one
+    ///
+    /// It is empty if no parameter has a type.
+    pub parameters: Vec<Option<Type>>,
+    /// The type the function returns, if it is declared
+    pub returns: Option<Type>,
+}
+
+impl Types {
+    /// Returns the type of parameter `index`, if it has one
+    pub fn parameter(&self, index: usize) -> Option<Type> {
+        self.parameters.get(index).cloned().flatten()
+    }
+}
+
+/// An Ident is an interned name
+pub type Ident = Symbol;
+
+/// A Signature is a Vec of Idents
+pub type Signature = Vec<Ident>;
+
+/// Which file of a loaded program a node was written in, which is the index
+/// of its module in the `Loader`
+///
+/// A program parsed from one source is all in the file 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileId(pub u32);
+
+impl FileId {
+    /// Returns the index of the file's module
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Where a node of the syntax tree was written, from its first token to its last
+///
+/// Like tokens, spans are ignored when nodes are compared or hashed, so a
+/// tree built by hand is equal to the same tree parsed from source.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Span {
+    /// Where the first token starts
+    pub start: ScanState,
+    /// Where the last token starts
+    pub end: ScanState,
+    /// The file the tokens are in
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub file: FileId,
+    /// The number the parser gave the node, which is unique in its file, or
+    /// None for a node that wasn't parsed from there
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub node: Option<u32>,
+}
+
+impl Span {
+    /// Constructs a new Span in the file 0, of a node without a number
+    pub fn new(start: ScanState, end: ScanState) -> Span {
+        Span { start, end, file: FileId::default(), node: None }
+    }
+
+    /// Returns the id of the node the span is of, if the parser numbered it
+    pub fn id(&self) -> Option<ast::NodeId> {
+        Some(ast::NodeId { file: self.file, node: self.node? })
+    }
+
+    /// Returns true if the span is the empty span of a node that wasn't parsed
+    pub fn is_empty(&self) -> bool {
+        self.start == ScanState::empty()
+    }
+}
+
+impl Default for Span {
+    /// Returns the empty span of a node that wasn't parsed, like one built by a pass
+    fn default() -> Span {
+        Span::new(ScanState::empty(), ScanState::empty())
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _other: &Span) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+impl Hash for Span {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// Writes where the span starts, like "3:5"
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.start.line, self.start.column)
+    }
+}
+
+/// A use declaration, which imports the functions of another file
+///
+/// use "math_helpers" as math
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Use {
+    /// The name of the file to import, as it was written
+    pub path: String,
+    /// The name used to qualify the file's functions, if it was given with `as`
+    pub alias: Option<Ident>,
+}
+
+impl Use {
+    /// Returns the namespace the used file's functions can be qualified with
+    ///
+    /// This is the alias if there is one, or else the file name without
+    /// its directory and extension.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::parser::Use;
+    /// let plain = Use { path: "lib/mathlib.hau".to_string(), alias: None };
+    /// assert_eq!(plain.namespace(), "mathlib");
+    /// let aliased = Use { path: "mathlib".to_string(), alias: Some("m".into()) };
+    /// assert_eq!(aliased.namespace(), "m");
+    /// ```
+    pub fn namespace(&self) -> &str {
+        match self.alias {
+            Some(ref alias) => alias,
+            None => {
+                let name = self.path.rsplit('/').next().unwrap_or(&self.path);
+                match name.find('.') {
+                    Some(dot) if dot > 0 => &name[..dot],
+                    _ => name,
+                }
+            },
+        }
+    }
+}
+
+/// A directive that tells the compiler how to build the program
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Directive {
+    /// Link the program with a C library
+    ///
+    /// link with "m"
+    Link(String),
+    /// Include a C header in the compiled program
+    ///
+    /// include header "math.h"
+    Include(String),
+    /// Bake the contents of a file into the program
+    ///
+    /// embed "table.txt" as data
+    ///
+    /// The program can read the file's bytes with `data(index)`, and its
+    /// length with `data_size()`.
+    Embed {
+        /// The name of the file, as it was written
+        path: String,
+        /// The name of the function that reads the file's bytes
+        name: Ident,
+        /// The contents of the file, which are read by the loader
+        bytes: Vec<u8>,
+    },
+}
 
-/// A Type is a String (for now)
-pub type Type = String;
+/// A constant declaration
+///
+/// define SIZE as 8 * 8
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Constant {
+    /// The name of the constant
+    pub name: Ident,
+    /// The expression that gives the constant's value
+    pub value: Expression,
+}
 
-/// An Ident is a String
-pub type Ident = String;
+/// A template, whose code is pasted in wherever it is used
+///
+/// template swap with (a, b) do ... end
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Template {
+    /// The name of the template
+    pub name: Ident,
+    /// The parameters of the template
+    pub signature: Option<Signature>,
+    /// The code the template expands to
+    pub body: Statement,
+}
+
+/// A Module is a single parsed source file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Module {
+    /// The files this module imports
+    pub uses: Vec<Use>,
+    /// The build directives of this module
+    pub directives: Vec<Directive>,
+    /// The constants declared in this module
+    pub constants: Vec<Constant>,
+    /// The templates declared in this module
+    pub templates: Vec<Template>,
+    /// The functions defined in this module
+    pub functions: Program,
+}
+
+/// Whether a function can be used outside of the file that defines it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Visibility {
+    /// The function can be used by files that use this file,
+    /// and is exported from the compiled program
+    ///
+    /// public to helper ...
+    Public,
+    /// The function can only be used in this file (the default)
+    ///
+    /// private to helper ...
+    Private,
+}
 
-/// A Signature is a Vec of Strings
-pub type Signature = Vec<String>;
+/// A function that is implemented outside of Haumea
+///
+/// external to sqrt with (x) from "math.h"
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct External {
+    /// The C header that declares the function, if it was given with `from`
+    pub header: Option<String>,
+}
 
 /// A function is a callable unit of code that returns a value
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Function {
     /// The name of the function
-    pub name: String,
+    pub name: Ident,
+    /// Whether the function can be used outside of its file
+    pub visibility: Visibility,
+    /// Some(External) if the function is implemented outside of Haumea,
+    /// in which case its code is an empty Do block
+    pub external: Option<External>,
     /// The signature of the function
     ///
     /// It is a Some(Signature) when there is a signature,
     /// or None if there is no signature, which means that
     /// the function takes no arguments and return the Integer 0
     pub signature: Option<Signature>,
+    /// The types declared in the signature
+    pub types: Types,
     /// The code of the function
     pub code: Statement,
+    /// Where the function is defined
+    pub span: Span,
 }
 
 /// A Haumea statement
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Every statement ends with where it was written.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Statement {
     /// A return statement
     ///
     /// return 1
-    Return(Expression),
+    Return(Expression, Span),
 /*    /// A let statement
     ///
     /// let x be an Integer
@@ -47,15 +359,15 @@ pub enum Statement {
     /// A variable statement
     ///
     /// variable x
-    Var(Ident),
+    Var(Ident, Span),
     /// An assignment statement
     ///
     /// set x to 5
-    Set(Ident, Expression),
+    Set(Ident, Expression, Span),
     /// A change statement
     ///
     /// change x by -2
-    Change(Ident, Expression),
+    Change(Ident, Expression, Span),
     /// An if statement
     ///
     /// if True then return 1
@@ -63,8 +375,9 @@ pub enum Statement {
     /// (else is optional)
     If {
         cond: Expression,
-        if_clause: Rc<Statement>,
-        else_clause: Rc<Option<Statement>>,
+        if_clause: Arc<Statement>,
+        else_clause: Arc<Option<Statement>>,
+        span: Span,
     },
     /// A Do statement
     ///
@@ -72,24 +385,29 @@ pub enum Statement {
     ///   statement1
     ///   statement2
     /// end
-    Do(Block),
+    Do(Block, Span),
     /// A call statment
     ///
     /// write_ln(1)
     Call {
         function: Ident,
         arguments: Vec<Expression>,
+        span: Span,
     },
-    /// A forever loop
+    /// A forever loop, with its label if it has one
     ///
     /// forever do ... end
-    Forever(Rc<Statement>),
+    /// forever as outer do ... end
+    Forever(Arc<Statement>, Option<Ident>, Span),
     /// A while loop
     ///
     /// while x < 5 change x by 1
     While {
         cond: Expression,
-        body: Rc<Statement>,
+        body: Arc<Statement>,
+        /// The loop's label, which `break out of` can name
+        label: Option<Ident>,
+        span: Span,
     },
     /// A for each loop
     ForEach {
@@ -98,12 +416,84 @@ pub enum Statement {
         end: Expression,
         by: Expression,
         range_type: String,
-        body: Rc<Statement>,
+        body: Arc<Statement>,
+        /// The loop's label, which `break out of` can name
+        label: Option<Ident>,
+        span: Span,
+    },
+    /// Leaves the loop it is in, or the loop around it with the label
+    ///
+    /// break
+    /// break out of outer
+    Break(Option<Ident>, Span),
+    /// Goes on to the next time around the loop it is in
+    ///
+    /// continue
+    Continue(Span),
+}
+
+impl Statement {
+    /// Returns where the statement was written
+    pub fn span(&self) -> Span {
+        match *self {
+            Statement::Return(_, span) | Statement::Var(_, span) | Statement::Set(_, _, span) |
+            Statement::Change(_, _, span) | Statement::Do(_, span) | Statement::Forever(_, _, span) |
+            Statement::If { span, .. } | Statement::Call { span, .. } | Statement::While { span, .. } |
+            Statement::ForEach { span, .. } | Statement::Break(_, span) | Statement::Continue(span) => span,
+        }
+    }
+
+    /// Returns where the statement was written, to be changed
+    pub fn span_mut(&mut self) -> &mut Span {
+        match *self {
+            Statement::Return(_, ref mut span) | Statement::Var(_, ref mut span) |
+            Statement::Set(_, _, ref mut span) | Statement::Change(_, _, ref mut span) |
+            Statement::Do(_, ref mut span) | Statement::Forever(_, _, ref mut span) |
+            Statement::If { ref mut span, .. } | Statement::Call { ref mut span, .. } |
+            Statement::While { ref mut span, .. } | Statement::ForEach { ref mut span, .. } |
+            Statement::Break(_, ref mut span) | Statement::Continue(ref mut span) => span,
+        }
+    }
+
+    /// Moves the statements inside this one to `statements`, leaving empty blocks
+    /// in their place, unless they are shared with another tree
+    fn take_children(&mut self, statements: &mut Vec<Statement>) {
+        let mut take = |child: &mut Arc<Statement>| statements.extend(Arc::get_mut(child).map(mem::take));
+        match *self {
+            Statement::Do(ref mut block, _) => block.iter_mut().for_each(take),
+            Statement::If { ref mut if_clause, ref mut else_clause, .. } => {
+                take(if_clause);
+                statements.extend(Arc::get_mut(else_clause).and_then(Option::take));
+            },
+            Statement::Forever(ref mut body, ..) | Statement::While { ref mut body, .. } |
+            Statement::ForEach { ref mut body, .. } => take(body),
+            _ => (),
+        }
+    }
+}
+
+/// An empty block, which is left behind when a statement is taken out of the tree
+impl Default for Statement {
+    fn default() -> Statement {
+        Statement::Do(vec![], Span::default())
+    }
+}
+
+/// Statements are taken apart one at a time rather than recursively, so
+/// dropping a deeply nested one doesn't overflow the stack
+impl Drop for Statement {
+    fn drop(&mut self) {
+        let mut statements = vec![];
+        self.take_children(&mut statements);
+        while let Some(mut statement) = statements.pop() {
+            statement.take_children(&mut statements);
+        }
     }
 }
 
 /// The operators in Haumea
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Operator {
     /// Addition (+)
     Add,
@@ -141,43 +531,765 @@ pub enum Operator {
     BinaryOr,
     /// Binary Not (~)
     BinaryNot,
+    /// Binary Exclusive Or (^)
+    BinaryXor,
+    /// Shift Left (<<)
+    ShiftLeft,
+    /// Shift Right (>>), which keeps the sign
+    ShiftRight,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Every binary operator, which the parser finds by how they are written
+const BINARY_OPERATORS: &[Operator] = &[
+    Operator::LogicalOr, Operator::LogicalAnd,
+    Operator::Equals, Operator::NotEquals, Operator::Lt, Operator::Lte, Operator::Gt, Operator::Gte,
+    Operator::BinaryOr, Operator::BinaryXor, Operator::BinaryAnd,
+    Operator::ShiftLeft, Operator::ShiftRight,
+    Operator::Add, Operator::Sub,
+    Operator::Mul, Operator::Div, Operator::Modulo,
+];
+
+/// Every prefix operator, with `-` parsed as `Negate` so it is told apart from subtraction
+const PREFIX_OPERATORS: &[Operator] = &[Operator::Negate, Operator::LogicalNot, Operator::BinaryNot];
+
+impl Operator {
+    /// Returns how tightly the operator binds, from 1 for `or` to 10 for the prefix operators
+    ///
+    /// The bitwise operators and shifts bind in the same order as in C, but
+    /// all of them bind more tightly than comparisons, so `a & 1 = 0` is `(a & 1) = 0`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::parser::Operator;
+    /// assert!(Operator::Mul.precedence() > Operator::Add.precedence());
+    /// assert!(Operator::BinaryAnd.precedence() > Operator::Equals.precedence());
+    /// assert!(Operator::Add.precedence() > Operator::ShiftLeft.precedence());
+    /// ```
+    pub fn precedence(&self) -> u8 {
+        match *self {
+            Operator::LogicalOr => 1,
+            Operator::LogicalAnd => 2,
+            Operator::Equals | Operator::NotEquals | Operator::Gt |
+            Operator::Lt | Operator::Gte | Operator::Lte => 3,
+            Operator::BinaryOr => 4,
+            Operator::BinaryXor => 5,
+            Operator::BinaryAnd => 6,
+            Operator::ShiftLeft | Operator::ShiftRight => 7,
+            Operator::Add | Operator::Sub => 8,
+            Operator::Mul | Operator::Div | Operator::Modulo => 9,
+            Operator::Negate | Operator::LogicalNot | Operator::BinaryNot => 10,
+        }
+    }
+
+    /// Returns true if the operator compares its operands, giving 1 or 0
+    pub fn is_comparison(&self) -> bool {
+        self.precedence() == Operator::Equals.precedence()
+    }
+
+    /// Returns true if the operator works on Reals as well as Integers, which
+    /// the arithmetic operators other than `modulo` and the comparisons do
+    pub fn works_on_reals(&self) -> bool {
+        matches!(*self, Operator::Add | Operator::Sub | Operator::Negate | Operator::Mul | Operator::Div) ||
+            self.is_comparison()
+    }
+
+    /// Returns how the operator is written in Haumea
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            Operator::Add => "+",
+            Operator::Sub | Operator::Negate => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Modulo => "modulo",
+            Operator::Equals => "=",
+            Operator::NotEquals => "!=",
+            Operator::Gt => ">",
+            Operator::Lt => "<",
+            Operator::Gte => ">=",
+            Operator::Lte => "<=",
+            Operator::LogicalAnd => "and",
+            Operator::LogicalOr => "or",
+            Operator::LogicalNot => "not",
+            Operator::BinaryAnd => "&",
+            Operator::BinaryOr => "|",
+            Operator::BinaryNot => "~",
+            Operator::BinaryXor => "^",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// The value of a decimal literal
+///
+/// Literals are never NaN, so unlike an `f64` it can be compared and hashed
+/// like the rest of the tree, by its bits.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Float) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Float {}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+/// Writes the value as it would be written in Haumea, always with a decimal point
+///
+/// # Examples
+/// ```
+/// # use haumea::parser::Float;
+/// assert_eq!(Float(1.5).to_string(), "1.5");
+/// assert_eq!(Float(2.0).to_string(), "2.0");
+/// ```
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.0.to_string();
+        if digits.contains('.') {
+            write!(f, "{}", digits)
+        } else {
+            write!(f, "{}.0", digits)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Expression {
     /// A binary operation (eg, "1 + 2" or "True or False")
     BinaryOp {
         operator: Operator,
-        left: Rc<Expression>,
-        right: Rc<Expression>,
+        left: Arc<Expression>,
+        right: Arc<Expression>,
+        span: Span,
     },
     /// A unary operation (eg, "not False" or "-(1 + 2)")
     UnaryOp {
         operator: Operator,
-        expression: Rc<Expression>,
+        expression: Arc<Expression>,
+        span: Span,
     },
     /// An integer literal
-    Integer(i32),
+    ///
+    /// Integers are as wide as the `long long` the C backend stores them in
+    Integer(i64, Span),
+    /// An integer literal too large for an i64, which only `--bignum` programs can have
+    ///
+    /// The content is the digits of the number
+    BigInteger(String, Span),
+    /// A decimal literal, like `1.5`
+    Float(Float, Span),
     /// An identifier
-    Ident(Ident),
+    Ident(Ident, Span),
+    /// A string literal, with its escapes replaced
+    Str(String, Span),
+    /// `true` or `false`
+    Boolean(bool, Span),
+    /// A list literal, like `[1, 2, 3]`
+    List(Vec<Arc<Expression>>, Span),
+    /// An item of a list, counting from 0, like `xs[0]`
+    Index {
+        list: Arc<Expression>,
+        index: Arc<Expression>,
+        span: Span,
+    },
+    /// The number of items in a list, like `length of xs`
+    Length(Arc<Expression>, Span),
     /// A function call
     Call {
         function: Ident,
-        arguments: Vec<Rc<Expression>>,
+        arguments: Vec<Arc<Expression>>,
+        span: Span,
     },
 }
 
-pub fn parse(scanner: Scanner) -> Program {
-    let mut tokens = scanner.collect::<Vec<_>>();
-    let mut program = vec![];
+/// The number 0, which is left behind when an expression is taken out of the tree
+impl Default for Expression {
+    fn default() -> Expression {
+        Expression::Integer(0, Span::default())
+    }
+}
+
+impl Expression {
+    /// Returns where the expression was written
+    pub fn span(&self) -> Span {
+        match *self {
+            Expression::Integer(_, span) | Expression::BigInteger(_, span) | Expression::Float(_, span) |
+            Expression::Ident(_, span) | Expression::Str(_, span) | Expression::Boolean(_, span) | Expression::List(_, span) | Expression::Length(_, span) |
+            Expression::Index { span, .. } | Expression::BinaryOp { span, .. } | Expression::UnaryOp { span, .. } |
+            Expression::Call { span, .. } => span,
+        }
+    }
+
+    /// Returns where the expression was written, to be changed
+    pub fn span_mut(&mut self) -> &mut Span {
+        match *self {
+            Expression::Integer(_, ref mut span) | Expression::BigInteger(_, ref mut span) |
+            Expression::Float(_, ref mut span) | Expression::Ident(_, ref mut span) |
+            Expression::Str(_, ref mut span) | Expression::Boolean(_, ref mut span) |
+            Expression::List(_, ref mut span) | Expression::Length(_, ref mut span) |
+            Expression::Index { ref mut span, .. } | Expression::BinaryOp { ref mut span, .. } |
+            Expression::UnaryOp { ref mut span, .. } | Expression::Call { ref mut span, .. } => span,
+        }
+    }
+}
+
+/// A mistake in a program that stops it from being parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Something other than what was expected was found
+    Unexpected {
+        /// What was expected, like "an expression"
+        expected: String,
+        /// What was found instead, like "')'"
+        found: String,
+        state: ScanState,
+    },
+    /// The input ended before something that was expected
+    UnexpectedEnd {
+        expected: String,
+        /// The unfinished construct the input ended in, like "the 'do' block"
+        inside: Option<String>,
+        /// Where the unfinished construct starts, or where the input ends if there isn't one
+        state: ScanState,
+    },
+    /// The scanner found a mistake, like a character that can't be used
+    Scan(ScanError),
+    /// A keyword or word operator was used as a name
+    ReservedWord { word: String, state: ScanState },
+    /// A qualified name like `mathlib.helper` was used where only plain names can be
+    QualifiedName { name: String, state: ScanState },
+    /// Something that isn't a statement was found where a statement was expected
+    NotAStatement { found: String, state: ScanState },
+    /// The code is nested more than MAX_DEPTH deep
+    TooDeep(ScanState),
+    /// There are statements outside of any function, and a function called `main`
+    MainAndStatements {
+        /// Where the first statement outside of any function is
+        statement: ScanState,
+        /// Where `main` is defined
+        main: ScanState,
+    },
+    /// A comparison is compared again, like `1 < x < 3`
+    ChainedComparison {
+        /// The chain as it was written
+        written: String,
+        /// The comparisons joined with `and`, which is what was probably meant
+        meant: String,
+        /// Where the second comparison's operator is
+        state: ScanState,
+    },
+    /// A constant's value can't be worked out
+    InvalidConstant(String),
+    /// A constant is used where it can't be
+    InvalidConstantUse(String),
+    /// A template is used where it can't be
+    InvalidTemplateUse(String),
+}
+
+impl ParseError {
+    /// Returns where in the source the mistake is, if it is at one place
+    pub fn state(&self) -> Option<ScanState> {
+        match *self {
+            ParseError::Unexpected { state, .. } | ParseError::UnexpectedEnd { state, .. } |
+            ParseError::ReservedWord { state, .. } | ParseError::QualifiedName { state, .. } |
+            ParseError::NotAStatement { state, .. } | ParseError::TooDeep(state) |
+            ParseError::ChainedComparison { state, .. } => Some(state),
+            ParseError::Scan(ref error) => Some(error.state()),
+            ParseError::MainAndStatements { statement, .. } => Some(statement),
+            ParseError::InvalidConstant(_) | ParseError::InvalidConstantUse(_) |
+            ParseError::InvalidTemplateUse(_) => None,
+        }
+    }
+
+    /// Returns where in the source the mistake is, to be changed
+    pub fn state_mut(&mut self) -> Option<&mut ScanState> {
+        match *self {
+            ParseError::Unexpected { ref mut state, .. } | ParseError::UnexpectedEnd { ref mut state, .. } |
+            ParseError::ReservedWord { ref mut state, .. } | ParseError::QualifiedName { ref mut state, .. } |
+            ParseError::NotAStatement { ref mut state, .. } | ParseError::TooDeep(ref mut state) |
+            ParseError::ChainedComparison { ref mut state, .. } => Some(state),
+            ParseError::Scan(ref mut error) => Some(error.state_mut()),
+            ParseError::MainAndStatements { ref mut statement, .. } => Some(statement),
+            ParseError::InvalidConstant(_) | ParseError::InvalidConstantUse(_) |
+            ParseError::InvalidTemplateUse(_) => None,
+        }
+    }
+
+    /// Returns what the mistake is, without where it is
+    pub fn message(&self) -> String {
+        match *self {
+            ParseError::Unexpected { ref expected, ref found, .. } => {
+                format!("expected {}, but found {}!", expected, found)
+            },
+            ParseError::UnexpectedEnd { ref expected, inside: Some(ref inside), .. } => {
+                format!("unexpected end of input in {}, expected {}!", inside, expected)
+            },
+            ParseError::UnexpectedEnd { ref expected, inside: None, .. } => {
+                format!("unexpected end of input, expected {}!", expected)
+            },
+            ParseError::ReservedWord { ref word, .. } => {
+                format!("'{}' is a reserved word and cannot be used as a name!", word)
+            },
+            ParseError::QualifiedName { ref name, .. } => {
+                format!("expected a name without a '.', but found the name '{}'!", name)
+            },
+            ParseError::NotAStatement { ref found, .. } => {
+                format!("found {} where a statement was expected!", found)
+            },
+            ParseError::Scan(ref error) => error.message(),
+            ParseError::TooDeep(_) => format!("the code is nested more than {} levels deep!", MAX_DEPTH),
+            ParseError::MainAndStatements { main, .. } => {
+                format!("this statement is outside of any function, so it is part of the function 'main', \
+                        but 'main' is also defined at line {:}:{:}!", main.line, main.column)
+            },
+            ParseError::ChainedComparison { ref written, ref meant, .. } => {
+                format!("comparisons can't be chained, write `{}` instead of `{}`!", meant, written)
+            },
+            ParseError::InvalidConstant(ref error) => format!("Invalid constant: {:}!", error),
+            ParseError::InvalidConstantUse(ref error) => format!("Invalid use of a constant: {:}!", error),
+            ParseError::InvalidTemplateUse(ref error) => format!("Invalid use of a template: {:}!", error),
+        }
+    }
+
+    /// Returns the diagnostic that reports the mistake
+    pub fn diagnostic(&self) -> Diagnostic {
+        let code = match *self {
+            ParseError::Scan(ref error) => return error.diagnostic(),
+            ParseError::Unexpected { .. } => "E0101",
+            ParseError::UnexpectedEnd { .. } => "E0102",
+            ParseError::ReservedWord { .. } => "E0103",
+            ParseError::QualifiedName { .. } => "E0104",
+            ParseError::NotAStatement { .. } => "E0105",
+            ParseError::TooDeep(_) => "E0106",
+            ParseError::MainAndStatements { .. } => "E0107",
+            ParseError::InvalidConstant(_) => "E0108",
+            ParseError::InvalidConstantUse(_) => "E0109",
+            ParseError::InvalidTemplateUse(_) => "E0110",
+            ParseError::ChainedComparison { .. } => "E0111",
+        };
+        Diagnostic::error(code, self.message(), self.state().map_or(Span::default(), diagnostics::point))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let ParseError::Scan(ref error) = *self {
+            return write!(f, "{}", error);
+        }
+        if let Some(s) = self.state() {
+            write!(f, "At line {:}:{:}, ", s.line, s.column)?;
+        }
+        write!(f, "{}", self.message())
+    }
+}
+
+/// Parses the functions of a single source file
+///
+/// Any use declarations are skipped; see `parse_module` and the `loader`
+/// module to resolve them.
+pub fn parse(scanner: Scanner) -> Result<Program, Vec<ParseError>> {
+    parse_module(scanner).map(|module| module.functions)
+}
+
+/// Parses the functions in `source`
+///
+/// This never panics, whatever `source` is: every mistake in it is returned
+/// as an error instead, which makes it safe to call on untrusted input.
+///
+/// # Examples
+/// ```
+/// # use haumea::parser::parse_str;
+/// assert!(parse_str("to main display(1)").is_ok());
+/// assert!(parse_str("to main display(").is_err());
+/// ```
+pub fn parse_str(source: &str) -> Result<Program, Vec<ParseError>> {
+    parse(Scanner::new(source))
+}
+
+/// Parses a single source file into a Module
+///
+/// Every mistake the scanner finds is returned, even in code the parser
+/// skipped after an earlier mistake.
+pub fn parse_module(mut scanner: Scanner) -> Result<Module, Vec<ParseError>> {
+    let parsed = parse_module_tokens(&mut scanner);
+    with_scan_errors(parsed, scanner.errors())
+}
+
+/// Adds the scanner's mistakes, `scan_errors`, to the result of parsing its tokens
+pub(crate) fn with_scan_errors(parsed: Result<Module, Vec<ParseError>>, scan_errors: &[ScanError])
+                    -> Result<Module, Vec<ParseError>> {
+    if scan_errors.is_empty() {
+        return parsed;
+    }
+    let mut errors: Vec<ParseError> = scan_errors.iter().cloned().map(ParseError::Scan).collect();
+    // The parser stops at the first of the scanner's mistakes in a declaration,
+    // which are already in `errors`
+    if let Err(parse_errors) = parsed {
+        errors.extend(parse_errors.into_iter().filter(|error| match error.state() {
+            Some(state) => !scan_errors.iter().any(|scan_error| scan_error.state() == state),
+            None => true,
+        }));
+    }
+    errors.sort_by_key(|error| error.state().map_or((u32::MAX, u32::MAX), |state| (state.line, state.column)));
+    Err(errors)
+}
+
+/// Parses the tokens of a single source file into a Module
+///
+/// The tokens are read one at a time, so they don't need to be collected first.
+///
+/// After a mistake, the parser skips to the next declaration that starts a
+/// line and carries on, so every mistake in the file is returned, in order.
+pub fn parse_module_tokens<'a, I>(tokens: I) -> Result<Module, Vec<ParseError>>
+    where I: IntoIterator<Item=Token>, I::IntoIter: 'a {
+    let mut tokens = TokenStream::new(tokens);
+    let mut module = Module::default();
+    let mut script = Script::default();
+    let mut errors = vec![];
+    while !tokens.is_empty() {
+        let before = tokens.consumed;
+        if let Err(error) = parse_declaration(&mut tokens, &mut module, &mut script) {
+            errors.push(*error);
+            tokens.recover(before);
+        }
+    }
+    finish_module(module, script, errors, tokens.eof.state())
+}
+
+/// Finishes a module once all of its declarations are parsed, given the
+/// mistakes found so far and where its last token is
+///
+/// The statements outside of any function become `main`, and constants and
+/// templates are replaced by their values and code.
+fn finish_module(mut module: Module, script: Script, mut errors: Vec<ParseError>, end: ScanState)
+                 -> Result<Module, Vec<ParseError>> {
+    if let Some(statement) = script.start {
+        if let Some(main) = script.main {
+            errors.push(ParseError::MainAndStatements { statement, main });
+        }
+        let span = Span::new(statement, end);
+        module.functions.push(Function {
+            name: Symbol::from("main"),
+            visibility: Visibility::Private,
+            external: None,
+            signature: None,
+            types: Types::default(),
+            code: Statement::Do(script.statements, span),
+            span,
+        });
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    if !module.constants.is_empty() {
+        let values = constants::evaluate_all(&module.constants)
+            .map_err(|error| vec![ParseError::InvalidConstant(error)])?;
+        for func in &mut module.functions {
+            if let Err(error) = constants::substitute(func, &values) {
+                errors.push(ParseError::InvalidConstantUse(error));
+            }
+        }
+    }
+    if !module.templates.is_empty() {
+        let mut expander = templates::Expander::new(&module.templates);
+        for func in &mut module.functions {
+            if let Err(error) = expander.expand_function(func) {
+                errors.push(ParseError::InvalidTemplateUse(error));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(module)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Returns the number of tokens in each declaration in `tokens`, or the first
+/// mistake in them
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::declaration_sizes;
+/// let tokens = Scanner::new("use lib to main return 0");
+/// assert_eq!(declaration_sizes(tokens), Ok(vec![2, 4]));
+/// ```
+pub fn declaration_sizes<'a, I>(tokens: I) -> Result<Vec<usize>, ParseError>
+    where I: IntoIterator<Item=Token>, I::IntoIter: 'a {
+    let mut tokens = TokenStream::new(tokens);
+    let mut module = Module::default();
+    let mut script = Script::default();
+    let mut sizes = vec![];
     while !tokens.is_empty() {
-        program.push(parse_function(&mut tokens));
+        let before = tokens.consumed;
+        parse_declaration(&mut tokens, &mut module, &mut script).map_err(|error| *error)?;
+        sizes.push(tokens.consumed - before);
+    }
+    Ok(sizes)
+}
+
+/// How deeply statements and expressions can be nested
+///
+/// This stops deeply nested code from overflowing the stack, in the parser
+/// and in every pass over the tree after it.
+pub const MAX_DEPTH: usize = 256;
+
+/// The tokens being parsed, read from the scanner one at a time
+struct TokenStream<'a> {
+    tokens: Peekable<Box<dyn Iterator<Item=Token> + 'a>>,
+    /// The number of tokens read so far
+    consumed: usize,
+    /// The token returned once every token has been read, at the last token's position
+    eof: Token,
+    /// How many statements and expressions are being parsed inside each other
+    depth: usize,
+    /// The constructs that are being parsed, innermost last, with where they start
+    open: Vec<(Construct, ScanState)>,
+    /// The number the next node parsed is given
+    next_node: u32,
+}
+
+/// A part of the program that must be finished before the input ends
+#[derive(Debug, Clone, Copy)]
+enum Construct {
+    /// A declaration, which starts with a keyword like `to` or `use`
+    Declaration(Keyword),
+    /// A statement that starts with a keyword like `if` or `do`
+    Statement(Keyword),
+    /// A call to a function
+    Call(Symbol),
+    /// An expression in parentheses
+    Parentheses,
+    /// A list literal
+    List,
+    /// The index of a list item
+    Index,
+}
+
+/// Describes the construct for error messages, like "the 'do' block"
+impl fmt::Display for Construct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Construct::Declaration(Keyword::To) => write!(f, "the function"),
+            Construct::Declaration(Keyword::Define) => write!(f, "the constant"),
+            Construct::Declaration(Keyword::Template) => write!(f, "the template"),
+            Construct::Declaration(keyword) => write!(f, "the '{}' declaration", keyword),
+            Construct::Statement(Keyword::Do) => write!(f, "the 'do' block"),
+            Construct::Statement(keyword) => write!(f, "the '{}' statement", keyword),
+            Construct::Call(function) => write!(f, "the call to '{}'", function),
+            Construct::Parentheses => write!(f, "the parentheses"),
+            Construct::List => write!(f, "the list"),
+            Construct::Index => write!(f, "the index"),
+        }
+    }
+}
+
+impl<'a> TokenStream<'a> {
+    fn new<I>(tokens: I) -> TokenStream<'a> where I: IntoIterator<Item=Token>, I::IntoIter: 'a {
+        // Comments, which a scanner made `with_comments` reads, aren't part of the code
+        let tokens: Box<dyn Iterator<Item=Token> + 'a> = Box::new(tokens.into_iter()
+            .filter(|token| !matches!(*token, Token::Comment(..))));
+        TokenStream {
+            tokens: tokens.peekable(),
+            consumed: 0,
+            eof: Token::EOF(ScanState::new(1, 1)),
+            depth: 0,
+            open: vec![],
+            next_node: 0,
+        }
+    }
+
+    /// Starts parsing `construct`, which starts at `state`
+    fn open(&mut self, construct: Construct, state: ScanState) {
+        self.open.push((construct, state));
+    }
+
+    /// Finishes parsing the construct started last with `open`
+    fn close(&mut self) {
+        self.open.pop();
+    }
+
+    /// Starts parsing a statement or expression inside the ones being parsed
+    ///
+    /// Fails if they are nested more than MAX_DEPTH deep.
+    fn enter(&mut self) -> Result<(), Box<ParseError>> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(Box::new(ParseError::TooDeep(self.peek().state())));
+        }
+        Ok(())
+    }
+
+    /// Finishes parsing a statement or expression started with `enter`
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Returns true if every token has been read
+    fn is_empty(&mut self) -> bool {
+        self.tokens.peek().is_none()
+    }
+
+    /// Returns the next token without reading it
+    fn peek(&mut self) -> &Token {
+        match self.tokens.peek() {
+            Some(token) => token,
+            None => &self.eof,
+        }
+    }
+
+    /// Returns true if the next token is `keyword`
+    fn at(&mut self, keyword: Keyword) -> bool {
+        match *self.peek() {
+            Token::Keyword(next, _) => next == keyword,
+            _ => false,
+        }
+    }
+
+    /// Skips the rest of a declaration that has a mistake in it
+    ///
+    /// Tokens are skipped up to a keyword that starts a declaration at the
+    /// start of a line, since `to` can also be part of a statement. At least
+    /// one token is skipped if none have been read since `before`.
+    fn recover(&mut self, before: usize) {
+        self.depth = 0;
+        self.open.clear();
+        if self.consumed == before {
+            self.next_token();
+        }
+        while !self.is_empty() {
+            let starts_line = self.peek().state().line > self.eof.state().line;
+            let starts_declaration = match *self.peek() {
+                Token::Keyword(keyword, _) => matches!(keyword, Keyword::To | Keyword::Public | Keyword::Private |
+                                                                Keyword::External | Keyword::Use | Keyword::Link |
+                                                                Keyword::Include | Keyword::Embed |
+                                                                Keyword::Define | Keyword::Template),
+                _ => false,
+            };
+            if starts_line && starts_declaration {
+                break;
+            }
+            self.next_token();
+        }
+    }
+
+    /// Returns the span of a new node, from `start` to the last token read
+    fn span_from(&mut self, start: ScanState) -> Span {
+        self.next_node += 1;
+        Span { node: Some(self.next_node - 1), ..Span::new(start, self.eof.state()) }
+    }
+
+    /// Reads the next token
+    fn next_token(&mut self) -> Token {
+        match self.tokens.next() {
+            Some(token) => {
+                self.consumed += 1;
+                self.eof = Token::EOF(token.state());
+                token
+            },
+            None => self.eof.clone(),
+        }
+    }
+}
+
+/// The statements written outside of any function, which are the code of the function `main`
+///
+/// Small programs can be written as a list of statements, like a script.
+#[derive(Clone, Default)]
+struct Script {
+    statements: Block,
+    /// Where the first statement is
+    start: Option<ScanState>,
+    /// Where a function called `main` is defined
+    main: Option<ScanState>,
+}
+
+// The parsing functions box their errors, which keeps the results they return
+// small, so deeply nested code doesn't overflow the stack in debug builds
+
+/// Parses the next declaration in `tokens`, adding it to `module`, or the
+/// next statement outside of any function, adding it to `script`
+fn parse_declaration(tokens: &mut TokenStream, module: &mut Module, script: &mut Script)
+                     -> Result<(), Box<ParseError>> {
+    let start = tokens.peek().state();
+    let keyword = match *tokens.peek() {
+        Token::Keyword(keyword @ Keyword::Use, _) | Token::Keyword(keyword @ Keyword::Link, _) |
+        Token::Keyword(keyword @ Keyword::Include, _) | Token::Keyword(keyword @ Keyword::Embed, _) |
+        Token::Keyword(keyword @ Keyword::Define, _) | Token::Keyword(keyword @ Keyword::Template, _) => keyword,
+        ref token if starts_statement(token) => {
+            script.start.get_or_insert(start);
+            script.statements.push(Arc::new(parse_statement(tokens)?));
+            return Ok(());
+        },
+        _ => Keyword::To,
+    };
+    tokens.open(Construct::Declaration(keyword), start);
+    parse_declaration_kind(tokens, module)?;
+    tokens.close();
+    if keyword == Keyword::To && script.main.is_none() && module.functions.last().is_some_and(|func| func.name == "main") {
+        script.main = Some(start);
+    }
+    Ok(())
+}
+
+/// Returns true if `token` can only be the start of a statement
+fn starts_statement(token: &Token) -> bool {
+    match *token {
+        Token::Ident(..) => true,
+        Token::Keyword(keyword, _) => matches!(keyword, Keyword::Return | Keyword::Do | Keyword::If | Keyword::Set |
+                                               Keyword::Change | Keyword::Variable | Keyword::Forever |
+                                               Keyword::While | Keyword::For | Keyword::Break |
+                                               Keyword::Continue),
+        _ => false,
     }
-    program
 }
 
-fn match_token(mut token_stream: &mut Vec<Token>, expected: &Token) -> Result<Token, Token> {
-    let t = token_stream.remove(0);
+fn parse_declaration_kind(tokens: &mut TokenStream, module: &mut Module) -> Result<(), Box<ParseError>> {
+    if tokens.at(Keyword::Use) {
+        module.uses.push(parse_use(tokens)?);
+    } else if tokens.at(Keyword::Link) ||
+              tokens.at(Keyword::Include) ||
+              tokens.at(Keyword::Embed) {
+        module.directives.push(parse_directive(tokens)?);
+    } else if tokens.at(Keyword::Define) {
+        module.constants.push(parse_constant(tokens)?);
+    } else if tokens.at(Keyword::Template) {
+        module.templates.push(parse_template(tokens)?);
+    } else {
+        module.functions.push(parse_function(tokens)?);
+    }
+    Ok(())
+}
+
+/// Returns `name` as a Symbol, for anything but a call
+///
+/// Only calls can use qualified names like `mathlib.helper`, since the
+/// loader replaces them with the name of the function they refer to.
+fn local_name(name: String, state: ScanState) -> Result<Symbol, Box<ParseError>> {
+    if name.contains('.') {
+        return Err(Box::new(ParseError::QualifiedName { name, state }));
+    }
+    Ok(Symbol::from(name))
+}
+
+fn match_token(token_stream: &mut TokenStream, expected: &Token) -> Result<Token, Token> {
+    let t = token_stream.next_token();
     if t == *expected {
         Ok(t)
     } else {
@@ -185,421 +1297,655 @@ fn match_token(mut token_stream: &mut Vec<Token>, expected: &Token) -> Result<To
     }
 }
 
-fn match_panic(mut token_stream: &mut Vec<Token>, expected: Token) {
-    match match_token(&mut token_stream, &expected) {
-        Ok(_) => (),
-        Err(t) => panic!(format!("Expected {:?}, but found {:?}!", expected, t)),
+fn match_panic(token_stream: &mut TokenStream, expected: Token) -> Result<(), Box<ParseError>> {
+    match match_token(token_stream, &expected) {
+        Ok(_) => Ok(()),
+        Err(t) => Err(unexpected(token_stream, &expected.to_string(), &t)),
     }
 }
 
-fn parse_function(mut token_stream: &mut Vec<Token>) -> Function {
-    match_panic(&mut token_stream, Token::Keyword("to".to_string(), ScanState::empty()));
-    let name = match token_stream.remove(0) {
-        Token::Ident(s, _) => s,
-        t => {
-            let s = t.clone().state();
-            panic!("At line {:}:{:}, expected an identifier, but found {:?}!", 
-            s.line, s.column, t)
+/// Returns the error for finding `found` when `expected` was expected
+///
+/// If the input ended, the error is reported where the innermost unfinished construct started.
+fn unexpected(token_stream: &TokenStream, expected: &str, found: &Token) -> Box<ParseError> {
+    if let Some(error) = ScanError::from_token(found) {
+        return Box::new(ParseError::Scan(error));
+    }
+    let expected = expected.to_string();
+    Box::new(match *found {
+        Token::EOF(s) => match token_stream.open.last() {
+            Some(&(construct, state)) => {
+                ParseError::UnexpectedEnd { expected, inside: Some(construct.to_string()), state }
+            },
+            None => ParseError::UnexpectedEnd { expected, inside: None, state: s },
         },
+        _ => ParseError::Unexpected { expected, found: found.to_string(), state: found.state() },
+    })
+}
+
+fn match_keyword(token_stream: &mut TokenStream, keyword: Keyword) -> Result<(), Box<ParseError>> {
+    match_panic(token_stream, Token::Keyword(keyword, ScanState::empty()))
+}
+
+/// Returns the error for finding `found` when a name was expected
+///
+/// Keywords and operators spelt with letters, like `and`, get their own
+/// error, since they look like names.
+fn unexpected_name(token_stream: &TokenStream, found: &Token) -> Box<ParseError> {
+    match *found {
+        Token::Keyword(keyword, state) => {
+            Box::new(ParseError::ReservedWord { word: keyword.as_str().to_string(), state })
+        },
+        Token::Operator(ref op, state) if op.chars().all(|c| c.is_ascii_alphabetic()) => {
+            Box::new(ParseError::ReservedWord { word: op.clone(), state })
+        },
+        _ => unexpected(token_stream, "an identifier", found),
+    }
+}
+
+fn parse_use(token_stream: &mut TokenStream) -> Result<Use, Box<ParseError>> {
+    match_keyword(token_stream, Keyword::Use)?;
+    let path = match token_stream.next_token() {
+        Token::StringLit(path, _) | Token::Ident(path, _) => path,
+        t => return Err(unexpected(token_stream, "a file name", &t)),
+    };
+    let alias = if !token_stream.is_empty() &&
+                   token_stream.at(Keyword::As) {
+        token_stream.next_token();
+        match token_stream.next_token() {
+            Token::Ident(alias, state) => Some(local_name(alias, state)?),
+            t => return Err(unexpected_name(token_stream, &t)),
+        }
+    } else {
+        None
+    };
+    Ok(Use { path, alias })
+}
+
+fn parse_constant(token_stream: &mut TokenStream) -> Result<Constant, Box<ParseError>> {
+    match_keyword(token_stream, Keyword::Define)?;
+    let name = match token_stream.next_token() {
+        Token::Ident(name, state) => local_name(name, state)?,
+        t => return Err(unexpected_name(token_stream, &t)),
     };
-    let signature = parse_signature(&mut token_stream);
-    let code = parse_statement(&mut token_stream);
-    Function {
-               name: name,
-               signature: signature,
-               code: code,
-             }
+    match_keyword(token_stream, Keyword::As)?;
+    let value = parse_expression(token_stream)?;
+    Ok(Constant { name, value })
+}
+
+fn parse_template(token_stream: &mut TokenStream) -> Result<Template, Box<ParseError>> {
+    match_keyword(token_stream, Keyword::Template)?;
+    let name = match token_stream.next_token() {
+        Token::Ident(name, state) => local_name(name, state)?,
+        t => return Err(unexpected_name(token_stream, &t)),
+    };
+    let signature = parse_signature(token_stream, None)?;
+    let body = parse_statement(token_stream)?;
+    Ok(Template { name, signature, body })
+}
+
+fn parse_directive(token_stream: &mut TokenStream) -> Result<Directive, Box<ParseError>> {
+    let keyword = token_stream.next_token();
+    if keyword == Token::Keyword(Keyword::Embed, ScanState::empty()) {
+        return parse_embed(token_stream);
+    }
+    let is_link = keyword == Token::Keyword(Keyword::Link, ScanState::empty());
+    if is_link {
+        match_keyword(token_stream, Keyword::With)?;
+    } else {
+        match_keyword(token_stream, Keyword::Header)?;
+    }
+    let name = match token_stream.next_token() {
+        Token::StringLit(name, _) => name,
+        t => return Err(unexpected(token_stream, if is_link { "a library name" } else { "a header name" }, &t)),
+    };
+    if is_link {
+        Ok(Directive::Link(name))
+    } else {
+        Ok(Directive::Include(name))
+    }
 }
 
-fn parse_signature(mut token_stream: &mut Vec<Token>) -> Option<Signature> {
-    if token_stream[0] == Token::Keyword("with".to_string(), ScanState::empty()) {
+fn parse_embed(token_stream: &mut TokenStream) -> Result<Directive, Box<ParseError>> {
+    let path = match token_stream.next_token() {
+        Token::StringLit(path, _) => path,
+        t => return Err(unexpected(token_stream, "a file name", &t)),
+    };
+    match_keyword(token_stream, Keyword::As)?;
+    let name = match token_stream.next_token() {
+        Token::Ident(name, state) => local_name(name, state)?,
+        t => return Err(unexpected_name(token_stream, &t)),
+    };
+    Ok(Directive::Embed { path, name, bytes: vec![] })
+}
+
+fn parse_function(token_stream: &mut TokenStream) -> Result<Function, Box<ParseError>> {
+    let start = token_stream.peek().state();
+    let visibility = if token_stream.at(Keyword::Public) {
+        token_stream.next_token();
+        Visibility::Public
+    } else {
+        if token_stream.at(Keyword::Private) {
+            token_stream.next_token();
+        }
+        Visibility::Private
+    };
+    let is_external = token_stream.at(Keyword::External);
+    if is_external {
+        token_stream.next_token();
+    }
+    match_keyword(token_stream, Keyword::To)?;
+    let name = match token_stream.next_token() {
+        Token::Ident(s, state) => local_name(s, state)?,
+        t => return Err(unexpected_name(token_stream, &t)),
+    };
+    let mut types = Types::default();
+    let signature = parse_signature(token_stream, Some(&mut types.parameters))?;
+    if token_stream.at(Keyword::Returns) {
+        token_stream.next_token();
+        types.returns = Some(parse_type(token_stream)?);
+    }
+    let (external, code) = if is_external {
+        (Some(parse_external(token_stream)?), Statement::Do(vec![], Span::default()))
+    } else {
+        (None, parse_statement(token_stream)?)
+    };
+    Ok(Function {
+               name,
+               visibility,
+               external,
+               signature,
+               types,
+               code,
+               span: token_stream.span_from(start),
+             })
+}
+
+fn parse_external(token_stream: &mut TokenStream) -> Result<External, Box<ParseError>> {
+    let header = if !token_stream.is_empty() &&
+                    token_stream.at(Keyword::From) {
+        token_stream.next_token();
+        match token_stream.next_token() {
+            Token::StringLit(header, _) => Some(header),
+            t => return Err(unexpected(token_stream, "a header name", &t)),
+        }
+    } else {
+        None
+    };
+    Ok(External { header })
+}
+
+/// Parses the parameters after `with`, if there are any
+///
+/// The type of each parameter is pushed to `types`, or types aren't allowed if it is None.
+fn parse_signature(token_stream: &mut TokenStream, mut types: Option<&mut Vec<Option<Type>>>)
+                   -> Result<Option<Signature>, Box<ParseError>> {
+    if !token_stream.is_empty() && token_stream.at(Keyword::With) {
         let mut args = vec![];
-        match_panic(&mut token_stream, Token::Keyword("with".to_string(), ScanState::empty()));
-        match_panic(&mut token_stream, Token::Lp(ScanState::empty()));
+        match_keyword(token_stream, Keyword::With)?;
+        match_panic(token_stream, Token::Lp(ScanState::empty()))?;
         loop {
-            args.push(match token_stream.remove(0) {
-                Token::Ident(name, _) => name,
+            args.push(match token_stream.next_token() {
+                Token::Ident(name, state) => local_name(name, state)?,
                 Token::Rp(_) => break,
-                t => {
-                    let s = t.clone().state();
-                    panic!("At line {:}:{:}, expected an identifier, but found {:?}!", 
-                    s.line, s.column, t)
-                },
+                t => return Err(unexpected_name(token_stream, &t)),
             });
-            if token_stream[0] == Token::Rp(ScanState::empty()) {
-                token_stream.remove(0);
+            if let Some(ref mut types) = types {
+                let declared = if *token_stream.peek() == Token::Colon(ScanState::empty()) {
+                    token_stream.next_token();
+                    Some(parse_type(token_stream)?)
+                } else {
+                    None
+                };
+                types.push(declared);
+            }
+            if *token_stream.peek() == Token::Rp(ScanState::empty()) {
+                token_stream.next_token();
                 break;
             }
-            match_panic(&mut token_stream, Token::Comma(ScanState::empty()));
+            match_panic(token_stream, Token::Comma(ScanState::empty()))?;
+        }
+        // A function without types has no parameter types, like one built without a signature
+        if let Some(types) = types {
+            if types.iter().all(Option::is_none) {
+                types.clear();
+            }
         }
-        Some(args)
+        Ok(Some(args))
     } else {
-        None
+        Ok(None)
     }
 }
 
-fn parse_statement(mut token_stream: &mut Vec<Token>) -> Statement {
-    match token_stream.remove(0) {
-        Token::Keyword(t, _) => {
-            if t == "return" {
-                parse_return(&mut token_stream)
-            } else if t == "do" {
-                parse_do(&mut token_stream)
-            } else if t == "if" {
-                parse_if(&mut token_stream)
-            } else if t == "set" {
-                parse_set(&mut token_stream)
-            } else if t == "change" {
-                parse_change(&mut token_stream)
-            } else if t == "variable" {
-                parse_declare(&mut token_stream)
-            } else if t == "forever" {
-                parse_forever(&mut token_stream)
-            } else if t == "while" {
-                parse_while(&mut token_stream)
-            } else if t == "for" {
-                parse_for_each(&mut token_stream)
-            } else {
-                panic!("Invalid statement!")
-            }
+/// Parses the name of a type, like `Integer`
+fn parse_type(token_stream: &mut TokenStream) -> Result<Type, Box<ParseError>> {
+    let token = token_stream.next_token();
+    if let Token::Ident(ref name, _) = token {
+        if let Some(declared) = Type::from_name(name) {
+            return Ok(declared);
         }
-        t @ Token::Ident(..) => {
-            token_stream.insert(0, t);
-            parse_call(&mut token_stream)
+    }
+    Err(unexpected(token_stream, "a type, like 'Integer', 'Text' or 'List'", &token))
+}
+
+fn parse_statement(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    token_stream.enter()?;
+    let start = token_stream.peek().state();
+    // Each kind of statement is parsed without its span, which is filled in here
+    let mut statement = parse_statement_kind(token_stream)?;
+    *statement.span_mut() = token_stream.span_from(start);
+    token_stream.leave();
+    Ok(statement)
+}
+
+fn parse_statement_kind(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    if let Token::Ident(..) = *token_stream.peek() {
+        return parse_call(token_stream);
+    }
+    match token_stream.next_token() {
+        Token::Keyword(keyword, s) => {
+            token_stream.open(Construct::Statement(keyword), s);
+            let statement = match keyword {
+                Keyword::Return => parse_return(token_stream),
+                Keyword::Do => parse_do(token_stream),
+                Keyword::If => parse_if(token_stream),
+                Keyword::Set => parse_set(token_stream),
+                Keyword::Change => parse_change(token_stream),
+                Keyword::Variable => parse_declare(token_stream),
+                Keyword::Forever => parse_forever(token_stream),
+                Keyword::While => parse_while(token_stream),
+                Keyword::For => parse_for_each(token_stream),
+                Keyword::Break => parse_break(token_stream),
+                Keyword::Continue => Ok(Statement::Continue(Span::default())),
+                _ => {
+                    let found = Token::Keyword(keyword, s).to_string();
+                    return Err(Box::new(ParseError::NotAStatement { found, state: s }));
+                },
+            };
+            token_stream.close();
+            statement
         },
-        t => {
-            let s = t.clone().state();
-            panic!("Syntax error at line {:}:{:}, found {:?}", 
-            s.line, s.column, t)
+        t @ Token::EOF(_) | t @ Token::UnterminatedComment(_) | t @ Token::BadEscape(..) |
+        t @ Token::Error(..) | t @ Token::TooLarge(..) => {
+            Err(unexpected(token_stream, "a statement", &t))
         },
+        t => Err(Box::new(ParseError::NotAStatement { found: t.to_string(), state: t.state() })),
     }
 }
 
-fn parse_forever(mut token_stream: &mut Vec<Token>) -> Statement {
-    Statement::Forever(Rc::new(parse_statement(&mut token_stream)))
+fn parse_forever(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    let label = parse_label(token_stream)?;
+    Ok(Statement::Forever(Arc::new(parse_statement(token_stream)?), label, Span::default()))
+}
+
+fn parse_while(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    let cond = parse_expression(token_stream)?;
+    let label = parse_label(token_stream)?;
+    Ok(Statement::While{
+        cond,
+        body: Arc::new(parse_statement(token_stream)?),
+        label,
+        span: Span::default(),
+    })
+}
+
+/// Parses the label a loop is given with `as`, before its body, if it has one
+fn parse_label(token_stream: &mut TokenStream) -> Result<Option<Ident>, Box<ParseError>> {
+    if !token_stream.at(Keyword::As) {
+        return Ok(None);
+    }
+    token_stream.next_token();
+    match token_stream.next_token() {
+        Token::Ident(name, state) => Ok(Some(local_name(name, state)?)),
+        t => Err(unexpected_name(token_stream, &t)),
+    }
 }
 
-fn parse_while(mut token_stream: &mut Vec<Token>) -> Statement {
-    Statement::While{
-        cond: parse_expression(&mut token_stream),
-        body: Rc::new(parse_statement(&mut token_stream))
+/// Parses `break`, and the label of the loop it leaves if it is `break out of` one
+fn parse_break(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    if !token_stream.at(Keyword::Out) {
+        return Ok(Statement::Break(None, Span::default()));
+    }
+    token_stream.next_token();
+    match_keyword(token_stream, Keyword::Of)?;
+    match token_stream.next_token() {
+        Token::Ident(name, state) => Ok(Statement::Break(Some(local_name(name, state)?), Span::default())),
+        t => Err(unexpected_name(token_stream, &t)),
     }
 }
 
-fn parse_for_each(mut token_stream: &mut Vec<Token>) -> Statement {
-    match_panic(&mut token_stream, Token::Keyword("each".to_string(), ScanState::empty()));
-    let ident = match token_stream.remove(0) {
-        Token::Ident(name, _) => name,
-        t => {
-            let s = t.clone().state();
-            panic!("At line {:}:{:}, expected an identifier, but found {:?}!", 
-            s.line, s.column, t)
-        },
+fn parse_for_each(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    match_keyword(token_stream, Keyword::Each)?;
+    let ident = match token_stream.next_token() {
+        Token::Ident(name, state) => local_name(name, state)?,
+        t => return Err(unexpected_name(token_stream, &t)),
     };
-    match_panic(&mut token_stream, Token::Keyword("in".to_string(), ScanState::empty()));
-    let start = parse_expression(&mut token_stream);
+    match_keyword(token_stream, Keyword::In)?;
+    let start = parse_expression(token_stream)?;
     
-    let range_token = token_stream.remove(0);
-    let end = parse_expression(&mut token_stream);
+    let range_token = token_stream.next_token();
     let range_type;
-    
-    if range_token == Token::Keyword("to".to_string(), ScanState::empty()) {
+    if range_token == Token::Keyword(Keyword::To, ScanState::empty()) {
         range_type = "to";
-    } else if range_token == Token::Keyword("through".to_string(), ScanState::empty()) {
+    } else if range_token == Token::Keyword(Keyword::Through, ScanState::empty()) {
         range_type = "through";
     } else {
-        let s = range_token.clone().state();
-        panic!("At line {:}:{:}, expected 'to' or 'through', not {:?}", s.line, s.column, range_token);
+        return Err(unexpected(token_stream, "'to' or 'through'", &range_token));
     }
+    let end = parse_expression(token_stream)?;
     
-    let by = match token_stream[0] {
-        Token::Keyword(ref kw, _) => kw == &"by",
-        _ => false,
-    };
-    let by = if by {
-        token_stream.remove(0);
-        parse_expression(&mut token_stream)
+    let by = if token_stream.at(Keyword::By) {
+        token_stream.next_token();
+        parse_expression(token_stream)?
     } else {
-        Expression::Integer(1)
+        Expression::Integer(1, Span::default())
     };
-    Statement::ForEach {
-        ident: ident,
-        start: start,
-        end: end,
-        by: by,
+    let label = parse_label(token_stream)?;
+    Ok(Statement::ForEach {
+        ident,
+        start,
+        end,
+        by,
         range_type: range_type.to_string(),
-        body: Rc::new(parse_statement(&mut token_stream))
-    }
+        body: Arc::new(parse_statement(token_stream)?),
+        label,
+        span: Span::default(),
+    })
 }
 
-fn parse_return(mut token_stream: &mut Vec<Token>) -> Statement {
-    Statement::Return(parse_expression(&mut token_stream))
+fn parse_return(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    Ok(Statement::Return(parse_expression(token_stream)?, Span::default()))
 }
 
-fn parse_declare(mut token_stream: &mut Vec<Token>) -> Statement {
-    let ident = match token_stream.remove(0) {
-        Token::Ident(ident, _) => ident,
-        t => {
-            let s = t.clone().state();
-            panic!("At line {:}:{:}, expected an identifier, but found {:?}!", 
-            s.line, s.column, t)
-        },
+fn parse_declare(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    let ident = match token_stream.next_token() {
+        Token::Ident(ident, state) => local_name(ident, state)?,
+        t => return Err(unexpected_name(token_stream, &t)),
     };
-    Statement::Var(ident)
+    Ok(Statement::Var(ident, Span::default()))
 }
-fn parse_do(mut token_stream: &mut Vec<Token>) -> Statement {
+fn parse_do(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
     let mut block = vec![];
-    while token_stream[0] != Token::Keyword("end".to_string(), ScanState::empty()) {
-        block.push(Rc::new(parse_statement(&mut token_stream)));
+    while !token_stream.at(Keyword::End) {
+        if token_stream.is_empty() {
+            let t = token_stream.next_token();
+            return Err(unexpected(token_stream, "a statement or the keyword 'end'", &t));
+        }
+        block.push(Arc::new(parse_statement(token_stream)?));
     }
-    token_stream.remove(0);
-    Statement::Do(block)
+    token_stream.next_token();
+    Ok(Statement::Do(block, Span::default()))
 }
 
-fn parse_if(mut token_stream: &mut Vec<Token>) -> Statement {
-    let cond = parse_expression(&mut token_stream);
-    match_panic(&mut token_stream, Token::Keyword("then".to_string(), ScanState::empty()));
-    let if_clause = Rc::new(parse_statement(&mut token_stream));
-    let else_clause = Rc::new(if !token_stream.is_empty() &&
-                                 token_stream[0] == Token::Keyword("else".to_string(), ScanState::empty()) {
-        match_panic(&mut token_stream, Token::Keyword("else".to_string(), ScanState::empty()));
-        Some(parse_statement(&mut token_stream))
+fn parse_if(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    let cond = parse_expression(token_stream)?;
+    match_keyword(token_stream, Keyword::Then)?;
+    let if_clause = Arc::new(parse_statement(token_stream)?);
+    let else_clause = Arc::new(if !token_stream.is_empty() &&
+                                 token_stream.at(Keyword::Else) {
+        match_keyword(token_stream, Keyword::Else)?;
+        Some(parse_statement(token_stream)?)
     } else {
         None
     });
-    Statement::If {
-        cond: cond,
-        if_clause: if_clause,
-        else_clause: else_clause,
-    }
+    Ok(Statement::If {
+        cond,
+        if_clause,
+        else_clause,
+        span: Span::default(),
+    })
 }
 
-fn parse_set(mut token_stream: &mut Vec<Token>) -> Statement {
-    let ident = match token_stream.remove(0) {
-        Token::Ident(ident, _) => ident,
-        t => {
-            let s = t.clone().state();
-            panic!("At line {:}:{:}, expected an identifier, but found {:?}!", 
-            s.line, s.column, t)
-        },
+fn parse_set(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    let ident = match token_stream.next_token() {
+        Token::Ident(ident, state) => local_name(ident, state)?,
+        t => return Err(unexpected_name(token_stream, &t)),
     };
-    match_panic(&mut token_stream, Token::Keyword("to".to_string(), ScanState::empty()));
-    let expr = parse_expression(&mut token_stream);
-    Statement::Set(ident, expr)
-}
-
-fn parse_change(mut token_stream: &mut Vec<Token>) -> Statement {
-    let ident = match token_stream.remove(0) {
-        Token::Ident(ident, _) => ident,
-        t => {
-            let s = t.clone().state();
-            panic!("At line {:}:{:}, expected an identifier, but found {:?}!", 
-            s.line, s.column, t)
-        },
+    match_keyword(token_stream, Keyword::To)?;
+    let expr = parse_expression(token_stream)?;
+    Ok(Statement::Set(ident, expr, Span::default()))
+}
+
+fn parse_change(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    let ident = match token_stream.next_token() {
+        Token::Ident(ident, state) => local_name(ident, state)?,
+        t => return Err(unexpected_name(token_stream, &t)),
     };
-    match_panic(&mut token_stream, Token::Keyword("by".to_string(), ScanState::empty()));
-    let expr = parse_expression(&mut token_stream);
-    Statement::Change(ident, expr)
-}
-
-fn parse_call(mut token_stream: &mut Vec<Token>) -> Statement {
-    let ident = match token_stream.remove(0) {
-        Token::Ident(ident, _) => ident,
-        t => {
-            let s = t.clone().state();
-            panic!("At line {:}:{:}, expected an identifier, but found {:?}!", 
-            s.line, s.column, t)
-        },
+    match_keyword(token_stream, Keyword::By)?;
+    let expr = parse_expression(token_stream)?;
+    Ok(Statement::Change(ident, expr, Span::default()))
+}
+
+fn parse_call(token_stream: &mut TokenStream) -> Result<Statement, Box<ParseError>> {
+    let (ident, state) = match token_stream.next_token() {
+        Token::Ident(ident, state) => (Symbol::from(ident), state),
+        t => return Err(unexpected_name(token_stream, &t)),
     };
-    match_panic(&mut token_stream, Token::Lp(ScanState::empty()));
+    token_stream.open(Construct::Call(ident), state);
+    match_panic(token_stream, Token::Lp(ScanState::empty()))?;
     let mut args = vec![];
-    if token_stream[0] != Token::Rp(ScanState::empty()) {
+    if *token_stream.peek() == Token::Rp(ScanState::empty()) {
+        token_stream.next_token();
+    } else {
         loop {
-            args.push(parse_expression(&mut token_stream));
-            if token_stream[0] == Token::Rp(ScanState::empty()) {
-                token_stream.remove(0);
+            args.push(parse_expression(token_stream)?);
+            if *token_stream.peek() == Token::Rp(ScanState::empty()) {
+                token_stream.next_token();
                 break;
             }
-            match_panic(&mut token_stream, Token::Comma(ScanState::empty()));
+            match_panic(token_stream, Token::Comma(ScanState::empty()))?;
         }
     }
-    Statement::Call{
+    token_stream.close();
+    Ok(Statement::Call{
         function: ident,
         arguments: args,
-    }
+        span: Span::default(),
+    })
 }
 
-fn parse_expression(mut token_stream: &mut Vec<Token>) -> Expression {
-    prec_4(&mut token_stream)
+fn parse_expression(token_stream: &mut TokenStream) -> Result<Expression, Box<ParseError>> {
+    token_stream.enter()?;
+    let exp = parse_binary(token_stream, 1)?;
+    token_stream.leave();
+    Ok(exp)
 }
 
-fn prec_0(mut token_stream: &mut Vec<Token>) -> Expression {
-    if token_stream[0] == Token::Lp(ScanState::empty()) {
-        token_stream.remove(0);
-        let exp = parse_expression(&mut token_stream);
-        match_panic(&mut token_stream, Token::Rp(ScanState::empty()));
-        exp
-    } else {
-        match token_stream.remove(0) {
-            Token::Number(n, _) => Expression::Integer(n),
-            Token::Operator(op, s) => {
-                if op == "-" {
-                    Expression::UnaryOp {
-                        operator: Operator::Sub,
-                        expression: Rc::new(parse_expression(&mut token_stream))
-                    }
-                } else {
-                    panic!("At line {:}:{:}, expected \"-\", but found {:?}!", 
-                           s.line, s.column, op)
+/// Parses an expression whose operators bind at least as tightly as `min_precedence`
+///
+/// Each operator's right hand side is parsed with a higher `min_precedence`, so
+/// every binary operator groups to the left and `1 - 2 - 3` is `(1 - 2) - 3`.
+fn parse_binary(token_stream: &mut TokenStream, min_precedence: u8) -> Result<Expression, Box<ParseError>> {
+    let mut lh = parse_unary(token_stream)?;
+    let mut depth = 0;
+    while let Some(operator) = binary_operator(token_stream.peek()).filter(|op| op.precedence() >= min_precedence) {
+        let state = token_stream.next_token().state();
+        // A long chain of operators is as deeply nested as the tree it makes
+        token_stream.enter()?;
+        depth += 1;
+        let rh = parse_binary(token_stream, operator.precedence() + 1)?;
+        // `1 < x < 3` would compare the 1 or 0 that `1 < x` gives with 3, but a
+        // comparison in parentheses was parsed by `parse_unary` and can be compared
+        if operator.is_comparison() && depth > 1 {
+            if let Expression::BinaryOp { operator: first, ref right, .. } = lh {
+                if first.is_comparison() {
+                    let written = format!("{} {} {}", ast::expression_to_source(&lh), operator,
+                                          ast::expression_to_source(&rh));
+                    let meant = format!("{} and {} {} {}", ast::expression_to_source(&lh),
+                                        ast::expression_to_source(right), operator, ast::expression_to_source(&rh));
+                    return Err(Box::new(ParseError::ChainedComparison { written, meant, state }));
                 }
             }
-            Token::Ident(id, _) => {
-                if !token_stream.is_empty() && token_stream[0] == Token::Lp(ScanState::empty()) {
-                    match_panic(&mut token_stream, Token::Lp(ScanState::empty()));
+        }
+        let span = token_stream.span_from(lh.span().start);
+        lh = Expression::BinaryOp {
+            operator,
+            left: Arc::new(lh),
+            right: Arc::new(rh),
+            span,
+        };
+    }
+    for _ in 0..depth {
+        token_stream.leave();
+    }
+    Ok(lh)
+}
+
+/// Returns the binary operator `token` is, if it is one
+fn binary_operator(token: &Token) -> Option<Operator> {
+    match *token {
+        Token::Operator(ref name, _) => BINARY_OPERATORS.iter().find(|op| op.symbol() == name).copied(),
+        _ => None,
+    }
+}
+
+/// Parses `-`, `not` or `~` and the operand after it, or just an operand
+///
+/// Prefix operators bind tighter than any binary operator, so `-a + b` is `(-a) + b`.
+fn parse_unary(token_stream: &mut TokenStream) -> Result<Expression, Box<ParseError>> {
+    let operator = match *token_stream.peek() {
+        Token::Operator(ref name, _) => PREFIX_OPERATORS.iter().find(|op| op.symbol() == name).copied(),
+        _ => None,
+    };
+    let operator = match operator {
+        Some(operator) => operator,
+        None => return parse_postfix(token_stream),
+    };
+    let start = token_stream.next_token().state();
+    // The smallest i64 is only a literal once it is negated, since its digits are too large on their own
+    if let (Operator::Negate, Token::TooLarge(digits, _)) = (operator, token_stream.peek()) {
+        if let Some(n) = scanner::negated(digits) {
+            token_stream.next_token();
+            return Ok(Expression::Integer(n, token_stream.span_from(start)));
+        }
+    }
+    token_stream.enter()?;
+    let expression = parse_unary(token_stream)?;
+    token_stream.leave();
+    // A negative decimal is a literal of its own, since only Integers can be negated
+    if let (Operator::Negate, Expression::Float(n, _)) = (operator, &expression) {
+        return Ok(Expression::Float(Float(-n.0), token_stream.span_from(start)));
+    }
+    let expression = Arc::new(expression);
+    Ok(Expression::UnaryOp {
+        operator,
+        expression,
+        span: token_stream.span_from(start),
+    })
+}
+
+/// Parses an operand and any indexes after it
+fn parse_postfix(token_stream: &mut TokenStream) -> Result<Expression, Box<ParseError>> {
+    let start = token_stream.peek().state();
+    let exp = parse_operand(token_stream)?;
+    if *token_stream.peek() == Token::Lb(ScanState::empty()) {
+        parse_indexes(token_stream, exp, start)
+    } else {
+        Ok(exp)
+    }
+}
+
+/// Parses a literal, name, call, or expression in parentheses
+fn parse_operand(token_stream: &mut TokenStream) -> Result<Expression, Box<ParseError>> {
+    if *token_stream.peek() == Token::Lp(ScanState::empty()) {
+        let start = token_stream.next_token().state();
+        token_stream.open(Construct::Parentheses, start);
+        let mut exp = parse_expression(token_stream)?;
+        match_panic(token_stream, Token::Rp(ScanState::empty()))?;
+        token_stream.close();
+        *exp.span_mut() = token_stream.span_from(start);
+        Ok(exp)
+    } else {
+        match token_stream.next_token() {
+            Token::Number(n, state) => Ok(Expression::Integer(n, token_stream.span_from(state))),
+            Token::BigNumber(digits, state) => Ok(Expression::BigInteger(digits, token_stream.span_from(state))),
+            Token::Float(n, state) => Ok(Expression::Float(Float(n), token_stream.span_from(state))),
+            Token::StringLit(text, state) => Ok(Expression::Str(text, token_stream.span_from(state))),
+            Token::Keyword(Keyword::True, state) => Ok(Expression::Boolean(true, token_stream.span_from(state))),
+            Token::Keyword(Keyword::False, state) => Ok(Expression::Boolean(false, token_stream.span_from(state))),
+            Token::Keyword(Keyword::Length, state) => parse_length(token_stream, state),
+            Token::Lb(state) => parse_list(token_stream, state),
+            Token::TooLarge(digits, state) => Err(Box::new(ParseError::Scan(ScanError::TooLarge(digits, state)))),
+            Token::Operator(op, state) => Err(Box::new(ParseError::Unexpected {
+                expected: "an expression".to_string(),
+                found: format!("the operator '{}'", op),
+                state,
+            })),
+            Token::Ident(id, state) => {
+                if !token_stream.is_empty() && *token_stream.peek() == Token::Lp(ScanState::empty()) {
+                    let function = Symbol::from(id);
+                    token_stream.open(Construct::Call(function), state);
+                    match_panic(token_stream, Token::Lp(ScanState::empty()))?;
                     let mut args = vec![];
-                    if token_stream[0] != Token::Rp(ScanState::empty()) {
+                    if *token_stream.peek() != Token::Rp(ScanState::empty()) {
                         loop {
-                            args.push(Rc::new(parse_expression(&mut token_stream)));
-                            if token_stream[0] == Token::Rp(ScanState::empty()) {
-                                token_stream.remove(0);
+                            args.push(Arc::new(parse_expression(token_stream)?));
+                            if *token_stream.peek() == Token::Rp(ScanState::empty()) {
+                                token_stream.next_token();
                                 break;
                             }
-                            match_panic(&mut token_stream, Token::Comma(ScanState::empty()));
+                            match_panic(token_stream, Token::Comma(ScanState::empty()))?;
                         }
                     } else {
-                        token_stream.remove(0);
+                        token_stream.next_token();
                     }
-                    Expression::Call{
-                        function: id,
+                    token_stream.close();
+                    Ok(Expression::Call{
+                        function,
                         arguments: args,
-                    }
+                        span: token_stream.span_from(state),
+                    })
                 } else {
-                    Expression::Ident(id)
+                    Ok(Expression::Ident(local_name(id, state)?, token_stream.span_from(state)))
                 }
             },
-            t => {
-                let s = t.clone().state();
-                panic!("At line {:}:{:}, expected an expression, but found {:?}!", 
-                s.line, s.column, t)
-            },
+            t => Err(unexpected(token_stream, "an expression", &t)),
         }
     }
 }
 
-fn prec_1(mut token_stream: &mut Vec<Token>) -> Expression {
-    let lh = prec_0(&mut token_stream);
-    if !token_stream.is_empty() {
-        let op = match token_stream.get(0) {
-            Some(&Token::Operator(ref name, _)) => {
-                if *name == "*" {
-                    Operator::Mul
-                } else if *name == "/" {
-                    Operator::Div
-                } else if *name == "modulo" {
-                    Operator::Modulo
-                } else {
-                    return lh
-                }
-            },
-            _ => return lh,
+/// Parses any `[index]`s after `list`, which started at `start`
+///
+/// Indexes bind tighter than any operator, so `xs[0] + 1` adds to the item.
+fn parse_indexes(token_stream: &mut TokenStream, list: Expression, start: ScanState) -> Result<Expression, Box<ParseError>> {
+    let mut exp = list;
+    while *token_stream.peek() == Token::Lb(ScanState::empty()) {
+        let state = token_stream.next_token().state();
+        token_stream.open(Construct::Index, state);
+        let index = parse_expression(token_stream)?;
+        match_panic(token_stream, Token::Rb(ScanState::empty()))?;
+        token_stream.close();
+        exp = Expression::Index {
+            list: Arc::new(exp),
+            index: Arc::new(index),
+            span: token_stream.span_from(start),
         };
-        token_stream.remove(0);
-        let rh = prec_1(&mut token_stream);
-        Expression::BinaryOp {
-            operator: op,
-            left: Rc::new(lh),
-            right: Rc::new(rh),
-        }
-    } else {
-        lh
     }
+    Ok(exp)
 }
 
-fn prec_2(mut token_stream: &mut Vec<Token>) -> Expression {
-    let lh = prec_1(&mut token_stream);
-    if !token_stream.is_empty() {
-        let op = match token_stream.get(0) {
-            Some(&Token::Operator(ref name, _)) => {
-                if *name == "+" {
-                    Operator::Add
-                } else if *name == "-" {
-                    Operator::Sub
-                } else {
-                    return lh
-                }
-            },
-            _ => return lh,
-        };
-        token_stream.remove(0);
-        let rh = prec_2(&mut token_stream);
-        Expression::BinaryOp {
-            operator: op,
-            left: Rc::new(lh),
-            right: Rc::new(rh),
-        }
-    } else {
-        lh
-    }
-}
-
-fn prec_3(mut token_stream: &mut Vec<Token>) -> Expression {
-    let lh = prec_2(&mut token_stream);
-    if !token_stream.is_empty() {
-        let op = match token_stream.get(0) {
-            Some(&Token::Operator(ref name, _)) => {
-                if *name == ">" {
-                    Operator::Gt
-                } else if *name == ">=" {
-                    Operator::Gte
-                } else if *name == "<" {
-                    Operator::Lt
-                } else if *name == "<=" {
-                    Operator::Lte
-                } else if *name == "=" {
-                    Operator::Equals
-                } else if *name == "!=" {
-                    Operator::NotEquals
-                } else {
-                    return lh
-                }
-            },
-            _ => return lh
-        };
-        token_stream.remove(0);
-        let rh = prec_3(&mut token_stream);
-        Expression::BinaryOp {
-            operator: op,
-            left: Rc::new(lh),
-            right: Rc::new(rh),
-        }
-    } else {
-        lh
-    }
+/// Parses `length of list`, after the keyword `length` at `start`
+fn parse_length(token_stream: &mut TokenStream, start: ScanState) -> Result<Expression, Box<ParseError>> {
+    match_keyword(token_stream, Keyword::Of)?;
+    let list = Arc::new(parse_postfix(token_stream)?);
+    Ok(Expression::Length(list, token_stream.span_from(start)))
 }
 
-fn prec_4(mut token_stream: &mut Vec<Token>) -> Expression {
-    let lh = prec_3(&mut token_stream);
-    if !token_stream.is_empty() {
-        let op = match token_stream.get(0) {
-            Some(&Token::Operator(ref name, _)) => {
-                if *name == "and" {
-                    Operator::LogicalAnd
-                } else if *name == "or" {
-                    Operator::LogicalOr
-                } else {
-                    return lh
-                }
-            },
-            _ => return lh
-        };
-        token_stream.remove(0);
-        let rh = prec_4(&mut token_stream);
-        Expression::BinaryOp {
-            operator: op,
-            left: Rc::new(lh),
-            right: Rc::new(rh),
+/// Parses a list literal, after the `[` at `start`
+fn parse_list(token_stream: &mut TokenStream, start: ScanState) -> Result<Expression, Box<ParseError>> {
+    token_stream.open(Construct::List, start);
+    let mut items = vec![];
+    if *token_stream.peek() != Token::Rb(ScanState::empty()) {
+        loop {
+            items.push(Arc::new(parse_expression(token_stream)?));
+            if *token_stream.peek() == Token::Rb(ScanState::empty()) {
+                break;
+            }
+            match_panic(token_stream, Token::Comma(ScanState::empty()))?;
         }
-    } else {
-        lh
     }
+    token_stream.next_token();
+    token_stream.close();
+    Ok(Expression::List(items, token_stream.span_from(start)))
 }
+
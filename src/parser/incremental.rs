@@ -0,0 +1,289 @@
+//! src/parser/incremental.rs
+//! Parses a file again as it is edited, without parsing what the edit didn't change.
+//!
+//! A `ParseSession` keeps the tokens of each declaration in a file, and what
+//! they were parsed into. After an edit, only the declarations it touches are
+//! scanned and parsed again, and the ones after it are moved to where they now
+//! are. Typing in one function of a large file only parses that function
+//! again, which keeps an editor that checks the file as it is typed quick.
+//!
+//! ```
+//! # use haumea::cst::TextEdit;
+//! # use haumea::parser::incremental::ParseSession;
+//! let mut session = ParseSession::new("to one return 1\nto two return 2\nto three return 3\n");
+//! let edit = TextEdit { range: 30..31, text: "20".to_string() };
+//! assert_eq!(session.edit(&edit), 1..2);
+//! assert_eq!(session.source(), "to one return 1\nto two return 20\nto three return 3\n");
+//! assert_eq!(session.module().unwrap().functions.len(), 3);
+//! ```
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+use ast::{self, Folder};
+use cst::TextEdit;
+use scanner::{Scanner, ScanError, ScanState, Token};
+use super::{Constant, Expression, Function, Module, ParseError, Script, Span, Statement, Template, TokenStream};
+use super::{finish_module, parse_declaration, with_scan_errors};
+
+/// A declaration in the file, or a statement outside of any function
+#[derive(Clone)]
+struct Declaration {
+    /// The declaration's tokens, with where they are in the source, in bytes
+    tokens: Vec<(Token, Range<usize>)>,
+    /// What the declaration adds to the module
+    module: Module,
+    /// What the declaration adds to `main`, if it is a statement outside of any function
+    script: Script,
+    /// The mistake in the declaration, if it has one
+    error: Option<ParseError>,
+}
+
+impl Declaration {
+    /// Returns where the declaration starts in the source, in bytes
+    fn start(&self) -> usize {
+        self.tokens[0].1.start
+    }
+}
+
+/// A file that is parsed again each time it is edited
+pub struct ParseSession {
+    source: String,
+    declarations: Vec<Declaration>,
+    /// The number the next node parsed is given, so the nodes parsed after
+    /// an edit don't have the ids of the nodes that weren't parsed again
+    next_node: u32,
+}
+
+impl ParseSession {
+    /// Parses `source`, ready for it to be edited
+    pub fn new(source: &str) -> ParseSession {
+        let mut scanner = Scanner::new(source);
+        let mut tokens = vec![];
+        loop {
+            match scanner.next_token_with_range() {
+                (Token::EOF(_), _) => break,
+                token => tokens.push(token),
+            }
+        }
+        let mut next_node = 0;
+        let (declarations, _) = parse_declarations(tokens.iter(), &[], &mut next_node);
+        ParseSession { source: source.to_string(), declarations, next_node }
+    }
+
+    /// Returns the source as it is after the edits so far
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Makes `edit` to the source, and parses the declarations it changed again
+    ///
+    /// Returns the positions of the declarations that were parsed again, among
+    /// the file's declarations and statements outside of functions.
+    pub fn edit(&mut self, edit: &TextEdit) -> Range<usize> {
+        let source = edit.apply(&self.source);
+        // Where a declaration ends can depend on the token after it, so the one
+        // before the edit is parsed again if the edit changes its first token
+        let mut first = self.declarations.iter().rposition(|declaration| declaration.tokens[0].1.end < edit.range.start);
+        // A scanner started partway through would skip a `#!` line or a byte order mark there
+        if let Some(index) = first {
+            let rest = &source[self.declarations[index].start()..];
+            if rest.starts_with("#!") || rest.starts_with('\u{feff}') {
+                first = None;
+            }
+        }
+        let (start, to_source) = match first {
+            Some(index) => {
+                let (ref token, ref range) = self.declarations[index].tokens[0];
+                (range.start, Move { from: ScanState::new(1, 1), to: token.state(), bytes: range.start as isize })
+            },
+            None => (0, Move { from: ScanState::new(1, 1), to: ScanState::new(1, 1), bytes: 0 }),
+        };
+        let first = first.unwrap_or(0);
+
+        // Scans from the first declaration that changed up to the first one after
+        // the edit that starts where it did before, at the same token
+        let new_end = edit.range.start + edit.text.len();
+        let mut scanner = Scanner::new(&source[start..]);
+        let mut region = vec![];
+        let (kept, moved) = loop {
+            let (mut token, range) = scanner.next_token_with_range();
+            if let Token::EOF(_) = token {
+                break (self.declarations.len(), None);
+            }
+            to_source.state(token.state_mut());
+            let range = to_source.range(range);
+            if range.start >= new_end {
+                let old_start = range.start - edit.text.len() + edit.range.len();
+                if let Ok(index) = self.declarations.binary_search_by_key(&old_start, Declaration::start) {
+                    let old = &self.declarations[index].tokens[0].0;
+                    if *old == token {
+                        let bytes = edit.text.len() as isize - edit.range.len() as isize;
+                        break (index, Some(Move { from: old.state(), to: token.state(), bytes }));
+                    }
+                }
+            }
+            region.push((token, range));
+        };
+
+        let mut later = self.declarations.split_off(kept);
+        if let Some(moved) = moved {
+            for declaration in &mut later {
+                moved.declaration(declaration);
+            }
+        }
+        self.declarations.truncate(first);
+        // The declarations after the region are parsed again as well if the
+        // last one in it carries on into them
+        let mut boundaries = vec![region.len()];
+        for declaration in &later {
+            let last = boundaries[boundaries.len() - 1];
+            boundaries.push(last + declaration.tokens.len());
+        }
+        let tokens = region.iter().chain(later.iter().flat_map(|declaration| declaration.tokens.iter()));
+        let (parsed, stopped) = parse_declarations(tokens, &boundaries, &mut self.next_node);
+        let changed = first..first + parsed.len();
+        self.declarations.extend(parsed);
+        self.declarations.extend(later.drain(stopped.unwrap_or(later.len())..));
+        self.source = source;
+        changed
+    }
+
+    /// Returns the module the source parses into, or every mistake in it
+    ///
+    /// This is the same as `parse_module` returns for the source.
+    pub fn module(&self) -> Result<Module, Vec<ParseError>> {
+        let mut module = Module::default();
+        let mut script = Script::default();
+        let mut errors = vec![];
+        let mut scan_errors = vec![];
+        for declaration in &self.declarations {
+            let part = &declaration.module;
+            module.uses.extend(part.uses.iter().cloned());
+            module.directives.extend(part.directives.iter().cloned());
+            module.constants.extend(part.constants.iter().cloned());
+            module.templates.extend(part.templates.iter().cloned());
+            module.functions.extend(part.functions.iter().cloned());
+            script.statements.extend(declaration.script.statements.iter().cloned());
+            script.start = script.start.or(declaration.script.start);
+            script.main = script.main.or(declaration.script.main);
+            errors.extend(declaration.error.iter().cloned());
+            scan_errors.extend(declaration.tokens.iter().filter_map(|(token, _)| ScanError::from_token(token)));
+        }
+        let end = self.declarations.last()
+            .and_then(|declaration| declaration.tokens.last())
+            .map_or(ScanState::new(1, 1), |(token, _)| token.state());
+        with_scan_errors(finish_module(module, script, errors, end), &scan_errors)
+    }
+}
+
+/// Parses `tokens` into declarations
+///
+/// `boundaries` are the numbers of tokens after which the rest of the tokens
+/// don't need to be parsed. Parsing stops when a declaration ends at one of
+/// them, and its index in `boundaries` is returned with the declarations.
+/// The nodes parsed are numbered from `next_node`, which is moved past them.
+fn parse_declarations<'a, I>(tokens: I, boundaries: &[usize], next_node: &mut u32) -> (Vec<Declaration>, Option<usize>)
+    where I: Iterator<Item = &'a (Token, Range<usize>)> + Clone + 'a {
+    let mut stream = TokenStream::new(tokens.clone().map(|(token, _)| token.clone()));
+    stream.next_node = *next_node;
+    let mut tokens = tokens;
+    let mut declarations = vec![];
+    loop {
+        *next_node = stream.next_node;
+        if let Some(stopped) = boundaries.iter().position(|&boundary| boundary == stream.consumed) {
+            return (declarations, Some(stopped));
+        }
+        if stream.is_empty() {
+            return (declarations, None);
+        }
+        let before = stream.consumed;
+        let mut module = Module::default();
+        let mut script = Script::default();
+        let error = parse_declaration(&mut stream, &mut module, &mut script).err().map(|error| *error);
+        if error.is_some() {
+            stream.recover(before);
+        }
+        let tokens = tokens.by_ref().take(stream.consumed - before).cloned().collect();
+        declarations.push(Declaration { tokens, module, script, error });
+    }
+}
+
+/// How positions in the source move, from one place to another
+///
+/// Positions on the same line as `from` move by as many columns as it does,
+/// and every position moves by as many lines.
+#[derive(Debug, Clone, Copy)]
+struct Move {
+    from: ScanState,
+    to: ScanState,
+    /// How many bytes later in the source positions are, or earlier if it is negative
+    bytes: isize,
+}
+
+impl Move {
+    fn state(&self, state: &mut ScanState) {
+        // Nodes that weren't parsed have no position
+        if *state == ScanState::empty() {
+            return;
+        }
+        if state.line == self.from.line {
+            state.column = (i64::from(state.column) + i64::from(self.to.column) - i64::from(self.from.column)) as u32;
+        }
+        state.line = (i64::from(state.line) + i64::from(self.to.line) - i64::from(self.from.line)) as u32;
+    }
+
+    fn span(&self, span: &mut Span) {
+        self.state(&mut span.start);
+        self.state(&mut span.end);
+    }
+
+    fn range(&self, range: Range<usize>) -> Range<usize> {
+        (range.start as isize + self.bytes) as usize..(range.end as isize + self.bytes) as usize
+    }
+
+    /// Moves everything in `declaration`
+    fn declaration(mut self, declaration: &mut Declaration) {
+        for &mut (ref mut token, ref mut range) in &mut declaration.tokens {
+            self.state(token.state_mut());
+            *range = self.range(range.clone());
+        }
+        let module = &mut declaration.module;
+        module.constants = mem::take(&mut module.constants).into_iter()
+            .map(|constant| Constant { value: self.fold_expression(constant.value), ..constant })
+            .collect();
+        module.templates = mem::take(&mut module.templates).into_iter()
+            .map(|template| Template { body: self.fold_statement(template.body), ..template })
+            .collect();
+        module.functions = ast::fold_program(&mut self, mem::take(&mut module.functions));
+        let script = &mut declaration.script;
+        script.statements = mem::take(&mut script.statements).into_iter()
+            .map(|statement| Arc::new(self.fold_statement((*statement).clone())))
+            .collect();
+        for state in script.start.iter_mut().chain(script.main.iter_mut()) {
+            self.state(state);
+        }
+        if let Some(state) = declaration.error.as_mut().and_then(ParseError::state_mut) {
+            self.state(state);
+        }
+    }
+}
+
+impl Folder for Move {
+    fn fold_function(&mut self, func: Function) -> Function {
+        let mut func = ast::rebuild_function(self, func);
+        self.span(&mut func.span);
+        func
+    }
+
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        let mut statement = ast::rebuild_statement(self, statement);
+        self.span(statement.span_mut());
+        statement
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        let mut expression = ast::rebuild_expression(self, expression);
+        self.span(expression.span_mut());
+        expression
+    }
+}
@@ -0,0 +1,72 @@
+//! src/prelude.rs
+//! The Haumea prelude.
+//!
+//! The prelude is a set of helper functions written in Haumea itself
+//! (see `prelude.hau`) that is parsed ahead of the user's program.
+//!
+//! Duplicate definitions are resolved in favour of the user's program:
+//! if the program defines a function with the same name as a prelude
+//! function, the prelude version is dropped.
+use scanner::Scanner;
+use parser::{self, Expression, Function, Program, Statement};
+use ast::{self, Folder};
+
+/// The Haumea source code of the prelude
+pub const SOURCE: &str = include_str!("prelude.hau");
+
+/// Parses the prelude into a Program
+///
+/// Its nodes have no ids, since they would be the same as the ids of the
+/// nodes in the file the prelude is included in.
+pub fn parse() -> Program {
+    ast::fold_program(&mut Unnumbered, parser::parse(Scanner::new(SOURCE)).expect("The prelude is valid"))
+}
+
+/// Removes the ids of the nodes it folds
+struct Unnumbered;
+
+impl Folder for Unnumbered {
+    fn fold_function(&mut self, func: Function) -> Function {
+        let mut func = ast::rebuild_function(self, func);
+        func.span.node = None;
+        func
+    }
+
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        let mut statement = ast::rebuild_statement(self, statement);
+        statement.span_mut().node = None;
+        statement
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        let mut expression = ast::rebuild_expression(self, expression);
+        expression.span_mut().node = None;
+        expression
+    }
+}
+
+/// Returns `program` with the prelude functions placed ahead of it
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// let program = parse(Scanner::new("to main do display(maximum(1, 2)) end")).unwrap();
+/// let program = haumea::prelude::include(program);
+/// assert!(program.iter().any(|func| func.name == "maximum"));
+/// assert_eq!(program.last().unwrap().name, "main");
+/// ```
+pub fn include(program: Program) -> Program {
+    merge(parse(), program)
+}
+
+/// Merges two Programs, placing `base` before `overrides`
+///
+/// Functions in `base` that are redefined in `overrides` are dropped.
+pub fn merge(base: Program, overrides: Program) -> Program {
+    let mut merged: Program = base.into_iter()
+        .filter(|func| !overrides.iter().any(|o| o.name == func.name))
+        .collect();
+    merged.extend(overrides);
+    merged
+}
@@ -0,0 +1,322 @@
+//! src/query.rs
+//! A database of memoized compiler queries, for tools that compile the same
+//! files again and again as they are edited.
+//!
+//! Each stage of compiling a file is a query: its source is scanned into
+//! tokens, the tokens are parsed into a module, the module becomes a program
+//! with the prelude, and the program is checked and compiled to C. The
+//! database remembers the result of every query, and when a file's source is
+//! changed with `set_source`, only the queries that depend on it run again.
+//! If a query's new result is equal to its old one, the queries after it are
+//! not run again either. For example, tokens don't remember where they were
+//! read, so adding spaces or comments to a file scans it again, but doesn't
+//! parse or compile it again.
+//!
+//! Mistakes in a file are results like any other, so a file that doesn't
+//! parse or compile gives the diagnostics that report why.
+//!
+//! ```
+//! # use haumea::query::Database;
+//! let mut db = Database::new();
+//! db.set_source("main", "to main return 1");
+//! let first = db.c_code("main");
+//! db.set_source("main", "to main do  return 1  end");
+//! db.set_source("main", "to main /* one */ return 1");
+//! assert_eq!(db.c_code("main"), first);
+//! assert_eq!(db.runs("module"), 1);
+//! db.set_source("main", "to main return x");
+//! assert_eq!(db.diagnostics("main")[0].code, "E0201");
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use scanner::{Scanner, ScanError, Token};
+use parser::{self, Directive, Module, Program};
+use codegen::{self, CodeGen};
+use codegen::c::CodeGenerator;
+use diagnostics::Diagnostic;
+use check;
+use semantic;
+use typecheck;
+use prelude;
+
+/// A number that goes up every time an input changes
+type Revision = u64;
+
+/// The remembered result of a query
+struct Memo<T> {
+    value: Rc<T>,
+    /// The last revision at which the value was known to be up to date
+    verified_at: Revision,
+    /// The revision at which the value last changed
+    changed_at: Revision,
+}
+
+impl<T> Memo<T> {
+    fn input(value: T, revision: Revision) -> Memo<T> {
+        Memo { value: Rc::new(value), verified_at: revision, changed_at: revision }
+    }
+}
+
+/// A whole program, ready to be checked and compiled
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Loaded {
+    /// The program's functions
+    pub program: Program,
+    /// The directives of every file in the program
+    pub directives: Vec<Directive>,
+}
+
+/// Remembers the inputs of the compiler and the results of its queries
+pub struct Database {
+    revision: Revision,
+    sources: HashMap<String, Memo<String>>,
+    /// The names of the programs that were set with `set_program`, which are inputs in `programs`
+    loaded: HashSet<String>,
+    options: Memo<codegen::Options>,
+    tokens: HashMap<String, Memo<Vec<Token>>>,
+    modules: HashMap<String, Memo<Result<Module, Vec<Diagnostic>>>>,
+    programs: HashMap<String, Memo<Result<Loaded, Vec<Diagnostic>>>>,
+    diagnostics: HashMap<String, Memo<Vec<Diagnostic>>>,
+    c_code: HashMap<String, Memo<Result<String, Vec<Diagnostic>>>>,
+    /// How many times each query has run
+    runs: HashMap<&'static str, usize>,
+}
+
+impl Default for Database {
+    fn default() -> Database {
+        Database::new()
+    }
+}
+
+impl Database {
+    /// Constructs a new, empty Database
+    pub fn new() -> Database {
+        Database {
+            revision: 0,
+            sources: HashMap::new(),
+            loaded: HashSet::new(),
+            options: Memo::input(codegen::Options::default(), 0),
+            tokens: HashMap::new(),
+            modules: HashMap::new(),
+            programs: HashMap::new(),
+            diagnostics: HashMap::new(),
+            c_code: HashMap::new(),
+            runs: HashMap::new(),
+        }
+    }
+
+    /// Sets the source code of the file called `file`
+    pub fn set_source(&mut self, file: &str, source: &str) {
+        if self.sources.get(file).is_some_and(|memo| *memo.value == source) {
+            return;
+        }
+        self.revision += 1;
+        self.loaded.remove(file);
+        self.sources.insert(file.to_string(), Memo::input(source.to_string(), self.revision));
+    }
+
+    /// Sets the program called `file` to one that has already been loaded, like
+    /// one the `Loader` read from several files
+    ///
+    /// The program is checked and compiled as it is, so it should already
+    /// include the prelude if it uses it.
+    pub fn set_program(&mut self, file: &str, program: Program, directives: Vec<Directive>) {
+        // Programs that are equal can still be in different places in their files,
+        // which the diagnostics show, so a program set again is always a change
+        self.revision += 1;
+        self.sources.remove(file);
+        self.loaded.insert(file.to_string());
+        self.programs.insert(file.to_string(), Memo::input(Ok(Loaded { program, directives }), self.revision));
+    }
+
+    /// Sets the options files are compiled with
+    pub fn set_options(&mut self, options: codegen::Options) {
+        if *self.options.value != options {
+            self.revision += 1;
+            self.options = Memo::input(options, self.revision);
+        }
+    }
+
+    /// Returns how many times the query called `query` has run, for any file
+    pub fn runs(&self, query: &str) -> usize {
+        self.runs.get(query).cloned().unwrap_or(0)
+    }
+
+    /// Returns the source code of `file`
+    ///
+    /// Panics if the source of `file` has not been set.
+    pub fn source(&self, file: &str) -> Rc<String> {
+        self.source_memo(file).value.clone()
+    }
+
+    fn source_memo(&self, file: &str) -> &Memo<String> {
+        self.sources.get(file).unwrap_or_else(|| panic!("The source of `{}` has not been set!", file))
+    }
+
+    /// Returns the revision at which the source or program of `file` was last set
+    fn input_changed_at(&self, file: &str) -> Revision {
+        if self.loaded.contains(file) {
+            self.programs[file].changed_at
+        } else {
+            self.source_memo(file).changed_at
+        }
+    }
+
+    /// Returns the tokens in `file`, which are read as big numbers if the options ask for them
+    pub fn tokens(&mut self, file: &str) -> Rc<Vec<Token>> {
+        let changed_at = self.source_memo(file).changed_at.max(self.options.changed_at);
+        let revision = self.revision;
+        if let Some(value) = fresh(self.tokens.get_mut(file), changed_at, revision) {
+            return value;
+        }
+        let source = self.source(file);
+        let big_numbers = self.options.value.bignum;
+        let tokens = self.run("tokens", || {
+            let scanner = Scanner::new(&source);
+            if big_numbers { scanner.with_big_numbers().collect() } else { scanner.collect() }
+        });
+        store(&mut self.tokens, file, tokens, revision)
+    }
+
+    /// Returns the module parsed from `file`, or the diagnostics that report
+    /// every mistake in its tokens
+    ///
+    /// A file is only parsed again when its tokens change, so the positions in
+    /// the module can be from an earlier source; `diagnostics` has the current ones.
+    pub fn module(&mut self, file: &str) -> Rc<Result<Module, Vec<Diagnostic>>> {
+        self.tokens(file);
+        let changed_at = self.tokens[file].changed_at;
+        let revision = self.revision;
+        if let Some(value) = fresh(self.modules.get_mut(file), changed_at, revision) {
+            return value;
+        }
+        let tokens = self.tokens[file].value.clone();
+        let module = self.run("module", || parse(&tokens));
+        store(&mut self.modules, file, module, revision)
+    }
+
+    /// Returns the program called `file`, which is the program it was set to,
+    /// or its module with the prelude
+    ///
+    /// Only the file's own functions are in the program; the files it uses are not loaded.
+    pub fn program(&mut self, file: &str) -> Rc<Result<Loaded, Vec<Diagnostic>>> {
+        if self.loaded.contains(file) {
+            return self.programs[file].value.clone();
+        }
+        self.module(file);
+        let changed_at = self.modules[file].changed_at;
+        let revision = self.revision;
+        if let Some(value) = fresh(self.programs.get_mut(file), changed_at, revision) {
+            return value;
+        }
+        let module = self.modules[file].value.clone();
+        let program = self.run("program", || match *module {
+            Ok(ref module) => Ok(with_prelude(module)),
+            Err(ref diagnostics) => Err(diagnostics.clone()),
+        });
+        store(&mut self.programs, file, program, revision)
+    }
+
+    /// Returns every mistake in `file`: the ones in its syntax, or if it has
+    /// none, the ones found by checking its program, its names and its types
+    ///
+    /// The diagnostics say where each mistake is, which can change when the
+    /// program doesn't, so they are found again from the current tokens of a
+    /// file whenever its source changes.
+    pub fn diagnostics(&mut self, file: &str) -> Rc<Vec<Diagnostic>> {
+        // How the tokens are read depends on the options
+        let changed_at = self.input_changed_at(file).max(self.options.changed_at);
+        let revision = self.revision;
+        if let Some(value) = fresh(self.diagnostics.get_mut(file), changed_at, revision) {
+            return value;
+        }
+        let diagnostics = if self.loaded.contains(file) {
+            let loaded = self.programs[file].value.clone();
+            self.run("diagnostics", || match *loaded {
+                Ok(ref loaded) => check_all(loaded),
+                Err(ref diagnostics) => diagnostics.clone(),
+            })
+        } else {
+            let tokens = self.tokens(file);
+            self.run("diagnostics", || match parse(&tokens) {
+                Ok(module) => check_all(&with_prelude(&module)),
+                Err(diagnostics) => diagnostics,
+            })
+        };
+        store(&mut self.diagnostics, file, diagnostics, revision)
+    }
+
+    /// Returns the C code compiled from the program called `file`, or the
+    /// diagnostics that report why it couldn't be compiled
+    ///
+    /// The program is compiled even if `diagnostics` finds mistakes in it, so
+    /// tools should only use the C code of a program without errors.
+    pub fn c_code(&mut self, file: &str) -> Rc<Result<String, Vec<Diagnostic>>> {
+        self.program(file);
+        let changed_at = self.programs[file].changed_at.max(self.options.changed_at);
+        let revision = self.revision;
+        if let Some(value) = fresh(self.c_code.get_mut(file), changed_at, revision) {
+            return value;
+        }
+        let loaded = self.program(file);
+        let options = (*self.options.value).clone();
+        let code = self.run("c_code", || match *loaded {
+            Ok(Loaded { ref program, ref directives }) => {
+                CodeGenerator::with_directives(options, directives.clone()).compile(program)
+                    .map_err(|error| error.diagnostics())
+            },
+            Err(ref diagnostics) => Err(diagnostics.clone()),
+        });
+        store(&mut self.c_code, file, code, revision)
+    }
+
+    /// Runs the query called `query`, counting the run
+    fn run<T, F: FnOnce() -> T>(&mut self, query: &'static str, compute: F) -> T {
+        *self.runs.entry(query).or_insert(0) += 1;
+        compute()
+    }
+}
+
+/// Parses `tokens` into a module, or the diagnostics that report every mistake in them
+fn parse(tokens: &[Token]) -> Result<Module, Vec<Diagnostic>> {
+    let scan_errors: Vec<ScanError> = tokens.iter().filter_map(ScanError::from_token).collect();
+    parser::with_scan_errors(parser::parse_module_tokens(tokens.iter().cloned()), &scan_errors)
+        .map_err(|errors| errors.iter().map(parser::ParseError::diagnostic).collect())
+}
+
+/// Returns the program of `module`, with the prelude
+fn with_prelude(module: &Module) -> Loaded {
+    Loaded { program: prelude::include(module.functions.clone()), directives: module.directives.clone() }
+}
+
+/// Checks the program, its names and its types, returning every mistake found
+fn check_all(loaded: &Loaded) -> Vec<Diagnostic> {
+    let Loaded { ref program, ref directives } = *loaded;
+    let mut diagnostics: Vec<Diagnostic> = check::check(program).iter().map(check::Problem::diagnostic).collect();
+    diagnostics.extend(semantic::analyze(program, directives).iter().map(semantic::SemanticError::diagnostic));
+    diagnostics.extend(typecheck::typecheck(program).iter().map(check::Problem::diagnostic));
+    diagnostics
+}
+
+/// Returns the remembered value of a query, if its inputs haven't changed since it was verified
+fn fresh<T>(memo: Option<&mut Memo<T>>, inputs_changed_at: Revision, revision: Revision) -> Option<Rc<T>> {
+    match memo {
+        Some(memo) if inputs_changed_at <= memo.verified_at => {
+            memo.verified_at = revision;
+            Some(memo.value.clone())
+        },
+        _ => None,
+    }
+}
+
+/// Remembers a new value of a query, keeping the old revision it changed at if it is equal to the old value
+fn store<T: PartialEq>(memos: &mut HashMap<String, Memo<T>>, file: &str, value: T, revision: Revision) -> Rc<T> {
+    let changed_at = match memos.get(file) {
+        Some(old) if *old.value == value => old.changed_at,
+        _ => revision,
+    };
+    let memo = Memo { value: Rc::new(value), verified_at: revision, changed_at };
+    let value = memo.value.clone();
+    memos.insert(file.to_string(), memo);
+    value
+}
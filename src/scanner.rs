@@ -3,6 +3,14 @@
 
 use std::str::Chars; // We need to bring the Chars struct into scope
 use std::iter::Peekable;
+use std::ops::Range;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use locale::{Locale, Word};
+use diagnostics::{self, Diagnostic};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 /// The scanner struct
 #[derive(Debug)]
 pub struct Scanner<'a> {
@@ -14,25 +22,35 @@ pub struct Scanner<'a> {
     pub source_str: &'a str,
     /// An iterator of chars over the source str
     source_chars: Peekable<Chars<'a>>,
-    /// A vector of chars that can be in operators
-    operator_chars: Vec<char>,
-    /// A vector of allowed operators
-    operators: Vec<&'static str>,
-    /// A vector of chars that can be in identifiers
-    ident_chars: Vec<char>,
-    // A vector of keywords in haumea
-    reserved_words: Vec<&'static str>,
     /// The look ahead char
     pub peek: Option<char>,
     /// The column the scanner is on in the source
     pub column: u32,
     /// The line the scanner is on in the source
     pub line: u32,
+    /// The number of bytes of the source read into self.peek so far
+    consumed: usize,
+    /// The number of bytes at the start of the source that aren't code:
+    /// a byte order mark and a `#!` line, if the source has them
+    skipped: usize,
+    /// Where a comment that the input ended inside of started, if there was one
+    unterminated_comment: Option<ScanState>,
+    /// The other spellings of keywords and word operators, if there are any
+    locale: Option<&'a Locale>,
+    /// The mistakes found so far, in the order they were read
+    errors: Vec<ScanError>,
+    /// Whether comments are read as tokens instead of being skipped
+    comments: bool,
+    /// Whether whole numbers of any size can be written, as `--bignum` programs can
+    big_numbers: bool,
+    /// Whether the last token read was the operator `-`, which a number after it may be negated by
+    after_minus: bool,
 }
 
 /// A structure containing the state of the scanner when it found a token
 #[derive(Debug)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ScanState {
     /// The line the scanner was on
     pub line: u32,
@@ -43,7 +61,7 @@ pub struct ScanState {
 impl ScanState {
     /// Constructs a new ScanState
     pub fn new(line: u32, column: u32) -> ScanState {
-        ScanState { line: line, column: column }
+        ScanState { line, column }
     }
     /// Constructs an empty ScanState
     pub fn empty() -> ScanState {
@@ -51,6 +69,91 @@ impl ScanState {
     }
 }
 
+/// Defines the Keyword enum, with the word each keyword is spelt with
+macro_rules! keywords {
+    ($($keyword:ident => $word:expr),* $(,)*) => {
+        /// A reserved word (or keyword)
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Keyword {
+            $($keyword),*
+        }
+
+        impl Keyword {
+            /// Returns the keyword spelt `word`, if it is one
+            ///
+            /// # Examples
+            /// ```
+            /// # use haumea::scanner::Keyword;
+            /// assert_eq!(Keyword::from_word("forever"), Some(Keyword::Forever));
+            /// assert_eq!(Keyword::from_word("and"), None);
+            /// ```
+            pub fn from_word(word: &str) -> Option<Keyword> {
+                match word {
+                    $($word => Some(Keyword::$keyword),)*
+                    _ => None,
+                }
+            }
+
+            /// Returns how the keyword is spelt
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(Keyword::$keyword => $word),*
+                }
+            }
+        }
+    }
+}
+
+keywords! {
+    To => "to",
+    With => "with",
+    Is => "is",
+    Return => "return",
+    Do => "do",
+    End => "end",
+    If => "if",
+    Then => "then",
+    Else => "else",
+    Let => "let",
+    Be => "be",
+    Forever => "forever",
+    While => "while",
+    For => "for",
+    Each => "each",
+    In => "in",
+    Set => "set",
+    Through => "through",
+    Change => "change",
+    By => "by",
+    Variable => "variable",
+    Use => "use",
+    As => "as",
+    Public => "public",
+    Private => "private",
+    External => "external",
+    From => "from",
+    Link => "link",
+    Include => "include",
+    Header => "header",
+    Define => "define",
+    Template => "template",
+    Embed => "embed",
+    True => "true",
+    False => "false",
+    Length => "length",
+    Of => "of",
+    Returns => "returns",
+    Break => "break",
+    Out => "out",
+    Continue => "continue",
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// An enum representing the various tokens that can occur
 #[derive(Debug)]
 #[derive(Clone)]
@@ -58,67 +161,257 @@ pub enum Token {
     /// An integer number
     ///
     /// The content is the number read as an i64
-    Number(i32, ScanState),
+    Number(i64, ScanState),
+    /// A decimal number, like 1.5
+    Float(f64, ScanState),
+    /// A number that is too large to be an i64
+    ///
+    /// The content is the digits of the number
+    TooLarge(String, ScanState),
+    /// A number that is too large to be an i64, read by a scanner for `--bignum` programs
+    ///
+    /// The content is the digits of the number
+    BigNumber(String, ScanState),
     /// An identifier
     ///
     /// The content is the name of the identifier
     Ident(String, ScanState),
     /// A reserved word (or keyword)
-    ///
-    /// The content is the name of the keyword
-    Keyword(String, ScanState),
+    Keyword(Keyword, ScanState),
     /// An operator
     ///
     /// The content is the name of the operator
     Operator(String, ScanState),
+    /// A string literal
+    ///
+    /// The content is the text between the quotes, with its escapes replaced
+    StringLit(String, ScanState),
+    /// A string literal with an escape that isn't `\n`, `\t`, `\"` or `\\`
+    BadEscape(char, ScanState),
     /// Left parens
     Lp(ScanState),
     /// Right parens
     Rp(ScanState),
+    /// Left square bracket
+    Lb(ScanState),
+    /// Right square bracket
+    Rb(ScanState),
     /// A comma
     Comma(ScanState),
+    /// A colon, between a parameter and its type
+    Colon(ScanState),
     /// An unexpected char was read
     ///
     /// The content is the char read
     Error(char, ScanState),
+    /// A comment, which is only read by a scanner made `with_comments`
+    ///
+    /// The content is the comment as it was written, with its `/*` and `*/`
+    Comment(String, ScanState),
+    /// The input ended inside a comment, which started here
+    UnterminatedComment(ScanState),
     /// End of input
     EOF(ScanState),
 }
 
 impl Token {
-    pub fn state(self) -> ScanState {
+    /// Returns where the token was read
+    pub fn state(&self) -> ScanState {
         use self::Token::*;
-        match self {
+        match *self {
             Number(_, s) => s,
+            Float(_, s) => s,
+            TooLarge(_, s) => s,
+            BigNumber(_, s) => s,
             Ident(_, s) => s,
             Keyword(_, s) => s,
             Operator(_, s) => s,
+            StringLit(_, s) => s,
+            BadEscape(_, s) => s,
             Error(_, s) => s,
+            Comment(_, s) => s,
+            UnterminatedComment(s) => s,
             Lp(s) => s,
             Rp(s) => s,
+            Lb(s) => s,
+            Rb(s) => s,
             Comma(s) => s,
+            Colon(s) => s,
             EOF(s) => s,
         }
     }
+
+    /// Returns where the token was read, to be changed
+    pub fn state_mut(&mut self) -> &mut ScanState {
+        use self::Token::*;
+        match *self {
+            Number(_, ref mut s) | Float(_, ref mut s) | TooLarge(_, ref mut s) | BigNumber(_, ref mut s) |
+            Ident(_, ref mut s) | Keyword(_, ref mut s) | Operator(_, ref mut s) | StringLit(_, ref mut s) | BadEscape(_, ref mut s) |
+            Error(_, ref mut s) | Comment(_, ref mut s) => s,
+            UnterminatedComment(ref mut s) | Lp(ref mut s) | Rp(ref mut s) | Lb(ref mut s) | Rb(ref mut s) |
+            Comma(ref mut s) | Colon(ref mut s) | EOF(ref mut s) => s,
+        }
+    }
 }
 impl PartialEq for Token {
     fn eq(&self, other: &Token) -> bool {
         use self::Token::*;
         match (self, other) {
-            (&Number(ref a, _), &Number(ref b, _)) => a == b,
-            (&Ident(ref a, _), &Ident(ref b, _)) => a == b,
-            (&Keyword(ref a, _), &Keyword(ref b, _)) => a == b,
-            (&Operator(ref a, _), &Operator(ref b, _)) => a == b,
-            (&Lp(_), &Lp(_)) => true,
-            (&Rp(_), &Rp(_)) => true,
-            (&Comma(_), &Comma(_)) => true,
-            (&Error(ref a, _), &Error(ref b, _)) => a == b,
-            (&EOF(_), &EOF(_)) => true,
+            (Number(a, _), Number(b, _)) => a == b,
+            (Float(a, _), Float(b, _)) => a.to_bits() == b.to_bits(),
+            (TooLarge(a, _), TooLarge(b, _)) => a == b,
+            (BigNumber(a, _), BigNumber(b, _)) => a == b,
+            (Ident(a, _), Ident(b, _)) => a == b,
+            (Keyword(a, _), Keyword(b, _)) => a == b,
+            (Operator(a, _), Operator(b, _)) => a == b,
+            (StringLit(a, _), StringLit(b, _)) => a == b,
+            (BadEscape(a, _), BadEscape(b, _)) => a == b,
+            (Lp(_), Lp(_)) => true,
+            (Rp(_), Rp(_)) => true,
+            (Lb(_), Lb(_)) => true,
+            (Rb(_), Rb(_)) => true,
+            (Comma(_), Comma(_)) => true,
+            (Colon(_), Colon(_)) => true,
+            (Error(a, _), Error(b, _)) => a == b,
+            (Comment(a, _), Comment(b, _)) => a == b,
+            (UnterminatedComment(_), UnterminatedComment(_)) => true,
+            (EOF(_), EOF(_)) => true,
             _ => false,
         }
     }
 }
 
+/// Describes the token for error messages, like "the keyword 'to'"
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Token::*;
+        match *self {
+            Number(n, _) => write!(f, "the number {}", n),
+            Float(n, _) => write!(f, "the number {}", n),
+            TooLarge(ref digits, _) => write!(f, "the number {}, which is too large", digits),
+            BigNumber(ref digits, _) => write!(f, "the number {}", digits),
+            Ident(ref name, _) => write!(f, "the name '{}'", name),
+            Keyword(keyword, _) => write!(f, "the keyword '{}'", keyword),
+            Operator(ref name, _) => write!(f, "the operator '{}'", name),
+            StringLit(ref text, _) => write!(f, "the text \"{}\"", text),
+            BadEscape(c, _) => write!(f, "the unknown escape '\\{}'", c),
+            Lp(_) => write!(f, "'('"),
+            Rp(_) => write!(f, "')'"),
+            Lb(_) => write!(f, "'['"),
+            Rb(_) => write!(f, "']'"),
+            Comma(_) => write!(f, "','"),
+            Colon(_) => write!(f, "':'"),
+            Error(c, _) => write!(f, "the unexpected character '{}'", c),
+            Comment(..) => write!(f, "a comment"),
+            UnterminatedComment(_) => write!(f, "a comment that is never closed"),
+            EOF(_) => write!(f, "the end of the file"),
+        }
+    }
+}
+
+impl Eq for Token {}
+
+/// Like `eq`, hashing ignores where the token was read
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use self::Token::*;
+        mem::discriminant(self).hash(state);
+        match *self {
+            Number(n, _) => n.hash(state),
+            Float(n, _) => n.to_bits().hash(state),
+            TooLarge(ref s, _) | BigNumber(ref s, _) | Ident(ref s, _) | Operator(ref s, _) | StringLit(ref s, _) | Comment(ref s, _) => {
+                s.hash(state)
+            },
+            Keyword(keyword, _) => keyword.hash(state),
+            Error(c, _) | BadEscape(c, _) => c.hash(state),
+            Lp(_) | Rp(_) | Lb(_) | Rb(_) | Comma(_) | Colon(_) | UnterminatedComment(_) | EOF(_) => (),
+        }
+    }
+}
+
+/// A mistake found by the scanner, which it reads past to find any more
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanError {
+    /// A character that can't start a token
+    UnexpectedChar(char, ScanState),
+    /// A string literal that starts here is never closed
+    UnterminatedString(ScanState),
+    /// A comment that starts here is never closed
+    UnterminatedComment(ScanState),
+    /// A string literal has an escape that isn't `\n`, `\t`, `\"` or `\\`
+    BadEscape(char, ScanState),
+    /// An integer literal doesn't fit in an i64
+    ///
+    /// The content is the digits of the number
+    TooLarge(String, ScanState),
+}
+
+impl ScanError {
+    /// Returns the mistake `token` is, if it is one
+    pub fn from_token(token: &Token) -> Option<ScanError> {
+        match *token {
+            Token::Error('"', state) => Some(ScanError::UnterminatedString(state)),
+            Token::Error(c, state) => Some(ScanError::UnexpectedChar(c, state)),
+            Token::UnterminatedComment(state) => Some(ScanError::UnterminatedComment(state)),
+            Token::BadEscape(c, state) => Some(ScanError::BadEscape(c, state)),
+            Token::TooLarge(ref digits, state) => Some(ScanError::TooLarge(digits.clone(), state)),
+            _ => None,
+        }
+    }
+
+    /// Returns where in the source the mistake is
+    pub fn state(&self) -> ScanState {
+        match *self {
+            ScanError::UnexpectedChar(_, state) | ScanError::UnterminatedString(state) |
+            ScanError::UnterminatedComment(state) | ScanError::BadEscape(_, state) |
+            ScanError::TooLarge(_, state) => state,
+        }
+    }
+
+    /// Returns where in the source the mistake is, to be changed
+    pub fn state_mut(&mut self) -> &mut ScanState {
+        match *self {
+            ScanError::UnexpectedChar(_, ref mut state) | ScanError::UnterminatedString(ref mut state) |
+            ScanError::UnterminatedComment(ref mut state) | ScanError::BadEscape(_, ref mut state) |
+            ScanError::TooLarge(_, ref mut state) => state,
+        }
+    }
+
+    /// Returns what the mistake is, without where it is
+    pub fn message(&self) -> String {
+        match *self {
+            ScanError::UnexpectedChar(c, _) => format!("the character '{}' can't be used in Haumea code!", c),
+            ScanError::UnterminatedString(_) => "the text that starts here is never closed!".to_string(),
+            ScanError::UnterminatedComment(_) => "the comment that starts here is never closed!".to_string(),
+            ScanError::BadEscape(c, _) => {
+                format!("'\\{}' is not an escape, strings can only use \\n, \\t, \\\" and \\\\!", c)
+            },
+            ScanError::TooLarge(ref digits, _) => {
+                format!("the integer {} is too large, integers can be at most {} without --bignum!", digits, i64::MAX)
+            },
+        }
+    }
+
+    /// Returns the diagnostic that reports the mistake
+    pub fn diagnostic(&self) -> Diagnostic {
+        let code = match *self {
+            ScanError::UnexpectedChar(..) => "E0001",
+            ScanError::UnterminatedString(_) => "E0002",
+            ScanError::UnterminatedComment(_) => "E0003",
+            ScanError::BadEscape(..) => "E0004",
+            ScanError::TooLarge(..) => "E0005",
+        };
+        Diagnostic::error(code, self.message(), diagnostics::point(self.state()))
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state();
+        write!(f, "At line {:}:{:}, {}", state.line, state.column, self.message())
+    }
+}
+
 impl<'a> Scanner<'a> {
     /// Constructs a new Scanner from a source &str
     ///
@@ -130,26 +423,84 @@ impl<'a> Scanner<'a> {
     /// assert_eq!(scanner.source_str, source);
     /// assert_eq!(scanner.peek, Some(' '));
     /// ```
-    pub fn new(source: &'a str) -> Scanner {
-        let chars = source.chars().peekable();
+    pub fn new(source: &'a str) -> Scanner<'a> {
+        // Editors on Windows often start files with a byte order mark, which isn't part of the code
+        let mut code = source.strip_prefix('\u{feff}').unwrap_or(source);
+        // Scripts start with a line like `#!/usr/bin/env haumea`, which the shell reads instead
+        if code.starts_with("#!") {
+            code = &code[code.find('\n').unwrap_or(code.len())..];
+        }
+        let skipped = source.len() - code.len();
+        let chars = code.chars().peekable();
         let peek = Some(' ');
         Scanner {
             source_str: source,
             source_chars: chars,
-            operator_chars: vec!['+', '=', '-', '*', '/', '<', '>', '~', '|', '&', '(', ')', '!'],
-            operators: vec!["+", "=", "-", "*", "/", "<", ">", ">=", "<=",
-                            "~", "|", "&", "and", "or", "not", "(", ")", "!=", "modulo"],
-            ident_chars: "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_".chars().collect::<Vec<_>>(),
-            reserved_words: vec!["to", "with", "is", "return", "do", "end",
-                                 "if", "then", "else", "let", "be", "forever",
-                                 "while", "for", "each", "in",
-                                 "set", "to", "through", "change", "by", "variable"],
-            peek: peek,
+            peek,
             column: 0,
             line: 1,
+            consumed: skipped,
+            skipped,
+            unterminated_comment: None,
+            locale: None,
+            errors: vec![],
+            comments: false,
+            big_numbers: false,
+            after_minus: false,
         }
     }
 
+    /// Constructs a new Scanner that also reads the spellings of keywords and word operators in `locale`
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::scanner::{Scanner, Token, Keyword, ScanState};
+    /// # use haumea::locale::Locale;
+    /// let spanish = Locale::builtin("es").unwrap();
+    /// let mut s = Scanner::with_locale("si x y y", &spanish);
+    /// assert_eq!(s.next_token(), Token::Keyword(Keyword::If, ScanState::empty()));
+    /// assert_eq!(s.next_token(), Token::Ident("x".to_string(), ScanState::empty()));
+    /// assert_eq!(s.next_token(), Token::Operator("and".to_string(), ScanState::empty()));
+    /// ```
+    pub fn with_locale(source: &'a str, locale: &'a Locale) -> Scanner<'a> {
+        Scanner { locale: Some(locale), ..Scanner::new(source) }
+    }
+
+    /// Makes the scanner read comments as `Token::Comment`s instead of skipping them
+    ///
+    /// The parser skips comment tokens, so tools can read the comments from the
+    /// same tokens they parse.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::scanner::{Scanner, Token, ScanState};
+    /// let mut s = Scanner::new("/* Squares */ n * n /* twice */").with_comments();
+    /// assert_eq!(s.next_token(), Token::Comment("/* Squares */".to_string(), ScanState::empty()));
+    /// assert_eq!(s.next_token(), Token::Ident("n".to_string(), ScanState::empty()));
+    /// assert_eq!(s.nth(2), Some(Token::Comment("/* twice */".to_string(), ScanState::empty())));
+    /// ```
+    pub fn with_comments(mut self) -> Scanner<'a> {
+        self.comments = true;
+        self
+    }
+
+    /// Makes the scanner read whole numbers too large for an i64 as `Token::BigNumber`s
+    ///
+    /// Programs compiled with `--bignum` can use numbers of any size, so these
+    /// aren't mistakes in them.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::scanner::{Scanner, Token, ScanState};
+    /// let mut s = Scanner::new("99999999999999999999").with_big_numbers();
+    /// assert_eq!(s.next_token(), Token::BigNumber("99999999999999999999".to_string(), ScanState::empty()));
+    /// assert!(s.errors().is_empty());
+    /// ```
+    pub fn with_big_numbers(mut self) -> Scanner<'a> {
+        self.big_numbers = true;
+        self
+    }
+
     /// Returns the next token in the source. Token::EOF means that all the input has been read
     ///
     /// # Examples
@@ -162,24 +513,99 @@ impl<'a> Scanner<'a> {
     /// assert_eq!(s.next_token(), Token::EOF(ScanState::empty()));
     /// ```
     pub fn next_token(&mut self) -> Token {
+        let token = self.scan_token();
+        // -9223372036854775808 fits in an i64 even though its digits don't,
+        // so the parser checks a number just after a `-` once it knows if it is negated
+        let negatable = match token {
+            Token::TooLarge(ref digits, _) => self.after_minus && negated(digits).is_some(),
+            _ => false,
+        };
+        if let Some(error) = ScanError::from_token(&token) {
+            if !negatable {
+                self.errors.push(error);
+            }
+        }
+        if let Token::Comment(..) = token {
+            return token;
+        }
+        self.after_minus = match token {
+            Token::Operator(ref name, _) => name == "-",
+            _ => false,
+        };
+        token
+    }
+
+    /// Returns every mistake in the tokens read so far
+    ///
+    /// The scanner reads past each mistake, so once every token has been read,
+    /// these are all of the mistakes in the source.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::scanner::{Scanner, ScanError, ScanState};
+    /// let mut s = Scanner::new("1 $ 99999999999999999999 /* never closed");
+    /// assert_eq!(s.by_ref().count(), 4);
+    /// assert_eq!(s.errors(), [ScanError::UnexpectedChar('$', ScanState::new(1, 3)),
+    ///                         ScanError::TooLarge("99999999999999999999".to_string(), ScanState::new(1, 5)),
+    ///                         ScanError::UnterminatedComment(ScanState::new(1, 26))]);
+    /// ```
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
+    /// Reads the next token, which `next_token` checks for mistakes
+    fn scan_token(&mut self) -> Token {
         self.skip_white();
+        if let Some(state) = self.unterminated_comment.take() {
+            return Token::UnterminatedComment(state);
+        }
         let state = ScanState::new(self.line, self.column);
+        if self.comments && self.at_comment() {
+            let start = self.offset();
+            if !self.skip_until_comment_end() {
+                return Token::UnterminatedComment(state);
+            }
+            return Token::Comment(self.source_str[start..self.offset()].to_string(), state);
+        }
         match self.peek {
             Some(c) => {
-                if self.ident_chars.contains(&c) {
+                if is_ident_char(c) {
                     self.get_ident_token(state)
-                } else if c.is_digit(10) {
-                    Token::Number(self.get_num(), state)
+                } else if c.is_ascii_digit() {
+                    let digits = self.get_num();
+                    if digits.contains('.') {
+                        match digits.parse::<f64>() {
+                            Ok(n) if n.is_finite() => Token::Float(n, state),
+                            _ => Token::TooLarge(digits, state),
+                        }
+                    } else {
+                        match digits.parse() {
+                            Ok(n) => Token::Number(n, state),
+                            Err(_) if self.big_numbers => Token::BigNumber(digits, state),
+                            Err(_) => Token::TooLarge(digits, state),
+                        }
+                    }
                 } else if c == '(' {
                     self.get_char();
                     Token::Lp(state)
                 } else if c == ')' {
                     self.get_char();
                     Token::Rp(state)
+                } else if c == '[' {
+                    self.get_char();
+                    Token::Lb(state)
+                } else if c == ']' {
+                    self.get_char();
+                    Token::Rb(state)
                 } else if c == ',' {
                     self.get_char();
                     Token::Comma(state)
-                } else if self.operator_chars.contains(&c) {
+                } else if c == ':' {
+                    self.get_char();
+                    Token::Colon(state)
+                } else if c == '"' {
+                    self.get_string(state)
+                } else if is_operator_char(c) {
                     Token::Operator(self.get_op(), state)
                 } else {
                     self.get_char();
@@ -190,87 +616,164 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    /// Like `next_token`, but also returns where the token is in the source, in bytes
+    ///
+    /// The whitespace and comments before the token are not part of its range.
+    ///
+    /// # Examples
+    /// ```
+    /// # use haumea::scanner::{Scanner, Token, ScanState};
+    /// let mut s = Scanner::new("1 /* one */ + 1");
+    /// assert_eq!(s.next_token_with_range(), (Token::Number(1, ScanState::empty()), 0..1));
+    /// assert_eq!(s.next_token_with_range(), (Token::Operator("+".to_string(), ScanState::empty()), 12..13));
+    /// ```
+    pub fn next_token_with_range(&mut self) -> (Token, Range<usize>) {
+        self.skip_white();
+        let start = self.offset();
+        let token = self.next_token();
+        (token, start..self.offset())
+    }
+
+    /// Returns the byte offset in the source of self.peek
+    fn offset(&self) -> usize {
+        match self.peek {
+            Some(c) if self.consumed > self.skipped => self.consumed - c.len_utf8(),
+            // Before anything is read, self.peek is a space that isn't in the source
+            Some(_) => self.skipped,
+            None => self.source_str.len(),
+        }
+    }
+
     /// Sets self.peek to be the next char in self.source_chars
     fn get_char(&mut self) {
-        self.peek = self.source_chars.next();
-        self.column += 1;
-        if let Some('\n') = self.peek {
+        // A line break is the last char of its line, so the line after it starts with the next one
+        if self.peek == Some('\n') {
             self.line += 1;
-            self.column = 1;
-        };
+            self.column = 0;
+        }
+        let after_return = self.peek == Some('\r');
+        self.peek = self.source_chars.next();
+        if let Some(c) = self.peek {
+            self.consumed += c.len_utf8();
+        }
+        // The \r of a \r\n is part of the same line break as the \n
+        if !(after_return && self.peek == Some('\n')) {
+            self.column += 1;
+        }
     }
 
-    /// Skips over whitespace in self.source_chars
+    /// Skips over whitespace and comments in self.source_chars
     fn skip_white(&mut self) {
         loop {
             match self.peek {
                 Some(c) if c.is_whitespace() => {
                     self.get_char()
                 }
-                _ => break,
-            }
-        }
-        self.skip_comments();
-        loop {
-            match self.peek {
-                Some(c) if c.is_whitespace() => {
-                    self.get_char()
-                }
-                _ => break,
+                _ => if self.comments || !self.skip_comments() {
+                    break
+                },
             }
         }
     }
     
-    /// Skips over comments in self.source_chars
-    fn skip_comments(&mut self) {
-        let should_skip =  match self.peek {
-            Some(c) if c == '/' => {
-                if let Some(n) = self.source_chars.peek() {
-                    n == &'*'
-                } else {
-                    false
-                }
-            },
-            _ => false
-        };
-        if should_skip {
-            self.skip_until_comment_end()
+    /// Skips over a comment in self.source_chars
+    ///
+    /// Returns true if a comment was skipped. If the input ends inside the
+    /// comment, the next token is an error where the comment started.
+    fn skip_comments(&mut self) -> bool {
+        if !self.at_comment() {
+            return false;
+        }
+        let state = ScanState::new(self.line, self.column);
+        if !self.skip_until_comment_end() {
+            self.unterminated_comment = Some(state);
         }
+        true
     }
     
-    /// Skips until the end of a comment
-    fn skip_until_comment_end(&mut self) {
-        self.get_char(); // Skip the ? in the start of the comment
+    /// Returns true if a comment starts at self.peek
+    fn at_comment(&mut self) -> bool {
+        self.peek == Some('/') && self.source_chars.peek() == Some(&'*')
+    }
+
+    /// Skips until the end of a comment, and of the comments nested inside it
+    ///
+    /// Returns false if the input ends first
+    fn skip_until_comment_end(&mut self) -> bool {
+        // How many comments have been started but not ended
+        let mut depth = 0;
         loop {
-            self.get_char();
-            match self.peek {
-                Some(c) if c == '*' => {
-                    if let Some(n) = self.source_chars.peek() {
-                        if n == &'/' {
-                            break;
-                        }
+            match (self.peek, self.source_chars.peek().cloned()) {
+                (Some('/'), Some('*')) => {
+                    depth += 1;
+                    self.get_char();
+                },
+                (Some('*'), Some('/')) => {
+                    depth -= 1;
+                    self.get_char();
+                    if depth == 0 {
+                        self.get_char();
+                        return true;
                     }
                 },
-                Some(c) if c == '/' => self.skip_comments(),
-                _ => ()
+                (None, _) => return false,
+                _ => (),
             }
+            self.get_char();
         }
-        self.get_char();
-        self.get_char();
     }
     
-    /// Returns the next number that can be found in self.source_chars
-    fn get_num(&mut self) -> i32 {
+    /// Returns the digits of the next number that can be found in self.source_chars
+    ///
+    /// A point followed by more digits is part of the number, which makes it a decimal.
+    fn get_num(&mut self) -> String {
         let mut s = String::new();
         s.push(self.peek.unwrap());
         loop {
             self.get_char();
             match self.peek {
-                Some(c) if c.is_digit(10) => s.push(c),
+                Some(c) if c.is_ascii_digit() => s.push(c),
+                Some('.') if !s.contains('.') && self.source_chars.peek().is_some_and(char::is_ascii_digit) => {
+                    s.push('.')
+                },
                 _ => break,
             }
         }
-        s.parse::<i32>().unwrap()
+        s
+    }
+
+    /// Returns a Token::StringLit with the contents of the next string literal in self.source_chars
+    ///
+    /// Returns Token::Error if the input ends before the closing quote, or
+    /// Token::BadEscape for the first escape that isn't `\n`, `\t`, `\"` or `\\`.
+    /// The rest of the string is still read, so scanning carries on after it.
+    fn get_string(&mut self, state: ScanState) -> Token {
+        let mut s = String::new();
+        let mut bad_escape = None;
+        loop {
+            self.get_char();
+            match self.peek {
+                Some('"') => {
+                    self.get_char();
+                    return bad_escape.unwrap_or(Token::StringLit(s, state))
+                },
+                Some('\\') => {
+                    let escape_state = ScanState::new(self.line, self.column);
+                    self.get_char();
+                    match self.peek {
+                        Some('n') => s.push('\n'),
+                        Some('t') => s.push('\t'),
+                        Some(c @ '"') | Some(c @ '\\') => s.push(c),
+                        Some(c) => {
+                            bad_escape.get_or_insert(Token::BadEscape(c, escape_state));
+                        },
+                        None => return Token::Error('"', state),
+                    }
+                },
+                Some(c) => s.push(c),
+                None => return Token::Error('"', state),
+            }
+        }
     }
 
     /// Returns an Token that contains the next identifier in self.source_chars
@@ -279,33 +782,49 @@ impl<'a> Scanner<'a> {
     /// 1. Token::Keyword (if the identifier is a reserved word)
     /// 2. Token::Operator (if the identifier is the name of an operator like `and` or `or`)
     /// 3. Token::Ident (otherwise)
+    ///
+    /// A qualified name like `mathlib.helper` is read as a single identifier.
     fn get_ident_token(&mut self, state: ScanState) -> Token {
         let mut s = String::new();
         s.push(self.peek.unwrap());
         loop {
             self.get_char();
             match self.peek {
-                Some(c) if self.ident_chars.contains(&c) => s.push(c),
+                Some(c) if is_ident_char(c) => s.push(c),
+                Some('.') => {
+                    match self.source_chars.peek() {
+                        Some(&n) if is_ident_char(n) => s.push('.'),
+                        _ => break,
+                    }
+                },
                 _ => break,
             }
         };
-        if self.reserved_words.contains(&&s[..]) {
-            Token::Keyword(s, state)
-        } else if self.operators.contains(&&s[..]) {
+        if let Some(keyword) = Keyword::from_word(&s) {
+            Token::Keyword(keyword, state)
+        } else if is_operator_word(&s) {
             Token::Operator(s, state)
         } else {
-            Token::Ident(s, state)
+            match self.locale.and_then(|locale| locale.lookup(&s)) {
+                Some(&Word::Keyword(keyword)) => Token::Keyword(keyword, state),
+                Some(Word::Operator(name)) => Token::Operator(name.clone(), state),
+                None => Token::Ident(s, state),
+            }
         }
     }
 
     /// Returns a String containing the next symbol spelt operator
+    ///
+    /// `-` and `~` always start an operator of their own, and `~` is one on
+    /// its own, so prefix operators can be written right after another
+    /// operator, like `a*-b` or `-~x`.
     fn get_op(&mut self) -> String {
         let mut s = String::new();
         s.push(self.peek.unwrap());
         loop {
             self.get_char();
             match self.peek {
-                Some(c) if self.operator_chars.contains(&c) => s.push(c),
+                Some(c) if is_operator_char(c) && !starts_prefix(c) && s != "~" => s.push(c),
                 _ => break,
             }
         };
@@ -313,6 +832,38 @@ impl<'a> Scanner<'a> {
     }
 }
 
+/// Returns true if `c` can be in an identifier
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+/// Returns true if `c` can be in a symbol spelt operator
+fn is_operator_char(c: char) -> bool {
+    matches!(c, '+' | '=' | '-' | '*' | '/' | '<' | '>' | '~' | '|' | '&' | '^' | '!')
+}
+
+/// Returns true if `c` starts a prefix operator, so it is never in the middle of another operator
+fn starts_prefix(c: char) -> bool {
+    c == '-' || c == '~'
+}
+
+/// Returns the negative of the number with the digits `digits`, if it fits in an i64
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::negated;
+/// assert_eq!(negated("9223372036854775808"), Some(i64::MIN));
+/// assert_eq!(negated("9223372036854775809"), None);
+/// ```
+pub fn negated(digits: &str) -> Option<i64> {
+    format!("-{}", digits).parse().ok()
+}
+
+/// Returns true if `word` is the name of a word spelt operator, like `and`
+pub fn is_operator_word(word: &str) -> bool {
+    matches!(word, "and" | "or" | "not" | "modulo")
+}
+
 // Implement Iterator for Scanner
 impl<'a> Iterator for Scanner<'a> {
     type Item = Token;
@@ -324,7 +875,7 @@ impl<'a> Iterator for Scanner<'a> {
     /// # Examples
     ///```
     /// # use haumea::scanner::{Scanner, Token, ScanState};
-    /// let s = Scanner::new("1 + 1");
+    /// let mut s = Scanner::new("1 + 1");
     /// assert_eq!(s.next(), Some(Token::Number(1, ScanState::empty())));
     /// assert_eq!(s.next(), Some(Token::Operator("+".to_string(), ScanState::empty())));
     /// assert_eq!(s.next(), Some(Token::Number(1, ScanState::empty())));
@@ -0,0 +1,276 @@
+//! src/semantic.rs
+//! Checks that every name in a loaded program refers to something.
+//!
+//! A variable must be declared with `variable`, or be a parameter or the
+//! variable of a `for each` loop, before it is used, in the block it is used
+//! in or a block around it. A function must be defined exactly once, by the
+//! program, an `embed` directive or the builtins, and a procedure, which
+//! doesn't return a value, can only be called as a statement. Otherwise the
+//! generated C wouldn't compile.
+use std::collections::HashSet;
+use std::fmt;
+use parser::{Directive, Expression, Function, Ident, Span, Statement};
+use builtins::BUILTINS;
+use flow;
+use symbol::Symbol;
+use diagnostics::Diagnostic;
+
+/// A name that doesn't refer to anything, or refers to too many things
+#[derive(Debug, Clone, PartialEq)]
+pub enum SemanticError {
+    /// A variable is used without being declared
+    UndeclaredVariable { function: Ident, name: Ident, span: Span },
+    /// A function is called without being defined
+    UndeclaredFunction { function: Ident, name: Ident, span: Span },
+    /// A variable is declared twice in the same block
+    Redeclared { function: Ident, name: Ident, span: Span },
+    /// A function is defined more than once, where `span` is a later definition
+    DuplicateFunction { name: Ident, span: Span },
+    /// A procedure is called where a value is needed
+    ProcedureValue { function: Ident, name: Ident, span: Span },
+}
+
+/// Writes where an error is, like "at line 3:5, in `main`"
+fn write_location(f: &mut fmt::Formatter, span: Span, function: Ident) -> fmt::Result {
+    if span.is_empty() {
+        write!(f, "in `{}`", function)
+    } else {
+        write!(f, "at line {}, in `{}`", span, function)
+    }
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SemanticError::UndeclaredVariable { function, name, span } => {
+                write_location(f, span, function)?;
+                write!(f, ", the variable `{}` is not declared, add `variable {}` before it is used", name, name)
+            },
+            SemanticError::UndeclaredFunction { function, name, span } => {
+                write_location(f, span, function)?;
+                write!(f, ", `{}` is called, but there is no function called `{}`", name, name)
+            },
+            SemanticError::Redeclared { function, name, span } => {
+                write_location(f, span, function)?;
+                write!(f, ", the variable `{}` is declared twice in the same block", name)
+            },
+            SemanticError::DuplicateFunction { name, span } if !span.is_empty() => {
+                write!(f, "at line {}, the function `{}` is defined again", span, name)
+            },
+            SemanticError::DuplicateFunction { name, .. } => {
+                write!(f, "the function `{}` is defined more than once", name)
+            },
+            SemanticError::ProcedureValue { function, name, span } => {
+                write_location(f, span, function)?;
+                write!(f, ", `{}` doesn't return a value, so it can only be called as a statement", name)
+            },
+        }
+    }
+}
+
+impl SemanticError {
+    /// Returns the diagnostic that reports the error
+    pub fn diagnostic(&self) -> Diagnostic {
+        match *self {
+            SemanticError::UndeclaredVariable { function, name, span } => {
+                Diagnostic::error("E0201", format!("the variable `{}` is not declared, add `variable {}` before it is used",
+                                                   name, name), span)
+                    .with_note(format!("in the function `{}`", function))
+            },
+            SemanticError::UndeclaredFunction { function, name, span } => {
+                Diagnostic::error("E0202", format!("`{}` is called, but there is no function called `{}`", name, name), span)
+                    .with_note(format!("in the function `{}`", function))
+            },
+            SemanticError::Redeclared { function, name, span } => {
+                Diagnostic::error("E0203", format!("the variable `{}` is declared twice in the same block", name), span)
+                    .with_note(format!("in the function `{}`", function))
+            },
+            SemanticError::DuplicateFunction { name, span } if !span.is_empty() => {
+                Diagnostic::error("E0204", format!("the function `{}` is defined again", name), span)
+            },
+            SemanticError::DuplicateFunction { name, span } => {
+                Diagnostic::error("E0204", format!("the function `{}` is defined more than once", name), span)
+            },
+            SemanticError::ProcedureValue { function, name, span } => {
+                Diagnostic::error("E0205", format!("`{}` doesn't return a value, so it can only be called as a statement",
+                                                   name), span)
+                    .with_note(format!("in the function `{}`", function))
+            },
+        }
+    }
+}
+
+/// Checks the names in `program`, built with `directives`, returning every error found
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::semantic::analyze;
+/// let program = parse(Scanner::new("to main do set x to 5 end")).unwrap();
+/// let errors = analyze(&program, &[]);
+/// assert_eq!(errors[0].to_string(),
+///            "at line 1:12, in `main`, the variable `x` is not declared, add `variable x` before it is used");
+/// ```
+pub fn analyze(program: &[Function], directives: &[Directive]) -> Vec<SemanticError> {
+    let mut errors = vec![];
+    // Externals are only declared, so they can be declared by several files
+    let mut defined = HashSet::new();
+    let mut duplicated = HashSet::new();
+    for func in program.iter().filter(|func| func.external.is_none()) {
+        if !defined.insert(func.name) && duplicated.insert(func.name) {
+            errors.push(SemanticError::DuplicateFunction { name: func.name, span: func.span });
+        }
+    }
+    let mut functions: HashSet<Ident> = program.iter().map(|func| func.name).collect();
+    for directive in directives {
+        if let Directive::Embed { ref name, .. } = *directive {
+            functions.insert(*name);
+            functions.insert(Symbol::from(format!("{}_size", name)));
+        }
+    }
+    for builtin in BUILTINS {
+        functions.insert(Symbol::from(builtin.name));
+    }
+    let procedures = program.iter().filter(|func| flow::is_procedure(func)).map(|func| func.name).collect();
+    for func in program {
+        let mut analyzer = Analyzer {
+            functions: &functions,
+            procedures: &procedures,
+            function: func.name,
+            scopes: vec![func.signature.iter().flatten().cloned().collect()],
+            reported: HashSet::new(),
+            errors: &mut errors,
+        };
+        analyzer.statement(&func.code);
+    }
+    errors
+}
+
+struct Analyzer<'a> {
+    /// Every function that can be called
+    functions: &'a HashSet<Ident>,
+    /// The functions that don't return a value
+    procedures: &'a HashSet<Ident>,
+    /// The function being checked
+    function: Ident,
+    /// The variables declared in each block, innermost last
+    scopes: Vec<HashSet<Ident>>,
+    /// The names that have been reported in this function, so each is only reported once
+    reported: HashSet<Ident>,
+    errors: &'a mut Vec<SemanticError>,
+}
+
+impl<'a> Analyzer<'a> {
+    fn report(&mut self, error: SemanticError, name: Ident) {
+        if self.reported.insert(name) {
+            self.errors.push(error);
+        }
+    }
+
+    fn use_variable(&mut self, name: Ident, span: Span) {
+        if !self.scopes.iter().any(|scope| scope.contains(&name)) {
+            let function = self.function;
+            self.report(SemanticError::UndeclaredVariable { function, name, span }, name);
+        }
+    }
+
+    /// Checks `statement` in a block of its own
+    fn body(&mut self, statement: &Statement) {
+        self.scopes.push(HashSet::new());
+        self.statement(statement);
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match *statement {
+            Statement::Return(ref exp, _) => self.expression(exp),
+            Statement::Var(name, span) => {
+                let declared = self.scopes.last_mut().expect("There is always a scope").insert(name);
+                if !declared {
+                    let function = self.function;
+                    self.report(SemanticError::Redeclared { function, name, span }, name);
+                }
+            },
+            Statement::Set(name, ref exp, span) | Statement::Change(name, ref exp, span) => {
+                self.expression(exp);
+                self.use_variable(name, span);
+            },
+            Statement::If { ref cond, ref if_clause, ref else_clause, .. } => {
+                self.expression(cond);
+                self.body(if_clause);
+                if let Some(ref else_clause) = **else_clause {
+                    self.body(else_clause);
+                }
+            },
+            Statement::Do(ref block, _) => {
+                self.scopes.push(HashSet::new());
+                for sub in block {
+                    self.statement(sub);
+                }
+                self.scopes.pop();
+            },
+            Statement::Call { function, ref arguments, span } => {
+                self.call(function, span);
+                for arg in arguments {
+                    self.expression(arg);
+                }
+            },
+            Statement::Forever(ref body, ..) => self.body(body),
+            Statement::Break(..) | Statement::Continue(_) => (),
+            Statement::While { ref cond, ref body, .. } => {
+                self.expression(cond);
+                self.body(body);
+            },
+            Statement::ForEach { ident, ref start, ref end, ref by, ref body, .. } => {
+                self.expression(start);
+                self.expression(end);
+                self.expression(by);
+                self.scopes.push(Some(ident).into_iter().collect());
+                self.body(body);
+                self.scopes.pop();
+            },
+        }
+    }
+
+    fn call(&mut self, name: Ident, span: Span) {
+        if !self.functions.contains(&name) {
+            let function = self.function;
+            self.report(SemanticError::UndeclaredFunction { function, name, span }, name);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match *expression {
+            Expression::Integer(..) | Expression::BigInteger(..) | Expression::Float(..) | Expression::Str(..) |
+            Expression::Boolean(..) => (),
+            Expression::Ident(name, span) => self.use_variable(name, span),
+            Expression::BinaryOp { ref left, ref right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            },
+            Expression::UnaryOp { ref expression, .. } | Expression::Length(ref expression, _) => {
+                self.expression(expression)
+            },
+            Expression::Index { ref list, ref index, .. } => {
+                self.expression(list);
+                self.expression(index);
+            },
+            Expression::List(ref items, _) => {
+                for item in items {
+                    self.expression(item);
+                }
+            },
+            Expression::Call { function, ref arguments, span } => {
+                self.call(function, span);
+                if self.procedures.contains(&function) {
+                    let caller = self.function;
+                    self.errors.push(SemanticError::ProcedureValue { function: caller, name: function, span });
+                }
+                for arg in arguments {
+                    self.expression(arg);
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! src/symbol.rs
+//! Interned names.
+//!
+//! Every name in a program, like the names of functions and variables, is
+//! stored once, and the syntax tree refers to it with a `Symbol`. A Symbol is
+//! as cheap to copy as a reference, and two Symbols are compared by checking
+//! whether they refer to the same stored name, without comparing the text.
+//! Names are stored for as long as the compiler runs.
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A name, stored once for the whole compiler
+///
+/// # Examples
+/// ```
+/// # use haumea::symbol::Symbol;
+/// let name = Symbol::intern("main");
+/// assert_eq!(name, Symbol::intern(&"main".to_string()));
+/// assert_eq!(name, "main");
+/// assert_eq!(name.len(), 4);
+/// assert_eq!(format!("to {}", name), "to main");
+/// ```
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+/// The names stored so far
+fn names() -> &'static Mutex<HashSet<&'static str>> {
+    static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    NAMES.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+impl Symbol {
+    /// Returns the Symbol for `name`, storing it if it hasn't been stored before
+    pub fn intern(name: &str) -> Symbol {
+        let mut names = names().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(stored) = names.get(name) {
+            return Symbol(stored);
+        }
+        let stored: &'static str = Box::leak(name.to_string().into_boxed_str());
+        names.insert(stored);
+        Symbol(stored)
+    }
+
+    /// Returns the name
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        self.0.as_ptr() == other.0.as_ptr()
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state);
+    }
+}
+
+/// Symbols are ordered by their names
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        self.0.cmp(other.0)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl<'a> From<&'a str> for Symbol {
+    fn from(name: &'a str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Symbol {
+        Symbol::intern(&name)
+    }
+}
+
+impl<'a> From<&'a String> for Symbol {
+    fn from(name: &'a String) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> String {
+        symbol.0.to_string()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Symbol {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.0
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == other.0
+    }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+        String::deserialize(deserializer).map(Symbol::from)
+    }
+}
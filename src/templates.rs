@@ -0,0 +1,226 @@
+//! src/templates.rs
+//! Expands templates, which are pieces of code that are pasted in where they are used.
+//!
+//! template swap with (a, b) do
+//!     variable temp
+//!     set temp to a
+//!     set a to b
+//!     set b to temp
+//! end
+//!
+//! A statement like `swap(x, y)` is replaced by the template's body, with each
+//! parameter replaced by the matching argument. A parameter that is assigned to
+//! must be given a variable. Variables declared inside the template are renamed
+//! to fresh names, so they can't clash with the variables where it is used.
+use std::collections::HashMap;
+use std::sync::Arc;
+use parser::{Expression, Function, Span, Statement, Template};
+use names::UniqueNames;
+use ast::{rebuild_expression, rebuild_statement, Folder};
+use symbol::Symbol;
+
+/// How deeply templates can be used inside other templates
+///
+/// This stops a template that uses itself from expanding forever.
+const MAX_DEPTH: u32 = 64;
+
+/// Expands the templates used by the functions in a file
+pub struct Expander<'a> {
+    /// The file's templates, by name
+    templates: HashMap<Symbol, &'a Template>,
+    /// Generates the new names of the templates' variables
+    names: UniqueNames,
+}
+
+impl<'a> Expander<'a> {
+    /// Constructs a new Expander for `templates`
+    pub fn new(templates: &'a [Template]) -> Expander<'a> {
+        Expander {
+            templates: templates.iter().map(|template| (template.name, template)).collect(),
+            names: UniqueNames::new("__HAUMEA_LOCAL_"),
+        }
+    }
+
+    /// Replaces every use of a template in `func` with the template's code
+    pub fn expand_function(&mut self, func: &mut Function) -> Result<(), String> {
+        self.expand(&mut func.code, 0)
+            .map_err(|error| format!("in `{}`, {}", func.name, error))
+    }
+
+    fn expand(&mut self, statement: &mut Statement, depth: u32) -> Result<(), String> {
+        let expanded = match *statement {
+            Statement::Call { ref function, ref arguments, span } if self.templates.contains_key(function) => {
+                if depth >= MAX_DEPTH {
+                    return Err(format!("the template `{}` uses itself too many times", function));
+                }
+                let template = self.templates[function];
+                let mut expanded = self.instantiate(template, arguments)?;
+                *expanded.span_mut() = span;
+                expanded
+            },
+            Statement::Return(ref exp, _) |
+            Statement::Set(_, ref exp, _) |
+            Statement::Change(_, ref exp, _) => return self.check_expression(exp),
+            Statement::Var(..) | Statement::Break(..) | Statement::Continue(_) => return Ok(()),
+            Statement::Call { ref arguments, .. } => {
+                for arg in arguments {
+                    self.check_expression(arg)?;
+                }
+                return Ok(());
+            },
+            Statement::If { ref cond, ref mut if_clause, ref mut else_clause, .. } => {
+                self.check_expression(cond)?;
+                self.expand(Arc::make_mut(if_clause), depth)?;
+                return match *Arc::make_mut(else_clause) {
+                    Some(ref mut else_clause) => self.expand(else_clause, depth),
+                    None => Ok(()),
+                };
+            },
+            Statement::Do(ref mut block, _) => {
+                for sub in block {
+                    self.expand(Arc::make_mut(sub), depth)?;
+                }
+                return Ok(());
+            },
+            Statement::Forever(ref mut body, ..) => return self.expand(Arc::make_mut(body), depth),
+            Statement::While { ref cond, ref mut body, .. } => {
+                self.check_expression(cond)?;
+                return self.expand(Arc::make_mut(body), depth);
+            },
+            Statement::ForEach { ref start, ref end, ref by, ref mut body, .. } => {
+                self.check_expression(start)?;
+                self.check_expression(end)?;
+                self.check_expression(by)?;
+                return self.expand(Arc::make_mut(body), depth);
+            },
+        };
+        *statement = expanded;
+        // The template's code can use other templates
+        self.expand(statement, depth + 1)
+    }
+
+    /// Returns an error if `expression` uses a template, since templates aren't expressions
+    fn check_expression(&self, expression: &Expression) -> Result<(), String> {
+        match *expression {
+            Expression::Call { ref function, .. } if self.templates.contains_key(function) => {
+                Err(format!("the template `{}` can only be used as a statement", function))
+            },
+            Expression::Call { ref arguments, .. } => {
+                arguments.iter().try_for_each(|arg| self.check_expression(arg))
+            },
+            Expression::BinaryOp { ref left, ref right, .. } => {
+                self.check_expression(left)?;
+                self.check_expression(right)
+            },
+            Expression::UnaryOp { ref expression, .. } | Expression::Length(ref expression, _) => {
+                self.check_expression(expression)
+            },
+            Expression::Index { ref list, ref index, .. } => {
+                self.check_expression(list)?;
+                self.check_expression(index)
+            },
+            Expression::List(ref items, _) => items.iter().try_for_each(|item| self.check_expression(item)),
+            Expression::Integer(..) | Expression::BigInteger(..) | Expression::Float(..) | Expression::Ident(..) |
+            Expression::Str(..) | Expression::Boolean(..) => Ok(()),
+        }
+    }
+
+    /// Returns the code of `template` used with `arguments`
+    fn instantiate(&mut self, template: &Template, arguments: &[Expression]) -> Result<Statement, String> {
+        let params = template.signature.clone().unwrap_or_default();
+        if params.len() != arguments.len() {
+            return Err(format!("the template `{}` takes {} arguments, but was given {}",
+                               template.name, params.len(), arguments.len()));
+        }
+        let mut replacements: HashMap<Symbol, Expression> = params.into_iter()
+            .zip(arguments.iter().cloned())
+            .collect();
+        let mut locals = vec![];
+        declared_variables(&template.body, &mut locals);
+        for local in locals {
+            replacements.insert(local, Expression::Ident(Symbol::from(self.names.fresh()), Span::default()));
+        }
+        let mut replacer = Replacer { replacements: &replacements, error: None };
+        let body = replacer.fold_statement(template.body.clone());
+        match replacer.error {
+            Some(error) => Err(format!("in the template `{}`, {}", template.name, error)),
+            None => Ok(body),
+        }
+    }
+}
+
+/// Adds the names of the variables declared in `statement` to `names`
+fn declared_variables(statement: &Statement, names: &mut Vec<Symbol>) {
+    match *statement {
+        Statement::Var(ref name, _) | Statement::ForEach { ident: ref name, .. } => {
+            if !names.contains(name) {
+                names.push(*name);
+            }
+            if let Statement::ForEach { ref body, .. } = *statement {
+                declared_variables(body, names);
+            }
+        },
+        Statement::If { ref if_clause, ref else_clause, .. } => {
+            declared_variables(if_clause, names);
+            if let Some(ref else_clause) = **else_clause {
+                declared_variables(else_clause, names);
+            }
+        },
+        Statement::Do(ref block, _) => {
+            for sub in block {
+                declared_variables(sub, names);
+            }
+        },
+        Statement::Forever(ref body, ..) | Statement::While { ref body, .. } => declared_variables(body, names),
+        Statement::Return(..) | Statement::Set(..) | Statement::Change(..) | Statement::Call { .. } |
+        Statement::Break(..) | Statement::Continue(_) => (),
+    }
+}
+
+/// Replaces parameters with their arguments, and variables with their new names
+struct Replacer<'a> {
+    replacements: &'a HashMap<Symbol, Expression>,
+    /// The first error found
+    error: Option<String>,
+}
+
+impl<'a> Replacer<'a> {
+    /// Returns the variable a name that is assigned to should be replaced with
+    fn replace_target(&mut self, name: Symbol) -> Symbol {
+        match self.replacements.get(&name) {
+            Some(Expression::Ident(new_name, _)) => *new_name,
+            Some(_) => {
+                if self.error.is_none() {
+                    self.error = Some(format!("`{}` is changed, so it must be given a variable", name));
+                }
+                name
+            },
+            None => name,
+        }
+    }
+}
+
+impl<'a> Folder for Replacer<'a> {
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        let mut statement = rebuild_statement(self, statement);
+        // The copy of the template's code is not the code written in the template, so it has no id
+        statement.span_mut().node = None;
+        match statement {
+            Statement::Set(ref mut name, ..) | Statement::Change(ref mut name, ..) | Statement::Var(ref mut name, _) |
+            Statement::ForEach { ident: ref mut name, .. } => *name = self.replace_target(*name),
+            _ => (),
+        }
+        statement
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        match expression {
+            Expression::Ident(ref name, _) if self.replacements.contains_key(name) => self.replacements[name].clone(),
+            expression => {
+                let mut expression = rebuild_expression(self, expression);
+                expression.span_mut().node = None;
+                expression
+            },
+        }
+    }
+}
@@ -0,0 +1,711 @@
+//! src/typecheck.rs
+//! Checks a loaded program against the types declared in its signatures.
+//!
+//! Types are only known for literals, operators, parameters declared with a
+//! type or always given the same type, `for each` variables, variables that
+//! are always set to the same type and calls to functions that declare what
+//! they return, or that always return the same type. Everything else could be
+//! any type, and is never reported, so a program without any declared types is
+//! only checked where it can't be right, like `"text" + 1` or calling a
+//! function with the wrong number of arguments. The exceptions are `length of`
+//! and comparing with `=` and `!=`, which work differently on Text, so they
+//! need to know the type of what they are given.
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use parser::{Expression, Function, Ident, Operator, Span, Statement, Type};
+use ast::{self, SideTable, Visitor};
+use builtins;
+use check::{Problem, Severity};
+use flow;
+
+/// Checks the types in `program`, returning every error found
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::typecheck::typecheck;
+/// let source = "to twice with (n: Integer) returns Integer return n * 2 to main return twice(\"two\")";
+/// let program = parse(Scanner::new(source)).unwrap();
+/// let errors = typecheck(&program);
+/// assert_eq!(errors[0].to_string(),
+///            "error: at line 1:78, in `main`, argument 1 of `twice` is `\"two\"`, which is Text, \
+///             but it must be an Integer");
+/// ```
+pub fn typecheck(program: &[Function]) -> Vec<Problem> {
+    let inferred = infer(program);
+    let mut errors = vec![];
+    for func in program.iter().filter(|func| func.external.is_none()) {
+        check_function(program, &inferred, func, &mut errors);
+        for (index, _) in func.types.parameters.iter().enumerate().filter(|&(_, &declared)| declared == Some(Type::Nothing)) {
+            errors.push(Problem {
+                severity: Severity::Error,
+                code: "E0409",
+                function: func.name.to_string(),
+                span: func.span,
+                message: format!("parameter {} is declared Nothing, but only a function can return nothing", index + 1),
+            });
+        }
+        // A procedure reaching its end is how it usually finishes
+        if let Some(returns) = func.types.returns.filter(|&returns| returns != Type::Integer && returns != Type::Nothing) {
+            if flow::falls_through(&func.code) {
+                errors.push(Problem {
+                    severity: Severity::Error,
+                    code: "E0408",
+                    function: func.name.to_string(),
+                    span: func.span,
+                    message: format!("the function returns {}, but can reach its end, where it returns 0",
+                                     describe(returns)),
+                });
+            }
+        }
+    }
+    errors
+}
+
+/// Works out what each function that doesn't declare what it returns always returns
+///
+/// A function that can reach its end returns 0 there, so it can only always
+/// return an Integer. Procedures don't return anything, so they are left out.
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::{parse, Type};
+/// # use haumea::symbol::Symbol;
+/// # use haumea::typecheck::infer_returns;
+/// let source = "to greeting return \"hi\" to twice_greeting return greeting() to main display(1)";
+/// let inferred = infer_returns(&parse(Scanner::new(source)).unwrap());
+/// assert_eq!(inferred[&Symbol::from("twice_greeting")], Type::Text);
+/// assert_eq!(inferred[&Symbol::from("main")], Type::Integer);
+/// ```
+pub fn infer_returns(program: &[Function]) -> HashMap<Ident, Type> {
+    infer(program).returns
+}
+
+/// What is worked out about the functions that don't declare their types
+#[derive(Debug, Default)]
+struct Inferred {
+    /// What each function that doesn't declare it always returns
+    returns: HashMap<Ident, Type>,
+    /// The type each parameter without one is always given, by its function and position
+    parameters: HashMap<(Ident, usize), Type>,
+}
+
+impl Inferred {
+    /// Returns the type of the parameter at `index` of `func`, if it is declared or inferred
+    fn parameter(&self, func: &Function, index: usize) -> Option<Type> {
+        func.types.parameter(index).or_else(|| self.parameters.get(&(func.name, index)).cloned())
+    }
+}
+
+/// Works out what the functions that don't declare their types return and are given
+///
+/// A parameter without a type has the type every call gives it. It is first
+/// guessed from the calls whose arguments have a known type, which can make
+/// more arguments known, and then the guesses are dropped until every call
+/// gives each parameter that is left exactly its type. That way a function
+/// that passes its parameter on to itself, which is only known once the
+/// parameter is, still has it inferred.
+fn infer(program: &[Function]) -> Inferred {
+    let mut inferred = Inferred::default();
+    // A parameter given two different types is never guessed again
+    let mut conflicting = HashSet::new();
+    loop {
+        infer_returns_given(program, &mut inferred);
+        let mut changed = false;
+        for (key, given) in given_arguments(program, &inferred) {
+            let mut known = given.iter().flatten();
+            let first = match known.next() {
+                Some(&first) => first,
+                None => continue,
+            };
+            if known.any(|&found| found != first) {
+                changed |= conflicting.insert(key);
+                inferred.parameters.remove(&key);
+            } else if !conflicting.contains(&key) && !inferred.parameters.contains_key(&key) {
+                inferred.parameters.insert(key, first);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    loop {
+        let given = given_arguments(program, &inferred);
+        let before = inferred.parameters.len();
+        inferred.parameters.retain(|key, &mut guess| given[key].iter().all(|&found| found == Some(guess)));
+        if inferred.parameters.len() == before {
+            return inferred;
+        }
+        infer_returns_given(program, &mut inferred);
+    }
+}
+
+/// Works out what each function that doesn't declare what it returns always
+/// returns, given the parameters already in `inferred`
+fn infer_returns_given(program: &[Function], inferred: &mut Inferred) {
+    inferred.returns.clear();
+    // Each pass can learn from the functions inferred by the last one, and
+    // never forgets what they return, so this stops after a pass per function
+    loop {
+        // An external function without a type returns a C number, which is converted to an Integer
+        let mut learned: HashMap<Ident, Type> = program.iter()
+            .filter(|func| func.external.is_some() && func.types.returns.is_none() && !flow::is_procedure(func))
+            .map(|func| (func.name, Type::Integer))
+            .collect();
+        for func in program.iter().filter(|func| func.external.is_none() && func.types.returns.is_none())
+                                  .filter(|func| !flow::is_procedure(func)) {
+            let (mut returned, _) = check_function(program, inferred, func, &mut vec![]);
+            if flow::falls_through(&func.code) {
+                returned.push(Some(Type::Integer));
+            }
+            match returned.split_first() {
+                Some((&Some(first), rest)) if rest.iter().all(|&found| found == Some(first)) => {
+                    learned.insert(func.name, first);
+                },
+                _ => (),
+            }
+        }
+        if learned == inferred.returns {
+            return;
+        }
+        inferred.returns = learned;
+    }
+}
+
+/// Returns the type of every argument given to each parameter without a type, if it is known
+fn given_arguments(program: &[Function], inferred: &Inferred) -> HashMap<(Ident, usize), Vec<Option<Type>>> {
+    let mut given: HashMap<(Ident, usize), Vec<Option<Type>>> = HashMap::new();
+    for func in program.iter().filter(|func| func.external.is_none()) {
+        let (_, calls) = check_function(program, inferred, func, &mut vec![]);
+        for (name, arguments) in calls {
+            for (index, found) in arguments.into_iter().enumerate() {
+                given.entry((name, index)).or_default().push(found);
+            }
+        }
+    }
+    given.retain(|&(name, index), _| {
+        program.iter().find(|func| func.name == name)
+            .is_some_and(|func| func.external.is_none() &&
+                                  index < func.signature.as_ref().map_or(0, |sig| sig.len()) &&
+                                  func.types.parameter(index).is_none())
+    });
+    given
+}
+
+/// A call to one of the program's functions, with the type of each argument if it is known
+type Call = (Ident, Vec<Option<Type>>);
+
+/// Checks `func`, returning the type of each value it returns and of the
+/// arguments of each call it makes to the program's functions, if they are known
+fn check_function(program: &[Function], inferred: &Inferred, func: &Function,
+                  errors: &mut Vec<Problem>) -> (Vec<Option<Type>>, Vec<Call>) {
+    let variables = infer_variables(program, inferred, func);
+    let mut checker = Checker::new(program, inferred, &variables, func, errors);
+    checker.statement(&func.code);
+    (checker.returned, checker.calls)
+}
+
+/// Works out the type of each variable in `func` that is always set to the same type
+///
+/// Variables are declared without a type, so this is what lets `length of`,
+/// `=` and `display` tell a variable holding text from one holding a number.
+/// A name set to different types anywhere in the function, or that is a
+/// parameter whose type isn't known, is left out.
+fn infer_variables(program: &[Function], inferred: &Inferred, func: &Function) -> HashMap<Ident, Type> {
+    let mut variables = HashMap::new();
+    // Like `infer_returns`, each pass can learn from the variables the last one did
+    loop {
+        let mut errors = vec![];
+        let mut checker = Checker::new(program, inferred, &variables, func, &mut errors);
+        checker.statement(&func.code);
+        let learned: HashMap<Ident, Type> = checker.assigned.into_iter()
+            .filter_map(|(name, types)| match types.split_first() {
+                Some((&Some(first), rest)) if rest.iter().all(|&found| found == Some(first)) => Some((name, first)),
+                _ => None,
+            })
+            .collect();
+        if learned == variables {
+            return variables;
+        }
+        variables = learned;
+    }
+}
+
+/// The known types of a program's values, for backends that compile an
+/// operator differently for each type, like `=` on Text in C
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::ast::expr;
+/// # use haumea::parser::{parse, Type};
+/// # use haumea::symbol::Symbol;
+/// # use haumea::typecheck::Types;
+/// let source = "to main do variable name set name to join(\"a\", \"b\") display(name) end";
+/// let types = Types::new(&parse(Scanner::new(source)).unwrap());
+/// assert_eq!(types.expression(Symbol::from("main"), &expr::ident("name")), Some(Type::Text));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Types {
+    /// What each function and builtin that returns the same type every time returns
+    returns: HashMap<Ident, Type>,
+    /// The type of each variable whose type is known, in each function
+    variables: HashMap<Ident, HashMap<Ident, Type>>,
+    /// The type of each parameter of each of the program's functions, if it is known
+    parameters: HashMap<Ident, Vec<Option<Type>>>,
+    /// The type of each expression in the program with an id, if it is known
+    expressions: SideTable<Type>,
+}
+
+impl Types {
+    /// Works out the types in `program`
+    pub fn new(program: &[Function]) -> Types {
+        let inferred = infer(program);
+        // The program's functions shadow the builtins
+        let mut returns: HashMap<Ident, Type> = builtins::BUILTINS.iter()
+            .filter_map(|builtin| Some((Ident::from(builtin.name), builtin.returns?)))
+            .collect();
+        for func in program.iter().filter(|func| !flow::is_procedure(func)) {
+            if let Some(found) = func.types.returns.or_else(|| inferred.returns.get(&func.name).cloned()) {
+                returns.insert(func.name, found);
+            }
+        }
+        let variables = program.iter().filter(|func| func.external.is_none()).map(|func| {
+            let mut variables = infer_variables(program, &inferred, func);
+            for (index, param) in func.signature.iter().flatten().enumerate() {
+                if let Some(known) = inferred.parameter(func, index) {
+                    variables.insert(*param, known);
+                }
+            }
+            (func.name, variables)
+        }).collect();
+        let parameters = program.iter().map(|func| {
+            let count = func.signature.as_ref().map_or(0, |sig| sig.len());
+            (func.name, (0..count).map(|index| inferred.parameter(func, index)).collect())
+        }).collect();
+        let mut types = Types { returns, variables, parameters, expressions: SideTable::new() };
+        let mut expressions = SideTable::new();
+        for func in program.iter().filter(|func| func.external.is_none()) {
+            ast::walk_function(&mut Recorder { types: &types, function: func.name, found: &mut expressions }, func);
+        }
+        types.expressions = expressions;
+        types
+    }
+
+    /// Returns the type of the variable or parameter `name` in the function called `function`, if it is known
+    pub fn variable(&self, function: Ident, name: Ident) -> Option<Type> {
+        self.variables.get(&function)?.get(&name).cloned()
+    }
+
+    /// Returns the type of the parameter at `index` of the function or builtin called `function`, if it is known
+    pub fn parameter(&self, function: Ident, index: usize) -> Option<Type> {
+        match self.parameters.get(&function) {
+            Some(parameters) => parameters.get(index).cloned().flatten(),
+            None => builtins::lookup(&function)?.parameter(index),
+        }
+    }
+
+    /// Returns what the function or builtin called `function` returns every time, if it is known
+    pub fn returns(&self, function: Ident) -> Option<Type> {
+        self.returns.get(&function).cloned()
+    }
+
+    /// Returns the type of `expression` in the function called `function`, if it is known
+    pub fn expression(&self, function: Ident, expression: &Expression) -> Option<Type> {
+        // The types of the expressions of the program were worked out with it
+        expression.span().id()
+            .and_then(|id| self.expressions.get(&id).cloned())
+            .or_else(|| self.work_out(function, expression))
+    }
+
+    /// Works out the type of `expression` in the function called `function` from the types inside it
+    fn work_out(&self, function: Ident, expression: &Expression) -> Option<Type> {
+        match *expression {
+            Expression::Integer(..) | Expression::BigInteger(..) | Expression::Boolean(..) => Some(Type::Integer),
+            Expression::Float(..) => Some(Type::Real),
+            Expression::Str(..) => Some(Type::Text),
+            Expression::Ident(name, _) => self.variable(function, name),
+            // Arithmetic on Reals gives a Real, and the checker makes sure both operands are Reals
+            Expression::BinaryOp { operator, ref left, ref right, .. } if operator.works_on_reals() && !operator.is_comparison() => {
+                let real = self.expression(function, right) == Some(Type::Real) ||
+                           self.expression(function, left) == Some(Type::Real);
+                Some(if real { Type::Real } else { Type::Integer })
+            },
+            Expression::UnaryOp { operator, ref expression, .. } if operator.works_on_reals() => {
+                Some(if self.expression(function, expression) == Some(Type::Real) { Type::Real } else { Type::Integer })
+            },
+            Expression::BinaryOp { .. } | Expression::UnaryOp { .. } | Expression::Length(..) => Some(Type::Integer),
+            Expression::List(..) => Some(Type::List),
+            Expression::Index { .. } => None,
+            Expression::Call { function, .. } => self.returns(function),
+        }
+    }
+}
+
+/// Records the type of each expression with an id in the functions it walks
+struct Recorder<'t> {
+    types: &'t Types,
+    /// The function being walked
+    function: Ident,
+    found: &'t mut SideTable<Type>,
+}
+
+impl<'t> Visitor for Recorder<'t> {
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let (Some(id), Some(found)) = (expression.span().id(), self.types.work_out(self.function, expression)) {
+            self.found.insert(id, found);
+        }
+    }
+}
+
+/// Describes a type in a sentence, like "an Integer"
+fn describe(value: Type) -> &'static str {
+    match value {
+        Type::Integer => "an Integer",
+        Type::Real => "a Real",
+        Type::Text => "Text",
+        Type::List => "a List",
+        Type::Nothing => "Nothing",
+    }
+}
+
+/// Returns "1 argument", "2 arguments" and so on
+fn arguments(count: usize) -> String {
+    if count == 1 {
+        "1 argument".to_string()
+    } else {
+        format!("{} arguments", count)
+    }
+}
+
+/// Something waiting to be done by `Checker::statement`
+enum Check<'s> {
+    /// Check a statement
+    Statement(&'s Statement),
+    /// Start a block with the variables in the scope
+    Enter(HashMap<Ident, Option<Type>>),
+    /// Finish the block started last
+    Leave,
+}
+
+/// Checks `statement` in a block of its own, after what is already in `then`
+fn in_block<'s>(statement: &'s Statement, then: &mut Vec<Check<'s>>) {
+    then.extend([Check::Enter(HashMap::new()), Check::Statement(statement), Check::Leave]);
+}
+
+struct Checker<'a> {
+    /// Every function in the program
+    program: &'a [Function],
+    /// What the functions and parameters that don't declare their types always are
+    inferred: &'a Inferred,
+    /// The function being checked
+    function: &'a Function,
+    /// The type of each variable declared in each block, innermost last
+    scopes: Vec<HashMap<Ident, Option<Type>>>,
+    /// The types the variables without one are always set to, by `infer_variables`
+    variables: &'a HashMap<Ident, Type>,
+    /// The type of each value the function returns, if it is known
+    returned: Vec<Option<Type>>,
+    /// The type of each value each variable is given, if it is known
+    assigned: HashMap<Ident, Vec<Option<Type>>>,
+    /// The types of the arguments of each call to one of the program's functions, if they are known
+    calls: Vec<Call>,
+    errors: &'a mut Vec<Problem>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(program: &'a [Function], inferred: &'a Inferred, variables: &'a HashMap<Ident, Type>,
+           function: &'a Function, errors: &'a mut Vec<Problem>) -> Checker<'a> {
+        let params: HashMap<Ident, Option<Type>> = function.signature.iter().flatten().enumerate()
+            .map(|(index, param)| (*param, inferred.parameter(function, index)))
+            .collect();
+        let assigned = params.iter().map(|(&name, &known)| (name, vec![known])).collect();
+        Checker { program, inferred, function, scopes: vec![params], variables, returned: vec![], assigned, calls: vec![],
+                  errors }
+    }
+
+    /// Records that the variable `name` is given a value of type `found`
+    fn assign(&mut self, name: Ident, found: Option<Type>) {
+        self.assigned.entry(name).or_default().push(found);
+    }
+
+    fn error(&mut self, code: &'static str, span: Span, message: String) {
+        let function = self.function.name.to_string();
+        self.errors.push(Problem { severity: Severity::Error, code, function, span, message });
+    }
+
+    /// Returns the type of the variable `name`, if it is known
+    fn variable(&self, name: Ident) -> Option<Type> {
+        self.scopes.iter().rev().filter_map(|scope| scope.get(&name)).next().cloned().flatten()
+    }
+
+    /// Reports `expression` if its type is known and isn't `expected`
+    ///
+    /// `needs` says what needs the type, like "`+` needs", and is followed by the type in the message.
+    fn expect(&mut self, expression: &Expression, expected: Type, needs: &str) {
+        let found = self.expression(expression);
+        self.report(expression, found, expected, needs);
+    }
+
+    /// Reports `expression` if its type `found` is known and isn't `expected`, like `expect`
+    fn report(&mut self, expression: &Expression, found: Option<Type>, expected: Type, needs: &str) {
+        match found {
+            Some(found) if found != expected => {
+                let source = ast::expression_to_source(expression);
+                self.error("E0401", expression.span(), format!("`{}` is {}, but {} {}",
+                                                      source, describe(found), needs, describe(expected)));
+            },
+            _ => (),
+        }
+    }
+
+    /// Returns `found` as the type of an operator, unless a mistake has been
+    /// reported in it since there were `before` errors
+    ///
+    /// An operator with a mistake in it has no type, so the mistake isn't
+    /// reported again wherever the operator's result is used.
+    fn unless_reported(&self, before: usize, found: Type) -> Option<Type> {
+        Some(found).filter(|_| self.errors.len() == before)
+    }
+
+    /// Checks `statement` and the statements inside it
+    ///
+    /// The statements waiting to be checked are kept on a stack rather than
+    /// checked by recursion, since generated code can be nested too deeply to
+    /// recurse through.
+    fn statement(&mut self, statement: &Statement) {
+        let mut waiting = vec![Check::Statement(statement)];
+        let mut then = vec![];
+        while let Some(check) = waiting.pop() {
+            match check {
+                Check::Statement(statement) => self.check_statement(statement, &mut then),
+                Check::Enter(scope) => self.scopes.push(scope),
+                Check::Leave => {
+                    self.scopes.pop();
+                },
+            }
+            // What comes first is checked first, so it goes on the stack last
+            waiting.extend(then.drain(..).rev());
+        }
+    }
+
+    /// Checks `statement`, leaving the statements inside it to be checked in `then`
+    fn check_statement<'s>(&mut self, statement: &'s Statement, then: &mut Vec<Check<'s>>) {
+        match *statement {
+            Statement::Return(ref exp, span) => {
+                let found = self.expression(exp);
+                self.returned.push(found);
+                match (self.function.types.returns, found) {
+                    (Some(Type::Nothing), _) => {
+                        let source = ast::expression_to_source(exp);
+                        self.error("E0402", span, format!("the function returns Nothing, but returns `{}`", source));
+                    },
+                    (Some(returns), Some(found)) if returns != found => {
+                        let source = ast::expression_to_source(exp);
+                        self.error("E0402", span, format!("the function returns {}, but `{}` is {}",
+                                                 describe(returns), source, describe(found)));
+                    },
+                    _ => (),
+                }
+            },
+            Statement::Var(name, _) => {
+                let inferred = self.variables.get(&name).cloned();
+                self.scopes.last_mut().expect("There is always a scope").insert(name, inferred);
+            },
+            Statement::Set(name, ref exp, span) => {
+                let found = self.expression(exp);
+                self.assign(name, found);
+                match (self.variable(name), found) {
+                    (Some(declared), Some(found)) if declared != found => {
+                        let source = ast::expression_to_source(exp);
+                        self.error("E0403", span, format!("`{}` is {}, but is set to `{}`, which is {}",
+                                                 name, describe(declared), source, describe(found)));
+                    },
+                    _ => (),
+                }
+            },
+            Statement::Change(name, ref exp, span) => {
+                self.expect(exp, Type::Integer, "`change` needs");
+                self.assign(name, Some(Type::Integer));
+                if let Some(declared) = self.variable(name) {
+                    if declared != Type::Integer {
+                        self.error("E0404", span, format!("`{}` is {}, but `change` needs an Integer", name, describe(declared)));
+                    }
+                }
+            },
+            Statement::If { ref cond, ref if_clause, ref else_clause, .. } => {
+                self.expect(cond, Type::Integer, "the `if` condition must be");
+                in_block(if_clause, then);
+                if let Some(ref else_clause) = **else_clause {
+                    in_block(else_clause, then);
+                }
+            },
+            Statement::Do(ref block, _) => {
+                then.push(Check::Enter(HashMap::new()));
+                then.extend(block.iter().map(|sub| Check::Statement(sub)));
+                then.push(Check::Leave);
+            },
+            Statement::Call { function, ref arguments, span } => {
+                self.call(function, arguments, span);
+            },
+            Statement::Forever(ref body, ..) => in_block(body, then),
+            Statement::Break(..) | Statement::Continue(_) => (),
+            Statement::While { ref cond, ref body, .. } => {
+                self.expect(cond, Type::Integer, "the `while` condition must be");
+                in_block(body, then);
+            },
+            Statement::ForEach { ident, ref start, ref end, ref by, ref body, .. } => {
+                self.expect(start, Type::Integer, "`for each` counts from");
+                self.expect(end, Type::Integer, "`for each` counts to");
+                self.expect(by, Type::Integer, "`for each` counts by");
+                self.assign(ident, Some(Type::Integer));
+                then.push(Check::Enter(Some((ident, Some(Type::Integer))).into_iter().collect()));
+                in_block(body, then);
+                then.push(Check::Leave);
+            },
+        }
+    }
+
+    /// Checks a call to `name` with `arguments`, returning the type it returns if it is known
+    fn call<E: Borrow<Expression>>(&mut self, name: Ident, arguments: &[E], span: Span) -> Option<Type> {
+        // The program's functions shadow the builtins
+        let (parameters, variadic, takes_text, returns): (Vec<Option<Type>>, _, _, _) = match self.program.iter().find(|func| func.name == name) {
+            Some(func) => {
+                let count = func.signature.as_ref().map_or(0, |sig| sig.len());
+                // Calling a procedure for its value is reported by `semantic`
+                let returns = if flow::is_procedure(func) {
+                    None
+                } else {
+                    func.types.returns.or_else(|| self.inferred.returns.get(&name).cloned())
+                };
+                ((0..count).map(|index| self.inferred.parameter(func, index)).collect(), false, false, returns)
+            },
+            None => match builtins::lookup(&name) {
+                Some(builtin) => {
+                    // A variadic builtin has a parameter for every argument after its first ones
+                    let count = if builtin.variadic { arguments.len().max(builtin.arity) } else { builtin.arity };
+                    ((0..count).map(|index| builtin.parameter(index)).collect(), builtin.variadic, builtin.takes_text,
+                     builtin.returns)
+                },
+                // Undefined functions are reported by `semantic`
+                None => {
+                    for arg in arguments {
+                        self.expression(arg.borrow());
+                    }
+                    return None;
+                },
+            },
+        };
+        if parameters.len() != arguments.len() {
+            let least = if variadic { "at least " } else { "" };
+            self.error("E0405", span, format!("`{}` takes {}{}, but is called with {}",
+                                     name, least, self::arguments(parameters.len()), self::arguments(arguments.len())));
+        }
+        let mut given = vec![];
+        for (index, arg) in arguments.iter().enumerate() {
+            let arg = arg.borrow();
+            let found = self.expression(arg);
+            given.push(found);
+            match (parameters.get(index).cloned().flatten(), found) {
+                (Some(Type::Integer), Some(Type::Text)) if takes_text => (),
+                (Some(expected), Some(found)) if expected != found => {
+                    let source = ast::expression_to_source(arg);
+                    let or_text = if takes_text && expected == Type::Integer { " or Text" } else { "" };
+                    self.error("E0406", arg.span(), format!("argument {} of `{}` is `{}`, which is {}, but it must be {}{}",
+                                                   index + 1, name, source, describe(found), describe(expected), or_text));
+                },
+                _ => (),
+            }
+        }
+        if self.program.iter().any(|func| func.name == name) {
+            self.calls.push((name, given));
+        }
+        returns
+    }
+
+    /// Checks `expression`, returning its type if it is known
+    fn expression(&mut self, expression: &Expression) -> Option<Type> {
+        match *expression {
+            Expression::Integer(..) | Expression::BigInteger(..) | Expression::Boolean(..) => Some(Type::Integer),
+            Expression::Float(..) => Some(Type::Real),
+            Expression::Str(..) => Some(Type::Text),
+            Expression::Ident(name, _) => self.variable(name),
+            // Text can be compared with `=` and `!=` to other text
+            Expression::BinaryOp { operator: operator @ (Operator::Equals | Operator::NotEquals), ref left, ref right, .. } => {
+                let before = self.errors.len();
+                let message = format!("`{}` needs", operator);
+                let (lh, rh) = (self.expression(left), self.expression(right));
+                let expected = if lh == Some(Type::Text) || rh == Some(Type::Text) {
+                    Type::Text
+                } else if lh == Some(Type::Real) || rh == Some(Type::Real) {
+                    Type::Real
+                } else {
+                    Type::Integer
+                };
+                self.report(left, lh, expected, &message);
+                self.report(right, rh, expected, &message);
+                if lh.is_none() && rh.is_none() && self.errors.len() == before {
+                    self.error("E0410", expression.span(), format!("the types of `{}` and `{}` aren't known, so `{}` \
+                                                                   can't tell whether to compare Text or numbers",
+                                                                  ast::expression_to_source(left),
+                                                                  ast::expression_to_source(right), operator));
+                }
+                self.unless_reported(before, Type::Integer)
+            },
+            // Arithmetic and comparisons work on two Reals too, but not on a Real and an Integer
+            Expression::BinaryOp { operator, ref left, ref right, .. } => {
+                let before = self.errors.len();
+                let message = format!("`{}` needs", operator);
+                let (lh, rh) = (self.expression(left), self.expression(right));
+                let real = operator.works_on_reals() && (lh == Some(Type::Real) || rh == Some(Type::Real));
+                let expected = if real { Type::Real } else { Type::Integer };
+                self.report(left, lh, expected, &message);
+                self.report(right, rh, expected, &message);
+                self.unless_reported(before, if operator.is_comparison() { Type::Integer } else { expected })
+            },
+            Expression::UnaryOp { operator, ref expression, .. } => {
+                let before = self.errors.len();
+                let found = self.expression(expression);
+                let expected = if operator.works_on_reals() && found == Some(Type::Real) { Type::Real } else { Type::Integer };
+                self.report(expression, found, expected, &format!("`{}` needs", operator));
+                self.unless_reported(before, expected)
+            },
+            Expression::List(ref items, _) => {
+                for item in items {
+                    self.expression(item);
+                }
+                Some(Type::List)
+            },
+            // The items of a list can be of any type
+            Expression::Index { ref list, ref index, .. } => {
+                if let Some(found) = self.expression(list).filter(|&found| found != Type::List) {
+                    let source = ast::expression_to_source(list);
+                    self.error("E0407", list.span(), format!("`{}` is {}, but only a List can be indexed", source, describe(found)));
+                }
+                self.expect(index, Type::Integer, "an index must be");
+                None
+            },
+            Expression::Length(ref list, _) => {
+                let before = self.errors.len();
+                let source = ast::expression_to_source(list);
+                match self.expression(list) {
+                    Some(Type::List) | Some(Type::Text) => (),
+                    Some(found) => {
+                        self.error("E0401", list.span(), format!("`{}` is {}, but `length of` needs a List or Text",
+                                                        source, describe(found)));
+                    },
+                    None if self.errors.len() == before => {
+                        self.error("E0410", list.span(), format!("the type of `{}` isn't known, so `length of` \
+                                                                 can't tell whether it is a List or Text", source));
+                    },
+                    None => (),
+                }
+                self.unless_reported(before, Type::Integer)
+            },
+            Expression::Call { function, ref arguments, span } => self.call(function, arguments, span),
+        }
+    }
+}
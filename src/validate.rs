@@ -0,0 +1,267 @@
+//! src/validate.rs
+//! Checks that a syntax tree is well formed before it is compiled.
+//!
+//! The parser only builds well formed trees, but trees can also be built or
+//! rewritten by hand, by the passes in this crate or by other tools. A tree
+//! that breaks one of the rules checked here is a bug in whatever built it,
+//! not in the Haumea program, so it is reported as an internal compiler error.
+//!
+//! `diagnose` checks a whole program before it is compiled or run, adding the
+//! mistakes that otherwise only show up in the generated code, like calling a
+//! function with the wrong number of arguments or not defining `main`.
+use std::collections::HashMap;
+use std::fmt;
+use parser::{Expression, Function, Ident, Operator, Span, Statement};
+use ast::{walk_program, Visitor};
+use diagnostics::Diagnostic;
+
+/// A rule that a tree breaks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalid {
+    /// The function the problem is in
+    pub function: String,
+    /// What is wrong
+    pub message: String,
+    /// The code the problem is in, which is empty if the tree was built by hand
+    pub span: Span,
+}
+
+impl Invalid {
+    /// Returns the diagnostic that reports the problem
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error("E0501", self.message.clone(), self.span)
+            .with_note(format!("in the function `{}`", self.function))
+    }
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "internal compiler error: in `{}`, {}", self.function, self.message)
+    }
+}
+
+/// Checks every function in `program`, returning every problem found
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::validate::validate;
+/// let mut program = parse(Scanner::new("to main return f(1)")).unwrap();
+/// assert_eq!(validate(&program), Ok(()));
+/// program[0].name = "".into();
+/// assert!(validate(&program).is_err());
+/// ```
+pub fn validate(program: &[Function]) -> Result<(), Vec<Invalid>> {
+    check(program, false)
+}
+
+/// Checks `program` like `validate`, and also that every integer literal in it
+/// fits in a machine integer, as it must unless it is compiled with `--bignum`
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// # use haumea::validate::validate_machine_integers;
+/// let program = parse(Scanner::new("to main return 99999999999999999999").with_big_numbers()).unwrap();
+/// assert!(validate_machine_integers(&program).is_err());
+/// ```
+pub fn validate_machine_integers(program: &[Function]) -> Result<(), Vec<Invalid>> {
+    check(program, true)
+}
+
+/// Checks every function in `program`, and its integer literals too if `machine_integers` is true
+fn check(program: &[Function], machine_integers: bool) -> Result<(), Vec<Invalid>> {
+    let mut validator = Validator { function: String::new(), span: Span::default(), machine_integers, problems: vec![] };
+    walk_program(&mut validator, program);
+    if validator.problems.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.problems)
+    }
+}
+
+/// Checks that `program` is well formed and can be run, returning a diagnostic
+/// for every problem found
+///
+/// As well as the rules `validate` checks, every call to a function of the
+/// program must pass it the arguments it takes, and there must be a `main`
+/// function, defined by the program, that takes no parameters.
+///
+/// # Examples
+/// ```
+/// # use haumea::scanner::Scanner;
+/// # use haumea::parser::parse;
+/// let program = parse(Scanner::new("to f with (n) return n to main return f(1, 2)")).unwrap();
+/// let diagnostics = haumea::validate(&program);
+/// assert_eq!(diagnostics[0].code, "E0502");
+/// assert_eq!(diagnostics[0].message, "`f` takes 1 argument, but is called with 2 arguments");
+/// ```
+pub fn diagnose(program: &[Function]) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = match validate(program) {
+        Ok(()) => vec![],
+        Err(problems) => problems.iter().map(Invalid::diagnostic).collect(),
+    };
+    // A function defined more than once is reported by `semantic`, so only the first counts
+    let mut arities = HashMap::new();
+    for func in program {
+        arities.entry(func.name).or_insert_with(|| func.signature.as_ref().map_or(0, Vec::len));
+    }
+    let mut calls = Calls { arities: &arities, function: Ident::from(""), diagnostics: vec![] };
+    walk_program(&mut calls, program);
+    diagnostics.extend(calls.diagnostics);
+    match program.iter().find(|func| func.name == "main") {
+        None => diagnostics.push(Diagnostic::error("E0503", "there is no `main` function to run".to_string(),
+                                                   Span::default())),
+        Some(main) if main.external.is_some() => {
+            diagnostics.push(Diagnostic::error("E0504", "`main` is external, but must be defined by the program".to_string(),
+                                               main.span));
+        },
+        Some(main) => if let Some(count) = main.signature.as_ref().map(Vec::len).filter(|&count| count > 0) {
+            diagnostics.push(Diagnostic::error("E0504", format!("`main` takes {}, but must take none", arguments(count)),
+                                               main.span));
+        },
+    }
+    diagnostics
+}
+
+/// Writes a number of arguments, like "1 argument"
+fn arguments(count: usize) -> String {
+    if count == 1 {
+        "1 argument".to_string()
+    } else {
+        format!("{} arguments", count)
+    }
+}
+
+/// Returns whether `name` can be written in Haumea and C
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+struct Validator {
+    /// The name of the function being checked
+    function: String,
+    /// Where the statement or expression being checked is
+    span: Span,
+    /// Whether integer literals must fit in a machine integer
+    machine_integers: bool,
+    problems: Vec<Invalid>,
+}
+
+impl Validator {
+    fn problem(&mut self, message: String) {
+        self.problems.push(Invalid { function: self.function.clone(), message, span: self.span });
+    }
+
+    fn check_name(&mut self, kind: &str, name: &str) {
+        if !is_identifier(name) {
+            self.problem(format!("the {} name {:?} is not an identifier", kind, name));
+        }
+    }
+}
+
+impl Visitor for Validator {
+    fn visit_function(&mut self, func: &Function) {
+        self.function = func.name.to_string();
+        self.span = func.span;
+        self.check_name("function", &func.name);
+        for param in func.signature.iter().flatten() {
+            self.check_name("parameter", param);
+        }
+        if func.external.is_some() && func.code != Statement::Do(vec![], Span::default()) {
+            self.problem("an external function has code".to_string());
+        }
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        self.span = statement.span();
+        match *statement {
+            Statement::Var(ref name, _) |
+            Statement::Set(ref name, ..) |
+            Statement::Change(ref name, ..) => self.check_name("variable", name),
+            Statement::Call { ref function, .. } => self.check_name("function", function),
+            Statement::ForEach { ref ident, ref range_type, label, .. } => {
+                self.check_name("variable", ident);
+                if range_type != "to" && range_type != "through" {
+                    self.problem(format!("{:?} is not a range type", range_type));
+                }
+                if let Some(label) = label {
+                    self.check_name("label", &label);
+                }
+            },
+            Statement::Forever(_, Some(label), _) | Statement::While { label: Some(label), .. } |
+            Statement::Break(Some(label), _) => self.check_name("label", &label),
+            _ => (),
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        self.span = expression.span();
+        match *expression {
+            Expression::Ident(ref name, _) => self.check_name("variable", name),
+            Expression::Call { ref function, .. } => self.check_name("function", function),
+            Expression::BinaryOp { ref operator, .. } => match *operator {
+                Operator::Negate | Operator::LogicalNot | Operator::BinaryNot => {
+                    self.problem(format!("`{}` is used as a binary operator", operator));
+                },
+                _ => (),
+            },
+            Expression::UnaryOp { ref operator, .. } => match *operator {
+                Operator::Sub | Operator::Negate | Operator::LogicalNot | Operator::BinaryNot => (),
+                _ => self.problem(format!("`{}` is used as a unary operator", operator)),
+            },
+            Expression::BigInteger(ref digits, _) if self.machine_integers => {
+                self.problem(format!("the integer {} is too large for a machine integer", digits));
+            },
+            Expression::Integer(..) | Expression::BigInteger(..) | Expression::Float(..) | Expression::Str(..) |
+            Expression::Boolean(..) | Expression::List(..) | Expression::Index { .. } | Expression::Length(..) => (),
+        }
+    }
+}
+
+/// Checks that calls to the functions of a program pass the arguments they take
+struct Calls<'a> {
+    /// The number of parameters each function takes
+    arities: &'a HashMap<Ident, usize>,
+    /// The function being checked
+    function: Ident,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Calls<'a> {
+    fn check(&mut self, function: Ident, count: usize, span: Span) {
+        // Builtins and undefined functions are checked by `typecheck` and `semantic`
+        match self.arities.get(&function) {
+            Some(&arity) if arity != count => {
+                let message = format!("`{}` takes {}, but is called with {}", function, arguments(arity), arguments(count));
+                self.diagnostics.push(Diagnostic::error("E0502", message, span)
+                    .with_note(format!("in the function `{}`", self.function)));
+            },
+            _ => (),
+        }
+    }
+}
+
+impl<'a> Visitor for Calls<'a> {
+    fn visit_function(&mut self, func: &Function) {
+        self.function = func.name;
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Call { function, ref arguments, span } = *statement {
+            self.check(function, arguments.len(), span);
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::Call { function, ref arguments, span } = *expression {
+            self.check(function, arguments.len(), span);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Tests for `haumea::ast`
+#[macro_use]
+extern crate haumea;
+
+use std::fs;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::ast::*;
+
+/// Records the kind of every node it visits, in order
+struct Recorder(Vec<String>);
+
+impl Visitor for Recorder {
+    fn visit_function(&mut self, func: &Function) {
+        self.0.push(format!("function {}", func.name));
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        let kind = match *statement {
+            Statement::Do(..) => "do",
+            Statement::If { .. } => "if",
+            Statement::Return(..) => "return",
+            Statement::Set(..) => "set",
+            Statement::Var(..) => "variable",
+            Statement::Call { .. } => "call",
+            _ => "other",
+        };
+        self.0.push(kind.to_string());
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        let kind = match *expression {
+            Expression::Integer(n, _) => n.to_string(),
+            Expression::BigInteger(ref digits, _) => digits.clone(),
+            Expression::Float(n, _) => n.to_string(),
+            Expression::Ident(ref name, _) => name.to_string(),
+            Expression::Str(ref text, _) => format!("{:?}", text),
+            Expression::Boolean(value, _) => value.to_string(),
+            Expression::List(..) => "list".to_string(),
+            Expression::Index { .. } => "index".to_string(),
+            Expression::Length(..) => "length".to_string(),
+            Expression::Call { ref function, .. } => format!("{}()", function),
+            Expression::BinaryOp { .. } => "binary".to_string(),
+            Expression::UnaryOp { .. } => "unary".to_string(),
+        };
+        self.0.push(kind);
+    }
+}
+
+#[test]
+fn test_walk_program_order() {
+    let program = parse(Scanner::new("to double with (n) return n * 2
+    to main do
+        variable x
+        set x to double(3)
+        if x > 5 then display(x) else return 1
+    end")).unwrap();
+    let mut recorder = Recorder(vec![]);
+    walk_program(&mut recorder, &program);
+    assert_eq!(recorder.0, vec![
+        "function double", "return", "binary", "n", "2",
+        "function main", "do", "variable", "set", "double()", "3",
+        "if", "binary", "x", "5", "call", "x", "return", "1",
+    ]);
+}
+
+#[test]
+fn test_default_methods_do_nothing() {
+    struct Nothing;
+    impl Visitor for Nothing {}
+    let program = parse(Scanner::new("to main return 1")).unwrap();
+    walk_program(&mut Nothing, &program);
+}
+
+/// Doubles every number
+struct Doubler;
+
+impl Folder for Doubler {
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        match expression {
+            Expression::Integer(n, span) => Expression::Integer(n * 2, span),
+            expression => rebuild_expression(self, expression),
+        }
+    }
+}
+
+#[test]
+fn test_fold_program() {
+    let program = parse(Scanner::new("to main do
+        if 1 then display(2 + f(3)) else return 4
+    end")).unwrap();
+    let folded = fold_program(&mut Doubler, program);
+    let expected = parse(Scanner::new("to main do
+        if 2 then display(4 + f(6)) else return 8
+    end")).unwrap();
+    assert_eq!(folded, expected);
+}
+
+#[test]
+fn test_to_source_round_trips_examples() {
+    for entry in fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        let program = parse(Scanner::new(&fs::read_to_string(&path).unwrap())).unwrap();
+        let source = to_source(&program);
+        assert_eq!(parse(Scanner::new(&source)).unwrap(), program, "{} printed as\n{}", path.display(), source);
+    }
+}
+
+#[test]
+fn test_to_source_parentheses() {
+    let source = "to main do
+        return (1 - 2) - 3 * (4 + 5)
+        set x to - (a + b) * c
+        set y to - 1.50 * 3
+        if a then if b then f(1) else f(2)
+    end";
+    let program = parse(Scanner::new(source)).unwrap();
+    let printed = to_source(&program);
+    assert_eq!(printed, "to main do
+    return 1 - 2 - 3 * (4 + 5)
+    set x to -(a + b) * c
+    set y to -1.5 * 3
+    if a then if b then f(1) else f(2)
+end
+");
+    assert_eq!(parse(Scanner::new(&printed)).unwrap(), program);
+}
+
+#[test]
+fn test_to_source_keeps_else_with_outer_if() {
+    let inner = stmt::if_then(expr::ident("b"), stmt::ret(expr::int(1)));
+    let outer = stmt::if_else(expr::ident("a"), inner, stmt::ret(expr::int(2)));
+    let program = vec![Function::new("main").public().body(outer)];
+    assert_eq!(to_source(&program), "public to main if a then do
+    if b then return 1
+end else return 2
+");
+}
+
+#[test]
+fn test_builders_match_parser() {
+    let built = vec![
+        Function::new("square").params(&["n"]).body(stmt::ret(expr::mul(expr::ident("n"), expr::ident("n")))),
+        Function::new("main").public().body(block![
+            stmt::var("i"),
+            stmt::set("i", expr::neg(expr::int(1))),
+            stmt::for_each("j", expr::int(1), expr::int(10), expr::int(2), true, block![
+                stmt::change("i", expr::call("square", vec![expr::ident("j")])),
+            ]),
+            stmt::while_loop(expr::binary(Operator::Lt, expr::ident("i"), expr::int(100)), stmt::change("i", expr::int(1))),
+            stmt::call("display", vec![expr::div(expr::sub(expr::ident("i"), expr::int(1)), expr::int(2))]),
+        ]),
+    ];
+    let parsed = parse(Scanner::new("to square with (n) return n * n
+    public to main do
+        variable i
+        set i to -1
+        for each j in 1 through 10 by 2 do
+            change i by square(j)
+        end
+        while i < 100 change i by 1
+        display((i - 1) / 2)
+    end")).unwrap();
+    assert_eq!(built, parsed);
+}
+
+#[test]
+fn test_functions_can_be_deduplicated() {
+    use std::collections::HashSet;
+    let program = parse(Scanner::new("to f return 1 to f return 1
+        to f
+            return 1
+        to f return 2")).unwrap();
+    let unique: HashSet<&Function> = program.iter().collect();
+    assert_eq!(unique.len(), 2);
+}
+
+/// Records which function each call resolves to, by the index of the function
+struct Resolver<'a> {
+    program: &'a [Function],
+    calls: SideTable<usize>,
+}
+
+impl<'a> Visitor for Resolver<'a> {
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::Call { ref function, span, .. } = *expression {
+            if let Some(index) = self.program.iter().position(|func| func.name == *function) {
+                self.calls.insert(span.id().unwrap(), index);
+            }
+        }
+    }
+}
+
+/// Collects the id of every node
+struct Ids(Vec<Option<NodeId>>);
+
+impl Visitor for Ids {
+    fn visit_function(&mut self, func: &Function) {
+        self.0.push(func.span.id());
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        self.0.push(statement.span().id());
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        self.0.push(expression.span().id());
+    }
+}
+
+#[test]
+fn test_node_ids_and_side_tables() {
+    let source = "to one return 1 to main return one() + display(one())";
+    let program = parse(Scanner::new(source)).unwrap();
+    assert_eq!(node_count(&program), 9);
+    let mut ids = Ids(vec![]);
+    walk_program(&mut ids, &program);
+    let unique: std::collections::HashSet<_> = ids.0.iter().map(|id| id.unwrap()).collect();
+    assert_eq!(unique.len(), 9);
+    let mut resolver = Resolver { program: &program, calls: SideTable::new() };
+    walk_program(&mut resolver, &program);
+    assert_eq!(resolver.calls.len(), 2);
+    assert!(resolver.calls.values().all(|&index| index == 0));
+    // The ids are stored in the nodes, so a copy of the tree has the same ones
+    let copy = program.clone();
+    let mut again = Resolver { program: &copy, calls: SideTable::new() };
+    walk_program(&mut again, &copy);
+    assert_eq!(again.calls, resolver.calls);
+    // Nodes that weren't parsed have no id
+    let built = vec![Function::new("main").body(stmt::ret(expr::int(1)))];
+    let mut ids = Ids(vec![]);
+    walk_program(&mut ids, &built);
+    assert!(ids.0.iter().all(Option::is_none));
+}
+
+#[test]
+fn test_deep_trees_are_dropped_without_overflowing() {
+    let mut statement = stmt::ret(expr::int(0));
+    for depth in 0..200_000 {
+        statement = match depth % 3 {
+            0 => stmt::while_loop(expr::boolean(true), statement),
+            1 => stmt::if_else(expr::boolean(true), statement, Statement::Do(vec![stmt::break_loop().into()], Span::default())),
+            _ => stmt::forever(Statement::Do(vec![statement.into()], Span::default())),
+        };
+    }
+    let shared = statement.clone();
+    drop(statement);
+    drop(shared);
+}
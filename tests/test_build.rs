@@ -0,0 +1,103 @@
+//! Tests for `haumea::build`
+extern crate haumea;
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::thread;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::build::{build_executable, build_library, rust_bindings, LibraryKind};
+use haumea::codegen::CodeGen;
+use haumea::codegen::c::CodeGenerator;
+
+#[test]
+fn test_build_static_library() {
+    // Skip the test on machines without a C compiler
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let source = "long square(long n) {\n    return n * n;\n}\n";
+    let out_dir = env::temp_dir().join("haumea_test_build");
+    let library = build_library(source, "square", LibraryKind::Static, &out_dir, &[]).unwrap();
+    assert_eq!(library, out_dir.join("libsquare.a"));
+    assert!(library.is_file());
+}
+
+#[test]
+fn test_build_executable() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let source = "int main() {\n    return 7;\n}\n";
+    let out_dir = env::temp_dir().join("haumea_test_build");
+    let program = build_executable(source, "seven", &out_dir, &[]).unwrap();
+    assert_eq!(program, out_dir.join("seven"));
+    assert_eq!(Command::new(&program).status().unwrap().code(), Some(7));
+}
+
+#[test]
+fn test_builds_of_the_same_name_do_not_share_files() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let builds: Vec<_> = (0..4).map(|status| thread::spawn(move || {
+        let source = format!("int main() {{\n    return {};\n}}\n", status);
+        let out_dir = env::temp_dir().join(format!("haumea_test_build_{}", status));
+        build_executable(&source, "same", &out_dir, &[]).unwrap()
+    })).collect();
+    for (status, build) in builds.into_iter().enumerate() {
+        let program = build.join().unwrap();
+        assert_eq!(Command::new(&program).status().unwrap().code(), Some(status as i32));
+    }
+}
+
+#[test]
+fn test_rust_bindings() {
+    let program = parse(Scanner::new("public to add with (a, b) return a + b
+    public to type return 1
+    to hidden return 2
+    public external to tick
+    to main return 0")).unwrap();
+    let bindings = rust_bindings(&program, "adder", LibraryKind::Shared);
+    assert!(bindings.contains("#[link(name = \"adder\")]"));
+    assert!(bindings.contains("#[link_name = \"hm_fn_add\"]\n        pub fn add(a: c_longlong, b: c_longlong) -> c_longlong;"));
+    assert!(bindings.contains("unsafe { ffi::add(a, b) }"));
+    assert!(bindings.contains("#[link_name = \"hm_fn_type\"]\n        pub fn type_() -> c_longlong;"));
+    assert!(!bindings.contains("hidden"));
+    assert!(!bindings.contains("tick"));
+    assert!(!bindings.contains("main"));
+}
+
+#[test]
+fn test_rust_bindings_convert_reals_and_text() {
+    let source = "public to half with (x: Real) returns Real return x / 2.0
+    public to name returns Text return \"haumea\"
+    public to greet with (who: Text) returns Text return who
+    public to first with (xs: List) return xs[0]";
+    let program = parse(Scanner::new(source)).unwrap();
+    let bindings = rust_bindings(&program, "bound", LibraryKind::Static);
+    assert!(bindings.contains("pub fn half(x: f64) -> f64;"));
+    assert!(bindings.contains("pub fn half(x: f64) -> f64 {\n    unsafe { ffi::half(x) }\n}"));
+    assert!(bindings.contains("pub fn name() -> c_longlong;"));
+    assert!(bindings.contains("pub fn name() -> String {\n    unsafe { text(ffi::name()) }\n}"));
+    // Rust can't make Haumea text or lists to pass in
+    assert!(bindings.contains("//! `greet` takes Text or a List, or returns a List, which Rust can't make, so it has no binding."));
+    assert!(!bindings.contains("pub fn greet"));
+    assert!(!bindings.contains("pub fn first"));
+    // Skip the rest on machines without a C compiler
+    if Command::new("cc").arg("--version").output().is_err() || Command::new("rustc").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_bindings");
+    let code = CodeGenerator::new().compile(&program).unwrap();
+    build_library(&code, "bound", LibraryKind::Static, &out_dir, &[]).unwrap();
+    fs::write(out_dir.join("bound.rs"), bindings).unwrap();
+    fs::write(out_dir.join("main.rs"), "mod bound;\nfn main() { println!(\"{} {}\", bound::half(3.0), bound::name()); }\n").unwrap();
+    let program = out_dir.join("bound_user");
+    let status = Command::new("rustc").arg(out_dir.join("main.rs")).arg("-L").arg(&out_dir).arg("-o").arg(&program)
+        .status().unwrap();
+    assert!(status.success());
+    assert_eq!(String::from_utf8(Command::new(&program).output().unwrap().stdout).unwrap(), "1.5 haumea\n");
+}
@@ -0,0 +1,58 @@
+//! Tests for `haumea::builtins`
+extern crate haumea;
+
+use std::env;
+use std::process::Command;
+
+use haumea::scanner::*;
+use haumea::parser::*;
+use haumea::builtins;
+use haumea::codegen::CodeGen;
+use haumea::codegen::c::CodeGenerator;
+use haumea::build::build_executable;
+
+fn compile(source: &str) -> String {
+    CodeGenerator::new().compile(&parse(Scanner::new(source)).unwrap()).unwrap()
+}
+
+#[test]
+fn test_builtins_are_emitted() {
+    let out = compile("to main do display(read()) end");
+    assert!(out.contains("long long display(int count, ...)"));
+    assert!(out.contains("display(1, read());"));
+    assert!(out.contains("long long read()"));
+}
+
+/// Builds `source`, with the prelude, and runs it, returning what it displays,
+/// or None on machines without a C compiler
+fn build_and_run(source: &str, name: &str) -> Option<String> {
+    if Command::new("cc").arg("--version").output().is_err() {
+        return None;
+    }
+    let program = haumea::prelude::include(parse(Scanner::new(source)).unwrap());
+    let code = CodeGenerator::new().compile(&program).unwrap();
+    let out_dir = env::temp_dir().join("haumea_test_builtins");
+    let executable = build_executable(&code, name, &out_dir, &[]).unwrap();
+    let output = Command::new(&executable).output().unwrap();
+    assert!(output.status.success(), "{} failed", name);
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn test_user_definition_shadows_builtin() {
+    let source = "to display with (n) do display_text(\"shadowed\") return n end
+    to main do display(seven()) end
+    to seven return 7";
+    let program = parse(Scanner::new(source)).unwrap();
+    let available: Vec<&str> = builtins::available_to(&program).iter().map(|b| b.name).collect();
+    assert_eq!(available, vec!["display_no_newline", "display_text", "display_real", "read", "push", "append", "length_of",
+                               "item_at", "set_item", "remove_last", "join", "character_code", "character_from_code",
+                               "number_from_text", "text_from_number", "run_command", "random_between", "seed_random"]);
+    let out = compile(source);
+    assert!(!out.contains("long long display("));
+    assert!(out.contains("hm_fn_display(hm_fn_seven());"));
+    // The prelude calls the program's `display`, and `main` calls `seven` before it is defined
+    if let Some(output) = build_and_run(source, "shadows_display") {
+        assert_eq!(output, "shadowed\n");
+    }
+}
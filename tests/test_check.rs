@@ -0,0 +1,104 @@
+//! Tests for `haumea::check`
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::*;
+use haumea::check::{check, has_errors, Problem, Severity};
+
+#[test]
+fn test_loops_that_count_by_zero() {
+    let program = parse_module(Scanner::new("define STEP as 2 - 2
+    to main with (n) do
+        for each i in 1 to 10 by STEP display(i)
+        for each j in 1 to 10 by n - n display(j)
+        for each k in 10 to 1 by 0 - 1 display(k)
+    end")).unwrap().functions;
+    let problems = check(&program);
+    assert_eq!(problems, vec![Problem {
+        severity: Severity::Warning,
+        code: "W0301",
+        function: "main".to_string(),
+        span: Span::default(),
+        message: "the `for each` loop over `i` counts by 0, so it never ends".to_string(),
+    }]);
+    assert!(!has_errors(&problems));
+}
+
+#[test]
+fn test_parameters_with_the_same_name() {
+    let program = parse(Scanner::new("to f with (x, y, x, f, x) return x")).unwrap();
+    let problems = check(&program);
+    let messages: Vec<String> = problems.iter().map(|problem| problem.to_string()).collect();
+    assert_eq!(messages, vec![
+        "error: at line 1:1, in `f`, parameters 1, 3 and 5 have the same name, `x`",
+        "error: at line 1:1, in `f`, parameter 4 is named `f`, the same as its function",
+    ]);
+    assert!(has_errors(&problems));
+}
+
+#[test]
+fn test_conditions_that_are_numbers() {
+    let program = parse(Scanner::new("to main with (x) do
+        if x + 1 then display(x)
+        while read() display(x)
+        if x + 1 != 0 then display(x)
+        while is_even(x) and x display(x)
+    end")).unwrap();
+    let messages: Vec<String> = check(&program).iter().map(|problem| problem.to_string()).collect();
+    assert_eq!(messages, vec![
+        "warning: at line 2:12, in `main`, the `if` condition `x + 1` is a number, write `x + 1 != 0` if that is what you meant",
+        "warning: at line 3:15, in `main`, the `while` condition `read()` is a number, write `read() != 0` if that is what you meant",
+    ]);
+}
+
+#[test]
+fn test_break_and_continue_outside_of_loops() {
+    let program = parse(Scanner::new("to main do
+        while running if done then break else continue
+        if done then continue
+        break
+    end")).unwrap();
+    let messages: Vec<String> = check(&program).iter().map(|problem| problem.to_string()).collect();
+    assert_eq!(messages, vec![
+        "error: at line 3:22, in `main`, `continue` is used outside of a loop",
+        "error: at line 4:9, in `main`, `break` is used outside of a loop",
+    ]);
+}
+
+#[test]
+fn test_breaking_out_of_labeled_loops() {
+    let program = parse(Scanner::new("to main do
+        forever as outer do
+            while running break out of outer
+            break out of inner
+        end
+        break out of outer
+    end")).unwrap();
+    let messages: Vec<String> = check(&program).iter().map(|problem| problem.to_string()).collect();
+    assert_eq!(messages, vec![
+        "error: at line 4:13, in `main`, there is no loop labeled `inner` around this `break`, label one with `as inner`",
+        "error: at line 6:9, in `main`, there is no loop labeled `outer` around this `break`, label one with `as outer`",
+    ]);
+}
+
+#[test]
+fn test_code_after_a_return() {
+    let program = parse(Scanner::new("to sign with (n) do
+        if n > 0 then return 1
+        if n < 0 then return -1
+    end
+    to main do
+        forever do
+            break
+            display(1)
+        end
+        return sign(2)
+        display(2)
+    end")).unwrap();
+    let messages: Vec<String> = check(&program).iter().map(|problem| problem.to_string()).collect();
+    assert_eq!(messages, vec![
+        "warning: at line 1:1, in `sign`, `sign` returns a value on some paths, but can also reach its end, where it returns 0",
+        "warning: at line 11:9, in `main`, this statement is never run, because the one before it always returns",
+        "warning: at line 8:13, in `main`, this statement is never run, because the one before it always leaves the loop",
+    ]);
+}
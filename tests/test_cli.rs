@@ -0,0 +1,67 @@
+//! Tests for `haumea::cli`
+extern crate haumea;
+
+use std::path::Path;
+
+use haumea::cli::{self, Cli, Command};
+
+fn parse(args: &[&str]) -> Result<Cli, String> {
+    cli::parse(args.iter().map(|arg| arg.to_string()), |path| path.ends_with("script.hau"))
+}
+
+#[test]
+fn test_commands() {
+    assert_eq!(parse(&["check", "foo.hau"]).unwrap().command, Command::Check);
+    assert_eq!(parse(&["tokens", "foo.hau"]).unwrap().command, Command::Tokens);
+    let ast = parse(&["ast", "foo.hau", "--format", "json"]).unwrap();
+    assert_eq!(ast.command, Command::Ast);
+    assert_eq!(ast.value("--format"), Some("json"));
+    assert_eq!(parse(&["run", "--via", "c", "foo.hau"]).unwrap().value("--via"), Some("c"));
+    assert_eq!(parse(&["ast", "--format=source", "foo.hau"]).unwrap().value("--format"), Some("source"));
+    assert_eq!(parse(&["--help"]).unwrap().command, Command::Help);
+    let fmt = parse(&["fmt", "--check", "foo.hau"]).unwrap();
+    assert_eq!(fmt.command, Command::Fmt);
+    assert!(fmt.flag("--check"));
+    let cli = parse(&["--bignum", "foo.hau"]).unwrap();
+    assert_eq!(cli.command, Command::Build);
+    assert_eq!(cli.input.as_deref(), Some(Path::new("foo.hau")));
+    assert!(cli.flag("--bignum"));
+    assert!(!cli.flag("--desugar"));
+    assert!(!cli.optimizes("tailcall"));
+    assert!(parse(&["--opt", "tailcall", "foo.hau"]).unwrap().optimizes("tailcall"));
+    assert!(parse(&["--opt=tailcall", "foo.hau"]).unwrap().optimizes("tailcall"));
+    assert!(parse(&["--opt", "dead-code", "foo.hau"]).unwrap().optimizes("dead-code"));
+    // A file called `build` can still be compiled after the command
+    assert_eq!(parse(&["build", "build"]).unwrap().input.as_deref(), Some(Path::new("build")));
+}
+
+#[test]
+fn test_run_passes_on_the_arguments_after_the_file() {
+    let cli = parse(&["run", "--runtime-checks", "foo.hau", "1", "--bignum"]).unwrap();
+    assert_eq!(cli.command, Command::Run);
+    assert!(cli.flag("--runtime-checks"));
+    assert!(!cli.flag("--bignum"));
+    assert_eq!(cli.program_args, ["1", "--bignum"]);
+    let script = parse(&["script.hau", "2"]).unwrap();
+    assert_eq!(script.command, Command::Run);
+    assert_eq!(script.program_args, ["2"]);
+    assert_eq!(parse(&["build", "script.hau"]).unwrap().command, Command::Build);
+}
+
+#[test]
+fn test_mistakes_on_the_command_line() {
+    assert_eq!(parse(&["foo.hau", "--bogus"]), Err("unknown option `--bogus`".to_string()));
+    assert_eq!(parse(&["--keywords", "foo.hau"]), Err("unknown option `--keywords`".to_string()));
+    assert_eq!(parse(&["foo.hau", "bar.hau"]),
+               Err("unexpected argument `bar.hau`, only one file can be given".to_string()));
+    assert_eq!(parse(&["--backend", "llvm"]), Err("unknown backend `llvm`, expected `c`, `js` or `python`".to_string()));
+    assert_eq!(parse(&["foo.hau", "-o"]), Err("-o needs the name of a file".to_string()));
+    assert_eq!(parse(&["--opt", "inline"]), Err("unknown optimization `inline`, expected one of tailcall, dead-code".to_string()));
+    assert!(parse(&["check", "foo.hau", "-o", "foo.c"]).is_err());
+    assert_eq!(parse(&["build", "--check", "foo.hau"]), Err("--check can only be used with fmt".to_string()));
+    assert_eq!(parse(&["ast", "--format", "xml"]), Err("unknown format `xml`, expected `json` or `source`".to_string()));
+    assert_eq!(parse(&["check", "--format=json"]), Err("--format can only be used with ast".to_string()));
+    assert_eq!(parse(&["run", "--via", "js"]), Err("unknown way to run a program `js`, expected `c`".to_string()));
+    assert_eq!(parse(&["build", "--via=c", "foo.hau"]), Err("--via can only be used with run".to_string()));
+    assert!(parse(&["--crate-type=cdylib", "foo.hau", "-o", "foo.so"]).is_err());
+}
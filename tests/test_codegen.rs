@@ -1 +1,514 @@
-//! Tests for `haumea::codegen`
\ No newline at end of file
+//! Tests for `haumea::codegen`
+extern crate haumea;
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::Arc;
+
+use haumea::scanner::*;
+use haumea::parser::*;
+use haumea::codegen::{CodeGen, Options};
+use haumea::codegen::c::CodeGenerator;
+use haumea::build::build_executable;
+
+fn compile_with(source: &str, options: Options) -> String {
+    CodeGenerator::with_options(options).compile(&parse(Scanner::new(source)).unwrap()).unwrap()
+}
+
+#[test]
+fn test_machine_integers_by_default() {
+    let out = compile_with("to double with (n) return n * 2", Options::default());
+    assert!(out.contains("long long hm_fn_double(long long n)"));
+    assert!(out.contains("return (n * 2ll);"));
+    assert!(!out.contains("haumea_int"));
+}
+
+#[test]
+fn test_bignum_mode() {
+    let options = Options { bignum: true, ..Options::default() };
+    let out = compile_with("to double with (n) return n * 2
+    to main do
+        if double(3) > 5 then display(1)
+    end", options);
+    assert!(out.contains("Haumea bignum runtime"));
+    assert!(out.contains("haumea_int hm_fn_double(haumea_int n)"));
+    assert!(out.contains("return hm_mul(n, hm_from_long(2ll));"));
+    assert!(out.contains("if (hm_truthy(hm_from_long(hm_cmp(hm_fn_double(hm_from_long(3ll)), hm_from_long(5ll)) > 0)))"));
+    assert!(out.contains("haumea_int display(int count, ...)"));
+}
+
+#[test]
+fn test_text_is_displayed() {
+    let out = compile_with(r#"to main display_text("say \"hi\"\n")"#, Options::default());
+    assert!(out.contains("static long long display_text(long long text)"));
+    assert!(out.contains(r#"haumea_text_0 = { 9, "say \"hi\"\n" };"#));
+    assert!(out.contains("display_text(((long long) (intptr_t) haumea_text_0.chars));"));
+    let options = Options { bignum: true, ..Options::default() };
+    let out = compile_with(r#"to main display_text("hi")"#, options);
+    assert!(out.contains("display_text(hm_from_long((long long) (intptr_t) haumea_text_0.chars));"));
+}
+
+#[test]
+fn test_operators_on_text_use_the_text_runtime() {
+    let source = r#"to main do
+        variable name
+        set name to join("a", "b")
+        display(name, length of name, length of [1])
+        display(name = "ab", name != "ab", 1 = 2)
+    end"#;
+    let out = compile_with(source, Options::default());
+    assert!(out.contains("Haumea text runtime"));
+    assert!(out.contains("name = join(2, ((long long) (intptr_t) haumea_text_0.chars), ((long long) (intptr_t) haumea_text_1.chars));"));
+    assert!(out.contains("haumea_display_values(1, \"tnn\", name, haumea_text_length(name), haumea_list_length("));
+    assert!(out.contains("display(3, (haumea_text_equals(name, ((long long) (intptr_t) haumea_text_2.chars))), \
+                          (!haumea_text_equals(name, ((long long) (intptr_t) haumea_text_2.chars))), (1ll == 2ll));"));
+    let options = Options { bignum: true, ..Options::default() };
+    let out = compile_with(source, options);
+    assert!(out.contains("hm_from_long(haumea_text_length(hm_to_long(name)))"));
+    let out = compile_with("to main number_from_text(text_from_number(1))", Options::default());
+    assert!(out.contains("Haumea text runtime"));
+    assert!(out.contains("number_from_text(text_from_number(1ll), \"main\", \"number_from_text(...)\", 1);"));
+    let out = compile_with("to main display(1)", Options::default());
+    assert!(!out.contains("haumea_text"));
+}
+
+#[test]
+fn test_random_numbers_use_the_random_runtime() {
+    let out = compile_with("to main do seed_random(1) display(random_between(1, 6)) end", Options::default());
+    assert!(out.contains("Haumea random number runtime"));
+    assert!(out.contains("display(1, random_between(1ll, 6ll));"));
+    let options = Options { bignum: true, ..Options::default() };
+    let out = compile_with("to main display(random_between(1, 6))", options);
+    assert!(out.contains("return hm_from_long(haumea_random_between(hm_to_long(low), hm_to_long(high)));"));
+    let out = compile_with("to main display(6)", Options::default());
+    assert!(!out.contains("haumea_random"));
+}
+
+#[test]
+fn test_reals_use_the_real_runtime() {
+    let out = compile_with("to main do variable x set x to 2.5 display_real(x) end", Options::default());
+    assert!(out.contains("Haumea real runtime"));
+    assert!(out.contains("double x = 0.0;"));
+    assert!(out.contains("x = 2.5;"));
+    assert!(out.contains("static long long display_real(double n)"));
+    let options = Options { bignum: true, ..Options::default() };
+    let out = compile_with("to main display_real(-2.0)", options);
+    assert!(out.contains("display_real(-2.0);"));
+    // Arithmetic on Reals is done on doubles, which are kept in a value as their bits where the type isn't known
+    let out = compile_with("to half with (x: Real) returns Real return x / 2.0
+        to main do display(half(1.0) < 0.75) set_item([0], 0, half(3.0)) end", Options::default());
+    assert!(out.contains("static double hm_fn_half(double x) {"));
+    assert!(out.contains("return (x / 2.0);"));
+    assert!(out.contains("display(1, (hm_fn_half(1.0) < 0.75));"));
+    assert!(out.contains("haumea_real(hm_fn_half(3.0))"));
+    let out = compile_with("to main display(2)", Options::default());
+    assert!(!out.contains("haumea_real"));
+}
+
+#[test]
+fn test_runtime_checks() {
+    let source = "to main with (a, b) return a / (b - 1) + a modulo b";
+    let out = compile_with(source, Options::default());
+    assert!(!out.contains("haumea_divisor"));
+    let out = compile_with(source, Options { runtime_checks: true, ..Options::default() });
+    assert!(out.contains("Haumea runtime checks"));
+    assert!(out.contains("haumea_quotient(a, (b - 1ll), \"main\", \"a / (b - 1)\", 1)"));
+    assert!(out.contains("haumea_remainder(a, b, \"main\", \"a modulo b\", 1)"));
+    let out = compile_with("to main with (n) for each i in 1 to 10 by n display(i)",
+                           Options { runtime_checks: true, ..Options::default() });
+    assert!(out.contains("= haumea_step(n, \"main\", \"for each i in 1 to 10 by n\", 1);"));
+    let out = compile_with(source, Options { bignum: true, runtime_checks: true, ..Options::default() });
+    assert!(out.contains("hm_div(a, haumea_divisor(hm_sub(b, hm_from_long(1ll)), \"main\", \"a / (b - 1)\", 1))"));
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    // The smallest machine integer divided by -1 doesn't fit in one
+    let out_dir = env::temp_dir().join("haumea_test_codegen");
+    let overflows = "to main do
+        variable m
+        set m to -9223372036854775807 - 1
+        display(m / -1)
+    end";
+    let options = Options { runtime_checks: true, ..Options::default() };
+    let program = build_executable(&compile_with(overflows, options), "runtime_checks", &out_dir, &[]).unwrap();
+    let output = Command::new(&program).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(),
+               "Error: a number too large for a machine integer in main, at `m / (-1)` on line 4\n");
+}
+
+#[test]
+fn test_checked_arithmetic() {
+    let source = "to main do
+        variable x
+        set x to 9223372036854775807
+        change x by -x
+        return x * 2 - 1
+    end";
+    let options = Options { checked_arithmetic: true, ..Options::default() };
+    let out = compile_with(source, options.clone());
+    assert!(out.contains("x = haumea_add(x, haumea_neg(x, \"main\", \"-x\", 4), \"main\", \"change x by -x\", 4);"));
+    assert!(out.contains("haumea_sub(haumea_mul(x, 2ll, \"main\", \"x * 2\", 5), 1ll, \"main\", \"x * 2 - 1\", 5)"));
+    // Big numbers can't overflow
+    let out = compile_with(source, Options { bignum: true, ..options.clone() });
+    assert!(!out.contains("haumea_add"));
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_codegen");
+    let overflows = "to main return 9223372036854775807 + 1";
+    let program = build_executable(&compile_with(overflows, options), "checked_arithmetic", &out_dir, &[]).unwrap();
+    let output = Command::new(&program).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(),
+               "Error: a number too large for a machine integer in main, at `9223372036854775807 + 1` on line 1\n");
+}
+
+#[test]
+fn test_functions_can_have_names_c_uses() {
+    let source = "to malloc with (n) return n + 1
+    to hm_add with (a, b) return a - b
+    to main return hm_add(malloc(40), 1)";
+    let out = compile_with(source, Options::default());
+    assert!(out.contains("static long long hm_fn_malloc(long long n) {"));
+    assert!(out.contains("return haumea_exit_code(hm_fn_hm_add(hm_fn_malloc(40ll), 1ll));"));
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_codegen");
+    for &bignum in &[false, true] {
+        let options = Options { bignum, ..Options::default() };
+        let program = build_executable(&compile_with(source, options), "c_names", &out_dir, &[]).unwrap();
+        assert_eq!(Command::new(&program).status().unwrap().code(), Some(40));
+    }
+}
+
+#[test]
+fn test_main_return_is_exit_code() {
+    let out = compile_with("to helper return 300
+    to main return helper()", Options::default());
+    assert!(out.contains("int haumea_exit_code(long long value)"));
+    assert!(out.contains("return haumea_exit_code(hm_fn_helper());"));
+    assert!(out.contains("long long hm_fn_helper() {\n    return 300ll;"));
+    let out = compile_with("to main return 1", Options { bignum: true, ..Options::default() });
+    assert!(out.contains("return haumea_exit_code(hm_to_long(hm_from_long(1ll)));"));
+}
+
+#[test]
+fn test_private_functions_are_static() {
+    let out = compile_with("public to exported return 1
+    to hidden return 2
+    to main return hidden()", Options::default());
+    assert!(out.contains("\nlong long hm_fn_exported() {"));
+    assert!(out.contains("\nstatic long long hm_fn_hidden() {"));
+    assert!(out.contains("\nint main() {"));
+}
+
+#[test]
+fn test_procedures_are_void() {
+    let out = compile_with("to greet do display(1) end
+    public to wave with (n) returns Nothing display(n)
+    external to beep returns Nothing
+    to main do greet() wave(2) beep() end", Options::default());
+    assert!(out.contains("\nstatic void hm_fn_greet() {\n    {\n        display(1, 1ll);\n    }\n}\n"));
+    assert!(out.contains("\nvoid hm_fn_wave(long long n) {\n    display(1, n);\n}\n"));
+    assert!(out.contains("void beep(void);"));
+    assert!(out.contains("\nint main() {"));
+}
+
+#[test]
+fn test_external_functions_are_declared() {
+    let out = compile_with("external to abs with (x) from \"stdlib.h\"
+    external to labs with (x) from \"stdlib.h\"
+    external to tick
+    external to clamp with (x, low)
+    to main return abs(tick())", Options::default());
+    assert_eq!(out.matches("#include \"stdlib.h\"\n").count(), 1);
+    assert!(out.contains("long long tick(void);"));
+    assert!(out.contains("long long clamp(long long x, long long low);"));
+    assert!(!out.contains("long long abs("));
+    // What a function declared by a header returns is converted to a long long, whatever C type it is
+    assert!(out.contains("return haumea_exit_code(((long long) abs(tick())));"));
+}
+
+#[test]
+fn test_external_functions_are_called_with_their_types() {
+    let out = compile_with("external to scale with (x: Real, name: Text, n) returns Real
+    external to greeting returns Text
+    to main do display_real(scale(1.5, \"x\", 2)) display_text(greeting()) end", Options::default());
+    assert!(out.contains("double scale(double x, const char *name, long long n);"));
+    assert!(out.contains("const char * greeting(void);"));
+    assert!(out.contains("display_real(scale(1.5, (const char *) (intptr_t) ((long long) (intptr_t) haumea_text_0.chars), 2ll));"));
+    assert!(out.contains("display_text(((long long) (intptr_t) greeting()));"));
+    // A header is expected to declare the externals, so they aren't declared again
+    let directives = vec![Directive::Include("unistd.h".to_string())];
+    let program = parse(Scanner::new("external to getpid to main display(getpid() > 0)")).unwrap();
+    let out = CodeGenerator::with_directives(Options::default(), directives).compile(&program).unwrap();
+    assert!(!out.contains("getpid(void);"));
+    assert!(out.contains("display(1, (((long long) getpid()) > 0ll));"));
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_codegen");
+    let program = build_executable(&out, "getpid", &out_dir, &[]).unwrap();
+    assert_eq!(String::from_utf8(Command::new(&program).output().unwrap().stdout).unwrap(), "1\n");
+    // Arguments are passed to a function from a header as their own type, which C converts
+    let source = "external to sqrt with (x) from \"math.h\"
+    external to cbrt with (x: Real) returns Real from \"math.h\"
+    to main do display(sqrt(16)) display_real(cbrt(-27.0)) display(sqrt(2.25)) end";
+    let flags = ["-lm".to_string()];
+    for &bignum in &[false, true] {
+        let options = Options { bignum, ..Options::default() };
+        let program = build_executable(&compile_with(source, options), "libm", &out_dir, &flags).unwrap();
+        assert_eq!(String::from_utf8(Command::new(&program).output().unwrap().stdout).unwrap(), "4\n-3\n1\n");
+    }
+}
+
+#[test]
+fn test_directives_are_emitted() {
+    let directives = vec![
+        Directive::Link("m".to_string()),
+        Directive::Include("math.h".to_string()),
+    ];
+    let ast = parse(Scanner::new("external to cbrt with (x) from \"math.h\" to main return 0")).unwrap();
+    let out = CodeGenerator::with_directives(Options::default(), directives).compile(&ast).unwrap();
+    assert!(out.contains("/* Link with: -lm */"));
+    assert_eq!(out.matches("#include \"math.h\"\n").count(), 1);
+}
+
+#[test]
+fn test_embedded_files_are_compiled() {
+    let directives = vec![Directive::Embed {
+        path: "table.txt".to_string(),
+        name: "table".into(),
+        bytes: vec![104, 105],
+    }];
+    let ast = parse(Scanner::new("to main return table(table_size() - 1)")).unwrap();
+    let out = CodeGenerator::with_directives(Options::default(), directives).compile(&ast).unwrap();
+    assert!(out.contains("static const unsigned char haumea_embed_table[] = {\n    104, 105,\n    0\n};"));
+    assert!(out.contains("static const long long haumea_embed_table_size = 2ll;"));
+    assert!(out.contains("static long long table(long long i) {"));
+    assert!(out.contains("static long long table_size(void) {"));
+}
+
+#[test]
+fn test_bare_conditions_are_parenthesized() {
+    let out = compile_with("to main do
+        while 1 if x then return x
+    end", Options::default());
+    assert!(out.contains("while (1ll)"));
+    assert!(out.contains("if (x)"));
+}
+
+#[test]
+fn test_every_statement_is_on_its_own_line() {
+    let out = compile_with("to main do
+        if 1 then display(1) else display(2)
+        return 3
+    end", Options::default());
+    assert!(out.contains("\nint main() {\n    {\n        if (1ll)\n            display(1, 1ll);\n        else\n            display(1, 2ll);\n        return haumea_exit_code(3ll);\n    }\n}\n"));
+}
+
+#[test]
+fn test_deterministic_mode() {
+    let options = Options { deterministic: true, ..Options::default() };
+    let main = "to main for each i in 1 to 10 by 2 display(i)";
+    let first = compile_with(main, options.clone());
+    for _ in 0..10 {
+        assert_eq!(compile_with(main, options.clone()), first);
+    }
+    assert!(first.contains("long long __HAUMEA_TEMP_main_1 = 1ll;"));
+    // The loop before main doesn't change the names of main's temporaries
+    let out = compile_with(&format!("to count for each j in 1 to 3 display(j) {}", main), options);
+    assert!(out.contains("long long __HAUMEA_TEMP_count_1 = 1ll;"));
+    assert!(out.ends_with(&first[first.find("int main").unwrap()..]));
+}
+
+#[test]
+fn test_tail_calls_loop() {
+    let source = "to gcd with (a, b) if b = 0 then return a else return gcd(b, a modulo b)
+    to main return gcd(12, 18)";
+    let options = Options { tail_calls: true, deterministic: true, ..Options::default() };
+    let out = compile_with(source, options.clone());
+    assert!(out.contains("static long long hm_fn_gcd(long long a, long long b) {
+    haumea_start: ;
+    if (b == 0ll)
+        return a;
+    else
+        {
+            long long __HAUMEA_TEMP_gcd_1 = b;
+            long long __HAUMEA_TEMP_gcd_2 = (a % b);
+            a = __HAUMEA_TEMP_gcd_1;
+            b = __HAUMEA_TEMP_gcd_2;
+            goto haumea_start;
+        }
+}"));
+    assert!(out.contains("return haumea_exit_code(hm_fn_gcd(12ll, 18ll));"));
+    assert!(compile_with(source, Options::default()).contains("return hm_fn_gcd(b, (a % b));"));
+    // Functions that don't return calls to themselves don't need to loop
+    assert!(!compile_with("to main return 1", options).contains("haumea_start"));
+}
+
+#[test]
+fn test_booleans_are_one_and_zero() {
+    let out = compile_with("to main do variable done set done to false while done = false set done to true end",
+                           Options::default());
+    assert!(out.contains("done = 0ll;"));
+    assert!(out.contains("while (done == 0ll)"));
+    assert!(out.contains("done = 1ll;"));
+}
+
+#[test]
+fn test_lists_use_the_list_runtime() {
+    let out = compile_with("to main do variable xs set xs to [1, 2] push(xs, 3) return xs[length of xs - 1] end",
+                           Options::default());
+    assert!(out.contains("Haumea list runtime"));
+    assert!(out.contains("xs = haumea_list_new(2, 1ll, 2ll);"));
+    assert!(out.contains("push(xs, 3ll);"));
+    assert!(out.contains("haumea_list_get(xs, (haumea_list_length(xs) - 1ll), \"main\", \"xs[length of xs - 1]\", 1)"));
+    let out = compile_with("to main do variable xs set xs to [] set_item(xs, 0, length_of(xs)) end", Options::default());
+    assert!(out.contains("set_item(xs, 0ll, length_of(xs), \"main\", \"set_item(...)\", 1);"));
+    let out = compile_with("to count with (xs) return length_of(xs) to main return 0", Options::default());
+    assert!(out.contains("Haumea list runtime"));
+    let out = compile_with("to main display(1)", Options::default());
+    assert!(!out.contains("Haumea list runtime"));
+    assert!(!out.contains("push("));
+}
+
+#[test]
+fn test_variables_are_scoped() {
+    let out = compile_with("to main do
+        variable x
+        do variable x set x to 2 end
+        do variable x change x by 3 end
+        if x = 0 then variable int
+        for each display in 1 to 3 display(display + x)
+    end", Options::default());
+    assert!(out.contains("long long x = 0ll;"));
+    assert!(out.contains("long long x_2 = 0ll;\n            x_2 = 2ll;"));
+    assert!(out.contains("long long x_3 = 0ll;\n            x_3 += 3ll;"));
+    assert!(out.contains("if (x == 0ll)\n            {\n                long long int_1 = 0ll;\n            }"));
+    assert!(out.contains("display(1, (display_1 + x));"));
+}
+
+#[test]
+fn test_c_keywords_can_be_names() {
+    let source = "to double with (int) return int * 2
+    to int with (long) do variable char set char to double(long) return char end
+    to main return int(20)";
+    let out = compile_with(source, Options::default());
+    assert!(out.contains("static long long hm_fn_double(long long int_1) {\n    return (int_1 * 2ll);\n}"));
+    assert!(out.contains("long long char_1 = 0ll;\n        char_1 = hm_fn_double(long_1);"));
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_codegen");
+    let program = build_executable(&out, "c_keywords", &out_dir, &[]).unwrap();
+    assert_eq!(Command::new(&program).status().unwrap().code(), Some(40));
+}
+
+#[test]
+fn test_breaking_out_of_an_outer_loop_is_a_goto() {
+    let out = compile_with("to main do
+        forever as outer do
+            while 1 break out of outer
+            break out of outer
+        end
+    end", Options::default());
+    assert!(out.contains("while (1ll)\n                    goto haumea_break_outer;\n                break;"));
+    assert!(out.contains("haumea_break_outer: ;"));
+    let out = compile_with("to main forever as outer break out of outer", Options::default());
+    assert!(!out.contains("haumea_break_outer"));
+}
+
+#[test]
+fn test_the_c_compiles_with_every_option() {
+    // Skip the test on machines without a C compiler
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    // Every operator, with 17 modulo 5 * 3 - 10 / 4 + 2 + 5 - 1 = 10
+    let operators = "to main do
+        variable x
+        set x to 17 modulo 5 * 3 - 10 / 4 + (6 & 3) + (4 | 1) + ~0
+        if not (x = 10) or x != 10 or x < 0 or -x > 0 or x <= 0 or x >= 100 and true then return 1
+        return x
+    end";
+    let out_dir = env::temp_dir().join("haumea_test_codegen");
+    for (index, &(bignum, runtime_checks)) in [(false, false), (true, false), (false, true), (true, true)].iter().enumerate() {
+        let options = Options { bignum, runtime_checks, ..Options::default() };
+        let name = format!("operators_{}", index);
+        let program = build_executable(&compile_with(operators, options.clone()), &name, &out_dir, &[]).unwrap();
+        assert_eq!(Command::new(&program).status().unwrap().code(), Some(10), "{:?}", options);
+        for entry in fs::read_dir("examples").unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+            let code = compile_with(&source, options.clone());
+            let name = format!("{}_{}", path.file_stem().unwrap().to_string_lossy(), index);
+            assert!(build_executable(&code, &name, &out_dir, &[]).is_ok(), "{} doesn't compile with {:?}", path.display(), options);
+        }
+    }
+}
+
+#[test]
+fn test_bignum_bitwise_operators_keep_every_bit() {
+    // Skip the test on machines without a C compiler
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let source = "to main do
+        variable x
+        set x to 1
+        for each i in 1 through 80 do set x to x * 3 end
+        display(x << 3, x >> 70, ~x, -x | 1)
+        display(x & (x >> 1), x ^ -5, -x >> 3, 1 << 100)
+        display(x << -3, -x >> 200, -x & ((1 << 72) - 1), x >> (1 << 70))
+    end";
+    let options = Options { bignum: true, ..Options::default() };
+    let out_dir = env::temp_dir().join("haumea_test_codegen");
+    let program = build_executable(&compile_with(source, options), "bignum_bits", &out_dir, &[]).unwrap();
+    let output = Command::new(&program).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+1182470635314767386528665681651066380808 125198948409041546 -147808829414345923316083210206383297602 -147808829414345923316083210206383297601
+51925258556862664259518337723401814016 -147808829414345923316083210206383297606 -18476103676793240414510401275797912201 1267650600228229401496703205376
+18476103676793240414510401275797912200 -1 1249989177850867362751 0
+");
+}
+
+#[test]
+fn test_integer_literals_at_the_edges_of_each_mode() {
+    // Skip the test on machines without a C compiler
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_codegen");
+    let source = "to main display(-9223372036854775808, -9223372036854775807 - 1)";
+    let code = compile_with(source, Options::default());
+    let program = build_executable(&code, "smallest_long", &out_dir, &[]).unwrap();
+    let output = Command::new(&program).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-9223372036854775808 -9223372036854775808\n");
+    // With --bignum, literals can be any size
+    let source = "to main display(99999999999999999999 + 1, -9223372036854775808 - 1)";
+    let options = Options { bignum: true, ..Options::default() };
+    let ast = parse(Scanner::new(source).with_big_numbers()).unwrap();
+    let code = CodeGenerator::with_options(options).compile(&ast).unwrap();
+    let program = build_executable(&code, "big_literals", &out_dir, &[]).unwrap();
+    let output = Command::new(&program).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "100000000000000000000 -9223372036854775809\n");
+    // Without it, they are a mistake in whatever built the tree
+    assert!(CodeGenerator::new().compile(&ast).is_err());
+}
+
+#[test]
+fn test_deeply_nested_programs_compile() {
+    let mut code = haumea::ast::stmt::call("display", vec![haumea::ast::expr::int(1)]);
+    for _ in 0..100_000 {
+        code = Statement::Do(vec![Arc::new(code)], Span::default());
+    }
+    let program = vec![Function::new("main").body(code)];
+    let out = CodeGenerator::new().compile(&program).unwrap();
+    assert_eq!(out.matches('{').count(), out.matches('}').count());
+    // Lines are indented no further than 32 levels
+    assert!(out.contains(&format!("\n{}display(1, 1ll);\n", " ".repeat(4 * 32))));
+}
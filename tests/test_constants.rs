@@ -0,0 +1,65 @@
+//! Tests for `haumea::constants`
+extern crate haumea;
+
+use std::mem;
+
+use haumea::scanner::Scanner;
+use haumea::parser::*;
+use haumea::ast::{expr, stmt};
+use haumea::constants::evaluate_all;
+use haumea::symbol::Symbol;
+
+#[test]
+fn test_constants_are_evaluated() {
+    let module = parse_module(Scanner::new("define SIZE as 8 * 8
+    define LIMIT as - (SIZE - 1) * 2
+    define BIG as SIZE > 10 and SIZE < 100
+    define ODD as SIZE modulo 7
+    define MASK as 1 << SIZE - 2 ^ -1 >> 1")).unwrap();
+    let values = evaluate_all(&module.constants).unwrap();
+    assert_eq!(values[&Symbol::from("SIZE")], 64);
+    assert_eq!(values[&Symbol::from("LIMIT")], -126);
+    assert_eq!(values[&Symbol::from("BIG")], 1);
+    assert_eq!(values[&Symbol::from("ODD")], 1);
+    assert_eq!(values[&Symbol::from("MASK")], (1 << 62) ^ -1);
+}
+
+/// Returns a constant called `A` with the value `source`, without evaluating it
+fn constant(source: &str) -> Constant {
+    match parse(Scanner::new(&format!("to f return {}", source))).unwrap().remove(0).code {
+        Statement::Return(ref mut value, _) => Constant { name: "A".into(), value: mem::take(value) },
+        code => panic!("expected a return statement, found {:?}", code),
+    }
+}
+
+#[test]
+fn test_constant_errors() {
+    let errors: Vec<String> = [
+        vec![constant("B")],
+        vec![constant("1 / 0")],
+        vec![constant("f(1)")],
+        vec![constant("1"), constant("2")],
+        vec![constant("4294967296 * 4294967296")],
+        vec![constant("-9223372036854775807 - 2")],
+    ].iter().map(|constants| evaluate_all(constants).unwrap_err()).collect();
+    assert_eq!(errors, vec![
+        "in the constant `A`, `B` is not a constant defined before it",
+        "in the constant `A`, it divides by zero",
+        "in the constant `A`, the function `f` can't be called in a constant",
+        "the constant `A` is defined more than once",
+        "in the constant `A`, its value is too large",
+        "in the constant `A`, its value is too large",
+    ]);
+}
+
+#[test]
+fn test_constants_are_substituted() {
+    let program = parse(Scanner::new("define SIZE as 4 * 4 to area with (n) return n * SIZE")).unwrap();
+    assert_eq!(program[0].code, stmt::ret(expr::mul(expr::ident("n"), expr::int(16))));
+}
+
+#[test]
+fn test_constants_cannot_be_set() {
+    let errors = parse(Scanner::new("define SIZE as 4 to main do set SIZE to 5 end")).unwrap_err();
+    assert!(errors[0].to_string().contains("the constant `SIZE` can't be changed"));
+}
@@ -0,0 +1,98 @@
+//! Tests for `haumea::cst`
+extern crate haumea;
+
+use std::fs;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse_module;
+use haumea::cst::{self, SyntaxElement, SyntaxKind, TextEdit};
+
+#[test]
+fn test_examples_are_kept_exactly() {
+    for entry in fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        let source = fs::read_to_string(&path).unwrap();
+        let tree = cst::parse(&source);
+        assert_eq!(tree.text(), source, "{}", path.display());
+        assert_eq!(tree.lower().unwrap(), parse_module(Scanner::new(&source)).unwrap(), "{}", path.display());
+    }
+}
+
+#[test]
+fn test_tree_structure() {
+    let tree = cst::parse("use lib /* helpers /* nested */ */
+to main do
+    display(f(1, 2))  
+end
+");
+    let kinds: Vec<SyntaxKind> = tree.children.iter().map(|child| match *child {
+        SyntaxElement::Node(ref node) => node.kind,
+        SyntaxElement::Token(ref token) => token.kind,
+    }).collect();
+    assert_eq!(kinds, vec![SyntaxKind::Declaration, SyntaxKind::Whitespace, SyntaxKind::Comment,
+                           SyntaxKind::Whitespace, SyntaxKind::Declaration, SyntaxKind::Whitespace]);
+    let main = tree.child_nodes()[1];
+    let block = main.child_nodes()[0];
+    assert_eq!(block.kind, SyntaxKind::Block);
+    assert_eq!(block.text(), "do\n    display(f(1, 2))  \nend");
+    let outer = block.child_nodes()[0];
+    assert_eq!(outer.text(), "(f(1, 2))");
+    assert_eq!(outer.child_nodes()[0].text(), "(1, 2)");
+    let windows = "\u{feff}to main do\r\n    display(1)\r\nend\r\n";
+    let tree = cst::parse(windows);
+    assert_eq!(tree.text(), windows);
+    assert_eq!(tree.children[0], SyntaxElement::Token(cst::SyntaxToken {
+        kind: SyntaxKind::Whitespace,
+        text: "\u{feff}".to_string(),
+    }));
+    let script = "#!/usr/bin/env haumea\nto main display(1)\n";
+    let tree = cst::parse(script);
+    assert_eq!(tree.text(), script);
+    assert_eq!(tree.children[0], SyntaxElement::Token(cst::SyntaxToken {
+        kind: SyntaxKind::Comment,
+        text: "#!/usr/bin/env haumea".to_string(),
+    }));
+}
+
+/// Checks that `edit` reparses to the same tree as parsing the edited source
+fn assert_incremental(source: &str, edit: TextEdit) {
+    let reparsed = cst::parse_incremental(&cst::parse(source), &edit);
+    assert_eq!(reparsed.tree, cst::parse(&edit.apply(source)), "{:?} on\n{}", edit, source);
+}
+
+#[test]
+fn test_incremental_edits_match_full_parse() {
+    for entry in fs::read_dir("examples").unwrap() {
+        let source = fs::read_to_string(entry.unwrap().path()).unwrap();
+        let mut offset = 0;
+        for token in cst::parse(&source).tokens() {
+            offset += token.text.len();
+            let text = match token.kind {
+                SyntaxKind::Ident => "z",
+                SyntaxKind::Number => "0",
+                SyntaxKind::Whitespace => "\n  ",
+                _ => continue,
+            };
+            assert_incremental(&source, TextEdit { range: offset..offset, text: text.to_string() });
+        }
+    }
+}
+
+#[test]
+fn test_incremental_edits_across_declarations() {
+    let source = "to a return 1\nto b return 2\n";
+    // The new declaration is found between the others
+    assert_incremental(source, TextEdit { range: 14..14, text: "to c return 3\n".to_string() });
+    // The inserted code continues the declaration before it
+    assert_incremental(source, TextEdit { range: 14..14, text: "+ 5 ".to_string() });
+    // Comments can hide everything after them, so the whole file is parsed again
+    let source = "to a return 1 /* note */ to b return 2";
+    let edit = TextEdit { range: 4..4, text: "/* x */".to_string() };
+    assert_incremental(source, edit.clone());
+    assert_eq!(cst::parse_incremental(&cst::parse(source), &edit).changed, 0..5);
+    // Only the edited declaration and its neighbours are parsed again
+    let source = "to a return 1\nto b return 2\nto c return 3\n";
+    let edit = TextEdit { range: 3..4, text: "first".to_string() };
+    assert_incremental(source, edit.clone());
+    assert_eq!(cst::parse_incremental(&cst::parse(source), &edit).changed, 0..3);
+}
@@ -0,0 +1,38 @@
+//! Tests for `haumea::dead_code`
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::dead_code::{eliminate, roots, warnings};
+
+#[test]
+fn test_functions_main_never_calls_are_removed() {
+    let program = haumea::prelude::include(parse(Scanner::new("to countdown with (n) do
+        if n > 0 then countdown(n - 1)
+    end
+    to twice with (n) return n * 2
+    to unused return twice(1)
+    to main do
+        countdown(3)
+        display(maximum(1, 2))
+    end")).unwrap());
+    let roots = roots(&program, false);
+    let names: Vec<String> = eliminate(program, &roots).iter().map(|func| func.name.to_string()).collect();
+    assert_eq!(names, ["maximum", "countdown", "main"]);
+}
+
+#[test]
+fn test_unused_functions_are_warned_about() {
+    let program = parse(Scanner::new("to a return b()
+    to b return a()
+    public to exported return 1
+    to main return 0")).unwrap();
+    let messages: Vec<String> = warnings(&program, &roots(&program, false)).iter().map(|warning| warning.to_string()).collect();
+    assert_eq!(messages, [
+        "warning: at line 1:1, in `a`, `a` is never called, so it is left out of the compiled program",
+        "warning: at line 2:5, in `b`, `b` is never called, so it is left out of the compiled program",
+        "warning: at line 3:5, in `exported`, `exported` is never called, so it is left out of the compiled program",
+    ]);
+    // A library's public functions are called by whatever links it
+    assert_eq!(warnings(&program, &roots(&program, true)).len(), 2);
+}
@@ -0,0 +1,52 @@
+//! Tests for `haumea::desugar`
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::ast::to_source;
+use haumea::desugar::desugar;
+
+#[test]
+fn test_shorthand_statements_are_lowered() {
+    let program = desugar(parse(Scanner::new("to main forever if x then change x by 2 else change y by -1")).unwrap());
+    let expected = parse(Scanner::new("to main while 1 if x then set x to x + 2 else set y to y + -1")).unwrap();
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn test_for_each_becomes_while() {
+    let program = desugar(parse(Scanner::new("to main for each i in 1 through n do
+        display(i)
+    end")).unwrap());
+    assert_eq!(to_source(&program), "to main do
+    variable __HAUMEA_LOOP_1
+    set __HAUMEA_LOOP_1 to 1
+    variable __HAUMEA_LOOP_2
+    set __HAUMEA_LOOP_2 to n
+    variable __HAUMEA_LOOP_3
+    set __HAUMEA_LOOP_3 to 1
+    if __HAUMEA_LOOP_1 <= __HAUMEA_LOOP_2 and __HAUMEA_LOOP_3 < 0 or __HAUMEA_LOOP_1 > __HAUMEA_LOOP_2 and __HAUMEA_LOOP_3 > 0 then set __HAUMEA_LOOP_3 to -__HAUMEA_LOOP_3
+    variable i
+    set i to __HAUMEA_LOOP_1
+    while __HAUMEA_LOOP_1 <= __HAUMEA_LOOP_2 and i <= __HAUMEA_LOOP_2 or __HAUMEA_LOOP_1 > __HAUMEA_LOOP_2 and i >= __HAUMEA_LOOP_2 do
+        display(i)
+        set i to i + __HAUMEA_LOOP_3
+    end
+end
+");
+}
+
+#[test]
+fn test_continue_steps_a_for_each() {
+    let program = desugar(parse(Scanner::new("to main for each i in 1 to 9 do
+        if i = 3 then continue
+        while 1 continue
+    end")).unwrap());
+    let source = to_source(&program);
+    assert!(source.contains("if i = 3 then do
+            set i to i + __HAUMEA_LOOP_3
+            continue
+        end
+        while 1 continue
+"), "{}", source);
+}
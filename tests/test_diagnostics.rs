@@ -0,0 +1,48 @@
+//! Tests for `haumea::diagnostics`
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::{parse, parse_module};
+use haumea::check::check;
+use haumea::semantic::analyze;
+use haumea::diagnostics::Severity;
+
+#[test]
+fn test_parse_errors_show_their_line() {
+    let source = "to main do\n\tdisplay(x) $\nend";
+    let errors = parse_module(Scanner::new(source)).unwrap_err();
+    let rendered: Vec<String> = errors.iter().map(|error| error.diagnostic().render(Some(source), None)).collect();
+    assert_eq!(rendered, vec![
+        "error[E0001]: the character '$' can't be used in Haumea code!
+ --> 2:13
+  |
+2 | \tdisplay(x) $
+  | \t           ^
+".to_string(),
+    ]);
+    let errors = parse_module(Scanner::new("to main do variable do end")).unwrap_err();
+    assert_eq!(errors[0].diagnostic().code, "E0103");
+}
+
+#[test]
+fn test_problems_keep_their_function() {
+    let source = "to main do\n  return 1\n  display(2)\n  shout(3)\nend";
+    let program = parse(Scanner::new(source)).unwrap();
+    let warning = check(&program)[0].diagnostic();
+    assert_eq!(warning.severity, Severity::Warning);
+    assert_eq!(warning.render(Some(source), Some("main.hau")),
+               "warning[W0307]: this statement is never run, because the one before it always returns
+ --> main.hau:3:3
+  |
+3 |   display(2)
+  |   ^^^^^^^^^^
+  = note: in the function `main`
+");
+    let error = analyze(&program, &[])[0].diagnostic();
+    assert_eq!(error.to_string(), "error[E0202]: at line 4:3, `shout` is called, but there is no function called `shout`");
+    // Without the source, only where the mistake is can be shown
+    assert_eq!(error.render(None, None), "error[E0202]: `shout` is called, but there is no function called `shout`
+ --> 4:3
+  = note: in the function `main`
+");
+}
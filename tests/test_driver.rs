@@ -0,0 +1,78 @@
+//! Tests for `haumea::driver`
+extern crate haumea;
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use haumea::driver::{compile, private_dir, run, DriverError};
+
+#[test]
+fn test_compile() {
+    // Skip the test on machines without a C compiler
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let work_dir = env::temp_dir().join("haumea_test_driver");
+    let program = compile("int main() {\n    return 7;\n}\n", "seven", "cc", &work_dir, &[]).unwrap();
+    assert_eq!(program, work_dir.join("seven"));
+    assert_eq!(Command::new(&program).status().unwrap().code(), Some(7));
+}
+
+#[test]
+fn test_compiler_errors_show_the_generated_code() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let code = "int main() {\n    long x = 1;\n    long y = 2;\n    return x + y + z;\n}\n\n\nint unused;\n";
+    let work_dir = env::temp_dir().join("haumea_test_driver");
+    let error = compile(code, "broken", "cc", &work_dir, &[]).unwrap_err();
+    match error {
+        DriverError::Compile { ref compiler, status, ref messages, ref context } => {
+            assert_eq!(compiler, "cc");
+            assert_ne!(status, Some(0));
+            assert!(messages.contains("broken.c:4:"), "{}", messages);
+            let numbers: Vec<usize> = context.iter().map(|&(number, _)| number).collect();
+            assert_eq!(numbers, vec![2, 3, 4, 5, 6]);
+            assert_eq!(context[2].1, "    return x + y + z;");
+        },
+        error => panic!("expected the C compiler to fail, not {:?}", error),
+    }
+    assert!(error.to_string().contains("note: the generated C is\n2 |     long x = 1;\n"), "{}", error);
+}
+
+#[test]
+fn test_missing_compiler() {
+    let work_dir = env::temp_dir().join("haumea_test_driver");
+    match compile("int main() { return 0; }\n", "missing", "haumea-no-such-cc", &work_dir, &[]) {
+        Err(DriverError::Io(_)) => (),
+        result => panic!("expected the compiler not to start, not {:?}", result),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_killed_programs_report_the_signal() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    match run("#include <stdlib.h>\nint main() {\n    abort();\n}\n", "aborts", &[], &[]) {
+        Err(DriverError::Killed { signal }) => assert_eq!(signal, 6),
+        result => panic!("expected the program to be killed, not {:?}", result),
+    }
+}
+
+#[test]
+fn test_private_dirs_are_new_and_only_for_their_owner() {
+    let first = private_dir("haumea_test_driver").unwrap();
+    let second = private_dir("haumea_test_driver").unwrap();
+    assert_ne!(first, second);
+    assert!(first.is_dir() && second.is_dir());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&first).unwrap().permissions().mode() & 0o777, 0o700);
+    }
+    fs::remove_dir(first).unwrap();
+    fs::remove_dir(second).unwrap();
+}
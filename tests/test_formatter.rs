@@ -0,0 +1,58 @@
+//! Tests for `haumea::formatter`
+extern crate haumea;
+
+use std::fs;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse_module;
+use haumea::formatter::format;
+
+#[test]
+fn test_examples_keep_their_meaning() {
+    for entry in fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        let source = fs::read_to_string(&path).unwrap();
+        let formatted = format(&source).unwrap();
+        assert_eq!(parse_module(Scanner::new(&formatted)).unwrap(), parse_module(Scanner::new(&source)).unwrap(),
+                   "{}", path.display());
+        assert_eq!(format(&formatted).unwrap(), formatted, "{} is formatted differently twice", path.display());
+    }
+}
+
+#[test]
+fn test_nested_and_continued_lines() {
+    let source = "/* Compares */
+to compare with (a: Integer,
+b) returns Integer do
+if a < b then
+return -1
+  else if a>b then do
+  display_text( \"more\" )   /* bigger */
+return 1 end
+      else
+return lists[a] [ b ]
+
+
+end
+variable x
+set x to [1 ,2,
+3]
+";
+    assert_eq!(format(source).unwrap(), "/* Compares */
+to compare with (a: Integer,
+    b) returns Integer do
+    if a < b then
+        return -1
+    else if a > b then do
+        display_text(\"more\") /* bigger */
+        return 1 end
+    else
+        return lists[a][b]
+
+end
+variable x
+set x to [1, 2,
+    3]
+");
+    assert!(format("to main do display(1 end").is_err());
+}
@@ -0,0 +1,35 @@
+//! Tests for `haumea::ice`
+extern crate haumea;
+
+use haumea::ice::{minimize, Report};
+
+#[test]
+fn test_minimize_keeps_declarations_that_fail_together() {
+    let source = "/* Helpers */
+define LIMIT as 10
+to first return LIMIT
+to second return 2
+to main return first() + second()
+";
+    // Fails whenever both `LIMIT` and `first` are defined
+    let minimized = minimize(source, |source| source.contains("define LIMIT") && source.contains("to first"));
+    assert_eq!(minimized, "define LIMIT as 10\nto first return LIMIT\n");
+    // Sources that can't be parsed are kept as they are
+    assert_eq!(minimize("to main return (", |_| true), "to main return (");
+}
+
+#[test]
+fn test_reports_name_the_phase_and_source() {
+    let report = Report {
+        version: "0.1.0",
+        phase: "generating C",
+        message: "Dedented past the left margin!".to_string(),
+        location: None,
+        source: "to main return 0\n".to_string(),
+        ast: None,
+    };
+    let text = report.to_string();
+    assert!(text.contains("phase: generating C\nmessage: Dedented past the left margin!\n"));
+    assert!(text.contains("--- source ---\nto main return 0\n"));
+    assert!(!text.contains("location:"));
+}
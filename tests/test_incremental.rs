@@ -0,0 +1,101 @@
+//! Tests for `haumea::parser::incremental`
+extern crate haumea;
+
+use std::fs;
+
+use haumea::cst::TextEdit;
+use haumea::scanner::Scanner;
+use haumea::parser::parse_module;
+use haumea::parser::incremental::ParseSession;
+use haumea::ast::{self, Expression, Function, NodeId, Statement, Visitor};
+
+/// Asserts that the session has parsed its source like `parse_module` does,
+/// positions and all
+///
+/// The ids of the nodes are left out, since the session numbers the nodes it
+/// parses again after the ones it parsed before.
+fn assert_parsed_like_parse_module(session: &ParseSession, after: &str) {
+    let expected = parse_module(Scanner::new(session.source()));
+    assert_eq!(without_ids(&format!("{:?}", session.module())), without_ids(&format!("{:?}", expected)),
+               "after {}", after);
+}
+
+/// Removes the numbers of the nodes from the debug output of a tree
+fn without_ids(debug: &str) -> String {
+    let mut parts = debug.split("node: Some(");
+    let mut result = parts.next().unwrap().to_string();
+    for part in parts {
+        result.push_str("node: Some(");
+        result.push_str(part.trim_start_matches(|c: char| c.is_ascii_digit()));
+    }
+    result
+}
+
+/// Returns the id of every node in `program`
+fn ids(program: &[Function]) -> Vec<Option<NodeId>> {
+    struct Ids(Vec<Option<NodeId>>);
+    impl Visitor for Ids {
+        fn visit_statement(&mut self, statement: &Statement) {
+            self.0.push(statement.span().id());
+        }
+        fn visit_expression(&mut self, expression: &Expression) {
+            self.0.push(expression.span().id());
+        }
+    }
+    let mut ids = Ids(vec![]);
+    ast::walk_program(&mut ids, program);
+    ids.0
+}
+
+#[test]
+fn test_node_ids_are_kept_when_their_code_is_not_edited() {
+    let mut session = ParseSession::new("to one return 1\nto two return 2\n");
+    let before = session.module().unwrap().functions;
+    session.edit(&TextEdit { range: 14..15, text: "10 + 1".to_string() });
+    let after = session.module().unwrap().functions;
+    assert_eq!(ids(&after[1..]), ids(&before[1..]));
+    // The edited function's nodes are new, so none of them has an old id
+    assert!(ids(&after[..1]).iter().all(|id| id.is_some() && !ids(&before).contains(id)));
+}
+
+#[test]
+fn test_only_the_edited_function_is_parsed_again() {
+    let mut session = ParseSession::new("to one return 1\nto two do\n  return 2\nend\nto three return 3\n");
+    // A line added in the middle moves the function after it down
+    let changed = session.edit(&TextEdit { range: 26..26, text: "  variable x\n".to_string() });
+    assert_eq!(changed, 1..2);
+    assert_eq!(session.module().unwrap().functions[2].span.start.line, 6);
+    assert_parsed_like_parse_module(&session, "adding a line");
+    // Breaking a function parses the ones after it again, until they start where they did
+    let changed = session.edit(&TextEdit { range: 50..53, text: String::new() });
+    assert_eq!(changed, 1..2);
+    assert!(session.module().is_err());
+    assert_parsed_like_parse_module(&session, "removing `end`");
+}
+
+#[test]
+fn test_edits_parse_like_the_whole_file() {
+    let insertions = ["\n", " ", "x", "1", "end", "to f return 2\n", "(", ")", "/*", "*/", "\"", "do ", "+", "#!"];
+    // A simple generator, so the edits are the same every time
+    let mut seed: u64 = 2545;
+    let mut random = |below: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) as usize % below
+    };
+    for entry in fs::read_dir("examples").unwrap() {
+        let source = fs::read_to_string(entry.unwrap().path()).unwrap();
+        let mut session = ParseSession::new(&source);
+        assert_parsed_like_parse_module(&session, "no edits");
+        for _ in 0..100 {
+            let boundaries: Vec<usize> = session.source().char_indices().map(|(index, _)| index)
+                .chain(Some(session.source().len())).collect();
+            let start = boundaries[random(boundaries.len())];
+            let after = boundaries.iter().position(|&index| index == start).unwrap() + random(4);
+            let end = boundaries[after.min(boundaries.len() - 1)];
+            let text = if random(2) == 0 { insertions[random(insertions.len())].to_string() } else { String::new() };
+            let edit = TextEdit { range: start..end, text };
+            session.edit(&edit);
+            assert_parsed_like_parse_module(&session, &format!("{:?}", edit));
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Tests for `haumea::interp`
+extern crate haumea;
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::interp::{Interpreter, RuntimeError, STACK_SIZE};
+use haumea::codegen::CodeGen;
+use haumea::codegen::c::CodeGenerator;
+use haumea::build::build_executable;
+
+/// Runs `source` with `input`, returning what it displays and how it ends
+fn run(source: &str, input: &str) -> (String, Result<i32, RuntimeError>) {
+    let program = haumea::prelude::include(parse(Scanner::new(source)).unwrap());
+    let mut output = vec![];
+    let result = Interpreter::new(input.as_bytes(), &mut output).run(&program);
+    (String::from_utf8(output).unwrap(), result)
+}
+
+#[test]
+fn test_examples_run_like_the_compiled_program() {
+    // Skip the test on machines without a C compiler
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_interp");
+    for name in &["bits", "characters", "factorial", "dice", "for-each", "greeting", "hello", "hailstone", "lists", "loops", "reals", "search", "stack"] {
+        let source = fs::read_to_string(format!("examples/{}.hau", name)).unwrap();
+        let (output, status) = run(&source, "27\n");
+        let program = haumea::prelude::include(parse(Scanner::new(&source)).unwrap());
+        let code = CodeGenerator::new().compile(&program).unwrap();
+        let executable = build_executable(&code, name, &out_dir, &[]).unwrap();
+        let mut child = Command::new(&executable).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        // Programs that don't read can exit before the input is written
+        let _ = child.stdin.as_mut().unwrap().write_all(b"27\n");
+        let compiled = child.wait_with_output().unwrap();
+        assert_eq!(output, String::from_utf8(compiled.stdout).unwrap(), "{} displays something else", name);
+        assert_eq!(status.ok(), compiled.status.code(), "{} exits with something else", name);
+    }
+}
+
+#[test]
+fn test_untyped_parameters_run_like_the_compiled_program() {
+    // Skip the test on machines without a C compiler
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    // The parameters' types come from the calls, so C knows they hold text
+    let source = "to size with (s) return length of s
+    to same with (a, b) return a = b
+    to count_down with (s, n) do
+        if n = 0 then return length of s
+        return count_down(s, n - 1)
+    end
+    to main do
+        display(size(\"hello\"), same(\"ab\", join(\"a\", \"b\")), count_down(\"abc\", 3))
+    end";
+    let (output, status) = run(source, "");
+    assert_eq!((output.as_str(), status), ("5 1 3\n", Ok(0)));
+    let program = haumea::prelude::include(parse(Scanner::new(source)).unwrap());
+    let code = CodeGenerator::new().compile(&program).unwrap();
+    let out_dir = env::temp_dir().join("haumea_test_interp");
+    let executable = build_executable(&code, "untyped_parameters", &out_dir, &[]).unwrap();
+    let compiled = Command::new(&executable).output().unwrap();
+    assert_eq!(String::from_utf8(compiled.stdout).unwrap(), output);
+}
+
+#[test]
+fn test_statements() {
+    let (output, status) = run("to main do
+        variable total
+        for each i in 10 through 1 by -3 change total by i
+        display(total)
+        do variable total set total to 1 end
+        while total > 5 set total to total / 2
+        display(total)
+        if 0 or total = 5 then display_text(\"or\") else display_text(\"neither\")
+        forever return total * 100
+    end", "");
+    assert_eq!(output, "22\n5\nor\n");
+    // The exit status wraps like the compiled program's
+    assert_eq!(status, Ok(244));
+}
+
+#[test]
+fn test_for_each_counts_towards_its_end_whatever_the_step() {
+    let (output, _) = run("to main do
+        for each i in 1 to 5 by 2 display_no_newline(i)
+        for each i in 1 to 5 by -2 display_no_newline(i)
+        for each i in 1 through 5 by 2 display_no_newline(i)
+        for each i in 1 through 5 by -2 display_no_newline(i)
+        display_text(\"\")
+        for each i in 5 to 1 by 2 display_no_newline(i)
+        for each i in 5 to 1 by -2 display_no_newline(i)
+        for each i in 5 through 1 by 2 display_no_newline(i)
+        for each i in 5 through 1 by -2 display_no_newline(i)
+        display_text(\"\")
+        for each i in 3 to 3 by 2 display_no_newline(i)
+        for each i in 3 to 3 by -2 display_no_newline(i)
+        for each i in 3 through 3 by 2 display_no_newline(i)
+        for each i in 3 through 3 by -2 display_no_newline(i)
+        display_text(\"\")
+    end", "");
+    assert_eq!(output, "1313135135\n5353531531\n33\n");
+}
+
+#[test]
+fn test_display_several_numbers() {
+    let (output, _) = run("to main do
+        display(1, 2, 3)
+        display_no_newline(4, 5)
+        display_text(\" and\")
+        display_no_newline(6)
+    end", "");
+    assert_eq!(output, "1 2 3\n4 5 and\n6");
+}
+
+#[test]
+fn test_prefix_operators_bind_tighter_than_binary_ones() {
+    let (output, _) = run("to three return 3
+    to main do
+        variable xs
+        set xs to [4, 5]
+        display(-5 + 3, -2 * 3 + 1, 2 - -3, -2 * -3, -xs[1] + 1, -three() * 2)
+        display(-(5 + 3), -5 modulo 3, 10 - 4 - 3, not 0 + 1, ~1 + 1, -1 < 0)
+    end", "");
+    assert_eq!(output, "-2 -5 5 6 -4 -6\n-8 -2 3 2 -1 1\n");
+}
+
+#[test]
+fn test_reals() {
+    let (output, _) = run("to main do
+        variable x
+        set x to 0.1
+        display_real(x)
+        display_real(-2.50)
+        display_real(x + 0.2)
+        display_real(-x / 0.0)
+        display(x < 0.2, x = 0.1, x * 10.0 != 1.0)
+        display_real(1.5 + 1)
+    end", "");
+    // Reals are IEEE doubles, and mixing them with Integers stops the program
+    assert_eq!(output, "0.1\n-2.5\n0.30000000000000004\n-inf\n1 1 0\n");
+}
+
+#[test]
+fn test_text() {
+    let (output, status) = run("to main do
+        variable name
+        set name to join(\"h\", \"é\", \"llo\")
+        display(name, length of name, name = \"héllo\", name != \"hello\")
+        display_text(join(name, \"!\"))
+        return length of \"\"
+    end", "");
+    assert_eq!(output, "héllo 5 1 1\nhéllo!\n");
+    assert_eq!(status, Ok(0));
+}
+
+#[test]
+fn test_random_numbers_repeat_with_a_seed() {
+    let source = "to main do
+        seed_random(2549)
+        display(random_between(1, 6), random_between(1, 6), random_between(1, 6))
+        seed_random(2549)
+        display(random_between(6, 1), random_between(-1000000, 1000000))
+        for each i in 0 to 100 do
+            variable n
+            set n to random_between(-2, 2)
+            if n < -2 or n > 2 then display(n)
+        end
+    end";
+    assert_eq!(run(source, "").0, "2 1 4\n2 -49972\n");
+}
+
+#[test]
+fn test_list_builtins() {
+    let source = "to main do
+        variable xs
+        set xs to [1, 2]
+        append(xs, 3)
+        set_item(xs, 0, item_at(xs, 2))
+        display(length_of(xs), remove_last(xs), length_of(xs), item_at(xs, 0))
+    end";
+    assert_eq!(run(source, "").0, "3 3 2 3\n");
+}
+
+#[test]
+fn test_conversions() {
+    let source = r#"to main do
+        display(character_code("a"), character_code("€"), character_from_code(97), character_from_code(8364))
+        display(number_from_text("-42"), number_from_text("007"), join(text_from_number(-3), "!"))
+    end"#;
+    assert_eq!(run(source, "").0, "97 8364 a €\n-42 7 -3!\n");
+}
+
+#[test]
+fn test_commands_are_only_run_when_allowed() {
+    let program = parse(Scanner::new("to main return run_command(\"exit 3\")")).unwrap();
+    let result = Interpreter::new(&b""[..], vec![]).run(&program);
+    assert_eq!(result.unwrap_err().to_string(), "commands aren't allowed in main, at `run_command(...)` on line 1");
+    if cfg!(unix) {
+        assert_eq!(Interpreter::new(&b""[..], vec![]).allow_commands().run(&program), Ok(3));
+    }
+}
+
+#[test]
+fn test_operators_lists_and_text() {
+    let (output, status) = run("to main do
+        display(10 - 4 - 3, -5 + 3, 7 / -2, -7 modulo 3, 1 << 3, -16 >> 2, 5 ^ 3, 6 & 3, 6 | 1, ~5, not 0)
+        variable xs
+        set xs to [1, 2, 3]
+        push(xs, 4)
+        display(length of xs, xs[3])
+        display_text(\"he said \\\"hi\\\"\\n\\tok\")
+        display(join(\"a\", \"b\", \"c\"), length of join(\"ab\", \"cd\"))
+        display_real(2.50)
+        display_real(-0.5)
+        display(\"x\" = \"x\", \"x\" != \"y\")
+        return 300
+    end", "");
+    assert_eq!(output, "3 -2 -3 -1 8 -4 6 2 7 -6 1\n4 4\nhe said \"hi\"\n\tok\nabc 4\n2.5\n-0.5\n1 1\n");
+    assert_eq!(status, Ok(44));
+}
+
+#[test]
+fn test_read() {
+    assert_eq!(run("to main do display(read() + read()) display(read()) end", "4 5\nnope\n"),
+               ("Enter an integer: Enter an integer: 9\nEnter an integer: 0\n".to_string(), Ok(0)));
+}
+
+#[test]
+fn test_runtime_errors() {
+    let error = |source: &str| run(source, "").1.unwrap_err().to_string();
+    assert_eq!(error("to main do variable zero return 1 / zero end"), "division by zero in main, at `1 / zero` on line 1");
+    assert_eq!(error("to main for each i in 1 to 5 by 0 display(i)"),
+               "a loop that counts by zero in main, at `for each i in 1 to 5 by 0` on line 1");
+    assert!(error("to main return 9223372036854775807 + 1").starts_with("a number too large"));
+    assert_eq!(error("to main display(0.5)"), "text and numbers are mixed up in main, at `display(...)` on line 1");
+    assert_eq!(error("to main return \"one\" = 1"), "text and numbers are mixed up in main, at `\"one\" = 1` on line 1");
+    assert_eq!(error("to main return [1, 2][-1]"), "the index -1 is outside a list of length 2 in main, at `[1, 2][-1]` on line 1");
+    assert_eq!(error("to main return item_at([1, 2], 2)"),
+               "the index 2 is outside a list of length 2 in main, at `item_at(...)` on line 1");
+    assert_eq!(error("to main set_item([], 0, 1)"), "the index 0 is outside a list of length 0 in main, at `set_item(...)` on line 1");
+    assert_eq!(error("to main return remove_last([])"), "the list is empty in main, at `remove_last(...)` on line 1");
+    assert_eq!(error("to main return character_code(\"ab\")"),
+               "the text is not one character in main, at `character_code(...)` on line 1");
+    assert_eq!(error("to main display_text(character_from_code(-1))"),
+               "the number is not the code of a character in main, at `character_from_code(...)` on line 1");
+    assert_eq!(error("to main return number_from_text(\"1.5\")"),
+               "the text is not a whole number in main, at `number_from_text(...)` on line 1");
+    assert!(error("to main return number_from_text(\"9223372036854775808\")").starts_with("a number too large"));
+    // `and` doesn't work out its right side when its left side is false
+    assert_eq!(run("to main return 0 and 1 / 0", "").1, Ok(0));
+}
+
+#[test]
+fn test_endless_recursion_is_stopped() {
+    let result = thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+        run("to forever_down with (n) return forever_down(n - 1)
+        to main return forever_down(0)", "").1
+    }).unwrap().join().unwrap();
+    assert_eq!(result, Err(RuntimeError::TooDeep("forever_down".into())));
+}
+
+#[test]
+fn test_recursion_inside_expressions_is_stopped() {
+    // A call from inside an expression takes more stack than a bare `return`
+    // of one, so these run out of stack before running out of calls
+    let result = thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+        run("to down with (n) do if n = 0 then return 0 return 1 + down(n - 1) * (1 + 0) end
+        to main return down(1000000)", "").1
+    }).unwrap().join().unwrap();
+    assert_eq!(result, Err(RuntimeError::TooDeep("down".into())));
+    let result = thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+        run("to down with (n) do if n = 0 then return 0 return 1 + down(n - 1) end
+        to main do display(down(9000)) end", "")
+    }).unwrap().join().unwrap();
+    match result {
+        (output, Ok(0)) => assert_eq!(output, "9000\n"),
+        (_, result) => assert_eq!(result, Err(RuntimeError::TooDeep("down".into()))),
+    }
+}
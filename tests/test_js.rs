@@ -0,0 +1,111 @@
+//! Tests for `haumea::codegen::js`
+extern crate haumea;
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::interp::Interpreter;
+use haumea::codegen::{CodeGen, Options};
+use haumea::codegen::js::CodeGenerator;
+
+fn compile_with(source: &str, options: Options) -> String {
+    CodeGenerator::with_options(options).compile(&parse(Scanner::new(source)).unwrap()).unwrap()
+}
+
+#[test]
+fn test_numbers_are_bigints() {
+    let out = compile_with("to main do variable n set n to 9223372036854775807 change n by 1 display(n = 0) end",
+                           Options::default());
+    assert!(out.contains("let n = 0n;"));
+    assert!(out.contains("n = 9223372036854775807n;"));
+    assert!(out.contains("n = BigInt.asIntN(64, n + 1n);"));
+    assert!(out.contains("display(haumea_bool(n === 0n));"));
+    assert!(out.contains("function display(...numbers) {"));
+    assert!(out.contains("export function run(input = () => 0, command = null, output = null) {"));
+}
+
+#[test]
+fn test_commands_need_a_command_option() {
+    let out = compile_with("to main return run_command(\"ls\")", Options::default());
+    assert!(out.contains("return run_command(\"ls\", \"main\", \"run_command(...)\", 1);"));
+    assert!(out.contains("if (haumea_command === null) {"));
+}
+
+#[test]
+fn test_reserved_words_are_renamed() {
+    let out = compile_with("public to new with (this) return this to main return new(1)", Options::default());
+    assert!(out.contains("export function new_(this_1) {"));
+    assert!(out.contains("return this_1;"));
+    assert!(out.contains("return new_(1n);"));
+    assert!(out.contains("\nfunction main() {"));
+}
+
+#[test]
+fn test_runtime_checks() {
+    let options = Options { runtime_checks: true, ..Options::default() };
+    let out = compile_with("to main do variable n set n to 1 / n end", options);
+    assert!(out.contains("n = BigInt.asIntN(64, 1n / haumea_divisor(n, \"main\", \"1 / n\", 1));"));
+}
+
+#[test]
+fn test_reals_are_numbers() {
+    // They divide like C doubles, and don't wrap around or get checked
+    let options = Options { runtime_checks: true, checked_arithmetic: true, ..Options::default() };
+    let out = compile_with("to main display_real(-1.5 * 2.0 / 0.0)", options);
+    assert!(out.contains("display_real(((-1.5 * 2.0) / 0.0));"), "{}", out);
+}
+
+#[test]
+fn test_arithmetic_wraps_around_like_c() {
+    // Skip the test on machines without node
+    if Command::new("node").arg("--version").output().is_err() {
+        return;
+    }
+    let source = "to main do
+        variable n
+        set n to 9223372036854775807
+        change n by 1
+        display(9223372036854775807 + 1, -n, 4611686018427387904 * 2, n - 1, n)
+    end";
+    let out_dir = env::temp_dir().join("haumea_test_js");
+    fs::create_dir_all(&out_dir).unwrap();
+    let module = out_dir.join("wraps.mjs");
+    let script = format!("import {{ run }} from {:?}; process.exitCode = run();", module.display().to_string());
+    fs::write(&module, compile_with(source, Options::default())).unwrap();
+    let output = Command::new("node").args(["--input-type=module", "-e", &script]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               "-9223372036854775808 -9223372036854775808 -9223372036854775808 9223372036854775807 -9223372036854775808\n");
+    // Unless arithmetic is checked, which stops the program instead
+    let options = Options { checked_arithmetic: true, ..Options::default() };
+    fs::write(&module, compile_with("to main return 9223372036854775807 + 1", options)).unwrap();
+    let output = Command::new("node").args(["--input-type=module", "-e", &script]).output().unwrap();
+    assert_ne!(output.status.code(), Some(0));
+    assert!(String::from_utf8(output.stderr).unwrap()
+            .contains("a number too large for a machine integer in main, at `9223372036854775807 + 1` on line 1"));
+}
+
+#[test]
+fn test_examples_run_like_the_interpreter() {
+    // Skip the test on machines without node
+    if Command::new("node").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_js");
+    fs::create_dir_all(&out_dir).unwrap();
+    for name in &["beer", "bits", "characters", "factorial", "dice", "for-each", "greeting", "hello", "hailstone", "lists", "loops", "reals", "search", "stack"] {
+        let source = fs::read_to_string(format!("examples/{}.hau", name)).unwrap();
+        let program = haumea::prelude::include(parse(Scanner::new(&source)).unwrap());
+        let mut output = vec![];
+        let status = Interpreter::new(&b"27\n"[..], &mut output).run(&program);
+        let module = out_dir.join(format!("{}.mjs", name));
+        fs::write(&module, CodeGenerator::new().compile(&program).unwrap()).unwrap();
+        let script = format!("import {{ run }} from {:?}; process.exitCode = run(() => 27);", module.display().to_string());
+        let compiled = Command::new("node").args(["--input-type=module", "-e", &script]).output().unwrap();
+        let output = String::from_utf8(output).unwrap().replace("Enter an integer: ", "");
+        assert_eq!(output, String::from_utf8(compiled.stdout).unwrap(), "{} displays something else", name);
+        assert_eq!(status.ok(), compiled.status.code(), "{} exits with something else", name);
+    }
+}
@@ -0,0 +1,325 @@
+//! Tests for `haumea::loader`
+extern crate haumea;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use haumea::loader::{self, Loader, LoadError};
+use haumea::ast::{self, Expression, Statement, Visitor};
+use haumea::parser::{Directive, FileId};
+
+/// Creates a fresh directory containing the given files
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("haumea_test_loader_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    for &(path, source) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+fn names(dir: &Path, main: &str) -> Vec<String> {
+    loader::load(&dir.join(main)).unwrap().into_iter().map(|func| func.name.to_string()).collect()
+}
+
+#[test]
+fn test_used_files_come_first() {
+    let dir = project("order", &[
+        ("main.hau", "use \"lib/helpers\" to main return square(2)"),
+        ("lib/helpers.hau", "use shared public to square with (n) return n * n"),
+        ("lib/shared.hau", "to shared return 1"),
+    ]);
+    assert_eq!(names(&dir, "main.hau"), vec!["shared", "square", "main"]);
+}
+
+#[test]
+fn test_files_are_loaded_once() {
+    let dir = project("once", &[
+        ("main.hau", "use a use b to main return 0"),
+        ("a.hau", "use common to a return 1"),
+        ("b.hau", "use common to b return 2"),
+        ("common.hau", "to common return 3"),
+    ]);
+    assert_eq!(names(&dir, "main.hau"), vec!["common", "a", "b", "main"]);
+}
+
+#[test]
+fn test_project_root_is_searched() {
+    let dir = project("root", &[
+        ("lib/inner.hau", "use outer to inner return 1"),
+        ("outer.hau", "to outer return 2"),
+    ]);
+    let mut loader = Loader::new(dir.clone());
+    loader.load_source("use \"lib/inner\" to main return 0", None).unwrap();
+    let names: Vec<String> = loader.finish().unwrap().into_iter().map(|func| func.name.to_string()).collect();
+    assert_eq!(names, vec!["outer", "inner", "main"]);
+}
+
+#[test]
+fn test_spans_know_their_file() {
+    let dir = project("spans", &[
+        ("main.hau", "use helpers to main return square(2)"),
+        ("helpers.hau", "public to square with (n) return n * n"),
+    ]);
+    let mut loader = Loader::new(dir.clone());
+    loader.load_file(&dir.join("main.hau")).unwrap();
+    let files = loader.files();
+    let program = loader.finish().unwrap();
+    let file_of = |func: &ast::Function| {
+        files[func.span.file.index()].as_ref().map(|path| path.file_name().unwrap().to_owned())
+    };
+    assert_eq!(file_of(&program[0]), Some("helpers.hau".into()));
+    assert_eq!(file_of(&program[1]), Some("main.hau".into()));
+    assert_ne!(program[0].span.file, program[1].span.file);
+    for func in &program {
+        let mut spans = Spans(vec![]);
+        ast::walk_function(&mut spans, func);
+        assert!(spans.0.iter().all(|&file| file == func.span.file));
+    }
+    let mut loader = Loader::new(dir.clone());
+    loader.load_source("use helpers to main return square(2)", None).unwrap();
+    let files = loader.files();
+    let program = loader.finish().unwrap();
+    assert_eq!(files[program[1].span.file.index()], None);
+}
+
+/// Collects the files of the spans of each statement and expression
+struct Spans(Vec<FileId>);
+
+impl Visitor for Spans {
+    fn visit_statement(&mut self, statement: &Statement) {
+        self.0.push(statement.span().file);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        self.0.push(expression.span().file);
+    }
+}
+
+#[test]
+fn test_cycles_are_detected() {
+    let dir = project("cycle", &[
+        ("main.hau", "use a to main return 0"),
+        ("a.hau", "use b to a return 1"),
+        ("b.hau", "use a to b return 2"),
+    ]);
+    match loader::load(&dir.join("main.hau")) {
+        Err(LoadError::Cycle(chain)) => {
+            let files: Vec<_> = chain.iter().map(|path| path.file_name().unwrap().to_owned()).collect();
+            assert_eq!(files, vec!["a.hau", "b.hau", "a.hau"]);
+        },
+        other => panic!("expected a cycle, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_missing_files_are_reported() {
+    let dir = project("missing", &[("main.hau", "use nowhere to main return 0")]);
+    match loader::load(&dir.join("main.hau")) {
+        Err(LoadError::NotFound { ref name, .. }) => assert_eq!(name, "nowhere"),
+        other => panic!("expected a missing file, found {:?}", other),
+    }
+}
+
+/// Collects the names of the functions called by each function
+struct Calls(Vec<String>);
+
+impl Visitor for Calls {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Call { ref function, .. } = *statement {
+            self.0.push(function.to_string());
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::Call { ref function, .. } = *expression {
+            self.0.push(function.to_string());
+        }
+    }
+}
+
+fn load_calls(dir: &Path) -> Result<Vec<(String, Vec<String>)>, LoadError> {
+    let program = loader::load(&dir.join("main.hau"))?;
+    Ok(program.into_iter().map(|func| {
+        let mut calls = Calls(vec![]);
+        ast::walk_function(&mut calls, &func);
+        (func.name.to_string(), calls.0)
+    }).collect())
+}
+
+#[test]
+fn test_qualified_calls() {
+    let dir = project("qualified", &[
+        ("main.hau", "use mathlib use \"lib/textlib\" as t
+        to helper return 0
+        to main do
+            display(mathlib.helper(1))
+            display(t.helper(2))
+            display(helper())
+            display(only_in_text())
+        end"),
+        ("mathlib.hau", "public to helper with (n) return n"),
+        ("lib/textlib.hau", "public to helper with (n) return n public to only_in_text return helper(3)"),
+    ]);
+    let calls = load_calls(&dir).unwrap();
+    assert_eq!(calls, vec![
+        ("mathlib__helper".to_string(), vec![]),
+        ("textlib__helper".to_string(), vec![]),
+        ("only_in_text".to_string(), vec!["textlib__helper".to_string()]),
+        ("helper".to_string(), vec![]),
+        ("main".to_string(), vec!["display", "mathlib__helper", "display", "textlib__helper",
+                                  "display", "helper", "display", "only_in_text"]
+                                 .into_iter().map(String::from).collect()),
+    ]);
+}
+
+#[test]
+fn test_ambiguous_calls_are_reported() {
+    let dir = project("ambiguous", &[
+        ("main.hau", "use a use b as bee to main return helper()"),
+        ("a.hau", "public to helper return 1"),
+        ("b.hau", "public to helper return 2"),
+    ]);
+    match load_calls(&dir) {
+        Err(LoadError::Ambiguous { ref name, ref namespaces, .. }) => {
+            assert_eq!(name, "helper");
+            assert_eq!(namespaces, &vec!["a".to_string(), "bee".to_string()]);
+        },
+        other => panic!("expected an ambiguous call, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_unknown_qualified_names_are_reported() {
+    let dir = project("unknown_namespace", &[
+        ("main.hau", "use a to main return b.helper()"),
+        ("a.hau", "to helper return 1"),
+    ]);
+    match load_calls(&dir) {
+        Err(LoadError::UnknownNamespace { ref name, .. }) => assert_eq!(name, "b"),
+        other => panic!("expected an unknown namespace, found {:?}", other),
+    }
+    let dir = project("unknown_function", &[
+        ("main.hau", "use a to main return a.missing()"),
+        ("a.hau", "to helper return 1"),
+    ]);
+    match load_calls(&dir) {
+        Err(LoadError::UnknownFunction { ref name, .. }) => assert_eq!(name, "a.missing"),
+        other => panic!("expected an unknown function, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_private_functions_cannot_be_used() {
+    let dir = project("private_qualified", &[
+        ("main.hau", "use a to main return a.helper()"),
+        ("a.hau", "to helper return 1"),
+    ]);
+    match load_calls(&dir) {
+        Err(LoadError::Private { ref name, .. }) => assert_eq!(name, "a.helper"),
+        other => panic!("expected a private function, found {:?}", other),
+    }
+    let dir = project("private_unqualified", &[
+        ("main.hau", "use a to main return helper()"),
+        ("a.hau", "private to helper return 1"),
+    ]);
+    match load_calls(&dir) {
+        Err(LoadError::Private { ref name, .. }) => assert_eq!(name, "helper"),
+        other => panic!("expected a private function, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_private_functions_are_skipped_when_another_is_public() {
+    let dir = project("private_skipped", &[
+        ("main.hau", "use a use b to main return helper()"),
+        ("a.hau", "to helper return 1"),
+        ("b.hau", "public to helper return 2"),
+    ]);
+    let calls = load_calls(&dir).unwrap();
+    assert_eq!(calls.last().unwrap().1, vec!["b__helper".to_string()]);
+}
+
+#[test]
+fn test_external_functions_are_not_renamed() {
+    let dir = project("external", &[
+        ("main.hau", "use a use b external to tick to main return tick()"),
+        ("a.hau", "external to tick public to helper return tick()"),
+        ("b.hau", "external to tick public to other return tick()"),
+    ]);
+    assert_eq!(names(&dir, "main.hau"), vec!["tick", "helper", "other", "main"]);
+}
+
+#[test]
+fn test_directives_are_collected() {
+    let dir = project("directives", &[
+        ("main.hau", "use a link with \"m\" to main return 0"),
+        ("a.hau", "link with \"m\" include header \"math.h\" public to helper return 1"),
+    ]);
+    let mut loader = Loader::new(dir.clone());
+    loader.load_file(&dir.join("main.hau")).unwrap();
+    assert_eq!(loader.directives(), vec![
+        Directive::Link("m".to_string()),
+        Directive::Include("math.h".to_string()),
+    ]);
+}
+
+#[test]
+fn test_embedded_files_are_read() {
+    let dir = project("embed", &[
+        ("main.hau", "use \"lib/a\" to main return 0"),
+        ("lib/a.hau", "embed \"data/table.txt\" as table public to helper return table(0)"),
+        ("lib/data/table.txt", "AB"),
+    ]);
+    let mut loader = Loader::new(dir.clone());
+    loader.load_file(&dir.join("main.hau")).unwrap();
+    assert_eq!(loader.directives(), vec![Directive::Embed {
+        path: "data/table.txt".to_string(),
+        name: "table".into(),
+        bytes: vec![65, 66],
+    }]);
+    let dir = project("embed_missing", &[("main.hau", "embed \"nothing.txt\" as data to main return 0")]);
+    match Loader::new(dir.clone()).load_file(&dir.join("main.hau")) {
+        Err(LoadError::NotFound { ref name, .. }) => assert_eq!(name, "nothing.txt"),
+        other => panic!("expected a missing file, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_many_files_are_loaded_in_order() {
+    let letters: Vec<String> = (b'a'..=b'z').map(|c| format!("f{}", c as char)).collect();
+    let mut files: Vec<(String, String)> = letters.windows(2)
+        .map(|pair| (format!("{}.hau", pair[0]), format!("use {} public to {} return 1", pair[1], pair[0])))
+        .collect();
+    files.push(("fz.hau".to_string(), "public to fz return 0".to_string()));
+    let uses: Vec<String> = letters.iter().map(|name| format!("use {}", name)).collect();
+    files.push(("main.hau".to_string(), format!("{} to main return 0", uses.join(" "))));
+    let files: Vec<(&str, &str)> = files.iter().map(|(path, source)| (&path[..], &source[..])).collect();
+    let dir = project("many", &files);
+    let mut expected: Vec<String> = letters.iter().rev().cloned().collect();
+    expected.push("main".to_string());
+    assert_eq!(names(&dir, "main.hau"), expected);
+}
+
+#[test]
+fn test_the_first_invalid_file_is_reported() {
+    let dir = project("invalid", &[
+        ("main.hau", "use a use b to main return 0"),
+        ("a.hau", "use c to a return 1"),
+        ("b.hau", "to b return +"),
+        ("c.hau", "to c return"),
+    ]);
+    for _ in 0..10 {
+        match loader::load(&dir.join("main.hau")) {
+            Err(LoadError::Parse { path, errors }) => {
+                assert!(path.unwrap().ends_with("c.hau"));
+                assert_eq!(errors[0].to_string(),
+                           "At line 1:6, unexpected end of input in the 'return' statement, expected an expression!");
+            },
+            other => panic!("expected c.hau to be reported, but got {:?}", other),
+        }
+    }
+}
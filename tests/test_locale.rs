@@ -0,0 +1,39 @@
+//! Tests for `haumea::locale`
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::locale::Locale;
+
+#[test]
+fn test_programs_in_spanish() {
+    let spanish = Locale::builtin("es").unwrap();
+    let program = parse(Scanner::with_locale("funcion main hacer
+        variable total
+        poner total hasta 0
+        para cada i en 1 hasta_incluido 10 por 2 hacer
+            si i modulo 3 = 0 y i < 9 o i = 1 entonces cambiar total por i
+            sino mientras total > 100 cambiar total por -1
+        fin
+        devolver total
+    fin", &spanish)).unwrap();
+    assert_eq!(program, parse(Scanner::new("to main do
+        variable total
+        set total to 0
+        for each i in 1 through 10 by 2 do
+            if i modulo 3 = 0 and i < 9 or i = 1 then change total by i
+            else while total > 100 change total by -1
+        end
+        return total
+    end")).unwrap());
+}
+
+#[test]
+fn test_keyword_map_errors() {
+    let error = Locale::parse("si = if\n\nsi = while").unwrap_err();
+    assert_eq!(error.to_string(), "at line 3 of the keyword map, `si` is given more than once");
+    let error = Locale::parse("end = fin").unwrap_err();
+    assert_eq!(error.to_string(), "at line 1 of the keyword map, `end` is already an English keyword");
+    assert!(Locale::parse("mientras while").is_err());
+    assert!(Locale::parse("después = after").is_err());
+}
@@ -1,7 +1,7 @@
 //! Tests for `haumea::parser`
 extern crate haumea;
 
-use std::rc::Rc;
+use std::sync::Arc;
 
 use haumea::scanner::*;
 use haumea::parser::*;
@@ -10,11 +10,16 @@ use haumea::parser::Operator::*;
 use haumea::parser::Expression::*;
 
 fn assert_parsed_is(source: &str, expected: Vec<Function>) {
-    let scanner = Scanner::new(&source);
-    let ast: Vec<Function> = parse(scanner);
+    let scanner = Scanner::new(source);
+    let ast: Vec<Function> = parse(scanner).unwrap();
     assert_eq!(ast, expected);
 }
 
+/// Returns the messages of the errors parsing `source` fails with
+fn errors(source: &str) -> Vec<String> {
+    parse(Scanner::new(source)).unwrap_err().iter().map(|error| error.to_string()).collect()
+}
+
 #[test]
 fn test_display_addition() {
     let hello_world_code = "to main do
@@ -23,22 +28,326 @@ fn test_display_addition() {
 
     let expected_ast = vec![
         Function {
-            name: "main".to_string(),
+            name: "main".into(),
+            visibility: Visibility::Private,
+            external: None,
             signature: None,
+            types: Types::default(),
             code: Do(vec![
-                Rc::new(Statement::Call {
-                    function: "display".to_string(),
+                Arc::new(Statement::Call {
+                    function: "display".into(),
                     arguments: vec![
                         BinaryOp {
                             operator: Add,
-                            left: Rc::new(Integer(1)),
-                            right: Rc::new(Integer(2))
+                            left: Arc::new(Integer(1, Span::default())),
+                            right: Arc::new(Integer(2, Span::default())),
+                            span: Span::default(),
                         }
-                    ]
+                    ],
+                    span: Span::default(),
                 })
-            ])
+            ], Span::default()),
+            span: Span::default(),
         }
     ];
 
-    assert_parsed_is(&hello_world_code, expected_ast);
+    assert_parsed_is(hello_world_code, expected_ast);
+}
+
+#[test]
+fn test_use_declarations() {
+    let module = parse_module(Scanner::new("use \"lib/helpers\" use shared
+    to main return 0")).unwrap();
+    assert_eq!(module.uses, vec![Use { path: "lib/helpers".to_string(), alias: None },
+                                 Use { path: "shared".to_string(), alias: None }]);
+    assert_eq!(module.functions.len(), 1);
+    assert_eq!(module.functions[0].name, "main");
+}
+
+#[test]
+fn test_visibility() {
+    let program = parse(Scanner::new("public to a return 1 private to b return 2 to c return 3")).unwrap();
+    let visibilities: Vec<Visibility> = program.iter().map(|func| func.visibility).collect();
+    assert_eq!(visibilities, vec![Visibility::Public, Visibility::Private, Visibility::Private]);
+}
+
+#[test]
+fn test_external_functions() {
+    let program = parse(Scanner::new("external to sqrt with (x) from \"math.h\"
+    public external to tick
+    to main return sqrt(4)")).unwrap();
+    assert_eq!(program[0].external, Some(External { header: Some("math.h".to_string()) }));
+    assert_eq!(program[0].signature, Some(vec!["x".into()]));
+    assert_eq!(program[1].external, Some(External { header: None }));
+    assert_eq!(program[1].visibility, Visibility::Public);
+    assert_eq!(program[2].external, None);
+}
+
+#[test]
+fn test_typed_signatures() {
+    let program = parse(Scanner::new("to add with (a: Integer, b) returns Integer return a + b
+    to main with (n) return add(n, 1)")).unwrap();
+    assert_eq!(program[0].signature, Some(vec!["a".into(), "b".into()]));
+    assert_eq!(program[0].types, Types { parameters: vec![Some(Type::Integer), None], returns: Some(Type::Integer) });
+    assert_eq!(program[1].types, Types::default());
+    assert_eq!(parse(Scanner::new(&haumea::ast::to_source(&program))).unwrap(), program);
+    assert_eq!(errors("to f with (a: Number) return a"),
+               ["At line 1:15, expected a type, like 'Integer', 'Text' or 'List', but found the name 'Number'!"]);
+}
+
+#[test]
+fn test_directives() {
+    let module = parse_module(Scanner::new("link with \"m\"
+    include header \"math.h\"
+    to main return 0")).unwrap();
+    assert_eq!(module.directives, vec![
+        Directive::Link("m".to_string()),
+        Directive::Include("math.h".to_string()),
+    ]);
+    assert_eq!(module.functions.len(), 1);
+}
+
+#[test]
+fn test_errors_describe_tokens() {
+    assert_eq!(errors("to main for x in 1 to 2 return x"), ["At line 1:13, expected the keyword 'each', but found the name 'x'!"]);
+}
+
+#[test]
+fn test_errors_at_the_end_of_the_file() {
+    assert_eq!(errors("to main return f(1"), ["At line 1:16, unexpected end of input in the call to 'f', expected ','!"]);
+}
+
+#[test]
+fn test_unfinished_blocks_are_reported_where_they_start() {
+    assert_eq!(errors("to main do\n    do\n        display(1)\n    end"), ["At line 1:9, unexpected end of input in the 'do' block, expected a statement or the keyword 'end'!"]);
+}
+
+#[test]
+fn test_keywords_are_not_names() {
+    assert_eq!(errors("to main do variable end return 0 end"), ["At line 1:21, 'end' is a reserved word and cannot be used as a name!"]);
+}
+
+#[test]
+fn test_only_calls_are_qualified() {
+    assert_eq!(errors("to main return mathlib.pi"), ["At line 1:16, expected a name without a '.', but found the name 'mathlib.pi'!"]);
+}
+
+#[test]
+fn test_comparisons_are_not_chained() {
+    assert_eq!(errors("to main with (x) return 1 < x + 1 <= 3"),
+               ["At line 1:35, comparisons can't be chained, write `1 < x + 1 and x + 1 <= 3` instead of `1 < x + 1 <= 3`!"]);
+    assert_eq!(errors("to main with (x) return x = 1 = 0"),
+               ["At line 1:31, comparisons can't be chained, write `x = 1 and 1 = 0` instead of `x = 1 = 0`!"]);
+    assert!(parse(Scanner::new("to main with (x) return (1 < x) = (x < 3) and x != 2")).is_ok());
+}
+
+#[test]
+fn test_integers_that_are_too_large() {
+    let program = parse(Scanner::new("to main return 9223372036854775807")).unwrap();
+    assert_eq!(program[0].code, Statement::Return(Expression::Integer(i64::MAX, Span::default()), Span::default()));
+    assert_eq!(errors("to main return 1 + 9223372036854775808"), ["At line 1:20, the integer 9223372036854775808 is too large, integers can be at most 9223372036854775807 without --bignum!"]);
+    // The smallest integer's digits are only too large until they are negated
+    let program = parse(Scanner::new("to main return -9223372036854775808")).unwrap();
+    assert_eq!(program[0].code, Statement::Return(Expression::Integer(i64::MIN, Span::default()), Span::default()));
+    assert_eq!(errors("to main return 1 - 9223372036854775808"), ["At line 1:20, the integer 9223372036854775808 is too large, integers can be at most 9223372036854775807 without --bignum!"]);
+    assert_eq!(errors("to main return -9223372036854775809"), ["At line 1:17, the integer 9223372036854775809 is too large, integers can be at most 9223372036854775807 without --bignum!"]);
+}
+
+#[test]
+fn test_integers_of_any_size_with_big_numbers() {
+    let program = parse(Scanner::new("to main return -99999999999999999999").with_big_numbers()).unwrap();
+    let digits = Arc::new(Expression::BigInteger("99999999999999999999".to_string(), Span::default()));
+    assert_eq!(program[0].code, Statement::Return(Expression::UnaryOp { operator: Negate, expression: digits, span: Span::default() },
+                                                  Span::default()));
+}
+
+#[test]
+fn test_strings() {
+    let program = parse(Scanner::new(r#"to main display_text("tab\there")"#)).unwrap();
+    assert_eq!(program[0].code, Statement::Call {
+        function: "display_text".into(),
+        arguments: vec![Str("tab\there".to_string(), Span::default())],
+        span: Span::default(),
+    });
+    assert_eq!(errors(r#"to main display_text("50\%")"#),
+               [r#"At line 1:25, '\%' is not an escape, strings can only use \n, \t, \" and \\!"#]);
+}
+
+#[test]
+fn test_calls_without_arguments() {
+    let program = parse(Scanner::new("to main do greet() end")).unwrap();
+    assert_eq!(program[0].code, Statement::Do(vec![Arc::new(Statement::Call {
+        function: "greet".into(),
+        arguments: vec![],
+        span: Span::default(),
+    })], Span::default()));
+}
+
+#[test]
+fn test_loop_labels() {
+    use haumea::ast::{expr, stmt};
+    let program = parse(Scanner::new("to main while running as outer forever as inner break out of outer")).unwrap();
+    assert_eq!(program[0].code, stmt::labeled("outer", stmt::while_loop(expr::ident("running"),
+        stmt::labeled("inner", stmt::forever(stmt::break_out_of("outer"))))));
+    assert_eq!(parse(Scanner::new(&haumea::ast::to_source(&program))).unwrap(), program);
+    assert_eq!(errors("to main forever as do end"), ["At line 1:20, 'do' is a reserved word and cannot be used as a name!"]);
+}
+
+#[test]
+fn test_unterminated_comments() {
+    assert_eq!(errors("to main do return 1 /* /* */ end"), ["At line 1:21, the comment that starts here is never closed!"]);
+}
+
+#[test]
+fn test_deeply_nested_code_is_parsed() {
+    let depth = MAX_DEPTH / 2 - 5;
+    let source = format!("to main do {} return {}1{} {} end", "do ".repeat(depth), "(".repeat(depth),
+                         ")".repeat(depth), "end ".repeat(depth));
+    assert_eq!(parse(Scanner::new(&source)).unwrap().len(), 1);
+}
+
+#[test]
+fn test_too_deeply_nested_code_is_an_error() {
+    assert_eq!(errors(&format!("to main return 1{}", " + 1".repeat(300))), ["At line 1:1036, the code is nested more than 256 levels deep!"]);
+}
+
+#[test]
+fn test_statements_outside_of_functions_are_main() {
+    let program = parse(Scanner::new("define TWO as 2
+    variable x
+    set x to double(TWO)
+    to double with (n) return n * 2
+    display(x)")).unwrap();
+    assert_eq!(program.len(), 2);
+    assert_eq!(program[1].name, "main");
+    assert_eq!(program[1].code, parse(Scanner::new("to main do
+        variable x
+        set x to double(2)
+        display(x)
+    end")).unwrap()[0].code);
+}
+
+#[test]
+fn test_statements_outside_of_functions_and_main() {
+    assert_eq!(errors("to main return 0\n    display(1)"), ["At line 2:5, this statement is outside of any function, so it is part of the function 'main', but 'main' is also defined at line 1:1!"]);
+}
+
+#[test]
+fn test_every_error_is_reported() {
+    let source = "to one return +
+to two return 2
+to three do set x 3 end
+define FOUR as 4
+to five return (1";
+    assert_eq!(errors(source), [
+        "At line 1:15, expected an expression, but found the operator '+'!",
+        "At line 3:19, expected the keyword 'to', but found the number 3!",
+        "At line 5:16, unexpected end of input in the parentheses, expected ')'!",
+    ]);
+}
+
+#[test]
+fn test_every_scan_error_is_reported() {
+    // The parser skips the rest of `main` after the `$`, but the scanner still finds the rest
+    let source = "to main do
+    display(1 $ 2)
+    display(99999999999999999999 + #)
+end
+to two return +";
+    assert_eq!(errors(source), [
+        "At line 2:15, the character '$' can't be used in Haumea code!",
+        "At line 3:13, the integer 99999999999999999999 is too large, integers can be at most 9223372036854775807 without --bignum!",
+        "At line 3:36, the character '#' can't be used in Haumea code!",
+        "At line 5:15, expected an expression, but found the operator '+'!",
+    ]);
+}
+
+#[test]
+fn test_errors_carry_where_they_are() {
+    let errors = parse(Scanner::new("to main return )")).unwrap_err();
+    assert_eq!(errors, [ParseError::Unexpected {
+        expected: "an expression".to_string(),
+        found: "')'".to_string(),
+        state: ScanState::new(1, 16),
+    }]);
+    assert_eq!(errors[0].state(), Some(ScanState::new(1, 16)));
+}
+
+#[test]
+fn test_spans() {
+    let program = parse(Scanner::new("to main do\n    display((1 + 2) * 3)\nend")).unwrap();
+    assert_eq!(program[0].span.to_string(), "1:1");
+    let span = program[0].code.span();
+    assert_eq!((span.start.line, span.start.column, span.end.line, span.end.column), (1, 9, 3, 1));
+    let call = match program[0].code {
+        Do(ref block, _) => block[0].clone(),
+        ref other => panic!("Expected a block, found {:?}", other),
+    };
+    let span = call.span();
+    assert_eq!((span.start.line, span.start.column, span.end.line, span.end.column), (2, 5, 2, 24));
+    match *call {
+        Statement::Call { ref arguments, .. } => {
+            let span = arguments[0].span();
+            assert_eq!((span.start.line, span.start.column, span.end.column), (2, 13, 23));
+        },
+        ref other => panic!("Expected a call, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_operator_precedence() {
+    let code = |source: &str| parse(Scanner::new(&format!("to main return {}", source))).unwrap()[0].code.clone();
+    for &(source, grouped) in &[
+        ("1 - 2 - 3", "(1 - 2) - 3"),
+        ("8 / 4 modulo 3 * 2", "((8 / 4) modulo 3) * 2"),
+        ("1 + 2*(3 - 4) - 5", "(1 + (2 * (3 - 4))) - 5"),
+        ("a | b & c + 1 = 1", "(a | (b & (c + 1))) = 1"),
+        ("-a + -b", "(-a) + (-b)"),
+        ("not a = b and ~c or d", "(((not a) = b) and (~c)) or d"),
+        ("a*-b--c", "(a * (-b)) - (-c)"),
+        ("-~not x", "-(~(not x))"),
+        ("a | b ^ c & d << 1 + 2 = e >> f", "(a | (b ^ (c & (d << (1 + 2))))) = (e >> f)"),
+    ] {
+        assert_eq!(code(source), code(grouped), "{} isn't grouped as {}", source, grouped);
+    }
+    let negated = UnaryOp { operator: Negate, expression: Arc::new(Ident("a".into(), Span::default())), span: Span::default() };
+    assert_eq!(code("-a"), Statement::Return(negated, Span::default()));
+    assert_eq!(errors("to main return 1 + * 2"), ["At line 1:20, expected an expression, but found the operator '*'!"]);
+}
+
+#[test]
+fn test_booleans() {
+    let program = parse(Scanner::new("to main return true or false")).unwrap();
+    assert_eq!(program[0].code, Statement::Return(BinaryOp {
+        operator: LogicalOr,
+        left: Arc::new(Boolean(true, Span::default())),
+        right: Arc::new(Boolean(false, Span::default())),
+        span: Span::default(),
+    }, Span::default()));
+    assert_eq!(errors("to main do variable true end"), ["At line 1:21, 'true' is a reserved word and cannot be used as a name!"]);
+}
+
+#[test]
+fn test_lists() {
+    use haumea::ast::expr;
+    let program = parse(Scanner::new("to main return xs[i + 1][0] * length of [1, read()]")).unwrap();
+    assert_eq!(program[0].code, Statement::Return(expr::mul(
+        expr::index(expr::index(expr::ident("xs"), expr::add(expr::ident("i"), expr::int(1))), expr::int(0)),
+        expr::length(expr::list(vec![expr::int(1), expr::call("read", vec![])])),
+    ), Span::default()));
+    assert_eq!(errors("to main return [1, 2"), ["At line 1:16, unexpected end of input in the list, expected ','!"]);
+}
+
+#[test]
+fn test_truncated_and_mangled_programs_never_panic() {
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let source = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        let boundaries: Vec<usize> = source.char_indices().map(|(index, _)| index).collect();
+        for (number, &index) in boundaries.iter().enumerate() {
+            let _ = parse_str(&source[..index]);
+            // The program without the char at `index`
+            let next = boundaries.get(number + 1).map_or(source.len(), |&next| next);
+            let _ = parse_str(&format!("{}{}", &source[..index], &source[next..]));
+        }
+    }
 }
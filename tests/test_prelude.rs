@@ -0,0 +1,38 @@
+//! Tests for `haumea::prelude`
+extern crate haumea;
+
+use haumea::scanner::*;
+use haumea::parser::*;
+use haumea::prelude;
+
+fn names(program: &Program) -> Vec<&str> {
+    program.iter().map(|func| &func.name[..]).collect()
+}
+
+#[test]
+fn test_prelude_parses() {
+    let program = prelude::parse();
+    let names = names(&program);
+    for helper in &["maximum", "minimum", "absolute", "is_even", "is_odd", "count_from"] {
+        assert!(names.contains(helper), "prelude is missing {}", helper);
+    }
+}
+
+#[test]
+fn test_prelude_comes_first() {
+    let program = prelude::include(parse(Scanner::new("to main do display(1) end")).unwrap());
+    assert_eq!(program.len(), prelude::parse().len() + 1);
+    assert_eq!(program.last().unwrap().name, "main");
+}
+
+#[test]
+fn test_user_definition_overrides_prelude() {
+    let source = "to maximum with (a, b) return a
+    to main do display(maximum(1, 2)) end";
+    let user = parse(Scanner::new(source)).unwrap();
+    let program = prelude::include(user.clone());
+    let maximums: Vec<&Function> = program.iter().filter(|func| func.name == "maximum").collect();
+    assert_eq!(maximums.len(), 1);
+    assert_eq!(*maximums[0], user[0]);
+    assert_eq!(&names(&program)[program.len() - 2..], &["maximum", "main"]);
+}
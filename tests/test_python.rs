@@ -0,0 +1,150 @@
+//! Tests for `haumea::codegen::python`
+extern crate haumea;
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::interp::Interpreter;
+use haumea::codegen::{CodeGen, Options};
+use haumea::codegen::python::CodeGenerator;
+
+fn compile_with(source: &str, options: Options) -> String {
+    CodeGenerator::with_options(options).compile(&parse(Scanner::new(source)).unwrap()).unwrap()
+}
+
+#[test]
+fn test_blocks_are_indented() {
+    let out = compile_with("to sign with (n) do
+        if n < 0 and not (n = -1 or n = -2) then return -1
+        else if n = 0 then do end
+        else return 1
+    end
+    to main do
+        variable total
+        for each i in 1 through 10 by 2 do
+            change total by i / 2
+            if total then continue
+        end
+        display(sign(total) = 1)
+    end", Options::default());
+    assert!(out.contains("
+def sign(n):
+    if n < 0 and not (n == -1 or n == -2):
+        return -1
+    elif n == 0:
+        pass
+    else:
+        return 1
+    return 0
+"));
+    assert!(out.contains("
+def main():
+    total = 0
+    for i in range(1, 11, 2):
+        total = haumea_wrap(total + haumea_wrap(haumea_div(i, 2)))
+        if total:
+            continue
+    display(int(sign(total) == 1))
+    return 0
+"));
+    assert!(out.contains("def display(*numbers):"));
+    assert!(out.ends_with("sys.exit(haumea_exit_code(main()))\n"));
+}
+
+#[test]
+fn test_loops_that_set_their_variable() {
+    let out = compile_with("to main with (n) for each i in 0 to n do
+        if i = 3 then continue
+        set i to i + 2
+    end", Options { deterministic: true, ..Options::default() });
+    assert!(out.contains("
+    __HAUMEA_TEMP_main_1 = 0
+    __HAUMEA_TEMP_main_2 = n
+    __HAUMEA_TEMP_main_3 = haumea_towards(__HAUMEA_TEMP_main_1, __HAUMEA_TEMP_main_2, 1)
+    i = __HAUMEA_TEMP_main_1
+    while haumea_in_range(i, __HAUMEA_TEMP_main_1, __HAUMEA_TEMP_main_2, False):
+        if i == 3:
+            i += __HAUMEA_TEMP_main_3
+            continue
+        i = haumea_wrap(i + 2)
+        i += __HAUMEA_TEMP_main_3
+"));
+}
+
+#[test]
+fn test_reals_are_floats() {
+    let source = "to main do
+        display_real(2.50)
+        display_real(0.0000001)
+        display_real(-3.0)
+        display_real(0.1 + 0.2 * 2.0)
+        display_real(1.0 / 0.0)
+    end";
+    let out = compile_with(source, Options::default());
+    assert!(out.contains("display_real(2.5)\n    display_real(1e-7)\n    display_real(-3.0)"), "{}", out);
+    // Arithmetic on Reals doesn't wrap around, and dividing by zero gives an infinity like in C
+    assert!(out.contains("display_real(0.1 + (0.2 * 2.0))\n    display_real(haumea_real_div(1.0, 0.0))"), "{}", out);
+    if Command::new("python3").arg("--version").output().is_err() {
+        return;
+    }
+    let script = env::temp_dir().join("haumea_test_python_reals.py");
+    fs::write(&script, out).unwrap();
+    let output = Command::new("python3").arg(&script).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2.5\n0.0000001\n-3\n0.5\ninf\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_arithmetic_wraps_around_like_c() {
+    // Skip the test on machines without python3
+    if Command::new("python3").arg("--version").output().is_err() {
+        return;
+    }
+    let source = "to main do
+        variable n
+        set n to 9223372036854775807
+        change n by 1
+        display(9223372036854775807 + 1, -n, 4611686018427387904 * 2, n - 1, n)
+    end";
+    let script = env::temp_dir().join("haumea_test_python_wraps.py");
+    fs::write(&script, compile_with(source, Options::default())).unwrap();
+    let output = Command::new("python3").arg(&script).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               "-9223372036854775808 -9223372036854775808 -9223372036854775808 9223372036854775807 -9223372036854775808\n");
+    // Unless arithmetic is checked, which stops the program instead
+    let options = Options { checked_arithmetic: true, ..Options::default() };
+    fs::write(&script, compile_with("to main return 9223372036854775807 + 1", options)).unwrap();
+    let output = Command::new("python3").arg(&script).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(),
+               "Error: a number too large for a machine integer in main, at `9223372036854775807 + 1` on line 1\n");
+}
+
+#[test]
+fn test_examples_run_like_the_interpreter() {
+    // Skip the test on machines without python3
+    if Command::new("python3").arg("--version").output().is_err() {
+        return;
+    }
+    let out_dir = env::temp_dir().join("haumea_test_python");
+    fs::create_dir_all(&out_dir).unwrap();
+    for name in &["beer", "bits", "characters", "factorial", "dice", "for-each", "greeting", "hello", "hailstone", "lists", "loops", "read", "reals", "search", "stack"] {
+        let source = fs::read_to_string(format!("examples/{}.hau", name)).unwrap();
+        let program = haumea::prelude::include(parse(Scanner::new(&source)).unwrap());
+        let mut output = vec![];
+        let status = Interpreter::new(&b"27\n"[..], &mut output).run(&program);
+        let script = out_dir.join(format!("{}.py", name));
+        fs::write(&script, CodeGenerator::new().compile(&program).unwrap()).unwrap();
+        let mut python = Command::new("python3").arg(&script).stdin(Stdio::piped()).stdout(Stdio::piped())
+            .spawn().unwrap();
+        python.stdin.take().unwrap().write_all(b"27\n").unwrap();
+        let compiled = python.wait_with_output().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), String::from_utf8(compiled.stdout).unwrap(),
+                   "{} displays something else", name);
+        assert_eq!(status.ok(), compiled.status.code(), "{} exits with something else", name);
+    }
+}
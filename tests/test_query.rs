@@ -0,0 +1,85 @@
+//! Tests for `haumea::query`
+extern crate haumea;
+
+use haumea::codegen::Options;
+use haumea::parser::parse;
+use haumea::scanner::Scanner;
+use haumea::query::Database;
+
+#[test]
+fn test_queries_are_remembered() {
+    let mut db = Database::new();
+    db.set_source("a", "to main return 1");
+    db.set_source("b", "to main return 2");
+    let a = db.c_code("a");
+    db.c_code("b");
+    assert_eq!(db.c_code("a"), a);
+    assert_eq!((db.runs("tokens"), db.runs("module"), db.runs("c_code")), (2, 2, 2));
+    // Changing one file only runs its own queries again
+    db.set_source("b", "to main return 3");
+    db.c_code("a");
+    assert!(db.c_code("b").as_ref().as_ref().unwrap().contains("return haumea_exit_code(3ll);"));
+    assert_eq!((db.runs("tokens"), db.runs("module"), db.runs("c_code")), (3, 3, 3));
+}
+
+#[test]
+fn test_equal_results_stop_recomputation() {
+    let mut db = Database::new();
+    db.set_source("main", "to main return 1");
+    db.c_code("main");
+    db.set_source("main", "to main\n    return 1 /* unchanged */");
+    db.c_code("main");
+    assert_eq!((db.runs("tokens"), db.runs("module"), db.runs("c_code")), (2, 1, 1));
+}
+
+#[test]
+fn test_options_are_an_input() {
+    let mut db = Database::new();
+    db.set_source("main", "to main return 1");
+    assert!(!db.c_code("main").as_ref().as_ref().unwrap().contains("haumea_int"));
+    db.set_options(Options { bignum: true, ..Options::default() });
+    assert!(db.c_code("main").as_ref().as_ref().unwrap().contains("haumea_int"));
+    assert_eq!((db.runs("module"), db.runs("c_code")), (1, 2));
+}
+
+#[test]
+fn test_big_numbers_are_read_with_bignum() {
+    let mut db = Database::new();
+    db.set_source("main", "to main display(99999999999999999999)");
+    assert_eq!(db.diagnostics("main")[0].code, "E0005");
+    db.set_options(Options { bignum: true, ..Options::default() });
+    assert!(db.diagnostics("main").is_empty());
+    assert!(db.c_code("main").is_ok());
+}
+
+#[test]
+fn test_mistakes_are_results() {
+    let mut db = Database::new();
+    db.set_source("main", "to main do\n    display(1 +\nend");
+    assert_eq!(db.diagnostics("main")[0].code, "E0101");
+    assert!(db.module("main").is_err());
+    assert!(db.c_code("main").is_err());
+    // Names and types are checked once the file parses
+    db.set_source("main", "to main do\n    display(x)\nend");
+    assert_eq!(db.diagnostics("main")[0].code, "E0201");
+    db.set_source("main", "to main do\n    display(1 + \"a\")\nend");
+    let diagnostics = db.diagnostics("main");
+    assert_eq!((diagnostics[0].code, diagnostics[0].span.start.line), ("E0401", 2));
+    // Moving the mistake changes where it is reported, even though the program is the same
+    db.set_source("main", "\nto main do\n    display(1 + \"a\")\nend");
+    assert_eq!(db.diagnostics("main")[0].span.start.line, 3);
+    assert_eq!(db.runs("diagnostics"), 4);
+}
+
+#[test]
+fn test_loaded_programs_are_checked_and_compiled() {
+    let mut db = Database::new();
+    let program = parse(Scanner::new("to main return x")).unwrap();
+    db.set_program("main", program, vec![]);
+    assert_eq!(db.diagnostics("main")[0].code, "E0201");
+    let program = parse(Scanner::new("to main return 2")).unwrap();
+    db.set_program("main", program, vec![]);
+    assert!(db.diagnostics("main").is_empty());
+    assert!(db.c_code("main").as_ref().as_ref().unwrap().contains("return haumea_exit_code(2ll);"));
+    assert_eq!((db.runs("tokens"), db.runs("diagnostics")), (0, 2));
+}
@@ -2,6 +2,7 @@
 extern crate haumea;
 use haumea::scanner::*;
 use haumea::scanner::Token::*;
+use haumea::scanner::Keyword;
 
 fn assert_scan_is(source: &str, expected: Vec<Token>) {
     let scanner = Scanner::new(source);
@@ -23,10 +24,17 @@ fn test_keywords() {
                         "if", "then", "else", "let", "be", "forever",
                         "while", "for", "each", "in",
                         "set", "to", "through", "change", "by", "variable"];
-    let keywords: Vec<Token> = keywords.iter().map(|kw| Keyword(kw.to_string(), ScanState::empty())).collect();
+    let keywords: Vec<Token> = keywords.iter().map(|kw| Keyword(Keyword::from_word(kw).unwrap(), ScanState::empty())).collect();
     assert_scan_is("to with is return do end if then else let be forever while for each in set to through change by variable", keywords);                              
 }
 
+#[test]
+fn test_decimals() {
+    assert_scan_is("1.25-0.5 7.x", vec![Float(1.25, ScanState::empty()), Operator("-".to_string(), ScanState::empty()),
+                                         Float(0.5, ScanState::empty()), Number(7, ScanState::empty()),
+                                         Error('.', ScanState::empty()), Ident("x".to_string(), ScanState::empty())]);
+}
+
 #[test]
 fn test_comments() {
      assert_scan_is("/* This is a comment 
@@ -34,3 +42,126 @@ fn test_comments() {
      assert_scan_is("/* This /* is /* a /* very */ nested */ comment */ */ 1+1", vec![Number(1, ScanState::empty()), Operator("+".to_string(), ScanState::empty()), Number(1, ScanState::empty())]);
 }
 
+
+#[test]
+fn test_comments_as_tokens() {
+    let tokens: Vec<Token> = Scanner::new("/* One /* nested */ */\n1 /* Two */\n/* never closed").with_comments().collect();
+    assert_eq!(tokens, vec![Comment("/* One /* nested */ */".to_string(), ScanState::empty()), Number(1, ScanState::empty()),
+                            Comment("/* Two */".to_string(), ScanState::empty()), UnterminatedComment(ScanState::empty())]);
+    assert_eq!(tokens[2].state(), ScanState::new(2, 3));
+    // The parser skips them
+    let source = "/* Squares */ to square with (n) /* a number */ return n * n";
+    assert_eq!(haumea::parser::parse(Scanner::new(source).with_comments()),
+               haumea::parser::parse(Scanner::new(source)));
+}
+
+#[test]
+fn test_consecutive_comments() {
+     assert_scan_is("/* One */ /* Two */
+     /* Three */ 1", vec![Number(1, ScanState::empty())]);
+}
+
+#[test]
+fn test_string_literals() {
+    assert_scan_is("use \"lib/math helpers\"", vec![Keyword(Keyword::Use, ScanState::empty()),
+                                                   StringLit("lib/math helpers".to_string(), ScanState::empty())]);
+    assert_scan_is("\"unterminated", vec![Error('"', ScanState::empty())]);
+}
+
+#[test]
+fn test_string_escapes() {
+    assert_scan_is(r#""say \"hi\"\n\tC:\\""#, vec![StringLit("say \"hi\"\n\tC:\\".to_string(), ScanState::empty())]);
+    // The whole string is still read, so scanning carries on after it
+    let tokens: Vec<Token> = Scanner::new(r#""a\qb\z" 1"#).collect();
+    assert_eq!(tokens, vec![BadEscape('q', ScanState::empty()), Number(1, ScanState::empty())]);
+    assert_eq!(tokens[0].state(), ScanState::new(1, 3));
+    assert_scan_is("\"ends in an escape\\", vec![Error('"', ScanState::empty())]);
+}
+
+#[test]
+fn test_qualified_names() {
+    assert_scan_is("mathlib.helper(1)", vec![Ident("mathlib.helper".to_string(), ScanState::empty()),
+                                             Lp(ScanState::empty()), Number(1, ScanState::empty()),
+                                             Rp(ScanState::empty())]);
+    assert_scan_is("use \"mathlib\" as m", vec![Keyword(Keyword::Use, ScanState::empty()),
+                                               StringLit("mathlib".to_string(), ScanState::empty()),
+                                               Keyword(Keyword::As, ScanState::empty()),
+                                               Ident("m".to_string(), ScanState::empty())]);
+}
+
+#[test]
+fn test_tokens_hash_without_position() {
+    use std::collections::HashSet;
+    let tokens: HashSet<Token> = Scanner::new("x + x\n+ 1").collect();
+    let expected: HashSet<Token> = vec![
+        Ident("x".to_string(), ScanState::empty()),
+        Operator("+".to_string(), ScanState::empty()),
+        Number(1, ScanState::empty()),
+    ].into_iter().collect();
+    assert_eq!(tokens, expected);
+}
+
+#[test]
+fn test_tokens_display() {
+    let tokens: Vec<String> = Scanner::new("to x + 1 \"a\" ( $").map(|token| token.to_string()).collect();
+    assert_eq!(tokens, vec!["the keyword 'to'", "the name 'x'", "the operator '+'", "the number 1",
+                            "the text \"a\"", "'('", "the unexpected character '$'"]);
+}
+
+#[test]
+fn test_scan_errors_are_tokens() {
+    assert_scan_is("99999999999999999999 1", vec![TooLarge("99999999999999999999".to_string(), ScanState::empty()),
+                                         Number(1, ScanState::empty())]);
+    let tokens: Vec<Token> = Scanner::new("1 /* a /* b */").collect();
+    assert_eq!(tokens, vec![Number(1, ScanState::empty()), UnterminatedComment(ScanState::empty())]);
+    assert_eq!(tokens[1].state().column, 3);
+}
+
+#[test]
+fn test_scan_errors_are_collected() {
+    let mut scanner = Scanner::new("say(\"hi\" # \"bye");
+    assert_eq!(scanner.by_ref().count(), 5);
+    assert_eq!(scanner.errors(), [ScanError::UnexpectedChar('#', ScanState::new(1, 10)),
+                                  ScanError::UnterminatedString(ScanState::new(1, 12))]);
+    assert_eq!(scanner.errors()[1].to_string(), "At line 1:12, the text that starts here is never closed!");
+}
+
+#[test]
+fn test_deeply_nested_comments() {
+    let source = format!("{}{} 1", "/* ".repeat(100_000), "*/ ".repeat(100_000));
+    assert_scan_is(&source, vec![Number(1, ScanState::empty())]);
+    assert_scan_is("/*/ 1 */ 2 /**/ 3 /* /**/ */ 4", vec![Number(2, ScanState::empty()), Number(3, ScanState::empty()),
+                                                    Number(4, ScanState::empty())]);
+    let tokens: Vec<Token> = Scanner::new("1\n  /* a */ /* b /* c */ */ /* d /* e").collect();
+    assert_eq!(tokens[1], UnterminatedComment(ScanState::empty()));
+    assert_eq!((tokens[1].state().line, tokens[1].state().column), (2, 27));
+}
+
+#[test]
+fn test_windows_line_endings() {
+    let positions = |source: &str| -> Vec<(u32, u32)> {
+        Scanner::new(source).map(|token| (token.state().line, token.state().column)).collect()
+    };
+    let unix = "to main do\n  display(1)\nend\n";
+    // Every line's columns count from 1
+    assert_eq!(positions(unix), vec![(1, 1), (1, 4), (1, 9), (2, 3), (2, 10), (2, 11), (2, 12), (3, 1)]);
+    assert_eq!(positions("to main do\r\n  display(1)\r\nend\r\n"), positions(unix));
+    assert_eq!(positions("to main do\r\n  display(1)\nend\r\n"), positions(unix));
+    assert_eq!(positions("\u{feff}to main do\r\n  display(1)\r\nend"), positions(unix));
+    assert_scan_is("\u{feff}1", vec![Number(1, ScanState::empty())]);
+    let mut scanner = Scanner::new("\u{feff}1 + 1");
+    assert_eq!(scanner.next_token_with_range(), (Number(1, ScanState::empty()), 3..4));
+}
+
+#[test]
+fn test_shebang_lines_are_skipped() {
+    let positions = |source: &str| -> Vec<(u32, u32)> {
+        Scanner::new(source).map(|token| (token.state().line, token.state().column)).collect()
+    };
+    assert_eq!(positions("#!/usr/bin/env haumea\nto main display(1)"), positions("\nto main display(1)"));
+    assert_scan_is("#!/usr/bin/env haumea\nto", vec![Keyword(Keyword::To, ScanState::empty())]);
+    assert_scan_is("\u{feff}#!/usr/bin/env haumea", vec![]);
+    // Only the first line can be a shebang
+    assert_scan_is("1\n#!", vec![Number(1, ScanState::empty()), Error('#', ScanState::empty()),
+                                 Operator("!".to_string(), ScanState::empty())]);
+}
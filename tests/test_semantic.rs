@@ -0,0 +1,55 @@
+//! Tests for `haumea::semantic`
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::{parse, parse_module, Span};
+use haumea::semantic::{analyze, SemanticError};
+
+fn errors(source: &str) -> Vec<SemanticError> {
+    analyze(&parse(Scanner::new(source)).unwrap(), &[])
+}
+
+#[test]
+fn test_variables_are_declared_in_their_block() {
+    assert_eq!(errors("to main with (n) do
+        variable total
+        for each i in 1 to n change total by i
+        do variable inner set inner to total end
+        return total
+    end"), []);
+    assert_eq!(errors("to main do
+        do variable inner end
+        set inner to 1
+        for each i in 1 to 3 display(i)
+        return i
+    end"), [
+        SemanticError::UndeclaredVariable { function: "main".into(), name: "inner".into(), span: Span::default() },
+        SemanticError::UndeclaredVariable { function: "main".into(), name: "i".into(), span: Span::default() },
+    ]);
+    assert_eq!(errors("to main do variable x variable x end"),
+               [SemanticError::Redeclared { function: "main".into(), name: "x".into(), span: Span::default() }]);
+}
+
+#[test]
+fn test_functions_are_defined_once() {
+    assert_eq!(errors("to main do display(twice(read())) missing(1) end to twice with (n) return n * 2"),
+               [SemanticError::UndeclaredFunction { function: "main".into(), name: "missing".into(), span: Span::default() }]);
+    assert_eq!(errors("to f return 1 to f return 2 to main return f()"),
+               [SemanticError::DuplicateFunction { name: "f".into(), span: Span::default() }]);
+    assert_eq!(errors("external to abs with (n) external to abs with (n) to main return abs(-1)"), []);
+}
+
+#[test]
+fn test_procedures_are_only_called_as_statements() {
+    assert_eq!(errors("to greet do display(1) end to main do greet() display(greet()) end"),
+               [SemanticError::ProcedureValue { function: "main".into(), name: "greet".into(), span: Span::default() }]);
+    assert_eq!(errors("to one returns Integer do end to main return one()"), []);
+}
+
+#[test]
+fn test_embedded_files_define_functions() {
+    let module = parse_module(Scanner::new("embed \"table.txt\" as table
+    to main return table(table_size() - 1)")).unwrap();
+    assert_eq!(analyze(&module.functions, &module.directives), []);
+    assert_eq!(analyze(&module.functions, &[]).len(), 2);
+}
@@ -0,0 +1,33 @@
+//! Tests for the `serde` feature
+#![cfg(feature = "serde")]
+extern crate haumea;
+
+use std::fs;
+
+use haumea::scanner::Scanner;
+use haumea::parser::{parse, Program};
+use haumea::serde_json;
+
+#[test]
+fn test_ast_round_trips_through_json() {
+    for entry in fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        let program = parse(Scanner::new(&fs::read_to_string(&path).unwrap())).unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        let read: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, program, "{}", path.display());
+    }
+}
+
+#[test]
+fn test_ast_json_shape() {
+    let program = parse(Scanner::new("to main return 1 + x")).unwrap();
+    let json = serde_json::to_value(&program).unwrap();
+    assert_eq!(json[0]["name"], "main");
+    assert_eq!(json[0]["visibility"], "Private");
+    assert_eq!(json[0]["code"]["Return"][0]["BinaryOp"]["operator"], "Add");
+    assert_eq!(json[0]["code"]["Return"][0]["BinaryOp"]["left"]["Integer"][0], 1);
+    assert_eq!(json[0]["code"]["Return"][0]["BinaryOp"]["right"]["Ident"][0], "x");
+    assert_eq!(json[0]["code"]["Return"][1]["start"]["column"], 9);
+    assert_eq!(json[0]["code"]["Return"][0]["BinaryOp"]["span"]["end"]["column"], 20);
+}
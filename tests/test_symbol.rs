@@ -0,0 +1,22 @@
+//! Tests for `haumea::symbol`
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::*;
+use haumea::symbol::Symbol;
+
+#[test]
+fn test_names_are_interned_once() {
+    let program = parse(Scanner::new("to main do variable x set x to 1 return x end")).unwrap();
+    let name = match program[0].code {
+        Statement::Do(ref block, _) => match *block[0] {
+            Statement::Var(name, _) => name,
+            ref other => panic!("Expected a variable declaration, found {:?}", other),
+        },
+        ref other => panic!("Expected a block, found {:?}", other),
+    };
+    assert_eq!(name.as_str().as_ptr(), Symbol::intern("x").as_str().as_ptr());
+    assert_eq!(program[0].name, Symbol::from("main".to_string()));
+    assert!(Symbol::from("a") < Symbol::from("b"));
+    assert_ne!(Symbol::from("a"), Symbol::from("b"));
+}
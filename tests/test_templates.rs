@@ -0,0 +1,65 @@
+//! Tests for `haumea::templates`
+#[macro_use]
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::*;
+use haumea::ast::{expr, stmt};
+
+#[test]
+fn test_templates_are_expanded() {
+    let program = parse(Scanner::new("template swap with (a, b) do
+        variable temp
+        set temp to a
+        set a to b
+        set b to temp
+    end
+    to main do
+        variable temp
+        swap(temp, y)
+    end")).unwrap();
+    assert_eq!(program[0].code, block![
+        stmt::var("temp"),
+        block![
+            stmt::var("__HAUMEA_LOCAL_1"),
+            stmt::set("__HAUMEA_LOCAL_1", expr::ident("temp")),
+            stmt::set("temp", expr::ident("y")),
+            stmt::set("y", expr::ident("__HAUMEA_LOCAL_1")),
+        ],
+    ]);
+}
+
+#[test]
+fn test_templates_can_use_templates() {
+    let program = parse(Scanner::new("template show with (n) display(n)
+    template show_twice with (n) do show(n) show(n + 1) end
+    to main show_twice(5)")).unwrap();
+    let display = |n| stmt::call("display", vec![n]);
+    assert_eq!(program[0].code, block![
+        display(expr::int(5)),
+        display(expr::add(expr::int(5), expr::int(1))),
+    ]);
+}
+
+/// Returns the message of the first error parsing `source` fails with
+fn error(source: &str) -> String {
+    parse(Scanner::new(source)).unwrap_err()[0].to_string()
+}
+
+#[test]
+fn test_changed_parameters_need_variables() {
+    assert!(error("template bump with (n) change n by 1 to main bump(3)")
+        .contains("in the template `bump`, `n` is changed, so it must be given a variable"));
+}
+
+#[test]
+fn test_templates_are_not_expressions() {
+    assert!(error("template bump with (n) change n by 1 to main return bump(3)")
+        .contains("the template `bump` can only be used as a statement"));
+}
+
+#[test]
+fn test_recursive_templates() {
+    assert!(error("template again with (n) again(n) to main again(1)")
+        .contains("the template `again` uses itself too many times"));
+}
@@ -0,0 +1,168 @@
+//! Tests for `haumea::typecheck`
+extern crate haumea;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::typecheck::typecheck;
+
+fn errors(source: &str) -> Vec<String> {
+    typecheck(&parse(Scanner::new(source)).unwrap()).iter().map(|error| error.to_string()).collect()
+}
+
+#[test]
+fn test_declared_types() {
+    assert_eq!(errors("to add with (a: Integer, b: Integer) returns Integer return a + b
+    to greeting returns Text return \"hello\"
+    to main do
+        display_text(greeting())
+        return add(1, length of [2, 3])
+    end"), Vec::<String>::new());
+    assert_eq!(errors("to name with (n: Integer) returns Text do
+        set n to \"one\"
+        return n
+    end"), [
+        "error: at line 2:9, in `name`, `n` is an Integer, but is set to `\"one\"`, which is Text",
+        "error: at line 3:9, in `name`, the function returns Text, but `n` is an Integer",
+    ]);
+}
+
+#[test]
+fn test_operands() {
+    assert_eq!(errors("to main with (xs: List) do
+        display(\"one\" + 1)
+        display(xs[\"first\"])
+        display(length of 5)
+        if xs then return 0
+        display_real(2 * 0.5)
+    end"), [
+        "error: at line 2:17, in `main`, `\"one\"` is Text, but `+` needs an Integer",
+        "error: at line 3:20, in `main`, `\"first\"` is Text, but an index must be an Integer",
+        "error: at line 4:27, in `main`, `5` is an Integer, but `length of` needs a List or Text",
+        "error: at line 5:12, in `main`, `xs` is a List, but the `if` condition must be an Integer",
+        "error: at line 6:22, in `main`, `2` is an Integer, but `*` needs a Real",
+    ]);
+    // An operator with a wrong operand isn't reported again where it is used
+    assert_eq!(errors("to main do
+        display_text(-(\"a\" + 1))
+        return length of 5 * 2
+    end"), [
+        "error: at line 2:24, in `main`, `\"a\"` is Text, but `+` needs an Integer",
+        "error: at line 3:26, in `main`, `5` is an Integer, but `length of` needs a List or Text",
+    ]);
+}
+
+#[test]
+fn test_calls() {
+    assert_eq!(errors("to twice with (n) return n * 2
+    to main do
+        display(twice(1, 2))
+        display_text(twice(1))
+        display_text(3)
+        display(1, display())
+        display_no_newline(1, 2.5)
+        return read(1)
+    end"), [
+        "error: at line 3:17, in `main`, `twice` takes 1 argument, but is called with 2 arguments",
+        "error: at line 4:22, in `main`, argument 1 of `display_text` is `twice(1)`, which is an Integer, but it must be Text",
+        "error: at line 5:22, in `main`, argument 1 of `display_text` is `3`, which is an Integer, but it must be Text",
+        "error: at line 6:20, in `main`, `display` takes at least 1 argument, but is called with 0 arguments",
+        "error: at line 7:31, in `main`, argument 2 of `display_no_newline` is `2.5`, which is a Real, but it must be an Integer or Text",
+        "error: at line 8:16, in `main`, `read` takes 0 arguments, but is called with 1 argument",
+    ]);
+}
+
+#[test]
+fn test_inferred_returns() {
+    assert_eq!(errors("to name with (n) if n > 0 then return \"some\" else return \"none\"
+    to describe with (n) return name(n)
+    to maybe with (n) if n > 0 then return \"some\"
+    to label returns Text if read() > 0 then return \"yes\"
+    to main do
+        display_real(describe(1))
+        display_real(maybe(1))
+    end"), [
+        "error: at line 4:5, in `label`, the function returns Text, but can reach its end, where it returns 0",
+        "error: at line 6:22, in `main`, argument 1 of `display_real` is `describe(1)`, which is Text, but it must be a Real",
+    ]);
+}
+
+#[test]
+fn test_procedures_return_nothing() {
+    assert_eq!(errors("to greet with (n) do display(n) end
+    to main do
+        greet(\"one\")
+        display(1)
+    end"), Vec::<String>::new());
+    assert_eq!(errors("to greet returns Nothing return 1
+    to skip with (n: Nothing) returns Integer return 1"), [
+        "error: at line 1:26, in `greet`, the function returns Nothing, but returns `1`",
+        "error: at line 2:5, in `skip`, parameter 1 is declared Nothing, but only a function can return nothing",
+    ]);
+}
+
+#[test]
+fn test_text() {
+    assert_eq!(errors("to main do
+        variable name
+        set name to join(\"Ada\", \" \", \"Lovelace\")
+        display(\"Hello,\", name, length of name)
+        if name = \"Ada Lovelace\" and name != \"\" then return 0
+        display(name = 1)
+        return name + 1
+    end"), [
+        "error: at line 6:24, in `main`, `1` is an Integer, but `=` needs Text",
+        "error: at line 7:16, in `main`, `name` is Text, but `+` needs an Integer",
+    ]);
+    // A variable set to text and to a number could be either
+    assert_eq!(errors("to main do
+        variable x
+        set x to \"one\"
+        set x to 1
+        return x + 1
+    end"), Vec::<String>::new());
+}
+
+#[test]
+fn test_parameter_types_come_from_calls() {
+    // `s` is always given text, and `n` is passed on to itself
+    assert_eq!(errors("to size with (s, n) do
+        if n > 0 then return size(s, n - 1)
+        return length of s
+    end
+    to main do
+        display(size(\"one\", 2), size(join(\"t\", \"wo\"), 1))
+        return size(\"three\", 0) + 1
+    end"), Vec::<String>::new());
+    assert_eq!(errors("to twice with (n) return n * 2
+    to main return twice(\"two\")"), [
+        "error: at line 1:26, in `twice`, `n` is Text, but `*` needs an Integer",
+    ]);
+    // Without a single type, `length of` and `=` can't tell what they are given
+    assert_eq!(errors("to size with (s) return length of s
+    to same with (a, b) return a = b
+    to main with (xs: List) do
+        display(size(\"one\"), size([1]), same(1, 1), same(\"a\", \"a\"))
+        display(length of xs[0], xs[0] = xs[1], xs[0] = 1)
+    end"), [
+        "error: at line 1:35, in `size`, the type of `s` isn't known, so `length of` can't tell whether it is a List or Text",
+        "error: at line 2:32, in `same`, the types of `a` and `b` aren't known, so `=` can't tell whether to compare Text or numbers",
+        "error: at line 5:27, in `main`, the type of `xs[0]` isn't known, so `length of` can't tell whether it is a List or Text",
+        "error: at line 5:34, in `main`, the types of `xs[0]` and `xs[1]` aren't known, so `=` can't tell whether to compare Text or numbers",
+    ]);
+}
+
+#[test]
+fn test_external_functions_have_c_types() {
+    // Without a type, an external function returns an Integer, and its parameters take anything
+    assert_eq!(errors("external to sqrt with (x) from \"math.h\"
+    external to cbrt with (x: Real) returns Real from \"math.h\"
+    to main do
+        display(sqrt(16), sqrt(2.25))
+        display_real(cbrt(27.0))
+        display_real(sqrt(2.25))
+        display_real(cbrt(27))
+    end"), [
+        "error: at line 6:22, in `main`, argument 1 of `display_real` is `sqrt(2.25)`, which is an Integer, but it must be a Real",
+        "error: at line 7:27, in `main`, argument 1 of `cbrt` is `27`, which is an Integer, but it must be a Real",
+    ]);
+}
@@ -0,0 +1,76 @@
+//! Tests for `haumea::validate`
+extern crate haumea;
+
+use std::fs;
+use std::sync::Arc;
+
+use haumea::scanner::Scanner;
+use haumea::parser::*;
+use haumea::ast::{expr, stmt};
+use haumea::codegen::{CodeGen, CodegenError};
+use haumea::codegen::c::CodeGenerator;
+use haumea::validate::{validate, Invalid};
+
+#[test]
+fn test_parsed_examples_are_valid() {
+    for entry in fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        let program = parse(Scanner::new(&fs::read_to_string(&path).unwrap())).unwrap();
+        assert_eq!(validate(&program), Ok(()), "{}", path.display());
+        assert_eq!(haumea::validate(&program), vec![], "{}", path.display());
+    }
+}
+
+#[test]
+fn test_malformed_trees_are_reported() {
+    let mut program = vec![
+        Function::new("main").params(&["", "n"]).body(stmt::for_each(
+            "i", expr::int(1), expr::int(2), expr::int(1), false,
+            stmt::set("i", expr::unary(Operator::Mul, expr::ident("2x"))),
+        )),
+    ];
+    if let Statement::ForEach { ref mut range_type, .. } = program[0].code {
+        *range_type = "until".to_string();
+    }
+    let messages: Vec<String> = validate(&program).unwrap_err().iter().map(Invalid::to_string).collect();
+    assert_eq!(messages, vec![
+        "internal compiler error: in `main`, the parameter name \"\" is not an identifier",
+        "internal compiler error: in `main`, \"until\" is not a range type",
+        "internal compiler error: in `main`, `*` is used as a unary operator",
+        "internal compiler error: in `main`, the variable name \"2x\" is not an identifier",
+    ]);
+}
+
+#[test]
+fn test_codegen_checks_trees() {
+    let program = vec![Function::new("")];
+    let error = CodeGenerator::new().compile(&program).unwrap_err();
+    assert_eq!(error, CodegenError::Invalid(validate(&program).unwrap_err()));
+    assert_eq!(error.to_string(), "internal compiler error: in ``, the function name \"\" is not an identifier");
+}
+
+#[test]
+fn test_programs_are_diagnosed() {
+    let mut program = parse(Scanner::new("to twice with (n) return n * 2
+    to main with (argc) do
+        display(twice(1, 2))
+        twice()
+        for each i in 1 to 3 display(i)
+    end")).unwrap();
+    if let Statement::Do(ref mut block, _) = program[1].code {
+        if let Statement::ForEach { ref mut range_type, .. } = *Arc::make_mut(&mut block[2]) {
+            *range_type = "until".to_string();
+        }
+    }
+    let diagnostics: Vec<(&str, String, String)> = haumea::validate(&program).iter()
+        .map(|diagnostic| (diagnostic.code, diagnostic.span.to_string(), diagnostic.message.clone()))
+        .collect();
+    assert_eq!(diagnostics, [
+        ("E0501", "5:9".to_string(), "\"until\" is not a range type".to_string()),
+        ("E0502", "3:17".to_string(), "`twice` takes 1 argument, but is called with 2 arguments".to_string()),
+        ("E0502", "4:9".to_string(), "`twice` takes 1 argument, but is called with 0 arguments".to_string()),
+        ("E0504", "2:5".to_string(), "`main` takes 1 argument, but must take none".to_string()),
+    ]);
+    let program = parse(Scanner::new("to start return 0")).unwrap();
+    assert_eq!(haumea::validate(&program)[0].message, "there is no `main` function to run");
+}
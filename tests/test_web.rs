@@ -0,0 +1,55 @@
+//! Tests for `haumea::codegen::web`
+extern crate haumea;
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use haumea::scanner::Scanner;
+use haumea::parser::parse;
+use haumea::codegen::CodeGen;
+use haumea::codegen::js::CodeGenerator;
+use haumea::codegen::web;
+
+fn compile(source: &str) -> String {
+    CodeGenerator::new().compile(&parse(Scanner::new(source)).unwrap()).unwrap()
+}
+
+#[test]
+fn test_page_has_the_program_in_it() {
+    let module = compile("to main display_text(\"</script>\")");
+    let page = web::page("<b>&", &module);
+    assert!(page.contains("<title>&lt;b&gt;&amp;</title>"));
+    assert!(page.contains("<script src=\"haumea.js\"></script>"));
+    assert!(page.contains("<script type=\"module\">\n/* Haumea runtime */"));
+    assert!(page.contains("display_text(\"<\\/script>\");"));
+    assert_eq!(page.matches("</script>").count(), 2);
+    assert!(page.contains("haumea_start(run);\n</script>"));
+}
+
+#[test]
+fn test_page_displays_in_the_pre_and_reads_with_a_prompt() {
+    // Skip the test on machines without node
+    if Command::new("node").arg("--version").output().is_err() {
+        return;
+    }
+    let dir = env::temp_dir().join("haumea_test_web");
+    let module = compile("to main do display(read() * 2) display_no_newline(\"so far\") return 1 / 0 end");
+    web::write(&dir, "double", &module).unwrap();
+    // The page's module is run with a <pre> and prompt like a browser's
+    let page = fs::read_to_string(dir.join("index.html")).unwrap();
+    let start = page.find("<script type=\"module\">\n").unwrap() + "<script type=\"module\">\n".len();
+    let end = page.rfind("</script>").unwrap();
+    fs::write(dir.join("page.mjs"), &page[start..end]).unwrap();
+    let script = format!("
+        import fs from 'fs';
+        const pre = {{ textContent: '' }};
+        globalThis.document = {{ getElementById: (id) => id === 'output' ? pre : null }};
+        globalThis.prompt = () => '21';
+        (0, eval)(fs.readFileSync({:?}, 'utf8'));
+        await import({:?});
+        process.stdout.write(pre.textContent);",
+        dir.join("haumea.js").display().to_string(), dir.join("page.mjs").display().to_string());
+    let out = Command::new("node").args(["--input-type=module", "-e", &script]).output().unwrap();
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "42\nso far\nError: Division by zero\n");
+}